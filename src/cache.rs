@@ -0,0 +1,113 @@
+//! On-disk cache of evaluation results, so the jobs of a workflow asking the same
+//! question of the same commits skip the diff and the matching.
+//!
+//! An entry's key hashes the commits compared with everything else that decides the
+//! result, and the gdf version, so an entry can only answer the question it was stored
+//! for: nothing needs invalidating when refs move or filters change, as the new inputs
+//! have another key. Entries not read for a week are removed.
+
+use crate::json::{self, Value};
+use crate::log::log;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// How long an entry is kept without being read
+const MAX_AGE: Duration = Duration::from_hours(7 * 24);
+
+/// The key of the entry for `inputs`, which must name everything deciding the result
+pub fn key(inputs: &[&str]) -> String {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    inputs.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// The entry stored under `key`, if there is a readable one
+pub fn load(dir: &Path, key: &str) -> Option<Value> {
+    let path = dir.join(format!("{key}.json"));
+    let text = fs::read_to_string(&path).ok()?;
+    let value = json::parse(&text).ok()?;
+    // Reading an entry keeps it from expiring
+    if let Ok(file) = fs::File::options().append(true).open(&path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    log!(Debug, "gdf", "Cached result: {}", path.display());
+    Some(value)
+}
+
+/// Store `value` under `key`, removing expired entries
+///
+/// The entry is written to a temporary file first and renamed into place, so jobs
+/// sharing the directory never read a partial one.
+///
+/// # Errors
+/// Returns an error when the directory or the entry cannot be written.
+pub fn store(dir: &Path, key: &str, value: &Value) -> Result<(), String> {
+    let failed =
+        |e: std::io::Error| format!("Failed to cache the result in {}: {e}", dir.display());
+    fs::create_dir_all(dir).map_err(failed)?;
+    remove_expired(dir);
+    let path = dir.join(format!("{key}.json"));
+    let partial = dir.join(format!("{key}.{}.tmp", std::process::id()));
+    fs::write(&partial, value.to_string()).map_err(failed)?;
+    fs::rename(&partial, &path).map_err(failed)
+}
+
+/// Remove the entries, and temporary files left by interrupted writes, not touched for
+/// [`MAX_AGE`]
+fn remove_expired(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > MAX_AGE);
+        if expired {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key(&["a", "b"]), key(&["a", "b"]));
+        assert_ne!(key(&["a", "b"]), key(&["b", "a"]));
+        // Inputs are hashed apart, not joined
+        assert_ne!(key(&["ab", ""]), key(&["a", "b"]));
+        assert_eq!(key(&[]).len(), 16);
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let dir = std::env::temp_dir().join(format!("gdf_test_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(load(&dir, "missing"), None);
+
+        let value = json::parse(r#"{"outcomes": [{"match": true}]}"#).unwrap();
+        store(&dir, "entry", &value).unwrap();
+        assert_eq!(load(&dir, "entry"), Some(value.clone()));
+
+        // An expired entry is removed when another is stored
+        let stale = dir.join("stale.json");
+        fs::write(&stale, "{}").unwrap();
+        let file = fs::File::options().append(true).open(&stale).unwrap();
+        file.set_modified(SystemTime::now() - MAX_AGE * 2).unwrap();
+        store(&dir, "other", &value).unwrap();
+        assert!(!stale.exists());
+        assert!(dir.join("entry.json").exists());
+
+        fs::write(dir.join("broken.json"), "{not json").unwrap();
+        assert_eq!(load(&dir, "broken"), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,192 @@
+//! `--cargo-workspace`: the crates of a Cargo workspace whose files changed, each found
+//! as the nearest `Cargo.toml` with a `[package]` above the file, so a CI job can run
+//! `cargo test -p <crate>` for those alone.
+
+use crate::json::{self, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The names of the crates owning `files`, given relative to `root`, sorted and once
+/// each
+///
+/// A file outside every package, like one next to a virtual manifest, belongs to no
+/// crate; so does a file of a crate whose manifest was deleted, unless a package
+/// above it owns it.
+pub fn affected_crates(root: &Path, files: &[String]) -> Vec<String> {
+    // The package declared in each directory looked at, if any
+    let mut packages: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut crates = BTreeSet::new();
+    for file in files {
+        let mut dir = Path::new(file).parent();
+        while let Some(current) = dir {
+            let package = packages.entry(current.to_path_buf()).or_insert_with(|| {
+                fs::read_to_string(root.join(current).join("Cargo.toml"))
+                    .ok()
+                    .and_then(|manifest| package_name(&manifest))
+            });
+            if let Some(name) = package {
+                crates.insert(name.clone());
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+    crates.into_iter().collect()
+}
+
+/// Each workspace member with the names of the packages it depends on, from
+/// `cargo metadata` run in `root`
+///
+/// `--no-deps` keeps cargo from resolving or fetching anything outside the workspace.
+///
+/// # Errors
+/// Returns an error when cargo cannot be run or fails, for example outside a workspace.
+pub fn dependency_graph(root: &Path) -> Result<Vec<(String, Vec<String>)>, String> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .current_dir(root)
+        .output()
+        .map_err(|e| format!("Failed to run cargo metadata: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_metadata(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Read the packages and their dependencies' names from `cargo metadata` output
+fn parse_metadata(text: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let document = json::parse(text).map_err(|e| format!("Invalid cargo metadata: {e}"))?;
+    let Some(Value::Array(packages)) = document.get("packages") else {
+        return Err("Invalid cargo metadata: no packages".to_string());
+    };
+    Ok(packages
+        .iter()
+        .filter_map(|package| {
+            let name = package.get("name")?.as_str()?.to_string();
+            let dependencies = match package.get("dependencies") {
+                Some(Value::Array(dependencies)) => dependencies
+                    .iter()
+                    .filter_map(|dependency| dependency.get("name")?.as_str())
+                    .map(str::to_string)
+                    .collect(),
+                _ => Vec::new(),
+            };
+            Some((name, dependencies))
+        })
+        .collect())
+}
+
+/// The `name` of the `[package]` table of a manifest, or `None` for a virtual manifest
+///
+/// Read line by line rather than with the config file's TOML parser, which rejects the
+/// `[[bin]]` tables manifests often have.
+fn package_name(manifest: &str) -> Option<String> {
+    let mut in_package = false;
+    for line in manifest.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if in_package && key.trim() == "name" {
+            let value = value.trim();
+            return ['"', '\'']
+                .into_iter()
+                .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+                .map(str::to_string);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(
+            package_name("[package]\nname = \"api\" # the server\nversion = \"0.1.0\"\n"),
+            Some("api".to_string())
+        );
+        assert_eq!(
+            package_name("[workspace]\nmembers = [\"api\"]\n\n[package]\nname = 'root'\n"),
+            Some("root".to_string())
+        );
+        // Only the package's own name counts
+        assert_eq!(
+            package_name("[dependencies]\nname = \"other\"\n\n[[bin]]\nname = \"gdf\"\n"),
+            None
+        );
+        assert_eq!(
+            package_name("[workspace]\nmembers = [\"crates/*\"]\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let text = r#"{"packages": [
+            {"name": "core", "dependencies": [{"name": "serde", "kind": null}]},
+            {"name": "api", "dependencies": [
+                {"name": "core", "kind": null, "path": "/ws/crates/core"},
+                {"name": "core", "kind": "dev", "rename": "core_dev"}
+            ]},
+            {"name": "docs"}
+        ], "workspace_members": []}"#;
+        let owned = |deps: &[&str]| deps.iter().map(|s| (*s).to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse_metadata(text),
+            Ok(vec![
+                ("core".to_string(), owned(&["serde"])),
+                ("api".to_string(), owned(&["core", "core"])),
+                ("docs".to_string(), Vec::new()),
+            ])
+        );
+        assert!(parse_metadata("{}").is_err());
+        assert!(parse_metadata("error: not a workspace").is_err());
+    }
+
+    #[test]
+    fn test_affected_crates() {
+        let root = std::env::temp_dir().join(format!("gdf_test_cargo_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("crates/api/src/routes")).unwrap();
+        fs::create_dir_all(root.join("crates/core/src")).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("crates/api/Cargo.toml"),
+            "[package]\nname = \"api-server\"\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("crates/core/Cargo.toml"),
+            "[package]\nname = \"core\"\n",
+        )
+        .unwrap();
+
+        let files = [
+            "crates/core/src/lib.rs",
+            "crates/api/src/routes/users.rs",
+            "crates/api/Cargo.toml",
+            "README.md",
+            "crates/removed/src/lib.rs",
+        ]
+        .map(str::to_string);
+        assert_eq!(affected_crates(&root, &files), ["api-server", "core"]);
+        assert!(affected_crates(&root, &[]).is_empty());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
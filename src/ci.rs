@@ -0,0 +1,271 @@
+//! Detection of the CI system running gdf from its standard environment variables, so
+//! that without `-b` or `BASE_REF`, a pull request build compares against its target
+//! branch and any other build against the default branch, and named outputs go where the
+//! system reads variables from, with no plumbing.
+
+use crate::git;
+use std::str::FromStr;
+
+/// A CI system gdf knows the environment of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    GitHubActions,
+    GitLab,
+    Jenkins,
+    Azure,
+    CircleCi,
+    Buildkite,
+}
+
+/// A file a CI system reads variables from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFile {
+    /// `KEY=value` lines, as `--dotenv` writes them
+    Dotenv(&'static str),
+    /// Java properties, as `--properties-file` writes them
+    Properties(&'static str),
+}
+
+/// Every provider, in the order they are detected
+const PROVIDERS: [Provider; 6] = [
+    Provider::GitHubActions,
+    Provider::GitLab,
+    Provider::Jenkins,
+    Provider::Azure,
+    Provider::CircleCi,
+    Provider::Buildkite,
+];
+
+impl Provider {
+    /// The CI system `var` describes, if any; `var` reads an environment variable
+    pub fn detect(var: &dyn Fn(&str) -> Option<String>) -> Option<Provider> {
+        PROVIDERS
+            .into_iter()
+            .find(|provider| provider.is_running(var))
+    }
+
+    /// The name given to `--ci`
+    #[must_use]
+    pub fn key(self) -> &'static str {
+        match self {
+            Provider::GitHubActions => "github",
+            Provider::GitLab => "gitlab",
+            Provider::Jenkins => "jenkins",
+            Provider::Azure => "azure",
+            Provider::CircleCi => "circleci",
+            Provider::Buildkite => "buildkite",
+        }
+    }
+
+    fn is_running(self, var: &dyn Fn(&str) -> Option<String>) -> bool {
+        let is_true =
+            |name: &str| var(name).is_some_and(|value| value.eq_ignore_ascii_case("true"));
+        match self {
+            Provider::GitHubActions => is_true("GITHUB_ACTIONS"),
+            Provider::GitLab => is_true("GITLAB_CI"),
+            Provider::Jenkins => var("JENKINS_URL").is_some_and(|url| !url.is_empty()),
+            Provider::Azure => is_true("TF_BUILD"),
+            Provider::CircleCi => is_true("CIRCLECI"),
+            Provider::Buildkite => is_true("BUILDKITE"),
+        }
+    }
+
+    /// The file named outputs are written to when no output file is given: GitLab's
+    /// dotenv report, or on Jenkins a properties file for `readProperties`
+    ///
+    /// GitHub Actions reads `GITHUB_OUTPUT`, and the other systems stdout.
+    #[must_use]
+    pub fn output_file(self) -> Option<OutputFile> {
+        match self {
+            Provider::GitLab => Some(OutputFile::Dotenv("gdf.env")),
+            Provider::Jenkins => Some(OutputFile::Properties("gdf.properties")),
+            _ => None,
+        }
+    }
+
+    /// The base refs to try in turn, as with -b: the target branch of a pull or merge
+    /// request, else the default branch, or on GitHub, the commit the event builds on
+    ///
+    /// A branch is tried as its `origin` remote-tracking branch first, as CI checkouts
+    /// rarely have local branches other than the one built.
+    pub fn base_refs(self, var: &dyn Fn(&str) -> Option<String>) -> Vec<String> {
+        let set = |name: &str| var(name).filter(|value| !value.is_empty() && value != "false");
+        let branch = |name: &str| {
+            set(name).map(|branch| {
+                let branch = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+                vec![format!("origin/{branch}"), branch.to_string()]
+            })
+        };
+        let refs = match self {
+            // Other events name the commit they build on, unless they create a branch
+            Provider::GitHubActions => branch("GITHUB_BASE_REF").or_else(|| {
+                Some(vec![
+                    git::EVENT_BASE.to_string(),
+                    git::AUTO_BASE.to_string(),
+                ])
+            }),
+            Provider::GitLab => set("CI_MERGE_REQUEST_DIFF_BASE_SHA")
+                .map(|sha| vec![sha])
+                .or_else(|| branch("CI_MERGE_REQUEST_TARGET_BRANCH_NAME"))
+                .or_else(|| branch("CI_DEFAULT_BRANCH")),
+            Provider::Jenkins => branch("CHANGE_TARGET"),
+            Provider::Azure => branch("SYSTEM_PULLREQUEST_TARGETBRANCH"),
+            Provider::CircleCi => None,
+            Provider::Buildkite => branch("BUILDKITE_PULL_REQUEST_BASE_BRANCH")
+                .or_else(|| branch("BUILDKITE_PIPELINE_DEFAULT_BRANCH")),
+        };
+        refs.unwrap_or_else(|| vec![git::AUTO_BASE.to_string()])
+    }
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Provider::GitHubActions => "GitHub Actions",
+            Provider::GitLab => "GitLab CI",
+            Provider::Jenkins => "Jenkins",
+            Provider::Azure => "Azure Pipelines",
+            Provider::CircleCi => "CircleCI",
+            Provider::Buildkite => "Buildkite",
+        })
+    }
+}
+
+/// Which CI system `--ci` says to take the base refs from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CiMode {
+    /// The one detected from the environment, if any
+    #[default]
+    Auto,
+    /// None: without `-b` or `BASE_REF`, there is no base ref
+    Off,
+    Provider(Provider),
+}
+
+impl CiMode {
+    /// The provider to take the base refs from; `var` reads an environment variable
+    pub fn provider(self, var: &dyn Fn(&str) -> Option<String>) -> Option<Provider> {
+        match self {
+            CiMode::Auto => Provider::detect(var),
+            CiMode::Off => None,
+            CiMode::Provider(provider) => Some(provider),
+        }
+    }
+}
+
+impl FromStr for CiMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(CiMode::Auto),
+            "none" => Ok(CiMode::Off),
+            _ => PROVIDERS
+                .into_iter()
+                .find(|provider| provider.key() == s)
+                .map(CiMode::Provider)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid CI system: {s} (expected auto, none, github, gitlab, jenkins, \
+                         azure, circleci or buildkite)"
+                    )
+                }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value).to_string())
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Provider::detect(&env(&[])), None);
+        assert_eq!(
+            Provider::detect(&env(&[("GITHUB_ACTIONS", "true")])),
+            Some(Provider::GitHubActions)
+        );
+        assert_eq!(
+            Provider::detect(&env(&[("TF_BUILD", "True")])),
+            Some(Provider::Azure)
+        );
+        assert_eq!(
+            Provider::detect(&env(&[("JENKINS_URL", "https://ci.example.com/")])),
+            Some(Provider::Jenkins)
+        );
+        assert_eq!(Provider::detect(&env(&[("CIRCLECI", "false")])), None);
+    }
+
+    #[test]
+    fn test_base_refs() {
+        let refs = |provider: Provider, vars: &[(&str, &str)]| provider.base_refs(&env(vars));
+        assert_eq!(
+            refs(Provider::GitHubActions, &[("GITHUB_BASE_REF", "main")]),
+            ["origin/main", "main"]
+        );
+        // A push has no target branch, but the commit it moved the branch from
+        assert_eq!(
+            refs(Provider::GitHubActions, &[("GITHUB_BASE_REF", "")]),
+            ["event", "auto"]
+        );
+        assert_eq!(
+            refs(
+                Provider::GitLab,
+                &[
+                    ("CI_MERGE_REQUEST_DIFF_BASE_SHA", "1a2b3c"),
+                    ("CI_MERGE_REQUEST_TARGET_BRANCH_NAME", "main"),
+                ]
+            ),
+            ["1a2b3c"]
+        );
+        assert_eq!(
+            refs(Provider::GitLab, &[("CI_DEFAULT_BRANCH", "trunk")]),
+            ["origin/trunk", "trunk"]
+        );
+        assert_eq!(
+            refs(
+                Provider::Azure,
+                &[("SYSTEM_PULLREQUEST_TARGETBRANCH", "refs/heads/release/2.x")]
+            ),
+            ["origin/release/2.x", "release/2.x"]
+        );
+        assert_eq!(
+            refs(
+                Provider::Buildkite,
+                &[
+                    ("BUILDKITE_PULL_REQUEST_BASE_BRANCH", ""),
+                    ("BUILDKITE_PIPELINE_DEFAULT_BRANCH", "main"),
+                ]
+            ),
+            ["origin/main", "main"]
+        );
+        assert_eq!(refs(Provider::CircleCi, &[]), ["auto"]);
+    }
+
+    #[test]
+    fn test_ci_mode() {
+        let github = env(&[("GITHUB_ACTIONS", "true")]);
+        assert_eq!("auto".parse::<CiMode>(), Ok(CiMode::Auto));
+        assert_eq!(
+            "auto".parse::<CiMode>().unwrap().provider(&github),
+            Some(Provider::GitHubActions)
+        );
+        assert_eq!("none".parse::<CiMode>().unwrap().provider(&github), None);
+        assert_eq!(
+            "gitlab".parse::<CiMode>().unwrap().provider(&github),
+            Some(Provider::GitLab)
+        );
+        assert!("travis"
+            .parse::<CiMode>()
+            .unwrap_err()
+            .starts_with("Invalid CI system: travis"));
+    }
+}
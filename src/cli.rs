@@ -1,59 +1,332 @@
 //! This module handles command-line argument parsing.
 
+use crate::config;
+use crate::interpolate;
 use std::env;
 
 /// Parsed command-line arguments
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Args {
     pub patterns: Vec<String>,
-    pub base_ref: Option<String>,
-    pub github_output: Option<String>,
+    pub base_refs: Vec<String>,
+    /// `-g` names, each with the number of patterns given before it; a name's own
+    /// patterns are the ones given after it
+    pub github_output: Vec<(String, usize)>,
+    pub anchoring: Option<String>,
+    pub renames: Option<String>,
+    pub find_copies: bool,
+    pub no_merge_base: bool,
+    pub source: Option<String>,
+    pub submodule_marker: Option<String>,
+    pub recurse_submodules: bool,
+    pub content_patterns: Vec<String>,
+    pub min_lines_changed: Option<String>,
+    pub max_lines_changed: Option<String>,
+    pub status: Option<String>,
+    pub auto_fetch: bool,
+    pub fetch_attempts: Option<String>,
+    pub git_retries: Option<String>,
+    pub git_retry_delay: Option<String>,
+    pub git_dir: Option<String>,
+    pub relative: bool,
+    pub sparse_paths: Option<String>,
+    pub ignore_whitespace: bool,
+    pub ignore_content_patterns: Vec<String>,
+    pub binary_only: bool,
+    pub text_only: bool,
+    pub mode_changes: Option<String>,
+    pub since: Option<String>,
+    pub last: Option<String>,
+    pub first_parent: bool,
+    pub repos: Vec<String>,
+    pub report_json: Option<String>,
+    pub list_files: bool,
+    pub step_summary: bool,
+    pub annotations: bool,
+    pub dotenv: Option<String>,
+    pub teamcity: bool,
+    pub properties_file: Option<String>,
+    pub circleci_parameters: Option<String>,
+    pub env_file: Option<String>,
+    pub bitbucket_report: Option<String>,
+    pub matrix_output: bool,
+    pub files_output: Option<String>,
+    pub files_output_nul: bool,
+    pub list_delimiter: Option<String>,
+    pub color: Option<String>,
+    pub trace_json: bool,
+    pub report_yaml: Option<String>,
+    pub format: Option<String>,
+    pub exit_codes: Option<String>,
+    pub report_md: Option<String>,
+    pub jsonl: bool,
+    pub stats: bool,
+    pub config: Option<String>,
+    pub no_config: bool,
+    pub ignore_case: bool,
+    /// Indexes of the `-g` names `--group-ignore-case` follows
+    pub group_ignore_case: Vec<usize>,
+    /// `--group-status` lists, each with the index of the `-g` name it follows
+    pub group_status: Vec<(usize, String)>,
+    pub min_fraction: Option<String>,
+    /// `--group-min-fraction` values, each with the index of the `-g` name it follows
+    pub group_min_fraction: Vec<(usize, String)>,
+    /// `--group-base-ref` values, each with the index of the `-g` name it follows
+    pub group_base_ref: Vec<(usize, String)>,
+    /// `--group-compose` compositions, each with the index of the `-g` name it follows
+    pub group_compose: Vec<(usize, String)>,
+    /// Patterns excluded from every filter, written without the leading `!`
+    pub default_excludes: Vec<String>,
+    /// Indexes of the `-g` names `--group-no-default-excludes` follows
+    pub group_no_default_excludes: Vec<usize>,
+    pub profile: Option<String>,
+    /// `--group-branch` globs, each with the index of the `-g` name it follows
+    pub group_branch: Vec<(usize, String)>,
+    /// Let environment variables win over the flags for the same value
+    pub prefer_env: bool,
+    pub cargo_workspace: bool,
+    pub js_workspace: bool,
+    pub include_dependents: bool,
+    pub targets: Vec<String>,
+    pub watch: bool,
+    pub serve: bool,
+    pub socket: Option<String>,
+    pub github_api: bool,
+    pub no_cache: bool,
+    pub cache_dir: Option<String>,
+    pub exclude_generated: bool,
+    pub generated_attribute: Option<String>,
+    /// `--expr` entries, each `<name>=<expression>`
+    pub expressions: Vec<String>,
+    /// `--each-base` entries, each `[<name>=]<ref>`
+    pub bases: Vec<String>,
+    pub ci: Option<String>,
+    /// `--test-map` entries, each `<source-glob>=<test-glob>`
+    pub test_map: Vec<String>,
+    /// `--job` entries, each `<job>=<expression>`
+    pub jobs: Vec<String>,
+    pub presets: Vec<String>,
+    /// `-g` names given with `--require`
+    pub required: Vec<String>,
+    pub enforce: bool,
+    pub print_config: bool,
+    /// The config file the defaults were read from, kept for `--print-config`
+    pub config_file: Option<String>,
+    /// Every flag given, kept for `--print-config`
+    pub given: Vec<GivenFlag>,
 }
 
-/// Parse command-line arguments from environment
+/// A flag with its values and where it was given: the command line or a config file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GivenFlag {
+    pub flag: String,
+    pub values: Vec<String>,
+    pub origin: String,
+}
+
+/// Parse command-line arguments from environment, with defaults from a config file
 pub fn parse_args() -> Result<Args, String> {
     let args: Vec<String> = env::args().skip(1).collect(); // Skip program name
-    parse_args_from_vec(&args)
+    let flags = parse_flags(&args)?;
+    let args = expand_patterns(args)?;
+    let config_path = config::config_path(flags.config.as_deref(), flags.no_config);
+    if flags.profile.is_some() && config_path.is_none() {
+        return Err("--profile requires a config file".to_string());
+    }
+    let env_profile = env::var("GDF_PROFILE").ok().filter(|s| !s.is_empty());
+    // The config file is not read yet, so only --prefer-env on the command line counts
+    let profile = if flags.prefer_env {
+        env_profile.or(flags.profile)
+    } else {
+        flags.profile.or(env_profile)
+    };
+    let file_args =
+        config::file_args(flags.config.as_deref(), flags.no_config, profile.as_deref())?;
+    let merged = with_file_args(&file_args, &args);
+    let mut parsed = parse_args_from_vec(&merged)?;
+    if parsed.print_config {
+        let config_file = config_path.map(|path| match &profile {
+            Some(profile) => format!("{} (profile {profile})", path.display()),
+            None => path.display().to_string(),
+        });
+        parsed.given = given_flags(&merged, merged.len() - args.len(), config_file.as_deref());
+        parsed.config_file = config_file;
+    }
+    Ok(parsed)
+}
+
+/// Expand `${VAR}` in the values of `-p`/`--pattern`
+fn expand_patterns(mut args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut starts = Vec::new();
+    parse_flags_with(&args, &mut |start| starts.push(start))?;
+    for start in starts {
+        if matches!(args[start].as_str(), "-p" | "--pattern") {
+            if let Some(pattern) = args.get_mut(start + 1) {
+                *pattern = interpolate::expand(pattern)?;
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Split arguments into flags with their values, the first `file_len` of them coming
+/// from the config file `config_file`
+fn given_flags(args: &[String], file_len: usize, config_file: Option<&str>) -> Vec<GivenFlag> {
+    let mut starts = Vec::new();
+    // The arguments were already parsed once, so they parse again
+    let _ = parse_flags_with(args, &mut |start| starts.push(start));
+    let ends = starts.iter().skip(1).copied().chain([args.len()]);
+    starts
+        .iter()
+        .zip(ends)
+        .map(|(&start, end)| GivenFlag {
+            flag: args[start].clone(),
+            values: args[start + 1..end].to_vec(),
+            origin: match config_file {
+                Some(path) if start < file_len => path.to_string(),
+                _ => "command line".to_string(),
+            },
+        })
+        .collect()
+}
+
+/// Put the options of a config file, one flag and its value per entry, before the
+/// command line, leaving out those the command line gives itself
+///
+/// Patterns and `-g` names go together: giving either on the command line replaces all
+/// of the file's filters.
+pub fn with_file_args(file_args: &[Vec<String>], args: &[String]) -> Vec<String> {
+    let canonical = |flag: &str| match flag {
+        "-p"
+        | "--pattern"
+        | "-g"
+        | "--github-output"
+        | "--group-ignore-case"
+        | "--group-status"
+        | "--group-base-ref"
+        | "--group-compose"
+        | "--group-no-default-excludes"
+        | "--group-branch"
+        | "--group-min-fraction" => "--pattern".to_string(),
+        "-b" => "--base-ref".to_string(),
+        _ => flag.to_string(),
+    };
+    let given: Vec<String> = args.iter().map(|arg| canonical(arg)).collect();
+    file_args
+        .iter()
+        .filter(|entry| {
+            entry
+                .first()
+                .is_some_and(|flag| !given.contains(&canonical(flag)))
+        })
+        .flatten()
+        .chain(args)
+        .cloned()
+        .collect()
 }
 
 /// Parse arguments from a vector (for testing)
 fn parse_args_from_vec(args: &[String]) -> Result<Args, String> {
-    let mut patterns = Vec::new();
-    let mut base_ref = None;
-    let mut github_output = None;
+    let parsed = parse_flags(args)?;
+
+    // Validate required flags
+    if parsed.patterns.is_empty() && parsed.presets.is_empty() {
+        return Err("at least one --pattern or --preset is required".to_string());
+    }
+
+    Ok(parsed)
+}
+
+/// Parse flags without requiring any of them
+pub fn parse_flags(args: &[String]) -> Result<Args, String> {
+    parse_flags_with(args, &mut |_| {})
+}
+
+/// Parse flags, calling `on_flag` with the index of each flag
+fn parse_flags_with(args: &[String], on_flag: &mut dyn FnMut(usize)) -> Result<Args, String> {
+    let mut parsed = Args::default();
 
     let mut i = 0;
     while i < args.len() {
         let arg = &args[i];
+        on_flag(i);
 
         match arg.as_str() {
-            "-p" | "--pattern" => {
-                i += 1;
-                if i >= args.len() {
-                    return Err(format!("{arg} requires a value"));
-                }
-                patterns.push(args[i].clone());
+            "-p" | "--pattern" => push_value(&mut parsed.patterns, arg, args, &mut i)?,
+            "-b" | "--base-ref" => push_value(&mut parsed.base_refs, arg, args, &mut i)?,
+            "-g" | "--github-output" => {
+                let mut names = Vec::new();
+                push_value(&mut names, arg, args, &mut i)?;
+                let first_pattern = parsed.patterns.len();
+                parsed
+                    .github_output
+                    .extend(names.into_iter().map(|name| (name, first_pattern)));
             }
-            "-b" | "--base-ref" => {
-                i += 1;
-                if base_ref.is_some() {
-                    return Err(format!("{arg} can only be specified once"));
-                }
-                if i >= args.len() {
-                    return Err(format!("{arg} requires a value"));
-                }
-                base_ref = Some(args[i].clone());
+            "--anchoring" => set_once(&mut parsed.anchoring, arg, args, &mut i)?,
+            "--renames" => set_once(&mut parsed.renames, arg, args, &mut i)?,
+            "--source" => set_once(&mut parsed.source, arg, args, &mut i)?,
+            "--submodule-marker" => set_once(&mut parsed.submodule_marker, arg, args, &mut i)?,
+            "--content-pattern" => {
+                push_value(&mut parsed.content_patterns, arg, args, &mut i)?;
             }
-            "-g" | "--github-output" => {
-                i += 1;
-                if github_output.is_some() {
-                    return Err(format!("{arg} can only be specified once"));
-                }
-                if i >= args.len() {
-                    return Err(format!("{arg} requires a value"));
-                }
-                github_output = Some(args[i].clone());
+            "--min-lines-changed" => set_once(&mut parsed.min_lines_changed, arg, args, &mut i)?,
+            "--max-lines-changed" => set_once(&mut parsed.max_lines_changed, arg, args, &mut i)?,
+            "--min-fraction" => set_once(&mut parsed.min_fraction, arg, args, &mut i)?,
+            "--status" => set_once(&mut parsed.status, arg, args, &mut i)?,
+            "--fetch-attempts" => set_once(&mut parsed.fetch_attempts, arg, args, &mut i)?,
+            "--git-retries" => set_once(&mut parsed.git_retries, arg, args, &mut i)?,
+            "--git-retry-delay" => set_once(&mut parsed.git_retry_delay, arg, args, &mut i)?,
+            "--git-dir" => set_once(&mut parsed.git_dir, arg, args, &mut i)?,
+            "--sparse-paths" => set_once(&mut parsed.sparse_paths, arg, args, &mut i)?,
+            "--mode-changes" => set_once(&mut parsed.mode_changes, arg, args, &mut i)?,
+            "--since" => set_once(&mut parsed.since, arg, args, &mut i)?,
+            "--last" => set_once(&mut parsed.last, arg, args, &mut i)?,
+            "--repo" => push_value(&mut parsed.repos, arg, args, &mut i)?,
+            "--report-json" => set_once(&mut parsed.report_json, arg, args, &mut i)?,
+            "--dotenv" => set_once(&mut parsed.dotenv, arg, args, &mut i)?,
+            "--properties-file" => set_once(&mut parsed.properties_file, arg, args, &mut i)?,
+            "--files-output" => set_once(&mut parsed.files_output, arg, args, &mut i)?,
+            "--list-delimiter" => set_once(&mut parsed.list_delimiter, arg, args, &mut i)?,
+            "--color" => set_once(&mut parsed.color, arg, args, &mut i)?,
+            "--report-yaml" => set_once(&mut parsed.report_yaml, arg, args, &mut i)?,
+            "--format" => set_once(&mut parsed.format, arg, args, &mut i)?,
+            "--exit-codes" => set_once(&mut parsed.exit_codes, arg, args, &mut i)?,
+            "--report-md" => set_once(&mut parsed.report_md, arg, args, &mut i)?,
+            "--config" => set_once(&mut parsed.config, arg, args, &mut i)?,
+            "--profile" => set_once(&mut parsed.profile, arg, args, &mut i)?,
+            "--target" => push_value(&mut parsed.targets, arg, args, &mut i)?,
+            "--expr" => push_value(&mut parsed.expressions, arg, args, &mut i)?,
+            "--each-base" => push_value(&mut parsed.bases, arg, args, &mut i)?,
+            "--ci" => set_once(&mut parsed.ci, arg, args, &mut i)?,
+            "--test-map" => push_value(&mut parsed.test_map, arg, args, &mut i)?,
+            "--job" => push_value(&mut parsed.jobs, arg, args, &mut i)?,
+            "--preset" => push_value(&mut parsed.presets, arg, args, &mut i)?,
+            "--require" => push_value(&mut parsed.required, arg, args, &mut i)?,
+            "--socket" => set_once(&mut parsed.socket, arg, args, &mut i)?,
+            "--cache-dir" => set_once(&mut parsed.cache_dir, arg, args, &mut i)?,
+            "--generated-attribute" => {
+                set_once(&mut parsed.generated_attribute, arg, args, &mut i)?;
             }
+            "--group-ignore-case"
+            | "--group-status"
+            | "--group-base-ref"
+            | "--group-compose"
+            | "--group-no-default-excludes"
+            | "--group-branch"
+            | "--group-min-fraction" => {
+                parse_group_flag(&mut parsed, arg, args, &mut i)?;
+            }
+            "--default-excludes" => push_value(&mut parsed.default_excludes, arg, args, &mut i)?,
+            "--env-file" => set_once(&mut parsed.env_file, arg, args, &mut i)?,
+            "--bitbucket-report" => set_once(&mut parsed.bitbucket_report, arg, args, &mut i)?,
+            "--circleci-parameters" => {
+                set_once(&mut parsed.circleci_parameters, arg, args, &mut i)?;
+            }
+            "--ignore-content-matching" => {
+                push_value(&mut parsed.ignore_content_patterns, arg, args, &mut i)?;
+            }
+            _ if set_switch(&mut parsed, arg) => {}
             _ => {
                 if arg.starts_with('-') {
                     return Err(format!("Unknown flag: {arg}"));
@@ -64,16 +337,159 @@ fn parse_args_from_vec(args: &[String]) -> Result<Args, String> {
         i += 1;
     }
 
-    // Validate required flags
-    if patterns.is_empty() {
-        return Err("at least one --pattern is required".to_string());
+    Ok(parsed)
+}
+
+/// Turn on the flag `arg` names when it takes no value, returning whether it does
+fn set_switch(parsed: &mut Args, arg: &str) -> bool {
+    let switch = match arg {
+        "--find-copies" => &mut parsed.find_copies,
+        "--no-merge-base" => &mut parsed.no_merge_base,
+        "--recurse-submodules" => &mut parsed.recurse_submodules,
+        "--auto-fetch" => &mut parsed.auto_fetch,
+        "--relative" => &mut parsed.relative,
+        "--ignore-whitespace" => &mut parsed.ignore_whitespace,
+        "--binary-only" => &mut parsed.binary_only,
+        "--text-only" => &mut parsed.text_only,
+        "--first-parent" => &mut parsed.first_parent,
+        "--list-files" => &mut parsed.list_files,
+        "--step-summary" => &mut parsed.step_summary,
+        "--annotations" => &mut parsed.annotations,
+        "--teamcity" => &mut parsed.teamcity,
+        "--matrix-output" => &mut parsed.matrix_output,
+        "--files-output-nul" => &mut parsed.files_output_nul,
+        "--trace-json" => &mut parsed.trace_json,
+        "--jsonl" => &mut parsed.jsonl,
+        "--stats" => &mut parsed.stats,
+        "--no-config" => &mut parsed.no_config,
+        "--ignore-case" => &mut parsed.ignore_case,
+        "--print-config" => &mut parsed.print_config,
+        "--prefer-env" => &mut parsed.prefer_env,
+        "--cargo-workspace" => &mut parsed.cargo_workspace,
+        "--js-workspace" => &mut parsed.js_workspace,
+        "--include-dependents" => &mut parsed.include_dependents,
+        "--watch" => &mut parsed.watch,
+        "--serve" => &mut parsed.serve,
+        "--github-api" => &mut parsed.github_api,
+        "--no-cache" => &mut parsed.no_cache,
+        "--exclude-generated" => &mut parsed.exclude_generated,
+        "--enforce" => &mut parsed.enforce,
+        _ => return false,
+    };
+    *switch = true;
+    true
+}
+
+/// Append the value following a repeatable flag, advancing past it
+fn push_value(
+    values: &mut Vec<String>,
+    arg: &str,
+    args: &[String],
+    i: &mut usize,
+) -> Result<(), String> {
+    *i += 1;
+    let value = args
+        .get(*i)
+        .ok_or_else(|| format!("{arg} requires a value"))?;
+    values.push(value.clone());
+    Ok(())
+}
+
+/// Store a flag that applies to the filter of the last `-g` name, advancing past its
+/// value
+fn parse_group_flag(
+    parsed: &mut Args,
+    arg: &str,
+    args: &[String],
+    i: &mut usize,
+) -> Result<(), String> {
+    let group = current_group(parsed, arg)?;
+    match arg {
+        "--group-ignore-case" => parsed.group_ignore_case.push(group),
+        "--group-no-default-excludes" => parsed.group_no_default_excludes.push(group),
+        "--group-status" => {
+            let mut lists = Vec::new();
+            push_value(&mut lists, arg, args, i)?;
+            if parsed.group_status.iter().any(|&(index, _)| index == group) {
+                return Err(format!("{arg} can only be specified once per -g name"));
+            }
+            parsed
+                .group_status
+                .extend(lists.into_iter().map(|list| (group, list)));
+        }
+        "--group-compose" => {
+            let mut compositions = Vec::new();
+            push_value(&mut compositions, arg, args, i)?;
+            if parsed
+                .group_compose
+                .iter()
+                .any(|&(index, _)| index == group)
+            {
+                return Err(format!("{arg} can only be specified once per -g name"));
+            }
+            parsed.group_compose.extend(
+                compositions
+                    .into_iter()
+                    .map(|composition| (group, composition)),
+            );
+        }
+        "--group-min-fraction" => {
+            let mut fractions = Vec::new();
+            push_value(&mut fractions, arg, args, i)?;
+            if parsed
+                .group_min_fraction
+                .iter()
+                .any(|&(index, _)| index == group)
+            {
+                return Err(format!("{arg} can only be specified once per -g name"));
+            }
+            parsed
+                .group_min_fraction
+                .extend(fractions.into_iter().map(|fraction| (group, fraction)));
+        }
+        "--group-branch" => {
+            let mut branches = Vec::new();
+            push_value(&mut branches, arg, args, i)?;
+            parsed
+                .group_branch
+                .extend(branches.into_iter().map(|branch| (group, branch)));
+        }
+        _ => {
+            let mut refs = Vec::new();
+            push_value(&mut refs, arg, args, i)?;
+            parsed
+                .group_base_ref
+                .extend(refs.into_iter().map(|base_ref| (group, base_ref)));
+        }
     }
+    Ok(())
+}
+
+/// The index of the last `-g` name, which a per-group flag applies to
+fn current_group(parsed: &Args, arg: &str) -> Result<usize, String> {
+    parsed
+        .github_output
+        .len()
+        .checked_sub(1)
+        .ok_or_else(|| format!("{arg} must follow the -g name it applies to"))
+}
 
-    Ok(Args {
-        patterns,
-        base_ref,
-        github_output,
-    })
+/// Store the value following a single-use flag, advancing past it
+fn set_once(
+    slot: &mut Option<String>,
+    arg: &str,
+    args: &[String],
+    i: &mut usize,
+) -> Result<(), String> {
+    *i += 1;
+    if slot.is_some() {
+        return Err(format!("{arg} can only be specified once"));
+    }
+    let value = args
+        .get(*i)
+        .ok_or_else(|| format!("{arg} requires a value"))?;
+    *slot = Some(value.clone());
+    Ok(())
 }
 
 #[cfg(test)]
@@ -92,8 +508,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string()],
-                base_ref: None,
-                github_output: None,
+                base_refs: vec![],
+                github_output: Vec::new(),
+                ..Default::default()
             })
         );
     }
@@ -105,8 +522,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string(), "*.rs".to_string()],
-                base_ref: None,
-                github_output: None,
+                base_refs: vec![],
+                github_output: Vec::new(),
+                ..Default::default()
             })
         );
     }
@@ -118,8 +536,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string()],
-                base_ref: Some("main".to_string()),
-                github_output: None,
+                base_refs: vec!["main".to_string()],
+                github_output: Vec::new(),
+                ..Default::default()
             })
         );
     }
@@ -131,8 +550,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string()],
-                base_ref: None,
-                github_output: Some("api".to_string()),
+                base_refs: vec![],
+                github_output: vec![("api".to_string(), 1)],
+                ..Default::default()
             })
         );
     }
@@ -144,8 +564,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string(), "*.rs".to_string()],
-                base_ref: Some("main".to_string()),
-                github_output: Some("api".to_string()),
+                base_refs: vec!["main".to_string()],
+                github_output: vec![("api".to_string(), 2)],
+                ..Default::default()
             })
         );
     }
@@ -164,8 +585,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string()],
-                base_ref: Some("main".to_string()),
-                github_output: Some("api".to_string()),
+                base_refs: vec!["main".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                ..Default::default()
             })
         );
     }
@@ -177,8 +599,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string()],
-                base_ref: Some("main".to_string()),
-                github_output: Some("api".to_string()),
+                base_refs: vec!["main".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                ..Default::default()
             })
         );
     }
@@ -188,7 +611,7 @@ mod tests {
         let result = parse(&["-b", "main"]);
         assert_eq!(
             result,
-            Err("at least one --pattern is required".to_string())
+            Err("at least one --pattern or --preset is required".to_string())
         );
     }
 
@@ -229,15 +652,40 @@ mod tests {
     }
 
     #[test]
-    fn test_error_duplicate_base_ref() {
-        let result = parse(&["-p", "*.txt", "-b", "main", "-b", "develop"]);
-        assert_eq!(result, Err("-b can only be specified once".to_string()));
+    fn test_parse_multiple_base_refs() {
+        let result = parse(&[
+            "-p",
+            "*.txt",
+            "-b",
+            "origin/main",
+            "--base-ref",
+            "main,v1.0",
+        ]);
+        assert_eq!(
+            result.unwrap().base_refs,
+            vec!["origin/main".to_string(), "main,v1.0".to_string()]
+        );
     }
 
     #[test]
-    fn test_error_duplicate_github_output() {
-        let result = parse(&["-p", "*.txt", "-g", "api", "-g", "service"]);
-        assert_eq!(result, Err("-g can only be specified once".to_string()));
+    fn test_parse_several_github_outputs() {
+        let result = parse(&[
+            "-p", "!*.md", "-g", "docs", "-p", "docs/**", "-g", "api", "-p", "src/**", "-p",
+            "api/**",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec![
+                    "!*.md".to_string(),
+                    "docs/**".to_string(),
+                    "src/**".to_string(),
+                    "api/**".to_string(),
+                ],
+                github_output: vec![("docs".to_string(), 1), ("api".to_string(), 2)],
+                ..Default::default()
+            })
+        );
     }
 
     #[test]
@@ -245,7 +693,7 @@ mod tests {
         let result = parse(&[]);
         assert_eq!(
             result,
-            Err("at least one --pattern is required".to_string())
+            Err("at least one --pattern or --preset is required".to_string())
         );
     }
 
@@ -256,8 +704,9 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["src/**/*.rs".to_string()],
-                base_ref: Some("refs/tags/v1.0".to_string()),
-                github_output: None,
+                base_refs: vec!["refs/tags/v1.0".to_string()],
+                github_output: Vec::new(),
+                ..Default::default()
             })
         );
     }
@@ -269,8 +718,1343 @@ mod tests {
             result,
             Ok(Args {
                 patterns: vec!["*.txt".to_string(), "*.rs".to_string()],
-                base_ref: Some("main".to_string()),
-                github_output: Some("api".to_string()),
+                base_refs: vec!["main".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_anchoring() {
+        let result = parse(&["-p", "*.md", "--anchoring", "unanchored"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["*.md".to_string()],
+                anchoring: Some("unanchored".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_error_duplicate_anchoring() {
+        let result = parse(&[
+            "-p",
+            "*.md",
+            "--anchoring",
+            "prefix",
+            "--anchoring",
+            "anchored",
+        ]);
+        assert_eq!(
+            result,
+            Err("--anchoring can only be specified once".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_renames() {
+        let result = parse(&["-p", "src/**", "--renames", "new"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                renames: Some("new".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_error_renames_without_value() {
+        let result = parse(&["-p", "src/**", "--renames"]);
+        assert_eq!(result, Err("--renames requires a value".to_string()));
+    }
+
+    #[test]
+    fn test_parse_find_copies() {
+        let result = parse(&["--find-copies", "-p", "templates/**"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["templates/**".to_string()],
+                find_copies: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_no_merge_base() {
+        let result = parse(&["-p", "src/**", "--no-merge-base"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                no_merge_base: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_source() {
+        let result = parse(&["-p", "src/**", "--source", "combined"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                source: Some("combined".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_submodule_flags() {
+        let result = parse(&[
+            "-p",
+            "vendor/**",
+            "--submodule-marker",
+            "@",
+            "--recurse-submodules",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["vendor/**".to_string()],
+                submodule_marker: Some("@".to_string()),
+                recurse_submodules: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_submodule_marker_missing_value() {
+        let result = parse(&["-p", "*.rs", "--submodule-marker"]);
+        assert_eq!(
+            result,
+            Err("--submodule-marker requires a value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_content_patterns() {
+        let result = parse(&[
+            "-p",
+            "**/*.tf",
+            "--content-pattern",
+            "^provider",
+            "--content-pattern",
+            "required_version",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["**/*.tf".to_string()],
+                content_patterns: vec!["^provider".to_string(), "required_version".to_string()],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_content_pattern_missing_value() {
+        let result = parse(&["-p", "*.tf", "--content-pattern"]);
+        assert_eq!(
+            result,
+            Err("--content-pattern requires a value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_line_thresholds() {
+        let result = parse(&[
+            "-p",
+            "*.rs",
+            "--min-lines-changed",
+            "5",
+            "--max-lines-changed",
+            "500",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["*.rs".to_string()],
+                min_lines_changed: Some("5".to_string()),
+                max_lines_changed: Some("500".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let result = parse(&["-p", "migrations/**", "--status", "added,R"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["migrations/**".to_string()],
+                status: Some("added,R".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_auto_fetch() {
+        let result = parse(&["-p", "*.rs", "--auto-fetch", "--fetch-attempts", "5"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["*.rs".to_string()],
+                auto_fetch: true,
+                fetch_attempts: Some("5".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_git_retries() {
+        let result = parse(&[
+            "-p",
+            "*.rs",
+            "--git-retries",
+            "4",
+            "--git-retry-delay",
+            "100",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["*.rs".to_string()],
+                git_retries: Some("4".to_string()),
+                git_retry_delay: Some("100".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_git_dir() {
+        let result = parse(&["-p", "*.rs", "--git-dir", "/srv/repos/app.git"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["*.rs".to_string()],
+                git_dir: Some("/srv/repos/app.git".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        let result = parse(&["-p", "src/**", "--relative"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                relative: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_sparse_paths() {
+        let result = parse(&["-p", "src/**", "--sparse-paths", "exclude"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                sparse_paths: Some("exclude".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_ignore_whitespace() {
+        let result = parse(&["-p", "src/**", "--ignore-whitespace"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                ignore_whitespace: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_ignore_content_matching() {
+        let result = parse(&[
+            "-p",
+            "src/**",
+            "--ignore-content-matching",
+            "^version = ",
+            "--ignore-content-matching",
+            "Copyright [0-9]+",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                ignore_content_patterns: vec![
+                    "^version = ".to_string(),
+                    "Copyright [0-9]+".to_string()
+                ],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_binary_and_text_only() {
+        let result = parse(&["-p", "assets/**", "--binary-only", "--text-only"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["assets/**".to_string()],
+                binary_only: true,
+                text_only: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_mode_changes() {
+        let result = parse(&["-p", "scripts/**", "--mode-changes", "ignore"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["scripts/**".to_string()],
+                mode_changes: Some("ignore".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let result = parse(&["-p", "services/**", "--since", "24 hours ago"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["services/**".to_string()],
+                since: Some("24 hours ago".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_first_parent() {
+        let result = parse(&["-p", "src/**", "-b", "v1.0", "--first-parent"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                base_refs: vec!["v1.0".to_string()],
+                first_parent: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_repos() {
+        let result = parse(&["-p", "src/**", "--repo", ".", "--repo", "core=libs/core"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                repos: vec![".".to_string(), "core=libs/core".to_string()],
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "src/**", "--repo"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_last() {
+        let result = parse(&["-p", "CHANGELOG.md", "--last", "20"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["CHANGELOG.md".to_string()],
+                last: Some("20".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_report_json() {
+        let result = parse(&["-p", "src/**", "--report-json", "report.json"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                report_json: Some("report.json".to_string()),
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "src/**", "--report-json", "a", "--report-json", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_list_files() {
+        let result = parse(&["-p", "src/**", "-g", "api", "--list-files"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                list_files: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_step_summary() {
+        let result = parse(&["-p", "src/**", "--step-summary"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                step_summary: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_annotations() {
+        let result = parse(&["-p", "src/**", "--annotations"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                annotations: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let result = parse(&["-p", "src/**", "-g", "api", "--dotenv", "build.env"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                dotenv: Some("build.env".to_string()),
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "src/**", "--dotenv"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_teamcity() {
+        let result = parse(&["-p", "src/**", "-g", "api", "--teamcity"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                teamcity: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_properties_file() {
+        let result = parse(&[
+            "-p",
+            "src/**",
+            "-g",
+            "api",
+            "--properties-file",
+            "gdf.properties",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                properties_file: Some("gdf.properties".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_circleci_parameters() {
+        let result = parse(&[
+            "-p",
+            "src/**",
+            "-g",
+            "api",
+            "--circleci-parameters",
+            "parameters.json",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                circleci_parameters: Some("parameters.json".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_bitbucket_outputs() {
+        let result = parse(&[
+            "-p",
+            "src/**",
+            "-g",
+            "api",
+            "--env-file",
+            "changes.sh",
+            "--bitbucket-report",
+            "report.json",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                env_file: Some("changes.sh".to_string()),
+                bitbucket_report: Some("report.json".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_matrix_output() {
+        let result = parse(&["-p", "src/**", "-g", "api", "--matrix-output"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("api".to_string(), 1)],
+                matrix_output: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_files_output() {
+        let result = parse(&[
+            "-p",
+            "src/**",
+            "--files-output",
+            "changed.txt",
+            "--files-output-nul",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                files_output: Some("changed.txt".to_string()),
+                files_output_nul: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_list_delimiter() {
+        let result = parse(&["-p", "src/**", "--list-delimiter", "json"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                list_delimiter: Some("json".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_color() {
+        let result = parse(&["-p", "src/**", "--color", "always"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                color: Some("always".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_trace_json() {
+        let result = parse(&["-p", "src/**", "--trace-json"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                trace_json: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_report_yaml() {
+        let result = parse(&["-p", "src/**", "--report-yaml", "report.yaml"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                report_yaml: Some("report.yaml".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let result = parse(&["-p", "src/**", "--format", "{result} ({count})"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                format: Some("{result} ({count})".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_exit_codes() {
+        let result = parse(&["-p", "src/**", "--exit-codes", "match=0,nomatch=78"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                exit_codes: Some("match=0,nomatch=78".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_report_md() {
+        let result = parse(&["-p", "src/**", "--report-md", "report.md"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                report_md: Some("report.md".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_jsonl() {
+        let result = parse(&["-p", "src/**", "--jsonl"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                jsonl: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let result = parse(&["-p", "src/**", "--stats"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                stats: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let result = parse(&["-p", "src/**", "--config", "ci/gdf.toml", "--no-config"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                config: Some("ci/gdf.toml".to_string()),
+                no_config: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_with_file_args() {
+        let entry =
+            |args: &[&str]| -> Vec<String> { args.iter().map(ToString::to_string).collect() };
+        let file_args = [
+            entry(&["--pattern", "!**/*.md"]),
+            entry(&["--base-ref", "main"]),
+            entry(&["--find-copies"]),
+            entry(&["--github-output", "api"]),
+            entry(&["--pattern", "services/api/**"]),
+            entry(&["--group-status", "added"]),
+        ];
+        assert_eq!(
+            with_file_args(&file_args, &entry(&["--list-files"])),
+            entry(&[
+                "--pattern",
+                "!**/*.md",
+                "--base-ref",
+                "main",
+                "--find-copies",
+                "--github-output",
+                "api",
+                "--pattern",
+                "services/api/**",
+                "--group-status",
+                "added",
+                "--list-files"
+            ])
+        );
+        // The command line's base ref and filters replace the file's
+        assert_eq!(
+            with_file_args(&file_args, &entry(&["-b", "v1", "-p", "docs/**"])),
+            entry(&["--find-copies", "-b", "v1", "-p", "docs/**"])
+        );
+        assert_eq!(
+            with_file_args(&file_args, &entry(&["-g", "docs"])),
+            entry(&["--base-ref", "main", "--find-copies", "-g", "docs"])
+        );
+    }
+
+    #[test]
+    fn test_parse_ignore_case() {
+        let result = parse(&[
+            "--ignore-case",
+            "-g",
+            "api",
+            "-p",
+            "api/**",
+            "--group-status",
+            "added,modified",
+            "-g",
+            "docs",
+            "--group-ignore-case",
+            "-p",
+            "docs/**",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["api/**".to_string(), "docs/**".to_string()],
+                github_output: vec![("api".to_string(), 0), ("docs".to_string(), 1)],
+                ignore_case: true,
+                group_ignore_case: vec![1],
+                group_status: vec![(0, "added,modified".to_string())],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["-p", "a/**", "--group-status", "added"]),
+            Err("--group-status must follow the -g name it applies to".to_string())
+        );
+        assert_eq!(
+            parse(&[
+                "-g",
+                "a",
+                "-p",
+                "a/**",
+                "--group-status",
+                "added",
+                "--group-status",
+                "deleted"
+            ]),
+            Err("--group-status can only be specified once per -g name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_group_base_ref() {
+        let result = parse(&[
+            "-g",
+            "infra",
+            "-p",
+            "infra/**",
+            "--group-base-ref",
+            "v2.0",
+            "--group-base-ref",
+            "v1.9",
+            "-g",
+            "app",
+            "-p",
+            "app/**",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["infra/**".to_string(), "app/**".to_string()],
+                github_output: vec![("infra".to_string(), 0), ("app".to_string(), 1)],
+                group_base_ref: vec![(0, "v2.0".to_string()), (0, "v1.9".to_string())],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["-p", "a/**", "--group-base-ref", "main"]),
+            Err("--group-base-ref must follow the -g name it applies to".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_print_config() {
+        let result = parse(&["-p", "src/**", "--print-config"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                print_config: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_given_flags() {
+        let args: Vec<String> = [
+            "--base-ref",
+            "main",
+            "--find-copies",
+            "-p",
+            "a/**",
+            "-p",
+            "b/**",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let given = |flag: &str, values: &[&str], origin: &str| GivenFlag {
+            flag: flag.to_string(),
+            values: values.iter().map(ToString::to_string).collect(),
+            origin: origin.to_string(),
+        };
+        assert_eq!(
+            given_flags(&args, 3, Some("gdf.toml")),
+            [
+                given("--base-ref", &["main"], "gdf.toml"),
+                given("--find-copies", &[], "gdf.toml"),
+                given("-p", &["a/**"], "command line"),
+                given("-p", &["b/**"], "command line"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_group_compose() {
+        let result = parse(&[
+            "-g",
+            "backend",
+            "-p",
+            "api/**",
+            "-g",
+            "tests",
+            "-p",
+            "**/tests/**",
+            "-g",
+            "code",
+            "--group-compose",
+            "backend - tests",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["api/**".to_string(), "**/tests/**".to_string()],
+                github_output: vec![
+                    ("backend".to_string(), 0),
+                    ("tests".to_string(), 1),
+                    ("code".to_string(), 2)
+                ],
+                group_compose: vec![(2, "backend - tests".to_string())],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&[
+                "-g",
+                "code",
+                "--group-compose",
+                "a - b",
+                "--group-compose",
+                "a"
+            ]),
+            Err("--group-compose can only be specified once per -g name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_default_excludes() {
+        let result = parse(&[
+            "--default-excludes",
+            "**/*.md",
+            "--default-excludes",
+            "**/fixtures/**",
+            "-g",
+            "docs",
+            "-p",
+            "docs/**",
+            "--group-no-default-excludes",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["docs/**".to_string()],
+                github_output: vec![("docs".to_string(), 0)],
+                default_excludes: vec!["**/*.md".to_string(), "**/fixtures/**".to_string()],
+                group_no_default_excludes: vec![0],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["-p", "a/**", "--group-no-default-excludes"]),
+            Err("--group-no-default-excludes must follow the -g name it applies to".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_patterns() {
+        let args =
+            |list: &[&str]| -> Vec<String> { list.iter().map(ToString::to_string).collect() };
+        // Only pattern values are expanded
+        assert_eq!(
+            expand_patterns(args(&["--format", "${path}", "-p", "$${a}/**"])),
+            Ok(args(&["--format", "${path}", "-p", "${a}/**"]))
+        );
+        assert!(expand_patterns(args(&["-p", "${GDF_TEST_UNDEFINED}/**"]))
+            .unwrap_err()
+            .starts_with("Undefined environment variable GDF_TEST_UNDEFINED"));
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let result = parse(&["-p", "src/**", "--profile", "release"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                profile: Some("release".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_group_branch() {
+        let result = parse(&[
+            "-g",
+            "release",
+            "-p",
+            "charts/**",
+            "--group-branch",
+            "release/*",
+            "--group-branch",
+            "hotfix/**",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["charts/**".to_string()],
+                github_output: vec![("release".to_string(), 0)],
+                group_branch: vec![(0, "release/*".to_string()), (0, "hotfix/**".to_string())],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["-p", "a/**", "--group-branch", "main"]),
+            Err("--group-branch must follow the -g name it applies to".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_prefer_env() {
+        let result = parse(&["-p", "src/**", "-b", "main", "--prefer-env"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                base_refs: vec!["main".to_string()],
+                prefer_env: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_cargo_workspace() {
+        let result = parse(&["-p", "crates/**", "--cargo-workspace"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["crates/**".to_string()],
+                cargo_workspace: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_js_workspace() {
+        let result = parse(&["-p", "packages/**", "--js-workspace"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["packages/**".to_string()],
+                js_workspace: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_include_dependents() {
+        let result = parse(&[
+            "-p",
+            "crates/**",
+            "--cargo-workspace",
+            "--include-dependents",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["crates/**".to_string()],
+                cargo_workspace: true,
+                include_dependents: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        let result = parse(&[
+            "-p",
+            "**",
+            "--target",
+            "//web:app=web/**",
+            "--target",
+            "//web:app=!web/**/*.md",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["**".to_string()],
+                targets: vec![
+                    "//web:app=web/**".to_string(),
+                    "//web:app=!web/**/*.md".to_string()
+                ],
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "**", "--target"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_watch() {
+        let result = parse(&["-p", "src/**", "--watch"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                watch: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_serve() {
+        let result = parse(&["-p", "src/**", "--serve", "--socket", "/tmp/gdf.sock"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                serve: true,
+                socket: Some("/tmp/gdf.sock".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_github_api() {
+        let result = parse(&["-p", "src/**", "--github-api"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                github_api: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_cache_options() {
+        let result = parse(&["-p", "src/**", "--no-cache", "--cache-dir", "/tmp/gdf"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                no_cache: true,
+                cache_dir: Some("/tmp/gdf".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_exclude_generated() {
+        let result = parse(&[
+            "-p",
+            "src/**",
+            "--exclude-generated",
+            "--generated-attribute",
+            "generated",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                exclude_generated: true,
+                generated_attribute: Some("generated".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_expr() {
+        let result = parse(&[
+            "-g",
+            "api",
+            "-p",
+            "api/**",
+            "--expr",
+            "deploy=api && !docs",
+            "--expr",
+            "skip=!api",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["api/**".to_string()],
+                github_output: vec![("api".to_string(), 0)],
+                expressions: vec!["deploy=api && !docs".to_string(), "skip=!api".to_string()],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_each_base() {
+        let result = parse(&[
+            "-p",
+            "api/**",
+            "--each-base",
+            "main",
+            "--each-base",
+            "release=origin/release/1.x",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["api/**".to_string()],
+                bases: vec!["main".to_string(), "release=origin/release/1.x".to_string()],
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "api/**", "--each-base"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_ci() {
+        let result = parse(&["-p", "src/**", "--ci", "gitlab"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["src/**".to_string()],
+                ci: Some("gitlab".to_string()),
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "src/**", "--ci", "none", "--ci", "auto"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_test_map() {
+        let result = parse(&[
+            "-p",
+            "**",
+            "--test-map",
+            "src/auth/**=tests/auth/**",
+            "--test-map",
+            "src/auth/**=tests/integration/login*",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["**".to_string()],
+                test_map: vec![
+                    "src/auth/**=tests/auth/**".to_string(),
+                    "src/auth/**=tests/integration/login*".to_string()
+                ],
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "**", "--test-map"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_jobs() {
+        let result = parse(&[
+            "-p",
+            "api/**",
+            "-g",
+            "backend",
+            "--job",
+            "unit-tests=backend",
+            "--job",
+            "deploy=backend && !docs",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["api/**".to_string()],
+                github_output: vec![("backend".to_string(), 1)],
+                jobs: vec![
+                    "unit-tests=backend".to_string(),
+                    "deploy=backend && !docs".to_string()
+                ],
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "**", "--job"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_presets() {
+        let result = parse(&["--preset", "docs-only", "-b", "main"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                base_refs: vec!["main".to_string()],
+                presets: vec!["docs-only".to_string()],
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["--preset"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_min_fraction() {
+        let result = parse(&[
+            "--min-fraction",
+            "0.5",
+            "-g",
+            "frontend",
+            "-p",
+            "web/**",
+            "--group-min-fraction",
+            "0.8",
+            "-g",
+            "backend",
+            "-p",
+            "api/**",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["web/**".to_string(), "api/**".to_string()],
+                github_output: vec![("frontend".to_string(), 0), ("backend".to_string(), 1)],
+                min_fraction: Some("0.5".to_string()),
+                group_min_fraction: vec![(0, "0.8".to_string())],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse(&["-p", "web/**", "--group-min-fraction", "0.8"]),
+            Err("--group-min-fraction must follow the -g name it applies to".to_string())
+        );
+        assert_eq!(
+            parse(&[
+                "-g",
+                "web",
+                "-p",
+                "web/**",
+                "--group-min-fraction",
+                "0.8",
+                "--group-min-fraction",
+                "0.9"
+            ]),
+            Err("--group-min-fraction can only be specified once per -g name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_required() {
+        let result = parse(&[
+            "-g",
+            "schema",
+            "-p",
+            "db/schema.sql",
+            "-g",
+            "migrations",
+            "-p",
+            "db/migrations/**",
+            "--require",
+            "schema",
+            "--require",
+            "migrations",
+        ]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["db/schema.sql".to_string(), "db/migrations/**".to_string()],
+                github_output: vec![("schema".to_string(), 0), ("migrations".to_string(), 1)],
+                required: vec!["schema".to_string(), "migrations".to_string()],
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["-p", "**", "--require"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_enforce() {
+        let result = parse(&["-p", "hotfix/**", "-p", "CHANGELOG.md", "--enforce"]);
+        assert_eq!(
+            result,
+            Ok(Args {
+                patterns: vec!["hotfix/**".to_string(), "CHANGELOG.md".to_string()],
+                enforce: true,
+                ..Default::default()
             })
         );
     }
@@ -0,0 +1,264 @@
+//! Filters composed of other filters, like `backend - tests`: the files another filter
+//! selects combined by union (`+` or `|`), intersection (`&`) and difference (`-`).
+//!
+//! `&` binds tighter than `+`, `|` and `-`, which apply left to right; parentheses
+//! group. `-` must be separated by spaces, as it may appear in filter names.
+
+use std::collections::HashSet;
+
+/// A composition, with every name referring to a filter that has its own patterns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Filter(String),
+    Union(Box<Expr>, Box<Expr>),
+    Intersection(Box<Expr>, Box<Expr>),
+    Difference(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parse a composition such as `backend - (tests + docs)`
+    ///
+    /// # Errors
+    /// Returns an error naming the problem when the text is not a valid composition.
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let tokens = tokenize(text);
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        parser
+            .union()
+            .and_then(|expr| match tokens.get(parser.pos) {
+                None => Ok(expr),
+                Some(token) => Err(format!("unexpected {token}")),
+            })
+            .map_err(|e| format!("Invalid composition '{text}': {e}"))
+    }
+
+    /// The filter names the composition refers to, in order of appearance
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Expr::Filter(name) => vec![name],
+            Expr::Union(left, right)
+            | Expr::Intersection(left, right)
+            | Expr::Difference(left, right) => [left.names(), right.names()].concat(),
+        }
+    }
+
+    /// Replace each name with the expression `resolve` gives for it
+    ///
+    /// # Errors
+    /// Returns the first error of `resolve`.
+    pub fn substitute(
+        self,
+        resolve: &mut dyn FnMut(String) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut side = |expr: Box<Expr>| expr.substitute(resolve).map(Box::new);
+        Ok(match self {
+            Expr::Filter(name) => resolve(name)?,
+            Expr::Union(left, right) => Expr::Union(side(left)?, side(right)?),
+            Expr::Intersection(left, right) => Expr::Intersection(side(left)?, side(right)?),
+            Expr::Difference(left, right) => Expr::Difference(side(left)?, side(right)?),
+        })
+    }
+
+    /// Whether the composition selects `path`, given the files each filter selects
+    pub fn contains<'a>(
+        &self,
+        path: &str,
+        selected: &dyn Fn(&str) -> Option<&'a HashSet<String>>,
+    ) -> bool {
+        match self {
+            Expr::Filter(name) => selected(name).is_some_and(|files| files.contains(path)),
+            Expr::Union(left, right) => {
+                left.contains(path, selected) || right.contains(path, selected)
+            }
+            Expr::Intersection(left, right) => {
+                left.contains(path, selected) && right.contains(path, selected)
+            }
+            Expr::Difference(left, right) => {
+                left.contains(path, selected) && !right.contains(path, selected)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    /// Parenthesize every operand that is itself a composition
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (left, operator, right) = match self {
+            Expr::Filter(name) => return write!(f, "{name}"),
+            Expr::Union(left, right) => (left, "+", right),
+            Expr::Intersection(left, right) => (left, "&", right),
+            Expr::Difference(left, right) => (left, "-", right),
+        };
+        let operand = |expr: &Expr| match expr {
+            Expr::Filter(name) => name.clone(),
+            _ => format!("({expr})"),
+        };
+        write!(f, "{} {operator} {}", operand(left), operand(right))
+    }
+}
+
+/// Split on spaces and around `+`, `|`, `&` and parentheses
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || "+|&()".contains(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    /// Terms joined by `+`, `|` and `-`, left to right
+    fn union(&mut self) -> Result<Expr, String> {
+        let mut expr = self.intersection()?;
+        while let Some(operator) = self.peek().filter(|token| ["+", "|", "-"].contains(token)) {
+            let difference = operator == "-";
+            self.pos += 1;
+            let right = Box::new(self.intersection()?);
+            expr = if difference {
+                Expr::Difference(Box::new(expr), right)
+            } else {
+                Expr::Union(Box::new(expr), right)
+            };
+        }
+        Ok(expr)
+    }
+
+    /// Operands joined by `&`
+    fn intersection(&mut self) -> Result<Expr, String> {
+        let mut expr = self.operand()?;
+        while self.peek() == Some("&") {
+            self.pos += 1;
+            expr = Expr::Intersection(Box::new(expr), Box::new(self.operand()?));
+        }
+        Ok(expr)
+    }
+
+    /// A filter name or a parenthesized composition
+    fn operand(&mut self) -> Result<Expr, String> {
+        let token = self
+            .peek()
+            .ok_or_else(|| "expected a filter name".to_string())?;
+        self.pos += 1;
+        match token {
+            "(" => {
+                let expr = self.union()?;
+                if self.peek() != Some(")") {
+                    return Err("expected )".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            ")" | "+" | "|" | "&" | "-" => Err(format!("expected a filter name before {token}")),
+            name => Ok(Expr::Filter(name.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("backend-api - (tests|docs)&x"),
+            ["backend-api", "-", "(", "tests", "|", "docs", ")", "&", "x"]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Expr::parse("backend - tests"),
+            Ok(Expr::Difference(
+                Box::new(Expr::Filter("backend".to_string())),
+                Box::new(Expr::Filter("tests".to_string()))
+            ))
+        );
+        // & binds tighter; the others apply left to right
+        assert_eq!(
+            Expr::parse("a + b & c - d").unwrap().to_string(),
+            "(a + (b & c)) - d"
+        );
+        assert_eq!(
+            Expr::parse("a - (b | c)").unwrap().to_string(),
+            "a - (b + c)"
+        );
+        assert_eq!(
+            Expr::parse("a -"),
+            Err("Invalid composition 'a -': expected a filter name".to_string())
+        );
+        assert_eq!(
+            Expr::parse("(a + b"),
+            Err("Invalid composition '(a + b': expected )".to_string())
+        );
+        assert_eq!(
+            Expr::parse("a b"),
+            Err("Invalid composition 'a b': unexpected b".to_string())
+        );
+        assert_eq!(
+            Expr::parse("& a"),
+            Err("Invalid composition '& a': expected a filter name before &".to_string())
+        );
+    }
+
+    #[test]
+    fn test_names_and_substitute() {
+        let expr = Expr::parse("a - b & a").unwrap();
+        assert_eq!(expr.names(), ["a", "b", "a"]);
+        let substituted = expr
+            .substitute(&mut |name| match name.as_str() {
+                "b" => Expr::parse("c + d"),
+                _ => Ok(Expr::Filter(name)),
+            })
+            .unwrap();
+        assert_eq!(substituted.to_string(), "a - ((c + d) & a)");
+    }
+
+    #[test]
+    fn test_contains() {
+        let backend = HashSet::from(["api/main.rs".to_string(), "api/tests/t.rs".to_string()]);
+        let tests = HashSet::from(["api/tests/t.rs".to_string(), "web/tests/t.ts".to_string()]);
+        let selected = |name: &str| match name {
+            "backend" => Some(&backend),
+            "tests" => Some(&tests),
+            _ => None,
+        };
+        let expr = Expr::parse("backend - tests").unwrap();
+        assert!(expr.contains("api/main.rs", &selected));
+        assert!(!expr.contains("api/tests/t.rs", &selected));
+        let expr = Expr::parse("backend & tests").unwrap();
+        assert!(expr.contains("api/tests/t.rs", &selected));
+        assert!(!expr.contains("web/tests/t.ts", &selected));
+        let expr = Expr::parse("backend | tests").unwrap();
+        assert!(expr.contains("web/tests/t.ts", &selected));
+        assert!(!Expr::parse("missing")
+            .unwrap()
+            .contains("api/main.rs", &selected));
+    }
+}
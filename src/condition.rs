@@ -0,0 +1,237 @@
+//! `--expr`: conditions over the results of named filters, like
+//! `(backend || shared) && !docs_only`, so gating logic lives next to the filters
+//! instead of in workflow `if:` strings.
+//!
+//! `!` binds tightest, then `&&`, then `||`; parentheses group.
+
+/// A condition over whether named filters matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Filter(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Parse a condition such as `(backend || shared) && !docs_only`
+    ///
+    /// # Errors
+    /// Returns an error naming the problem when the text is not a valid condition.
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let tokens = tokenize(text);
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        parser
+            .or()
+            .and_then(|condition| match tokens.get(parser.pos) {
+                None => Ok(condition),
+                Some(token) if token == "&" || token == "|" => {
+                    Err(format!("{token} is not an operator; use {token}{token}"))
+                }
+                Some(token) => Err(format!("unexpected {token}")),
+            })
+            .map_err(|e| format!("Invalid expression '{text}': {e}"))
+    }
+
+    /// The filter names the condition refers to, in order of appearance
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Condition::Filter(name) => vec![name],
+            Condition::Not(operand) => operand.names(),
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                [left.names(), right.names()].concat()
+            }
+        }
+    }
+
+    /// Whether the condition holds, given whether each filter matched
+    pub fn holds(&self, matched: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Condition::Filter(name) => matched(name),
+            Condition::Not(operand) => !operand.holds(matched),
+            Condition::And(left, right) => left.holds(matched) && right.holds(matched),
+            Condition::Or(left, right) => left.holds(matched) || right.holds(matched),
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    /// Parenthesize every operand that is itself a condition joined by an operator
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operand = |condition: &Condition| match condition {
+            Condition::Filter(_) | Condition::Not(_) => condition.to_string(),
+            _ => format!("({condition})"),
+        };
+        match self {
+            Condition::Filter(name) => write!(f, "{name}"),
+            Condition::Not(condition) => write!(f, "!{}", operand(condition)),
+            Condition::And(left, right) => write!(f, "{} && {}", operand(left), operand(right)),
+            Condition::Or(left, right) => write!(f, "{} || {}", operand(left), operand(right)),
+        }
+    }
+}
+
+/// Split on spaces and around `&&`, `||`, `!` and parentheses
+///
+/// A lone `&` or `|` is kept as a token of its own, to be reported as a mistake.
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_whitespace() && !"&|!()".contains(c) {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+        match c {
+            '&' | '|' if chars.peek() == Some(&c) => {
+                chars.next();
+                tokens.push(format!("{c}{c}"));
+            }
+            c if !c.is_whitespace() => tokens.push(c.to_string()),
+            _ => {}
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    /// Conjunctions joined by `||`
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.peek() == Some("||") {
+            self.pos += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    /// Operands joined by `&&`
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.operand()?;
+        while self.peek() == Some("&&") {
+            self.pos += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.operand()?));
+        }
+        Ok(condition)
+    }
+
+    /// A filter name, a negated operand or a parenthesized condition
+    fn operand(&mut self) -> Result<Condition, String> {
+        let token = self
+            .peek()
+            .ok_or_else(|| "expected a filter name".to_string())?;
+        self.pos += 1;
+        match token {
+            "!" => Ok(Condition::Not(Box::new(self.operand()?))),
+            "(" => {
+                let condition = self.or()?;
+                if self.peek() != Some(")") {
+                    return Err("expected )".to_string());
+                }
+                self.pos += 1;
+                Ok(condition)
+            }
+            "&" | "|" => Err(format!("{token} is not an operator; use {token}{token}")),
+            ")" | "&&" | "||" => Err(format!("expected a filter name before {token}")),
+            name => Ok(Condition::Filter(name.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("(backend||shared-libs)&& !docs_only"),
+            [
+                "(",
+                "backend",
+                "||",
+                "shared-libs",
+                ")",
+                "&&",
+                "!",
+                "docs_only"
+            ]
+        );
+        assert_eq!(tokenize("a & b"), ["a", "&", "b"]);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Condition::parse("!docs"),
+            Ok(Condition::Not(Box::new(Condition::Filter(
+                "docs".to_string()
+            ))))
+        );
+        // ! binds tightest, then &&, then ||
+        assert_eq!(
+            Condition::parse("a || b && !c || d").unwrap().to_string(),
+            "(a || (b && !c)) || d"
+        );
+        assert_eq!(
+            Condition::parse("!(a || b) && c").unwrap().to_string(),
+            "!(a || b) && c"
+        );
+        assert_eq!(
+            Condition::parse("a ||"),
+            Err("Invalid expression 'a ||': expected a filter name".to_string())
+        );
+        assert_eq!(
+            Condition::parse("(a && b"),
+            Err("Invalid expression '(a && b': expected )".to_string())
+        );
+        assert_eq!(
+            Condition::parse("a b"),
+            Err("Invalid expression 'a b': unexpected b".to_string())
+        );
+        assert_eq!(
+            Condition::parse("a & b"),
+            Err("Invalid expression 'a & b': & is not an operator; use &&".to_string())
+        );
+        assert_eq!(
+            Condition::parse("| a"),
+            Err("Invalid expression '| a': | is not an operator; use ||".to_string())
+        );
+        assert_eq!(
+            Condition::parse("&& a"),
+            Err("Invalid expression '&& a': expected a filter name before &&".to_string())
+        );
+    }
+
+    #[test]
+    fn test_holds() {
+        let matched = |name: &str| ["backend", "docs_only"].contains(&name);
+        let condition = Condition::parse("(backend || shared) && !docs_only").unwrap();
+        assert_eq!(condition.names(), ["backend", "shared", "docs_only"]);
+        assert!(!condition.holds(&matched));
+        assert!(Condition::parse("backend && docs_only")
+            .unwrap()
+            .holds(&matched));
+        assert!(Condition::parse("shared || !web").unwrap().holds(&matched));
+        assert!(!Condition::parse("!backend").unwrap().holds(&matched));
+    }
+}
@@ -1,216 +1,5026 @@
-//! Configuration merging from CLI arguments and environment variables.
+//! Configuration merging from CLI arguments, environment variables and the
+//! `.git-diff-filter.toml` config file.
 
-use crate::cli::Args;
+use crate::ci::{CiMode, OutputFile, Provider};
+use crate::cli::{self, Args, GivenFlag};
+use crate::compose::Expr;
+use crate::condition::Condition;
+use crate::git::{
+    self, ChangeKind, ContentKind, DiffOptions, DiffSource, LineThresholds, RenamePaths,
+    RetryPolicy, SparsePaths,
+};
+use crate::interpolate;
+use crate::json::Value;
+use crate::log::log;
+use crate::matcher::{Anchoring, Pattern, PatternSet};
+use crate::output::{self, ColorMode, ListDelimiter, Template};
+use crate::preset::Preset;
+use crate::{targets, test_map, toml, yaml};
 use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The output `--job` writes the jobs to run to
+pub const JOBS_OUTPUT: &str = "jobs";
+
+/// Fetch attempts used by `--auto-fetch` when `--fetch-attempts` is not given
+const DEFAULT_FETCH_ATTEMPTS: usize = 3;
+
+/// Top-level config keys, suggested for a mistyped one: the keys that are not flags,
+/// then the long flags a config file may set
+const TOP_LEVEL_KEYS: &[&str] = &[
+    "patterns",
+    "filters",
+    "aliases",
+    "targets",
+    "tests",
+    "jobs",
+    "profiles",
+    "extends",
+    "anchoring",
+    "annotations",
+    "auto-fetch",
+    "base-ref",
+    "binary-only",
+    "bitbucket-report",
+    "cache-dir",
+    "cargo-workspace",
+    "ci",
+    "circleci-parameters",
+    "color",
+    "content-pattern",
+    "default-excludes",
+    "dotenv",
+    "each-base",
+    "enforce",
+    "env-file",
+    "exclude-generated",
+    "exit-codes",
+    "expr",
+    "fetch-attempts",
+    "files-output",
+    "files-output-nul",
+    "find-copies",
+    "first-parent",
+    "format",
+    "generated-attribute",
+    "git-dir",
+    "git-retries",
+    "git-retry-delay",
+    "github-api",
+    "ignore-case",
+    "ignore-content-matching",
+    "ignore-whitespace",
+    "include-dependents",
+    "js-workspace",
+    "jsonl",
+    "last",
+    "list-delimiter",
+    "list-files",
+    "matrix-output",
+    "max-lines-changed",
+    "min-fraction",
+    "min-lines-changed",
+    "mode-changes",
+    "no-cache",
+    "no-merge-base",
+    "prefer-env",
+    "preset",
+    "print-config",
+    "properties-file",
+    "recurse-submodules",
+    "relative",
+    "renames",
+    "repo",
+    "report-json",
+    "report-md",
+    "report-yaml",
+    "require",
+    "since",
+    "source",
+    "sparse-paths",
+    "stats",
+    "status",
+    "step-summary",
+    "submodule-marker",
+    "teamcity",
+    "text-only",
+    "trace-json",
+];
+
+/// The keys of a `[filters.<name>]` table
+const FILTER_KEYS: [&str; 9] = [
+    "patterns",
+    "output",
+    "ignore-case",
+    "status",
+    "base-ref",
+    "branches",
+    "compose",
+    "default-excludes",
+    "min-fraction",
+];
+
+/// Config file names looked up in each directory, in order of preference
+pub const CONFIG_FILES: [&str; 3] = [
+    ".git-diff-filter.toml",
+    ".git-diff-filter.yaml",
+    ".git-diff-filter.yml",
+];
 
 /// Final configuration after merging CLI args with environment variables
-#[derive(Debug, PartialEq)]
+///
+/// Library users build it from [`Args`] with [`DiffFilter::config`](crate::DiffFilter::config)
+/// and hand it to [`DiffFilter::evaluate`](crate::DiffFilter::evaluate); its fields are
+/// only read inside the crate.
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
+    /// Filter groups, evaluated together; a single unnamed group without `-g`
+    pub(crate) groups: Vec<FilterGroup>,
+    /// Base refs to try in order; the first that resolves is used
+    pub(crate) base_refs: Vec<String>,
+    /// Diff from the last commit before this date instead of a base ref
+    pub(crate) since: Option<String>,
+    /// Inspect each of this many commits of HEAD instead of diffing from a base ref
+    pub(crate) last: Option<usize>,
+    pub(crate) github_output_filepath: Option<String>,
+    /// Path of the GitHub Actions event payload (`GITHUB_EVENT_PATH`)
+    pub(crate) github_event_path: Option<String>,
+    pub(crate) anchoring: Anchoring,
+    pub(crate) renames: RenamePaths,
+    /// Whether changed files outside a sparse checkout are considered
+    pub(crate) sparse_paths: SparsePaths,
+    pub(crate) diff_options: DiffOptions,
+    pub(crate) content_patterns: Vec<String>,
+    pub(crate) line_thresholds: LineThresholds,
+    /// Only consider binary or only text files; `None` considers both
+    pub(crate) content_kind: Option<ContentKind>,
+    /// Change kinds every group considers, dropped before any pattern is matched;
+    /// empty means all
+    pub(crate) statuses: Vec<ChangeKind>,
+    /// Checkouts evaluated separately; empty means the current repository only
+    pub(crate) repos: Vec<Repository>,
+    /// Where to write the JSON report, if anywhere
+    pub(crate) report_json: Option<String>,
+    /// Also write the matching files as a multiline `<name>_files` output
+    pub(crate) list_files: bool,
+    /// `GITHUB_STEP_SUMMARY` file to append a results table to, when `--step-summary` is set
+    pub(crate) step_summary_filepath: Option<String>,
+    /// Emit `::notice`/`::warning` workflow commands describing the result
+    pub(crate) annotations: bool,
+    /// Dotenv file to append the named outputs to, for GitLab CI
+    pub(crate) dotenv: Option<String>,
+    /// Print `##teamcity[setParameter]` service messages for the result
+    pub(crate) teamcity: bool,
+    /// Java properties file to append the named outputs to, for Jenkins
+    pub(crate) properties_file: Option<String>,
+    /// JSON file of CircleCI pipeline parameters to merge the results into
+    pub(crate) circleci_parameters: Option<String>,
+    /// Shell script to append `export` lines for the named outputs to
+    pub(crate) env_file: Option<String>,
+    /// Where to write a Bitbucket Code Insights report, if anywhere
+    pub(crate) bitbucket_report: Option<String>,
+    /// Also write a `<name>_matrix` output listing what matched, as a job matrix include
+    pub(crate) matrix_output: bool,
+    /// File to write every matching path to, for later steps to read
+    pub(crate) files_output: Option<String>,
+    /// Terminate the paths in `files_output` with NUL rather than a newline
+    pub(crate) files_output_nul: bool,
+    /// How file lists are joined in single-line values; `None` keeps `--list-files`
+    /// multiline and joins key-value file lists with commas
+    pub(crate) list_delimiter: Option<ListDelimiter>,
+    /// Print a colored summary instead of the plain `true`/`false` result
+    pub(crate) color: bool,
+    /// Write diagnostics to stderr as JSON lines
+    pub(crate) trace_json: bool,
+    /// Where to write the report as YAML, if anywhere
+    pub(crate) report_yaml: Option<String>,
+    /// Template printed to stdout for each filter instead of the usual lines
+    pub(crate) format: Option<Template>,
+    pub(crate) exit_codes: ExitCodes,
+    /// Where to write the report as Markdown, if anywhere
+    pub(crate) report_md: Option<String>,
+    /// Print one JSON line per changed file and filter group instead of the usual lines
+    pub(crate) jsonl: bool,
+    /// Print changed and matched file counts per extension and top-level directory
+    pub(crate) stats: bool,
+    /// Print the resolved configuration instead of evaluating the filters
+    pub(crate) print_resolved: bool,
+    /// The config file the defaults were read from, kept for `--print-config`
+    pub(crate) defaults_file: Option<String>,
+    /// Every flag given, kept for `--print-config`
+    pub(crate) given: Vec<GivenFlag>,
+    /// Whether `--prefer-env` let `BASE_REF` replace the base refs the flags give
+    pub(crate) env_base_refs: bool,
+    /// Also write the Cargo workspace crates owning each group's matching files
+    pub(crate) cargo_workspace: bool,
+    /// Also write the npm, yarn or pnpm workspace packages owning each group's matching
+    /// files
+    pub(crate) js_workspace: bool,
+    /// Add the workspace members depending on those affected
+    pub(crate) include_dependents: bool,
+    /// Targets with the globs of their sources, to write those each group's matching
+    /// files belong to
+    pub(crate) targets: Vec<(String, PatternSet)>,
+    /// Evaluate again each time the work tree changes, until interrupted
+    pub(crate) watch: bool,
+    /// Answer evaluation requests instead of evaluating once
+    pub(crate) serve: bool,
+    /// Unix socket to take `--serve` requests on, instead of stdin
+    pub(crate) socket: Option<String>,
+    /// List the pull request's changed files with the GitHub API instead of git
+    pub(crate) github_api: bool,
+    /// Reuse the results of earlier runs comparing the same commits the same way
+    pub(crate) cache: bool,
+    /// Where results are cached; the repository's git directory by default
+    pub(crate) cache_dir: Option<String>,
+    /// Drop changed files for which this git attribute is set, such as
+    /// `linguist-generated`
+    pub(crate) generated_attribute: Option<String>,
+    /// `--expr` outputs, each with the condition over the named filters it reports
+    pub(crate) expressions: Vec<(String, Condition)>,
+    /// Bases each evaluated separately; empty means the base refs only
+    pub(crate) bases: Vec<Base>,
+    /// The CI system running gdf, detected or named with `--ci`, whose target or default
+    /// branch is the base without -b or `BASE_REF`
+    pub(crate) ci: Option<Provider>,
+    /// Source globs with the globs of their tests, to write the tests each group's
+    /// matching files call for
+    pub(crate) test_map: Vec<test_map::Rule>,
+    /// CI jobs, each with the conditions over the named filters under which it runs
+    pub(crate) jobs: Vec<(String, Vec<Condition>)>,
+    /// Filters that must all match for the run to match, instead of any filter
+    pub(crate) required: Vec<String>,
+    /// Fail when a changed file is selected by no filter
+    pub(crate) enforce: bool,
+}
+
+/// Process exit codes for each way a run can end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitCodes {
+    /// Some filter matched
+    pub on_match: i32,
+    /// No filter matched
+    pub no_match: i32,
+    /// The run failed
+    pub error: i32,
+}
+
+impl Default for ExitCodes {
+    fn default() -> Self {
+        ExitCodes {
+            on_match: 0,
+            no_match: 0,
+            error: 1,
+        }
+    }
+}
+
+impl ExitCodes {
+    /// The exit code of a successful run
+    pub fn for_result(self, has_match: bool) -> i32 {
+        if has_match {
+            self.on_match
+        } else {
+            self.no_match
+        }
+    }
+}
+
+impl FromStr for ExitCodes {
+    type Err = String;
+
+    /// Parse `match=<code>,nomatch=<code>,error=<code>`; omitted outcomes keep their
+    /// default code
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut codes = ExitCodes::default();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || {
+                format!("Invalid exit code mapping: {entry} (expected match=<code>, nomatch=<code> or error=<code>, with codes from 0 to 255)")
+            };
+            let (outcome, code) = entry.split_once('=').ok_or_else(invalid)?;
+            let code = code
+                .trim()
+                .parse::<u8>()
+                .map(i32::from)
+                .map_err(|_| invalid())?;
+            match outcome.trim() {
+                "match" => codes.on_match = code,
+                "nomatch" => codes.no_match = code,
+                "error" => codes.error = code,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(codes)
+    }
+}
+
+/// Patterns whose result is reported under one output name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterGroup {
+    /// The `-g` name; `None` for plain `true`/`false` output
+    pub name: Option<String>,
     pub patterns: Vec<String>,
-    pub base_ref: String,
-    pub github_output_name: Option<String>,
-    pub github_output_filepath: Option<String>,
+    /// Match the patterns regardless of case
+    pub ignore_case: bool,
+    /// Change kinds the group considers; empty means all
+    pub statuses: Vec<ChangeKind>,
+    /// Base refs the group compares against instead of the run's; empty means the run's
+    pub base_refs: Vec<String>,
+    /// How a group without patterns combines the files other groups select
+    pub composition: Option<Expr>,
+    /// Globs of the branches the group applies on; empty means every branch
+    pub branches: Vec<String>,
+    /// Match only when the group selects at least this fraction of the changed files it
+    /// considers
+    pub min_fraction: Option<f64>,
+    /// The `--preset` the group is the filter of
+    pub preset: Option<Preset>,
+}
+
+impl FilterGroup {
+    /// The patterns, or the composition of a composed group, for messages
+    #[must_use]
+    pub fn selection(&self) -> String {
+        match &self.composition {
+            Some(composition) => composition.to_string(),
+            None => self.patterns.join(", "),
+        }
+    }
+
+    /// Whether the group applies when `branch` is being built
+    ///
+    /// # Errors
+    /// Returns an error when a branch glob is invalid.
+    pub fn applies_on(&self, branch: &str) -> Result<bool, String> {
+        if self.branches.is_empty() {
+            return Ok(true);
+        }
+        PatternSet::new(&self.branches, Anchoring::Anchored)?.is_match(branch)
+    }
+
+    /// Whether the group looks at a change of this kind
+    #[must_use]
+    pub fn considers(&self, kind: ChangeKind) -> bool {
+        self.statuses.is_empty() || self.statuses.contains(&kind)
+    }
+}
+
+/// A checkout named with `--repo`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repository {
+    /// Suffix of the checkout's output name
+    pub name: String,
+    pub git_dir: PathBuf,
+    pub work_tree: PathBuf,
+}
+
+/// A base named with `--each-base`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Base {
+    pub name: String,
+    /// Suffix of the base's output name, `vs_<name>`
+    pub label: String,
+    /// The refs to try in turn, as with -b
+    pub refs: Vec<String>,
 }
 
 /// Merge CLI arguments with environment variables
+///
+/// A value given as a flag, on the command line or in the config file, wins over the
+/// environment variable for it (`BASE_REF`, `GIT_DIR`), unless `--prefer-env` turns
+/// that around for a set, non-empty variable.
+#[allow(clippy::too_many_lines)]
 pub fn from_args(args: Args) -> Result<Config, String> {
-    // Determine base_ref: CLI flag takes precedence over env var
-    let base_ref = args
-        .base_ref
+    check_flag_combinations(&args)?;
+    let list_delimiter = parse_list_delimiter(&args)?;
+    let format = parse_format(&args)?;
+    let color = use_color(parse_mode(args.color.as_deref())?, &args);
+
+    let source = parse_source(&args)?;
+    let repos = repositories(&args)?;
+
+    let (git_dir, work_tree) = if repos.is_empty() {
+        locate_git_dir(args.git_dir.clone(), args.prefer_env)?
+    } else {
+        (None, None)
+    };
+    let diff_options = diff_options(&args, source, git_dir, work_tree)?;
+    let mut groups = filter_groups(
+        args.patterns.clone(),
+        args.github_output.clone(),
+        &args.group_compose,
+    )?;
+    add_presets(&mut groups, &args.presets)?;
+    let statuses = group_options(&mut groups, &args)?;
+    let expressions = parse_expressions(&args.expressions, &groups)?;
+    let jobs = parse_jobs(&args.jobs, &groups, &expressions)?;
+    let required = required_filters(&args.required, &groups)?;
+
+    // With --prefer-env, BASE_REF replaces the base refs, --since and --last given
+    let env_base_refs = args
+        .prefer_env
+        .then(|| split_base_refs(env::var("BASE_REF").ok().as_deref()))
+        .filter(|base_refs| !base_refs.is_empty());
+    let (since, last) = history_bounds(
+        args.since.as_deref(),
+        args.last.as_deref(),
+        env_base_refs.is_some(),
+        source,
+    )?;
+    let ci = ci_provider(args.ci.as_deref())?;
+    let base_refs = base_refs_from(
+        env_base_refs.as_deref().unwrap_or(&args.base_refs),
+        ci,
+        since.as_deref(),
+        last,
+        groups.iter().any(|group| !group.base_refs.is_empty()),
+        base_refs_optional(&args, &groups),
+    )?;
+
+    let anchoring = parse_mode(args.anchoring.as_deref())?;
+    let ci_output = ci_output_file(&args, ci, &groups);
+
+    let line_thresholds = parse_line_thresholds(
+        args.min_lines_changed.as_deref(),
+        args.max_lines_changed.as_deref(),
+    )?;
+
+    Ok(Config {
+        groups,
+        base_refs,
+        since,
+        last,
+        // Outputs are printed while watching rather than appended to GITHUB_OUTPUT, which
+        // other CI systems do not read
+        github_output_filepath: env::var("GITHUB_OUTPUT").ok().filter(|_| {
+            !args.watch && ci.is_none_or(|provider| provider == Provider::GitHubActions)
+        }),
+        github_event_path: env::var("GITHUB_EVENT_PATH").ok().filter(|s| !s.is_empty()),
+        anchoring,
+        renames: parse_mode(args.renames.as_deref())?,
+        sparse_paths: parse_mode(args.sparse_paths.as_deref())?,
+        diff_options,
+        content_patterns: args.content_patterns,
+        line_thresholds,
+        content_kind: parse_content_kind(args.binary_only, args.text_only)?,
+        statuses,
+        repos,
+        report_json: args.report_json.filter(|s| !s.is_empty()),
+        list_files: args.list_files,
+        step_summary_filepath: step_summary_filepath(args.step_summary)?,
+        annotations: args.annotations,
+        dotenv: args.dotenv.filter(|s| !s.is_empty()).or(match ci_output {
+            Some(OutputFile::Dotenv(path)) => Some(path.to_string()),
+            _ => None,
+        }),
+        teamcity: args.teamcity,
+        properties_file: args
+            .properties_file
+            .filter(|s| !s.is_empty())
+            .or(match ci_output {
+                Some(OutputFile::Properties(path)) => Some(path.to_string()),
+                _ => None,
+            }),
+        circleci_parameters: args.circleci_parameters.filter(|s| !s.is_empty()),
+        env_file: args.env_file.filter(|s| !s.is_empty()),
+        bitbucket_report: args.bitbucket_report.filter(|s| !s.is_empty()),
+        matrix_output: args.matrix_output,
+        files_output: files_output_path(args.files_output, args.files_output_nul)?,
+        files_output_nul: args.files_output_nul,
+        list_delimiter,
+        color,
+        trace_json: args.trace_json,
+        report_yaml: args.report_yaml.filter(|s| !s.is_empty()),
+        format,
+        exit_codes: parse_mode(args.exit_codes.as_deref())?,
+        report_md: args.report_md.filter(|s| !s.is_empty()),
+        jsonl: args.jsonl,
+        stats: args.stats,
+        print_resolved: args.print_config,
+        defaults_file: args.config_file,
+        given: args.given,
+        env_base_refs: env_base_refs.is_some(),
+        cargo_workspace: args.cargo_workspace,
+        js_workspace: args.js_workspace,
+        include_dependents: args.include_dependents,
+        targets: targets::parse(&args.targets, anchoring)?,
+        watch: args.watch,
+        serve: args.serve,
+        socket: args.socket.filter(|s| !s.is_empty()),
+        github_api: args.github_api,
+        cache: !args.no_cache,
+        cache_dir: args.cache_dir.filter(|s| !s.is_empty()),
+        generated_attribute: generated_attribute(args.exclude_generated, args.generated_attribute)?,
+        expressions,
+        bases: parse_bases(&args.bases)?,
+        ci,
+        test_map: test_map::parse(&args.test_map, anchoring)?,
+        jobs,
+        required,
+        enforce: args.enforce,
+    })
+}
+
+impl Config {
+    /// Describe the configuration for `--print-config`: each flag given, then the values
+    /// the run uses, each with where it came from
+    #[must_use]
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Config file: {}",
+            self.defaults_file.as_deref().unwrap_or("none")
+        )];
+        lines.push("Flags:".to_string());
+        for given in &self.given {
+            let words: Vec<String> = [&given.flag]
+                .into_iter()
+                .chain(&given.values)
+                .map(|word| shell_word(word))
+                .collect();
+            lines.push(format!("  {}  ({})", words.join(" "), given.origin));
+        }
+        lines.push("Resolved:".to_string());
+        for (label, value, origin) in self
+            .resolved_filters()
+            .into_iter()
+            .chain(self.resolved_options())
+        {
+            lines.push(format!("  {label}: {value}  ({origin})"));
+        }
+        lines
+    }
+
+    /// The base refs and filter groups, each as a label, value and origin
+    fn resolved_filters(&self) -> Vec<(String, String, String)> {
+        let mut resolved = Vec::new();
+        if let Some(date) = &self.since {
+            resolved.push((
+                "Since".to_string(),
+                date.clone(),
+                self.origin(&["--since"], "default"),
+            ));
+        } else if let Some(count) = self.last {
+            resolved.push((
+                "Last".to_string(),
+                format!("{count} commits"),
+                self.origin(&["--last"], "default"),
+            ));
+        } else if !self.bases.is_empty() {
+            let origin = self.origin(&["--each-base"], "default");
+            for base in &self.bases {
+                let label = format!("Base {}", base.name);
+                resolved.push((label, base.refs.join(", "), origin.clone()));
+            }
+        } else if self.base_refs.is_empty() {
+            resolved.push((
+                "Base refs".to_string(),
+                "none".to_string(),
+                "each filter has its own".to_string(),
+            ));
+        } else {
+            // Without -b, the base refs came from BASE_REF, or else from the CI system
+            let ci = self
+                .ci
+                .filter(|_| split_base_refs(env::var("BASE_REF").ok().as_deref()).is_empty());
+            let default = ci.map_or("BASE_REF environment variable".to_string(), |provider| {
+                format!("{provider} environment")
+            });
+            let origin = if self.env_base_refs {
+                "BASE_REF environment variable (--prefer-env)".to_string()
+            } else {
+                self.origin(&["-b", "--base-ref"], &default)
+            };
+            resolved.push(("Base refs".to_string(), self.base_refs.join(", "), origin));
+        }
+        let origin = self.origin(&["-p", "--pattern", "-g", "--github-output"], "default");
+        let preset_origin = self.origin(&["--preset"], "default");
+        for group in &self.groups {
+            let mut value = group.selection();
+            if group.ignore_case {
+                value.push_str(" | ignore case");
+            }
+            if let Some(fraction) = group.min_fraction {
+                value = format!("{value} | min fraction: {fraction}");
+            }
+            if !group.statuses.is_empty() {
+                let kinds: Vec<&str> = group.statuses.iter().map(|kind| kind.name()).collect();
+                value = format!("{value} | status: {}", kinds.join(", "));
+            }
+            if !group.base_refs.is_empty() {
+                value = format!("{value} | base refs: {}", group.base_refs.join(", "));
+            }
+            let label = match &group.name {
+                Some(name) => format!("Filter {name}"),
+                None => "Filter".to_string(),
+            };
+            let origin = if group.preset.is_some() {
+                &preset_origin
+            } else {
+                &origin
+            };
+            resolved.push((label, value, origin.clone()));
+        }
+        resolved
+    }
+
+    /// The options deciding how changes are listed and reported, each as a label, value
+    /// and origin
+    fn resolved_options(&self) -> Vec<(String, String, String)> {
+        let yes_no = |flag: bool| if flag { "yes" } else { "no" }.to_string();
+        let kinds = if self.statuses.is_empty() {
+            "all".to_string()
+        } else {
+            let kinds: Vec<&str> = self.statuses.iter().map(|kind| kind.name()).collect();
+            kinds.join(", ")
+        };
+        let repos = if self.repos.is_empty() {
+            "current".to_string()
+        } else {
+            let names: Vec<&str> = self.repos.iter().map(|repo| repo.name.as_str()).collect();
+            names.join(", ")
+        };
+        let codes = &self.exit_codes;
+        let mut resolved: Vec<(String, String, String)> = [
+            (
+                "Anchoring",
+                format!("{:?}", self.anchoring).to_ascii_lowercase(),
+                "--anchoring",
+            ),
+            (
+                "Renames",
+                format!("{:?}", self.renames).to_ascii_lowercase(),
+                "--renames",
+            ),
+            (
+                "Source",
+                format!("{:?}", self.diff_options.source).to_ascii_lowercase(),
+                "--source",
+            ),
+            (
+                "Merge base",
+                yes_no(self.diff_options.merge_base),
+                "--no-merge-base",
+            ),
+            (
+                "Find copies",
+                yes_no(self.diff_options.find_copies),
+                "--find-copies",
+            ),
+            ("Change kinds", kinds, "--status"),
+            ("Repositories", repos, "--repo"),
+            (
+                "Exit codes",
+                format!(
+                    "match={}, nomatch={}, error={}",
+                    codes.on_match, codes.no_match, codes.error
+                ),
+                "--exit-codes",
+            ),
+        ]
+        .into_iter()
+        .map(|(label, value, flag)| (label.to_string(), value, self.origin(&[flag], "default")))
+        .collect();
+        for (label, value) in [
+            ("GITHUB_OUTPUT", &self.github_output_filepath),
+            ("GITHUB_EVENT_PATH", &self.github_event_path),
+        ] {
+            let value = value.as_deref().unwrap_or("not set").to_string();
+            resolved.push((label.to_string(), value, "environment".to_string()));
+        }
+        resolved
+    }
+
+    /// Where the last of `flags` was given, or `default` when none was
+    fn origin(&self, flags: &[&str], default: &str) -> String {
+        self.given
+            .iter()
+            .rev()
+            .find(|given| flags.contains(&given.flag.as_str()))
+            .map_or(default, |given| given.origin.as_str())
+            .to_string()
+    }
+}
+
+/// Quote a word for the shell unless it is made of safe characters only
+fn shell_word(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Gather the options of the git commands that list changes in one repository
+fn diff_options(
+    args: &Args,
+    source: DiffSource,
+    git_dir: Option<PathBuf>,
+    work_tree: Option<PathBuf>,
+) -> Result<DiffOptions, String> {
+    let fetch_attempts = match parse_count("--fetch-attempts", args.fetch_attempts.as_deref())? {
+        Some(attempts) => attempts,
+        None if args.auto_fetch => DEFAULT_FETCH_ATTEMPTS,
+        None => 0,
+    };
+    Ok(DiffOptions {
+        find_copies: args.find_copies,
+        merge_base: !args.no_merge_base,
+        source,
+        submodule_marker: args.submodule_marker.clone().filter(|s| !s.is_empty()),
+        recurse_submodules: args.recurse_submodules,
+        fetch_attempts,
+        retry: parse_retry_policy(args.git_retries.as_deref(), args.git_retry_delay.as_deref())?,
+        git_dir,
+        work_tree,
+        relative: args.relative,
+        ignore_whitespace: args.ignore_whitespace,
+        ignore_content: args.ignore_content_patterns.clone(),
+        mode_changes: parse_mode(args.mode_changes.as_deref())?,
+        first_parent: args.first_parent,
+    })
+}
+
+/// Parse `--git-retries` and `--git-retry-delay`, defaulting each one separately
+fn parse_retry_policy(retries: Option<&str>, delay: Option<&str>) -> Result<RetryPolicy, String> {
+    let defaults = RetryPolicy::default();
+    Ok(RetryPolicy {
+        retries: parse_count("--git-retries", retries)?.unwrap_or(defaults.retries),
+        delay: parse_count("--git-retry-delay", delay)?
+            .map_or(defaults.delay, |ms| Duration::from_millis(ms as u64)),
+    })
+}
+
+/// The `--repo` checkouts, which bring their own git directory and work tree
+fn repositories(args: &Args) -> Result<Vec<Repository>, String> {
+    let repos = parse_repos(&args.repos)?;
+    if !repos.is_empty() && (args.git_dir.is_some() || args.relative) {
+        return Err("--repo cannot be combined with --git-dir or --relative".to_string());
+    }
+    Ok(repos)
+}
+
+/// Check the options of `--serve`
+fn check_serve(args: &Args) -> Result<(), String> {
+    if args.socket.is_some() && !args.serve {
+        return Err("--socket requires --serve".to_string());
+    }
+    if args.serve && args.watch {
+        return Err("--serve cannot be combined with --watch".to_string());
+    }
+    Ok(())
+}
+
+/// Check the options of `--watch`, which evaluates again on each change: files that
+/// outputs are appended to would grow each time
+fn check_watch(args: &Args) -> Result<(), String> {
+    if !args.watch {
+        return Ok(());
+    }
+    let is_set = |path: &Option<String>| path.as_deref().is_some_and(|s| !s.is_empty());
+    let conflicts = [
+        ("--dotenv", is_set(&args.dotenv)),
+        ("--env-file", is_set(&args.env_file)),
+        ("--properties-file", is_set(&args.properties_file)),
+        ("--step-summary", args.step_summary),
+    ];
+    match conflicts.iter().find(|(_, given)| *given) {
+        Some((flag, _)) => Err(format!("--watch cannot be combined with {flag}")),
+        None => Ok(()),
+    }
+}
+
+/// Check the options of `--enforce`, which needs every changed file of a single run
+fn check_enforce(args: &Args) -> Result<(), String> {
+    if args.enforce && args.last.is_some() {
+        return Err("--enforce cannot be combined with --last".to_string());
+    }
+    if args.enforce && args.serve {
+        return Err("--enforce cannot be combined with --serve".to_string());
+    }
+    Ok(())
+}
+
+/// Check the options of `--github-api`, which has no checkout to look at
+fn check_github_api(args: &Args) -> Result<(), String> {
+    if !args.github_api {
+        return Ok(());
+    }
+    let conflicts = [
+        ("--repo", !args.repos.is_empty()),
+        ("--since", args.since.is_some()),
+        ("--last", args.last.is_some()),
+        ("--source", args.source.is_some()),
+        ("--watch", args.watch),
+        ("--group-base-ref", !args.group_base_ref.is_empty()),
+        ("--exclude-generated", args.exclude_generated),
+    ];
+    match conflicts.iter().find(|(_, given)| *given) {
+        Some((flag, _)) => Err(format!("--github-api cannot be combined with {flag}")),
+        None => Ok(()),
+    }
+}
+
+/// Check the options of `--each-base`, which replaces the run's base refs
+fn check_each_base(args: &Args) -> Result<(), String> {
+    if args.bases.is_empty() {
+        return Ok(());
+    }
+    let conflicts = [
+        ("-b/--base-ref", !args.base_refs.is_empty()),
+        ("--since", args.since.is_some()),
+        ("--last", args.last.is_some()),
+        ("--group-base-ref", !args.group_base_ref.is_empty()),
+        ("--repo", !args.repos.is_empty()),
+        ("--serve", args.serve),
+        ("--github-api", args.github_api),
+    ];
+    match conflicts.iter().find(|(_, given)| *given) {
+        Some((flag, _)) => Err(format!("--each-base cannot be combined with {flag}")),
+        None => Ok(()),
+    }
+}
+
+/// The attribute marking the generated files `--exclude-generated` drops
+fn generated_attribute(
+    exclude_generated: bool,
+    attribute: Option<String>,
+) -> Result<Option<String>, String> {
+    match attribute {
+        Some(_) if !exclude_generated => {
+            Err("--generated-attribute requires --exclude-generated".to_string())
+        }
+        Some(attribute) if attribute.is_empty() || attribute.starts_with('-') => Err(format!(
+            "Invalid --generated-attribute '{attribute}': expected an attribute name"
+        )),
+        attribute if exclude_generated => {
+            Ok(Some(attribute.unwrap_or("linguist-generated".to_string())))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The `--source`, which defaults to combined with `--watch` so that work in progress
+/// counts
+fn parse_source(args: &Args) -> Result<DiffSource, String> {
+    if args.watch {
+        if !args.repos.is_empty() {
+            return Err("--watch cannot be combined with --repo".to_string());
+        }
+        if args.last.is_some() {
+            return Err("--watch cannot be combined with --last".to_string());
+        }
+        if args.source.is_none() {
+            return Ok(DiffSource::Combined);
+        }
+    }
+    parse_mode(args.source.as_deref())
+}
+
+/// The `--since` date and `--last` count, unless `BASE_REF` replaces them
+fn history_bounds(
+    since: Option<&str>,
+    last: Option<&str>,
+    env_base_refs: bool,
+    source: DiffSource,
+) -> Result<(Option<String>, Option<usize>), String> {
+    if env_base_refs {
+        return Ok((None, None));
+    }
+    let last = parse_count("--last", last)?;
+    if last.is_some() && source == DiffSource::Combined {
+        return Err(
+            "--last inspects commits and cannot be combined with --source combined".to_string(),
+        );
+    }
+    let since = since.filter(|s| !s.is_empty()).map(str::to_string);
+    Ok((since, last))
+}
+
+/// Check the flags that only apply with, or cannot be given with, others
+fn check_flag_combinations(args: &Args) -> Result<(), String> {
+    check_named_outputs(args)?;
+    check_workspace_modes(args)?;
+    check_serve(args)?;
+    check_watch(args)?;
+    check_github_api(args)?;
+    check_each_base(args)?;
+    check_enforce(args)
+}
+
+/// Check the options of `--cargo-workspace`, `--js-workspace` and `--target`
+fn check_workspace_modes(args: &Args) -> Result<(), String> {
+    let workspace = args.cargo_workspace || args.js_workspace;
+    if !args.targets.is_empty() && !args.repos.is_empty() {
+        return Err("--repo cannot be combined with --target".to_string());
+    }
+    if !args.test_map.is_empty() && !args.repos.is_empty() {
+        return Err("--repo cannot be combined with --test-map".to_string());
+    }
+    if workspace && !args.repos.is_empty() {
+        return Err(
+            "--repo cannot be combined with --cargo-workspace or --js-workspace".to_string(),
+        );
+    }
+    if args.include_dependents && !workspace {
+        return Err(
+            "--include-dependents requires --cargo-workspace or --js-workspace".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Locate the git directory and work tree to run against, if given: the `--git-dir`
+/// flag takes precedence over the `GIT_DIR` env var, or the other way round with
+/// `--prefer-env`
+fn locate_git_dir(
+    flag: Option<String>,
+    prefer_env: bool,
+) -> Result<(Option<PathBuf>, Option<PathBuf>), String> {
+    let flag = flag.filter(|s| !s.is_empty());
+    let env = env::var("GIT_DIR").ok().filter(|s| !s.is_empty());
+    match if prefer_env {
+        env.or(flag)
+    } else {
+        flag.or(env)
+    } {
+        Some(dir) => {
+            let (git_dir, work_tree) = git::locate_git_dir(Path::new(&dir))?;
+            Ok((Some(git_dir), work_tree))
+        }
+        None => Ok((None, None)),
+    }
+}
+
+/// Parse `--binary-only` and `--text-only`, which exclude each other
+fn parse_content_kind(binary_only: bool, text_only: bool) -> Result<Option<ContentKind>, String> {
+    match (binary_only, text_only) {
+        (true, true) => Err("--binary-only and --text-only cannot be combined".to_string()),
+        (true, false) => Ok(Some(ContentKind::Binary)),
+        (false, true) => Ok(Some(ContentKind::Text)),
+        (false, false) => Ok(None),
+    }
+}
+
+/// Parse the `--format` template, which replaces the same lines as `--jsonl`
+fn parse_format(args: &Args) -> Result<Option<Template>, String> {
+    if args.format.is_some() && args.jsonl {
+        return Err("--jsonl cannot be combined with --format".to_string());
+    }
+    args.format.as_deref().map(str::parse).transpose()
+}
+
+/// Decide whether to print the colored summary, which replaces the plain result only:
+/// `-g` outputs are read by machines, as are `--format` and `--jsonl` lines
+fn use_color(mode: ColorMode, args: &Args) -> bool {
+    if !args.github_output.is_empty() || args.format.is_some() || args.jsonl {
+        return false;
+    }
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        }
+    }
+}
+
+/// Parse `--list-delimiter`, which only applies to file lists written on one line
+fn parse_list_delimiter(args: &Args) -> Result<Option<ListDelimiter>, String> {
+    let Some(value) = args.list_delimiter.as_deref() else {
+        return Ok(None);
+    };
+    let is_set = |path: &Option<String>| path.as_deref().is_some_and(|s| !s.is_empty());
+    if !args.list_files
+        && !is_set(&args.properties_file)
+        && !is_set(&args.env_file)
+        && args.format.is_none()
+        && !args.cargo_workspace
+        && !args.js_workspace
+        && args.targets.is_empty()
+        && args.test_map.is_empty()
+        && args.jobs.is_empty()
+    {
+        return Err(
+            "--list-delimiter requires --list-files, --properties-file, --env-file, --format, a workspace mode, --target, --test-map or --job"
+                .to_string(),
+        );
+    }
+    value.parse().map(Some)
+}
+
+/// The `--files-output` path, if any; `--files-output-nul` needs one to apply to
+fn files_output_path(path: Option<String>, nul: bool) -> Result<Option<String>, String> {
+    let path = path.filter(|s| !s.is_empty());
+    if nul && path.is_none() {
+        return Err("--files-output-nul requires --files-output".to_string());
+    }
+    Ok(path)
+}
+
+/// Reject outputs derived from the `-g` name when no name is given
+fn check_named_outputs(args: &Args) -> Result<(), String> {
+    if !args.github_output.is_empty() || !args.presets.is_empty() {
+        return Ok(());
+    }
+    if args.list_files {
+        return Err("--list-files requires -g/--github-output".to_string());
+    }
+    if args.matrix_output {
+        return Err("--matrix-output requires -g/--github-output".to_string());
+    }
+    if !args.jobs.is_empty() {
+        return Err("--job requires -g/--github-output to name the filters".to_string());
+    }
+    if !args.required.is_empty() {
+        return Err("--require requires -g/--github-output to name the filters".to_string());
+    }
+    if args.dotenv.as_deref().is_some_and(|s| !s.is_empty()) {
+        return Err("--dotenv requires -g/--github-output to name the variables".to_string());
+    }
+    if args
+        .properties_file
+        .as_deref()
+        .is_some_and(|s| !s.is_empty())
+    {
+        return Err(
+            "--properties-file requires -g/--github-output to name the properties".to_string(),
+        );
+    }
+    if args
+        .circleci_parameters
+        .as_deref()
+        .is_some_and(|s| !s.is_empty())
+    {
+        return Err(
+            "--circleci-parameters requires -g/--github-output to name the parameters".to_string(),
+        );
+    }
+    if args.env_file.as_deref().is_some_and(|s| !s.is_empty()) {
+        return Err("--env-file requires -g/--github-output to name the variables".to_string());
+    }
+    if args.teamcity {
+        return Err("--teamcity requires -g/--github-output to name the parameters".to_string());
+    }
+    Ok(())
+}
+
+/// Read the `GITHUB_STEP_SUMMARY` file path when `--step-summary` is set
+fn step_summary_filepath(enabled: bool) -> Result<Option<String>, String> {
+    if !enabled {
+        return Ok(None);
+    }
+    env::var("GITHUB_STEP_SUMMARY")
+        .ok()
         .filter(|s| !s.is_empty())
-        .or_else(|| env::var("BASE_REF").ok().filter(|s| !s.is_empty()))
+        .map(Some)
         .ok_or_else(|| {
+            "--step-summary requires the GITHUB_STEP_SUMMARY environment variable".to_string()
+        })
+}
+
+/// Parse a fraction of the changed files, above 0 and at most 1
+fn parse_fraction(flag: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|fraction| *fraction > 0.0 && *fraction <= 1.0)
+        .ok_or_else(|| format!("Invalid {flag}: {value} (expected a number above 0 and at most 1)"))
+}
+
+/// Parse `--min-lines-changed` and `--max-lines-changed`
+fn parse_line_thresholds(min: Option<&str>, max: Option<&str>) -> Result<LineThresholds, String> {
+    let line_thresholds = LineThresholds {
+        min: parse_count("--min-lines-changed", min)?,
+        max: parse_count("--max-lines-changed", max)?,
+    };
+    if let (Some(min), Some(max)) = (line_thresholds.min, line_thresholds.max) {
+        if min > max {
+            return Err(format!(
+                "--min-lines-changed ({min}) cannot exceed --max-lines-changed ({max})"
+            ));
+        }
+    }
+    Ok(line_thresholds)
+}
+
+/// Split the patterns into one group per `-g` name
+///
+/// A name's own patterns are the ones given after it, up to the next name; patterns
+/// given before the first name are shared by every group. A lone `-g` name takes every
+/// pattern, wherever it is given. Groups composed of others with `--group-compose`
+/// take no patterns, not even the shared ones.
+pub fn filter_groups(
+    patterns: Vec<String>,
+    names: Vec<(String, usize)>,
+    compositions: &[(usize, String)],
+) -> Result<Vec<FilterGroup>, String> {
+    let Some(&(_, shared)) = names.first() else {
+        return Ok(vec![FilterGroup {
+            name: None,
+            patterns,
+            ..FilterGroup::default()
+        }]);
+    };
+    let ends: Vec<usize> = names
+        .iter()
+        .skip(1)
+        .map(|&(_, start)| start)
+        .chain([patterns.len()])
+        .collect();
+    let several = names.len() > 1;
+    let mut groups: Vec<FilterGroup> = Vec::new();
+    for (index, ((name, start), end)) in names.into_iter().zip(ends).enumerate() {
+        output::check_output_name(&name)?;
+        if groups
+            .iter()
+            .any(|group| group.name.as_ref() == Some(&name))
+        {
+            return Err(format!("Duplicate output name '{name}'"));
+        }
+        if compositions.iter().any(|&(composed, _)| composed == index) {
+            if start.max(shared) < end {
+                return Err(format!(
+                    "-g {name} combines other filters with --group-compose, so it takes no patterns"
+                ));
+            }
+            groups.push(FilterGroup {
+                name: Some(name),
+                ..FilterGroup::default()
+            });
+            continue;
+        }
+        if several && start == end {
+            return Err(format!(
+                "-g {name} has no patterns; give them after it with -p"
+            ));
+        }
+        let mut group_patterns = patterns[..shared].to_vec();
+        group_patterns.extend_from_slice(&patterns[start.max(shared)..end]);
+        groups.push(FilterGroup {
+            name: Some(name),
+            patterns: group_patterns,
+            ..FilterGroup::default()
+        });
+    }
+    Ok(groups)
+}
+
+/// Add the filter of each `--preset` after the others; given alone, the presets replace
+/// the filter of plain `true`/`false` output
+fn add_presets(groups: &mut Vec<FilterGroup>, presets: &[String]) -> Result<(), String> {
+    if presets.is_empty() {
+        return Ok(());
+    }
+    if let [FilterGroup {
+        name: None,
+        patterns,
+        ..
+    }] = groups.as_slice()
+    {
+        if !patterns.is_empty() {
+            return Err(
+                "--preset requires -g/--github-output to name the filter of the -p patterns"
+                    .to_string(),
+            );
+        }
+        groups.clear();
+    }
+    for preset in presets {
+        let group = preset.parse::<Preset>()?.group();
+        if groups.iter().any(|other| other.name == group.name) {
+            return Err(format!(
+                "Duplicate output name '{}'",
+                group.name.unwrap_or_default()
+            ));
+        }
+        groups.push(group);
+    }
+    Ok(())
+}
+
+/// Parse a comma-separated list of change kinds
+fn parse_statuses(list: &str) -> Result<Vec<ChangeKind>, String> {
+    list.split(',').map(|kind| kind.trim().parse()).collect()
+}
+
+/// Give each group the case sensitivity, change kinds, base refs, branch conditions and
+/// composition of its `-g` name, falling back to `--ignore-case` and `--status`, and the
+/// `--default-excludes` unless it opts out
+///
+/// Returns the change kinds shared by every group, which can be filtered before
+/// matching; empty when the groups differ.
+pub fn group_options(groups: &mut [FilterGroup], args: &Args) -> Result<Vec<ChangeKind>, String> {
+    let statuses = args
+        .status
+        .as_deref()
+        .map(parse_statuses)
+        .transpose()?
+        .unwrap_or_default();
+    for group in groups.iter_mut() {
+        group.ignore_case = args.ignore_case;
+        group.statuses.clone_from(&statuses);
+    }
+    for &index in &args.group_ignore_case {
+        groups[index].ignore_case = true;
+    }
+    for (index, list) in &args.group_status {
+        groups[*index].statuses = parse_statuses(list)?;
+    }
+    for (index, base_ref) in &args.group_base_ref {
+        groups[*index]
+            .base_refs
+            .extend(split_base_refs([base_ref.as_str()]));
+    }
+    for (index, group) in groups.iter_mut().enumerate() {
+        let composed = args.group_compose.iter().any(|&(i, _)| i == index);
+        if composed || args.group_no_default_excludes.contains(&index) {
+            continue;
+        }
+        group.patterns.extend(
+            args.default_excludes
+                .iter()
+                .map(|pattern| format!("!{}", pattern.trim_start_matches('!'))),
+        );
+    }
+    let min_fraction = args
+        .min_fraction
+        .as_deref()
+        .map(|fraction| parse_fraction("--min-fraction", fraction))
+        .transpose()?;
+    for group in groups.iter_mut().filter(|group| group.preset.is_none()) {
+        group.min_fraction = min_fraction;
+    }
+    for (index, fraction) in &args.group_min_fraction {
+        groups[*index].min_fraction = Some(parse_fraction("--group-min-fraction", fraction)?);
+    }
+    for (index, branch) in &args.group_branch {
+        Pattern::parse(branch).map_err(|e| format!("Invalid branch glob {branch}: {e}"))?;
+        groups[*index].branches.push(branch.clone());
+    }
+    compose_groups(groups, &args.group_compose)?;
+    let shared = groups.first().map(|first| &first.statuses);
+    if groups.iter().all(|group| Some(&group.statuses) == shared) {
+        Ok(shared.cloned().unwrap_or_default())
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Resolve each `--group-compose` composition down to the groups with patterns it
+/// combines, and compare the composed group against their base refs
+fn compose_groups(
+    groups: &mut [FilterGroup],
+    compositions: &[(usize, String)],
+) -> Result<(), String> {
+    let parsed: Vec<(usize, Expr)> = compositions
+        .iter()
+        .map(|(index, text)| Ok((*index, Expr::parse(text)?)))
+        .collect::<Result<_, String>>()?;
+    for &(index, _) in &parsed {
+        let composition = resolve_composition(groups, &parsed, index, &mut Vec::new())?;
+        let name = groups[index].name.clone().unwrap_or_default();
+        if !groups[index].base_refs.is_empty() {
+            return Err(format!(
+                "--group-base-ref cannot be given for -g {name}, which combines other filters"
+            ));
+        }
+        let mut base_refs = composition.names().into_iter().map(|operand| {
+            groups
+                .iter()
+                .find(|group| group.name.as_deref() == Some(operand))
+                .map(|group| group.base_refs.clone())
+                .unwrap_or_default()
+        });
+        let first = base_refs.next().unwrap_or_default();
+        if base_refs.any(|refs| refs != first) {
+            return Err(format!(
+                "-g {name} combines filters compared against different base refs"
+            ));
+        }
+        groups[index].base_refs = first;
+        groups[index].composition = Some(composition);
+    }
+    Ok(())
+}
+
+/// The composition of the group at `index` with every composed group it names replaced
+/// by its own composition; `chain` holds the groups being resolved, to reject cycles
+fn resolve_composition(
+    groups: &[FilterGroup],
+    parsed: &[(usize, Expr)],
+    index: usize,
+    chain: &mut Vec<usize>,
+) -> Result<Expr, String> {
+    let name = |index: usize| groups[index].name.clone().unwrap_or_default();
+    let Some((_, composition)) = parsed.iter().find(|&&(composed, _)| composed == index) else {
+        return Ok(Expr::Filter(name(index)));
+    };
+    if chain.contains(&index) {
+        let cycle: Vec<String> = chain.iter().chain([&index]).map(|&i| name(i)).collect();
+        return Err(format!(
+            "Filters are composed of each other: {}",
+            cycle.join(" -> ")
+        ));
+    }
+    chain.push(index);
+    let resolved = composition.clone().substitute(&mut |operand| {
+        let position = groups
+            .iter()
+            .position(|group| group.name.as_deref() == Some(operand.as_str()))
+            .ok_or_else(|| {
+                format!(
+                    "Unknown filter '{operand}' in the composition of -g {}",
+                    name(index)
+                )
+            })?;
+        resolve_composition(groups, parsed, position, chain)
+    })?;
+    chain.pop();
+    Ok(resolved)
+}
+
+/// Parse `--expr <name>=<expression>` entries, whose expressions may only name filters
+/// given with `-g`
+fn parse_expressions(
+    entries: &[String],
+    groups: &[FilterGroup],
+) -> Result<Vec<(String, Condition)>, String> {
+    let mut expressions: Vec<(String, Condition)> = Vec::new();
+    for entry in entries {
+        let Some((name, text)) = entry.split_once('=') else {
+            return Err(format!(
+                "Invalid --expr '{entry}': expected <name>=<expression>"
+            ));
+        };
+        output::check_output_name(name)?;
+        let taken = groups
+            .iter()
+            .any(|group| group.name.as_deref() == Some(name))
+            || expressions.iter().any(|(other, _)| other == name);
+        if taken {
+            return Err(format!("--expr {name} reuses the name of an output"));
+        }
+        let condition = Condition::parse(text)?;
+        let unknown = condition.names().into_iter().find(|operand| {
+            !groups
+                .iter()
+                .any(|group| group.name.as_deref() == Some(operand))
+        });
+        if let Some(operand) = unknown {
+            return Err(format!(
+                "Unknown filter '{operand}' in --expr {name} (expected a -g name)"
+            ));
+        }
+        expressions.push((name.to_string(), condition));
+    }
+    Ok(expressions)
+}
+
+/// Parse `--job <job>=<expression>` entries, whose expressions may only name filters
+/// given with `-g`; a job given several times runs when any of its expressions holds
+fn parse_jobs(
+    entries: &[String],
+    groups: &[FilterGroup],
+    expressions: &[(String, Condition)],
+) -> Result<Vec<(String, Vec<Condition>)>, String> {
+    let mut jobs: Vec<(String, Vec<Condition>)> = Vec::new();
+    for entry in entries {
+        let Some((job, text)) = entry.split_once('=').filter(|(job, _)| !job.is_empty()) else {
+            return Err(format!(
+                "Invalid --job '{entry}': expected <job>=<expression>"
+            ));
+        };
+        let condition = Condition::parse(text)?;
+        let unknown = condition.names().into_iter().find(|operand| {
+            !groups
+                .iter()
+                .any(|group| group.name.as_deref() == Some(operand))
+        });
+        if let Some(operand) = unknown {
+            return Err(format!(
+                "Unknown filter '{operand}' in --job {job} (expected a -g name)"
+            ));
+        }
+        match jobs.iter_mut().find(|(other, _)| other == job) {
+            Some((_, conditions)) => conditions.push(condition),
+            None => jobs.push((job.to_string(), vec![condition])),
+        }
+    }
+    let taken = groups
+        .iter()
+        .any(|group| group.name.as_deref() == Some(JOBS_OUTPUT))
+        || expressions.iter().any(|(name, _)| name == JOBS_OUTPUT);
+    if !jobs.is_empty() && taken {
+        return Err(format!(
+            "--job writes a {JOBS_OUTPUT} output, which cannot also name a filter or --expr"
+        ));
+    }
+    Ok(jobs)
+}
+
+/// The filters named with `--require`, once each, which must be given with `-g`
+fn required_filters(names: &[String], groups: &[FilterGroup]) -> Result<Vec<String>, String> {
+    let mut required: Vec<String> = Vec::new();
+    for name in names {
+        if !groups
+            .iter()
+            .any(|group| group.name.as_deref() == Some(name))
+        {
+            return Err(format!(
+                "Unknown filter '{name}' in --require (expected a -g name)"
+            ));
+        }
+        if !required.contains(name) {
+            required.push(name.clone());
+        }
+    }
+    Ok(required)
+}
+
+/// Parse `--each-base [<name>=]<ref>` entries, whose ref may be a comma-separated list
+/// of fallbacks as with -b
+///
+/// The name defaults to the first ref without its `origin/` remote, with characters not
+/// allowed in output names replaced by `_`.
+fn parse_bases(entries: &[String]) -> Result<Vec<Base>, String> {
+    let mut bases: Vec<Base> = Vec::new();
+    for entry in entries {
+        let (name, refs) = match entry.split_once('=') {
+            Some((name, refs)) => (Some(name.to_string()), split_base_refs([refs])),
+            None => (None, split_base_refs([entry.as_str()])),
+        };
+        let Some(first) = refs.first() else {
+            return Err(format!(
+                "Invalid --each-base '{entry}': expected [<name>=]<ref>"
+            ));
+        };
+        let name = name.unwrap_or_else(|| {
+            first
+                .strip_prefix("origin/")
+                .unwrap_or(first)
+                .replace(|c| !output::is_name_char(c), "_")
+        });
+        if name.is_empty() || !name.chars().all(output::is_name_char) {
+            return Err(format!(
+                "Invalid base name '{name}': use letters, digits, '-' and '_'"
+            ));
+        }
+        if bases.iter().any(|base| base.name == name) {
+            return Err(format!(
+                "Duplicate base name '{name}'; name each one with --each-base <name>=<ref>"
+            ));
+        }
+        bases.push(Base {
+            label: format!("vs_{name}"),
+            name,
+            refs,
+        });
+    }
+    Ok(bases)
+}
+
+/// Parse `--repo [<name>=]<path>` entries
+///
+/// The name defaults to the checkout's directory name, with characters not allowed in
+/// output names replaced by `_`.
+fn parse_repos(entries: &[String]) -> Result<Vec<Repository>, String> {
+    let mut repos: Vec<Repository> = Vec::new();
+    for entry in entries {
+        let (name, path) = match entry.split_once('=') {
+            Some((name, path)) => (name.to_string(), path),
+            None => (default_repo_name(entry)?, entry.as_str()),
+        };
+        let path = Path::new(path);
+        if name.is_empty() || !name.chars().all(output::is_name_char) {
+            return Err(format!(
+                "Invalid repository name '{name}': use letters, digits, '-' and '_'"
+            ));
+        }
+        if repos.iter().any(|repo| repo.name == name) {
+            return Err(format!(
+                "Duplicate repository name '{name}'; name each one with --repo <name>=<path>"
+            ));
+        }
+        let (git_dir, _) = git::locate_git_dir(&path.join(".git"))?;
+        repos.push(Repository {
+            name,
+            git_dir,
+            work_tree: path.to_path_buf(),
+        });
+    }
+    Ok(repos)
+}
+
+/// Name a checkout after its directory
+fn default_repo_name(path: &str) -> Result<String, String> {
+    let dir = Path::new(path)
+        .canonicalize()
+        .map_err(|_| format!("Repository not found: {path}"))?;
+    Ok(dir
+        .file_name()
+        .map(|name| {
+            name.to_string_lossy()
+                .replace(|c| !output::is_name_char(c), "_")
+        })
+        .unwrap_or_default())
+}
+
+/// Whether the run needs no base ref of its own: a server may be given one with each
+/// request, the GitHub API compares the pull request's commits, and each `--each-base`
+/// base or `--group-base-ref` brings its own
+fn base_refs_optional(args: &Args, groups: &[FilterGroup]) -> bool {
+    args.serve
+        || args.github_api
+        || !args.bases.is_empty()
+        || groups.iter().all(|group| !group.base_refs.is_empty())
+}
+
+/// The file the CI system reads named outputs from, when no output file is given and the
+/// outputs are not only printed, as with `--watch` or `--serve`
+fn ci_output_file(args: &Args, ci: Option<Provider>, groups: &[FilterGroup]) -> Option<OutputFile> {
+    let is_set = |path: &Option<String>| path.as_deref().is_some_and(|s| !s.is_empty());
+    let file_given =
+        is_set(&args.dotenv) || is_set(&args.env_file) || is_set(&args.properties_file);
+    let named = groups.iter().any(|group| group.name.is_some());
+    ci.filter(|_| named && !file_given && !args.watch && !args.serve)
+        .and_then(Provider::output_file)
+}
+
+/// The CI system `--ci` names, or the one detected from the environment by default
+fn ci_provider(mode: Option<&str>) -> Result<Option<Provider>, String> {
+    let mode: CiMode = parse_mode(mode)?;
+    Ok(mode.provider(&|name| env::var(name).ok()))
+}
+
+/// Determine base refs: CLI flags take precedence over the `BASE_REF` env var, which
+/// takes precedence over the target or default branch the CI system names
+///
+/// With `--since` or `--last`, the bases are found in the history once the repository is
+/// known, and no base ref is taken. None is needed either when every group has its own
+/// `--group-base-ref`.
+fn base_refs_from(
+    flags: &[String],
+    ci: Option<Provider>,
+    since: Option<&str>,
+    last: Option<usize>,
+    group_based: bool,
+    every_group_based: bool,
+) -> Result<Vec<String>, String> {
+    let history_flag = match (since, last) {
+        (Some(_), Some(_)) => return Err("--since cannot be combined with --last".to_string()),
+        (None, Some(0)) => return Err("--last must be at least 1".to_string()),
+        (Some(_), None) => Some("--since"),
+        (None, Some(_)) => Some("--last"),
+        (None, None) => None,
+    };
+    if let Some(flag) = history_flag {
+        if !flags.is_empty() {
+            return Err(format!("{flag} cannot be combined with -b/--base-ref"));
+        }
+        if group_based {
+            return Err(format!("{flag} cannot be combined with --group-base-ref"));
+        }
+        return Ok(Vec::new());
+    }
+
+    let mut base_refs = split_base_refs(flags.iter().map(String::as_str));
+    if base_refs.is_empty() {
+        base_refs = split_base_refs(env::var("BASE_REF").ok().as_deref());
+    }
+    if let (true, false, Some(provider)) = (base_refs.is_empty(), every_group_based, ci) {
+        base_refs = provider.base_refs(&|name| env::var(name).ok());
+        log!(
+            Info,
+            "gdf",
+            "Detected {provider}: comparing against {}",
+            base_refs.join(", ")
+        );
+    }
+    if base_refs.is_empty() && !every_group_based {
+        return Err(
             "BASE_REF must be provided via -b/--base-ref flag or BASE_REF environment variable"
-                .to_string()
-        })?;
+                .to_string(),
+        );
+    }
+    Ok(base_refs)
+}
 
-    // Read GITHUB_OUTPUT file path from environment (if set)
-    let github_output_filepath = env::var("GITHUB_OUTPUT").ok();
+/// Parse an optional mode flag, falling back to the mode's default
+fn parse_mode<T>(value: Option<&str>) -> Result<T, String>
+where
+    T: FromStr<Err = String> + Default,
+{
+    value
+        .map(str::parse)
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
 
-    Ok(Config {
-        patterns: args.patterns,
-        base_ref,
-        github_output_name: args.github_output,
-        github_output_filepath,
-    })
+/// Split comma-separated base ref lists into individual refs, dropping empty entries
+pub fn split_base_refs<'a>(values: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    values
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|base_ref| !base_ref.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse a non-negative integer flag value
+fn parse_count(flag: &str, value: Option<&str>) -> Result<Option<usize>, String> {
+    value
+        .map(|value| {
+            value.parse().map_err(|_| {
+                format!("Invalid {flag} value: {value} (expected a non-negative integer)")
+            })
+        })
+        .transpose()
+}
+
+/// Read the options of the config file given with `--config`, or else of the nearest
+/// `.git-diff-filter.toml` (or `.yaml`, `.yml`), one flag and its value per entry (see
+/// [`cli::with_file_args`])
+///
+/// `--no-config` skips the lookup. Files ending in `.yaml` or `.yml` are read as YAML,
+/// others as TOML. Each top-level key names a long flag (`base-ref`, `find-copies`,
+/// ...); `patterns` holds the patterns shared by every filter, and each
+/// `filters.<name>` table a named filter group with its own `patterns` and options.
+pub fn file_args(
+    path: Option<&str>,
+    no_config: bool,
+    profile: Option<&str>,
+) -> Result<Vec<Vec<String>>, String> {
+    let Some(path) = config_path(path, no_config) else {
+        return Ok(Vec::new());
+    };
+    let document = load_document(&path, &mut Vec::new())?;
+    let in_file = |e: String| format!("{}: {e}", path.display());
+    let document = select_profile(document, profile).map_err(in_file)?;
+    // Errors name the key they are about, found again in the file's text
+    let located = |e: String| {
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        in_file(locate_error(&text, &document, e))
+    };
+    let entries = args_from_document(&document)
+        .and_then(expand_entries)
+        .map_err(&located)?;
+    // The options are checked as they would be on the command line
+    check_entries(&entries).map_err(&located)?;
+    log!(Debug, "gdf", "Config file: {}", path.display());
+    Ok(entries)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The document without its `profiles` table, with the table of `profile` merged over
+/// it when one is selected
+fn select_profile(document: Value, profile: Option<&str>) -> Result<Value, String> {
+    let Value::Object(mut members) = document else {
+        return Ok(document);
+    };
+    let profiles = match members.iter().position(|(key, _)| key == "profiles") {
+        Some(index) => match members.remove(index).1 {
+            Value::Object(profiles) => profiles,
+            _ => return Err("profiles must hold [profiles.<name>] tables".to_string()),
+        },
+        None => Vec::new(),
+    };
+    let mut document = Value::Object(members);
+    let Some(name) = profile else {
+        return Ok(document);
+    };
+    let names: Vec<&str> = profiles.iter().map(|(key, _)| key.as_str()).collect();
+    let Some(index) = names.iter().position(|key| *key == name) else {
+        return Err(if names.is_empty() {
+            format!("Unknown profile '{name}': the config defines no profiles")
+        } else {
+            format!("Unknown profile '{name}' (expected {})", names.join(", "))
+        });
+    };
+    match profiles.into_iter().nth(index) {
+        Some((_, overlay @ Value::Object(_))) => {
+            log!(Debug, "gdf", "Config profile: {name}");
+            merge(&mut document, overlay);
+            Ok(document)
+        }
+        _ => Err(format!("profiles.{name} must be a table")),
+    }
+}
+
+/// Check flag entries as the command line would be, naming the key of the first one
+/// that fails and what it expects
+fn check_entries(entries: &[Vec<String>]) -> Result<(), String> {
+    if cli::parse_flags(&entries.concat()).is_ok() {
+        return Ok(());
+    }
+    for end in 1..=entries.len() {
+        let Err(e) = cli::parse_flags(&entries[..end].concat()) else {
+            continue;
+        };
+        let entry = &entries[end - 1];
+        let key = entry[0].trim_start_matches('-');
+        return Err(if e.starts_with("Unknown flag") {
+            match did_you_mean(key, TOP_LEVEL_KEYS.iter().copied()) {
+                Some(suggestion) => format!("Unknown key {key}: did you mean {suggestion}?"),
+                None => format!(
+                    "Unknown key {key} (expected a long flag without its dashes, patterns, filters, aliases, targets, tests, jobs, profiles or extends)"
+                ),
+            }
+        } else if entry.len() > 1 && cli::parse_flags(&entry[..1]).is_ok() {
+            format!("{key} takes no value; set it to true or false")
+        } else if entry.len() == 1 {
+            format!("{key} takes a value, not true")
+        } else {
+            format!("{key}: {e}")
+        });
+    }
+    Ok(())
+}
+
+/// The closest of `candidates` to a mistyped `key`, if any is close: a few edits away,
+/// or starting with it
+fn did_you_mean<'a>(key: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (key.len() / 3).max(1);
+    candidates
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, candidate)| {
+            distance <= limit || (key.len() >= 3 && candidate.starts_with(key))
+        })
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The number of single-character insertions, deletions and substitutions turning `a`
+/// into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Prefix an error with the line and column of the longest key of `document` it names,
+/// when that key is found in the file's `text`
+fn locate_error(text: &str, document: &Value, message: String) -> String {
+    let mut paths = Vec::new();
+    key_paths(document, "", &mut paths);
+    let named = |path: &&String| {
+        message.match_indices(path.as_str()).any(|(start, _)| {
+            let before = message[..start].chars().next_back();
+            let after = message[start + path.len()..].chars().next();
+            let is_name_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+            !before.is_some_and(|c| is_name_char(c) || c == '.') && !after.is_some_and(is_name_char)
+        })
+    };
+    let position = paths
+        .iter()
+        .filter(named)
+        .max_by_key(|path| path.len())
+        .and_then(|path| key_position(text, path));
+    match position {
+        Some((line, column)) => format!("line {line}, column {column}: {message}"),
+        None => message,
+    }
+}
+
+/// Every dotted key path of a document's tables
+fn key_paths(value: &Value, prefix: &str, paths: &mut Vec<String>) {
+    if let Value::Object(members) = value {
+        for (key, value) in members {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            key_paths(value, &path, paths);
+            paths.push(path);
+        }
+    }
+}
+
+/// The line and column, both from 1, of the last key of a dotted path in TOML or YAML
+/// text, each key found after the one before it
+fn key_position(text: &str, path: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    for key in path.split('.') {
+        let offset = text[from..]
+            .match_indices(key)
+            .map(|(offset, _)| from + offset)
+            .find(|&offset| is_key_at(text, offset, key))?;
+        from = offset;
+    }
+    let before = &text[..from];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Some((
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    ))
+}
+
+/// Whether `key` is written as a key at `offset`: bare or quoted, and followed by `=`,
+/// `:`, `.` or the `]` of a table header
+fn is_key_at(text: &str, offset: usize, key: &str) -> bool {
+    let before = text[..offset].chars().next_back();
+    let starts = before.is_none_or(|c| c.is_whitespace() || "[.\"'".contains(c));
+    let after = text[offset + key.len()..].trim_start_matches(['"', '\'']);
+    starts
+        && after
+            .trim_start_matches([' ', '\t'])
+            .starts_with(['=', ':', '.', ']'])
+}
+
+/// The names of the profiles a config file defines, in order
+pub fn profile_names(path: &Path) -> Result<Vec<String>, String> {
+    Ok(match load_document(path, &mut Vec::new())? {
+        Value::Object(members) => members
+            .into_iter()
+            .find(|(key, _)| key == "profiles")
+            .and_then(|(_, profiles)| match profiles {
+                Value::Object(profiles) => {
+                    Some(profiles.into_iter().map(|(name, _)| name).collect())
+                }
+                _ => None,
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    })
+}
+
+/// Expand `${VAR}` in the values of flag entries, leaving the flags as they are
+fn expand_entries(entries: Vec<Vec<String>>) -> Result<Vec<Vec<String>>, String> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            let mut values = entry.into_iter();
+            let flag = values.next().into_iter().map(Ok);
+            flag.chain(values.map(|value| interpolate::expand(&value)))
+                .collect()
+        })
+        .collect()
+}
+
+/// The config file given with `--config`, or else the one discovered from the current
+/// directory unless `--no-config` is given
+pub fn config_path(path: Option<&str>, no_config: bool) -> Option<PathBuf> {
+    match path {
+        Some(path) => Some(PathBuf::from(path)),
+        None if no_config => None,
+        None => env::current_dir()
+            .ok()
+            .and_then(|dir| discover_config(&dir)),
+    }
+}
+
+/// Read a config file, merged over the files its `extends` key names
+///
+/// `extends` holds a path or a list of paths, resolved by [`extended_path`], merged in
+/// order. Tables merge key by key; any other value, arrays included, replaces the one
+/// it extends. `chain` holds the files being read, to reject cycles.
+fn load_document(path: &Path, chain: &mut Vec<PathBuf>) -> Result<Value, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+    let in_file = |e: String| format!("{}: {e}", path.display());
+    let is_yaml = path
+        .extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml");
+    let document = if is_yaml {
+        yaml::parse(&text)
+    } else {
+        toml::parse(&text)
+    }
+    .map_err(in_file)?;
+    let Value::Object(mut members) = document else {
+        return Ok(document);
+    };
+    let Some(index) = members.iter().position(|(key, _)| key == "extends") else {
+        return Ok(Value::Object(members));
+    };
+    let bases = match members.remove(index).1 {
+        Value::String(base) => vec![base],
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| {
+                in_file("extends must be a string or an array of strings".to_string())
+            })?,
+        _ => {
+            return Err(in_file(
+                "extends must be a string or an array of strings".to_string(),
+            ))
+        }
+    };
+
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&canonical) {
+        let cycle: Vec<String> = chain
+            .iter()
+            .chain([&canonical])
+            .map(|file| file.display().to_string())
+            .collect();
+        return Err(format!(
+            "Config files extend each other: {}",
+            cycle.join(" -> ")
+        ));
+    }
+    chain.push(canonical);
+    let mut merged = Value::Object(Vec::new());
+    for base in bases {
+        let base_path = extended_path(path, &base).map_err(in_file)?;
+        if !base_path.is_file() {
+            return Err(in_file(format!(
+                "extends {base}: no config file at {}",
+                base_path.display()
+            )));
+        }
+        log!(
+            Debug,
+            "gdf",
+            "{} extends {}",
+            path.display(),
+            base_path.display()
+        );
+        merge(&mut merged, load_document(&base_path, chain)?);
+    }
+    chain.pop();
+    merge(&mut merged, Value::Object(members));
+    Ok(merged)
+}
+
+/// Resolve an `extends` entry of the config file `file`
+///
+/// `${VAR}` is expanded first, so a job can point at a checkout whose place it chooses.
+/// A path starting with `//` is relative to the root of the repository holding `file`,
+/// so a file in another checkout resolves its own `//` paths against that checkout;
+/// any other relative path is relative to `file`'s directory.
+fn extended_path(file: &Path, base: &str) -> Result<PathBuf, String> {
+    let base = interpolate::expand(base)?;
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let Some(in_repository) = base.strip_prefix("//") else {
+        return Ok(dir.join(base));
+    };
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(|root| root.join(in_repository))
+        .ok_or_else(|| format!("extends {base}: the file is not inside a git repository"))
+}
+
+/// Merge `overlay` into `target`: tables key by key, other values replaced
+fn merge(target: &mut Value, overlay: Value) {
+    match (target, overlay) {
+        (Value::Object(members), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match members.iter_mut().find(|(existing, _)| *existing == key) {
+                    Some((_, existing)) => merge(existing, value),
+                    None => members.push((key, value)),
+                }
+            }
+        }
+        (target, overlay) => *target = overlay,
+    }
+}
+
+/// Find the config file in `dir` or its parents, stopping at the repository root
+fn discover_config(dir: &Path) -> Option<PathBuf> {
+    for dir in dir.ancestors() {
+        let candidate = CONFIG_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|candidate| candidate.is_file());
+        if candidate.is_some() {
+            return candidate;
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Turn a config document into flag entries: the shared patterns first, then the
+/// options, then each filter group's name and patterns, with pattern aliases expanded
+fn args_from_document(document: &Value) -> Result<Vec<Vec<String>>, String> {
+    let Value::Object(members) = document else {
+        return Err("the config must be a table".to_string());
+    };
+    let aliases = match members.iter().find(|(key, _)| key == "aliases") {
+        Some((_, value)) => pattern_aliases(value)?,
+        None => Vec::new(),
+    };
+    let mut patterns = Vec::new();
+    let mut options = Vec::new();
+    let mut filters = Vec::new();
+    for (key, value) in members {
+        match key.as_str() {
+            "aliases" => {}
+            "patterns" => patterns.extend(string_args("--pattern", key, value)?),
+            "filters" => filters = filter_args(value)?,
+            "targets" => options.extend(target_args(value)?),
+            "tests" => options.extend(test_map_args(value)?),
+            "jobs" => options.extend(job_args(value)?),
+            "target" | "test-map" | "job" => {
+                let table = match key.as_str() {
+                    "target" => "targets",
+                    "test-map" => "tests",
+                    _ => "jobs",
+                };
+                return Err(format!(
+                    "{key} cannot be set in a config file; use a [{table}] table"
+                ));
+            }
+            "pattern" | "github-output" => {
+                return Err(format!(
+                    "{key} cannot be set in a config file; use patterns and [filters.<name>] tables"
+                ))
+            }
+            "config" | "no-config" | "profile" | "watch" | "serve" | "socket" => {
+                return Err(format!("{key} cannot be set in a config file"))
+            }
+            _ => options.extend(option_args(key, value)?),
+        }
+    }
+    let mut entries = Vec::new();
+    for entry in [patterns, options, filters].concat() {
+        match entry.as_slice() {
+            [flag, pattern] if flag == "--pattern" => entries.extend(
+                expand_alias(pattern, &aliases, &mut Vec::new())?
+                    .into_iter()
+                    .map(|pattern| vec![flag.clone(), pattern]),
+            ),
+            _ => entries.push(entry),
+        }
+    }
+    Ok(entries)
+}
+
+/// The `aliases` table: pattern lists, each under a name patterns refer to as `@name`
+fn pattern_aliases(value: &Value) -> Result<Vec<(String, Vec<String>)>, String> {
+    let Value::Object(members) = value else {
+        return Err("aliases must hold named pattern lists".to_string());
+    };
+    members
+        .iter()
+        .map(|(name, value)| {
+            if name.is_empty() || !name.chars().all(output::is_name_char) {
+                return Err(format!(
+                    "Invalid alias name '{name}': use letters, digits, '-' and '_'"
+                ));
+            }
+            Ok((name.clone(), strings(&format!("aliases.{name}"), value)?))
+        })
+        .collect()
+}
+
+/// The patterns a pattern stands for: the alias's patterns for `@name`, their
+/// negations for `!@name`, and the pattern itself otherwise
+///
+/// Aliases may refer to other aliases; `chain` holds the ones being expanded, to reject
+/// cycles.
+fn expand_alias(
+    pattern: &str,
+    aliases: &[(String, Vec<String>)],
+    chain: &mut Vec<String>,
+) -> Result<Vec<String>, String> {
+    let (negated, reference) = match pattern.strip_prefix('!') {
+        Some(reference) => (true, reference),
+        None => (false, pattern),
+    };
+    let Some(name) = reference
+        .strip_prefix('@')
+        .filter(|name| !name.is_empty() && name.chars().all(output::is_name_char))
+    else {
+        return Ok(vec![pattern.to_string()]);
+    };
+    let Some((_, patterns)) = aliases.iter().find(|(alias, _)| alias == name) else {
+        return Err(format!(
+            "Unknown pattern alias @{name} (write \\@{name} for a path starting with @)"
+        ));
+    };
+    if chain.iter().any(|alias| alias == name) {
+        return Err(format!(
+            "Pattern aliases refer to each other: @{} -> @{name}",
+            chain.join(" -> @")
+        ));
+    }
+    chain.push(name.to_string());
+    let mut expanded = Vec::new();
+    for pattern in patterns {
+        for pattern in expand_alias(pattern, aliases, chain)? {
+            if !negated {
+                expanded.push(pattern);
+            } else if pattern.starts_with('!') {
+                return Err(format!("!@{name} cannot negate the exclusion {pattern}"));
+            } else {
+                expanded.push(format!("!{pattern}"));
+            }
+        }
+    }
+    chain.pop();
+    Ok(expanded)
+}
+
+/// Each `[filters.<name>]` table as a `--github-output` entry followed by its patterns
+/// and options
+///
+/// The output name defaults to the table's name; `output` sets another one.
+/// `ignore-case`, `status`, `base-ref`, `branches` and `compose` apply to that filter
+/// only, and `default-excludes = false` keeps the top-level `default-excludes` out of it.
+fn filter_args(value: &Value) -> Result<Vec<Vec<String>>, String> {
+    let Value::Object(filters) = value else {
+        return Err("filters must hold [filters.<name>] tables".to_string());
+    };
+    let mut entries = Vec::new();
+    for (name, filter) in filters {
+        let Value::Object(keys) = filter else {
+            return Err(format!("filters.{name} must be a table"));
+        };
+        let mut output = name.clone();
+        let mut options = Vec::new();
+        for (key, value) in keys {
+            let field = format!("filters.{name}.{key}");
+            match (key.as_str(), value) {
+                ("patterns", _) => options.extend(string_args("--pattern", &field, value)?),
+                ("output", Value::String(value)) => output.clone_from(value),
+                ("ignore-case", Value::Bool(ignore_case)) => {
+                    if *ignore_case {
+                        options.push(vec!["--group-ignore-case".to_string()]);
+                    }
+                }
+                ("status", _) => {
+                    let kinds = match value {
+                        Value::String(list) => Some(list.clone()),
+                        Value::Array(items) => items
+                            .iter()
+                            .map(|item| item.as_str())
+                            .collect::<Option<Vec<_>>>()
+                            .map(|kinds| kinds.join(",")),
+                        _ => None,
+                    }
+                    .ok_or_else(|| format!("{field} must be a string or an array of strings"))?;
+                    options.push(vec!["--group-status".to_string(), kinds]);
+                }
+                ("base-ref", _) => options.extend(string_args("--group-base-ref", &field, value)?),
+                ("branches", _) => options.extend(string_args("--group-branch", &field, value)?),
+                ("default-excludes", Value::Bool(applies)) => {
+                    if !*applies {
+                        options.push(vec!["--group-no-default-excludes".to_string()]);
+                    }
+                }
+                ("compose", Value::String(composition)) => {
+                    options.push(vec!["--group-compose".to_string(), composition.clone()]);
+                }
+                ("min-fraction", Value::Number(_)) => {
+                    options.push(vec!["--group-min-fraction".to_string(), value.to_string()]);
+                }
+                ("min-fraction", _) => return Err(format!("{field} must be a number")),
+                ("output" | "compose", _) => return Err(format!("{field} must be a string")),
+                ("ignore-case" | "default-excludes", _) => {
+                    return Err(format!("{field} must be a boolean"))
+                }
+                _ => {
+                    return Err(match did_you_mean(key, FILTER_KEYS.iter().copied()) {
+                        Some(suggestion) => {
+                            format!("Unknown key {field}: did you mean {suggestion}?")
+                        }
+                        None => {
+                            format!("Unknown key {field} (expected {})", FILTER_KEYS.join(", "))
+                        }
+                    })
+                }
+            }
+        }
+        entries.push(vec!["--github-output".to_string(), output]);
+        entries.extend(options);
+    }
+    Ok(entries)
+}
+
+/// The `[targets]` table as `--target` entries: each target with its glob or globs
+fn target_args(value: &Value) -> Result<Vec<Vec<String>>, String> {
+    let Value::Object(targets) = value else {
+        return Err("targets must map target names to globs".to_string());
+    };
+    let mut entries = Vec::new();
+    for (target, globs) in targets {
+        for glob in strings(&format!("targets.{target}"), globs)? {
+            entries.push(vec!["--target".to_string(), format!("{target}={glob}")]);
+        }
+    }
+    Ok(entries)
+}
+
+/// The `[tests]` table as `--test-map` entries: each source glob with its test glob or
+/// globs
+fn test_map_args(value: &Value) -> Result<Vec<Vec<String>>, String> {
+    let Value::Object(sources) = value else {
+        return Err("tests must map source globs to test globs".to_string());
+    };
+    let mut entries = Vec::new();
+    for (source, globs) in sources {
+        for glob in strings(&format!("tests.{source}"), globs)? {
+            entries.push(vec!["--test-map".to_string(), format!("{source}={glob}")]);
+        }
+    }
+    Ok(entries)
+}
+
+/// The `[jobs]` table as `--job` entries: each job with the expression or expressions
+/// under which it runs
+fn job_args(value: &Value) -> Result<Vec<Vec<String>>, String> {
+    let Value::Object(jobs) = value else {
+        return Err("jobs must map job names to filter expressions".to_string());
+    };
+    let mut entries = Vec::new();
+    for (job, expressions) in jobs {
+        for expression in strings(&format!("jobs.{job}"), expressions)? {
+            entries.push(vec!["--job".to_string(), format!("{job}={expression}")]);
+        }
+    }
+    Ok(entries)
+}
+
+/// One `flag` entry per string of a string or array of strings
+fn string_args(flag: &str, key: &str, value: &Value) -> Result<Vec<Vec<String>>, String> {
+    Ok(strings(key, value)?
+        .into_iter()
+        .map(|value| vec![flag.to_string(), value])
+        .collect())
+}
+
+/// The strings of a string or array of strings
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::String(value) => Ok(vec![value.clone()]),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("{key} must be a string or an array of strings")),
+        _ => Err(format!("{key} must be a string or an array of strings")),
+    }
+}
+
+/// The `--<key>` entries of an option: none for `false`, the bare flag for `true`, and
+/// one entry per value of an array
+fn option_args(key: &str, value: &Value) -> Result<Vec<Vec<String>>, String> {
+    let flag = format!("--{key}");
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(_) => Some(value.to_string()),
+        _ => None,
+    };
+    match value {
+        Value::Bool(true) => Ok(vec![vec![flag]]),
+        Value::Bool(false) => Ok(Vec::new()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| scalar(item).map(|item| vec![flag.clone(), item]))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("{key} must hold strings or numbers")),
+        _ => scalar(value)
+            .map(|value| vec![vec![flag, value]])
+            .ok_or_else(|| format!("{key} must be a string, number, boolean or array")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::ModeChanges;
+
+    #[test]
+    fn test_base_ref_from_cli_flag() {
+        let args = Args {
+            patterns: vec!["*.txt".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: Vec::new(),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.base_refs, vec!["main"]);
+        assert_eq!(
+            config.groups,
+            vec![FilterGroup {
+                name: None,
+                patterns: vec!["*.txt".to_string()],
+                ..FilterGroup::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_base_ref_from_env_var() {
+        unsafe {
+            env::set_var("BASE_REF", "develop");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec![],
+            github_output: Vec::new(),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.base_refs, vec!["develop"]);
+
+        unsafe {
+            env::remove_var("BASE_REF");
+        }
+    }
+
+    #[test]
+    fn test_cli_flag_overrides_env_var() {
+        unsafe {
+            env::set_var("BASE_REF", "develop");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: Vec::new(),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.base_refs, vec!["main"]); // CLI flag wins
+
+        unsafe {
+            env::remove_var("BASE_REF");
+        }
+    }
+
+    #[test]
+    fn test_prefer_env_overrides_flags() {
+        unsafe {
+            env::set_var("BASE_REF", "develop");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            last: Some("3".to_string()),
+            prefer_env: true,
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.base_refs, vec!["develop"]); // Environment wins
+        assert_eq!(config.last, None);
+        assert!(config.env_base_refs);
+
+        unsafe {
+            env::remove_var("BASE_REF");
+        }
+    }
+
+    #[test]
+    fn test_error_when_base_ref_missing() {
+        unsafe {
+            env::remove_var("BASE_REF");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec![],
+            github_output: Vec::new(),
+            // Not taken from the CI system running the tests
+            ci: Some("none".to_string()),
+            ..Default::default()
+        };
+
+        let result = from_args(args);
+        assert_eq!(
+            result,
+            Err(
+                "BASE_REF must be provided via -b/--base-ref flag or BASE_REF environment variable"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_error_when_base_ref_empty() {
+        unsafe {
+            env::set_var("BASE_REF", "");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec![],
+            github_output: Vec::new(),
+            // Not taken from the CI system running the tests
+            ci: Some("none".to_string()),
+            ..Default::default()
+        };
+
+        let result = from_args(args);
+        assert!(result.is_err());
+
+        unsafe {
+            env::remove_var("BASE_REF");
+        }
+    }
+
+    #[test]
+    fn test_github_output_name_passed_through() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.groups[0].name, Some("api".to_string()));
+    }
+
+    #[test]
+    fn test_github_output_file_from_env() {
+        unsafe {
+            env::set_var("GITHUB_OUTPUT", "/tmp/github_output.txt");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: Vec::new(),
+            // Whatever CI system runs the tests
+            ci: Some("none".to_string()),
+            ..Default::default()
+        };
+
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(
+            config.github_output_filepath,
+            Some("/tmp/github_output.txt".to_string())
+        );
+        // Printed instead while watching, as each change would append them again
+        let config = from_args(Args {
+            watch: true,
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.github_output_filepath, None);
+        // Only GitHub Actions reads GITHUB_OUTPUT
+        let config = from_args(Args {
+            ci: Some("circleci".to_string()),
+            ..args
+        })
+        .unwrap();
+        assert_eq!(config.github_output_filepath, None);
+
+        unsafe {
+            env::remove_var("GITHUB_OUTPUT");
+        }
+    }
+
+    #[test]
+    fn test_github_output_file_not_set() {
+        unsafe {
+            env::remove_var("GITHUB_OUTPUT");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: Vec::new(),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.github_output_filepath, None);
+    }
+
+    #[test]
+    fn test_all_config_fields() {
+        unsafe {
+            env::set_var("BASE_REF", "develop");
+            env::set_var("GITHUB_OUTPUT", "/tmp/output");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string(), "*.md".to_string()],
+            base_refs: vec![],
+            github_output: vec![("my-api".to_string(), 0)],
+            ci: Some("none".to_string()),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.groups[0].patterns, vec!["*.rs", "*.md"]);
+        assert_eq!(config.base_refs, vec!["develop"]);
+        assert_eq!(config.groups[0].name, Some("my-api".to_string()));
+        assert_eq!(
+            config.github_output_filepath,
+            Some("/tmp/output".to_string())
+        );
+
+        unsafe {
+            env::remove_var("BASE_REF");
+            env::remove_var("GITHUB_OUTPUT");
+        }
+    }
+
+    #[test]
+    fn test_anchoring_defaults_to_prefix() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.anchoring, Anchoring::Prefix);
+    }
+
+    #[test]
+    fn test_anchoring_parsed_from_args() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            anchoring: Some("unanchored".to_string()),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.anchoring, Anchoring::Unanchored);
+    }
+
+    #[test]
+    fn test_error_invalid_anchoring() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            anchoring: Some("sideways".to_string()),
+            ..Default::default()
+        };
+
+        let result = from_args(args);
+        assert!(result.unwrap_err().contains("Invalid anchoring mode"));
+    }
+
+    #[test]
+    fn test_renames_defaults_to_both() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.renames, RenamePaths::Both);
+    }
+
+    #[test]
+    fn test_error_invalid_renames() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            renames: Some("old".to_string()),
+            ..Default::default()
+        };
+
+        let result = from_args(args);
+        assert!(result.unwrap_err().contains("Invalid renames mode"));
+    }
+
+    #[test]
+    fn test_sparse_paths() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()).unwrap().sparse_paths,
+            SparsePaths::Include
+        );
+
+        let config = from_args(Args {
+            sparse_paths: Some("exclude".to_string()),
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.sparse_paths, SparsePaths::Exclude);
+
+        let result = from_args(Args {
+            sparse_paths: Some("skip".to_string()),
+            ..args
+        });
+        assert!(result.unwrap_err().contains("Invalid sparse paths mode"));
+    }
+
+    #[test]
+    fn test_find_copies_passed_to_diff_options() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            find_copies: true,
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert!(config.diff_options.find_copies);
+    }
+
+    #[test]
+    fn test_ignore_whitespace_passed_to_diff_options() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert!(config.diff_options.ignore_whitespace);
+    }
+
+    #[test]
+    fn test_ignore_content_passed_to_diff_options() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ignore_content_patterns: vec!["^version".to_string()],
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.diff_options.ignore_content, vec!["^version"]);
+    }
+
+    #[test]
+    fn test_content_kind_from_args() {
+        let args = Args {
+            patterns: vec!["*.png".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(from_args(args.clone()).unwrap().content_kind, None);
+
+        let binary = Args {
+            binary_only: true,
+            ..args.clone()
+        };
+        assert_eq!(
+            from_args(binary.clone()).unwrap().content_kind,
+            Some(ContentKind::Binary)
+        );
+
+        let text = Args {
+            text_only: true,
+            ..args
+        };
+        assert_eq!(
+            from_args(text.clone()).unwrap().content_kind,
+            Some(ContentKind::Text)
+        );
+
+        assert_eq!(
+            from_args(Args {
+                text_only: true,
+                ..binary
+            }),
+            Err("--binary-only and --text-only cannot be combined".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mode_changes_passed_to_diff_options() {
+        let args = Args {
+            patterns: vec!["*.sh".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()).unwrap().diff_options.mode_changes,
+            ModeChanges::Include
+        );
+
+        let config = from_args(Args {
+            mode_changes: Some("ignore".to_string()),
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.diff_options.mode_changes, ModeChanges::Ignore);
+
+        let result = from_args(Args {
+            mode_changes: Some("skip".to_string()),
+            ..args
+        });
+        assert!(result.unwrap_err().contains("Invalid mode changes setting"));
+    }
+
+    #[test]
+    fn test_merge_base_enabled_by_default() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        assert!(from_args(args).unwrap().diff_options.merge_base);
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            no_merge_base: true,
+            ..Default::default()
+        };
+        assert!(!from_args(args).unwrap().diff_options.merge_base);
+    }
+
+    #[test]
+    fn test_source_parsed_from_args() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            source: Some("combined".to_string()),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.diff_options.source, DiffSource::Combined);
+    }
+
+    #[test]
+    fn test_line_thresholds_parsed_from_args() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            min_lines_changed: Some("5".to_string()),
+            max_lines_changed: Some("500".to_string()),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(
+            config.line_thresholds,
+            LineThresholds {
+                min: Some(5),
+                max: Some(500),
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_invalid_line_threshold() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            min_lines_changed: Some("-1".to_string()),
+            ..Default::default()
+        };
+
+        let result = from_args(args);
+        assert_eq!(
+            result,
+            Err(
+                "Invalid --min-lines-changed value: -1 (expected a non-negative integer)"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_error_min_exceeds_max_lines() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            min_lines_changed: Some("10".to_string()),
+            max_lines_changed: Some("5".to_string()),
+            ..Default::default()
+        };
+
+        assert!(from_args(args).unwrap_err().contains("cannot exceed"));
+    }
+
+    #[test]
+    fn test_statuses_parsed_from_args() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            status: Some("added, D,renamed".to_string()),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(
+            config.statuses,
+            vec![ChangeKind::Added, ChangeKind::Deleted, ChangeKind::Renamed]
+        );
+    }
+
+    #[test]
+    fn test_statuses_default_to_all() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+
+        assert!(from_args(args).unwrap().statuses.is_empty());
+    }
+
+    #[test]
+    fn test_group_base_refs() {
+        let args = Args {
+            patterns: vec!["infra/**".to_string(), "app/**".to_string()],
+            github_output: vec![("infra".to_string(), 0), ("app".to_string(), 1)],
+            group_base_ref: vec![(0, "v2.0, v1.9".to_string()), (1, "main".to_string())],
+            ..Default::default()
+        };
+        // Every group has its own base refs, so none is needed for the run
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(config.groups[0].base_refs, ["v2.0", "v1.9"]);
+        assert_eq!(config.groups[1].base_refs, ["main"]);
+
+        assert_eq!(
+            from_args(Args {
+                last: Some("3".to_string()),
+                ..args
+            }),
+            Err("--last cannot be combined with --group-base-ref".to_string())
+        );
+    }
+
+    #[test]
+    fn test_group_options() {
+        let args = Args {
+            patterns: vec!["api/**".to_string(), "docs/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("api".to_string(), 0), ("docs".to_string(), 1)],
+            status: Some("added,modified".to_string()),
+            group_ignore_case: vec![1],
+            group_status: vec![(0, "deleted".to_string())],
+            ..Default::default()
+        };
+        let config = from_args(args).unwrap();
+        assert_eq!(
+            config.groups,
+            vec![
+                FilterGroup {
+                    name: Some("api".to_string()),
+                    patterns: vec!["api/**".to_string()],
+                    ignore_case: false,
+                    statuses: vec![ChangeKind::Deleted],
+                    ..FilterGroup::default()
+                },
+                FilterGroup {
+                    name: Some("docs".to_string()),
+                    patterns: vec!["docs/**".to_string()],
+                    ignore_case: true,
+                    statuses: vec![ChangeKind::Added, ChangeKind::Modified],
+                    ..FilterGroup::default()
+                },
+            ]
+        );
+        // The groups consider different kinds, so none is dropped up front
+        assert!(config.statuses.is_empty());
+
+        let args = Args {
+            patterns: vec!["api/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            ignore_case: true,
+            group_status: vec![(0, "added".to_string())],
+            ..Default::default()
+        };
+        let config = from_args(args).unwrap();
+        assert!(config.groups[0].ignore_case);
+        assert_eq!(config.statuses, vec![ChangeKind::Added]);
+
+        let args = Args {
+            patterns: vec!["api/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            group_status: vec![(0, "moved".to_string())],
+            ..Default::default()
+        };
+        assert!(from_args(args).is_err());
+    }
+
+    #[test]
+    fn test_default_excludes() {
+        let args = Args {
+            patterns: vec!["api/**".to_string(), "docs/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![
+                ("api".to_string(), 0),
+                ("docs".to_string(), 1),
+                ("all".to_string(), 2),
+            ],
+            group_compose: vec![(2, "api + docs".to_string())],
+            default_excludes: vec!["**/*.md".to_string(), "!**/fixtures/**".to_string()],
+            group_no_default_excludes: vec![1],
+            ..Default::default()
+        };
+        let config = from_args(args).unwrap();
+        assert_eq!(
+            config.groups[0].patterns,
+            ["api/**", "!**/*.md", "!**/fixtures/**"]
+        );
+        assert_eq!(config.groups[1].patterns, ["docs/**"]);
+        assert!(config.groups[2].patterns.is_empty());
+
+        let document = toml::parse(
+            r#"
+default-excludes = ["**/*.md"]
+
+[filters.docs]
+patterns = ["docs/**"]
+default-excludes = false
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            args_from_document(&document).unwrap(),
+            [
+                ["--default-excludes", "**/*.md"].as_slice(),
+                &["--github-output", "docs"],
+                &["--pattern", "docs/**"],
+                &["--group-no-default-excludes"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_branches() {
+        let args = Args {
+            patterns: vec!["charts/**".to_string(), "src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("release".to_string(), 0), ("code".to_string(), 1)],
+            group_branch: vec![
+                (0, "release/*".to_string()),
+                (0, "!release/legacy".to_string()),
+            ],
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        let release = &config.groups[0];
+        assert_eq!(release.applies_on("release/2.0"), Ok(true));
+        assert_eq!(release.applies_on("release/legacy"), Ok(false));
+        assert_eq!(release.applies_on("release/2.0/rc1"), Ok(false));
+        assert_eq!(release.applies_on("main"), Ok(false));
+        assert_eq!(config.groups[1].applies_on("main"), Ok(true));
+
+        assert!(from_args(Args {
+            group_branch: vec![(0, "release/[".to_string())],
+            ..args
+        })
+        .unwrap_err()
+        .starts_with("Invalid branch glob release/["));
+    }
+
+    #[test]
+    fn test_compose_groups() {
+        let group = |name: &str, base_ref: Option<&str>| FilterGroup {
+            name: Some(name.to_string()),
+            patterns: vec![format!("{name}/**")],
+            base_refs: base_ref.into_iter().map(str::to_string).collect(),
+            ..FilterGroup::default()
+        };
+        let composed = |name: &str| FilterGroup {
+            name: Some(name.to_string()),
+            ..FilterGroup::default()
+        };
+        let compose = |groups: &[FilterGroup], compositions: &[(usize, &str)]| {
+            let mut groups = groups.to_vec();
+            let compositions: Vec<(usize, String)> = compositions
+                .iter()
+                .map(|&(index, text)| (index, text.to_string()))
+                .collect();
+            compose_groups(&mut groups, &compositions).map(|()| groups)
+        };
+
+        // Composed groups refer through each other to the groups with patterns
+        let groups = [
+            group("backend", Some("v2")),
+            group("tests", Some("v2")),
+            group("docs", Some("v2")),
+            composed("code"),
+            composed("reviewed"),
+        ];
+        let resolved = compose(&groups, &[(3, "backend - tests"), (4, "code + docs")]).unwrap();
+        assert_eq!(
+            resolved[4].composition.as_ref().map(ToString::to_string),
+            Some("(backend - tests) + docs".to_string())
+        );
+        assert_eq!(resolved[4].base_refs, ["v2"]);
+        assert_eq!(resolved[4].selection(), "(backend - tests) + docs");
+
+        assert_eq!(
+            compose(&groups, &[(3, "backend - web")]),
+            Err("Unknown filter 'web' in the composition of -g code".to_string())
+        );
+        assert_eq!(
+            compose(&groups, &[(3, "reviewed & backend"), (4, "code")]),
+            Err("Filters are composed of each other: code -> reviewed -> code".to_string())
+        );
+        assert_eq!(
+            compose(&groups, &[(3, "backend -")]),
+            Err("Invalid composition 'backend -': expected a filter name".to_string())
+        );
+        let groups = [
+            group("backend", Some("v2")),
+            group("tests", None),
+            composed("code"),
+        ];
+        assert_eq!(
+            compose(&groups, &[(2, "backend - tests")]),
+            Err("-g code combines filters compared against different base refs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_error_invalid_status() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            status: Some("added,moved".to_string()),
+            ..Default::default()
+        };
+
+        assert!(from_args(args)
+            .unwrap_err()
+            .starts_with("Invalid status: moved"));
+    }
+
+    #[test]
+    fn test_fetch_attempts() {
+        let base = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        let attempts = |args: Args| from_args(args).unwrap().diff_options.fetch_attempts;
+
+        assert_eq!(attempts(base.clone()), 0);
+        assert_eq!(
+            attempts(Args {
+                auto_fetch: true,
+                ..base.clone()
+            }),
+            DEFAULT_FETCH_ATTEMPTS
+        );
+        assert_eq!(
+            attempts(Args {
+                fetch_attempts: Some("5".to_string()),
+                ..base
+            }),
+            5
+        );
+    }
+
+    #[test]
+    fn test_retry_policy_from_args() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            git_retries: Some("0".to_string()),
+            git_retry_delay: Some("50".to_string()),
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(
+            config.diff_options.retry,
+            RetryPolicy {
+                retries: 0,
+                delay: Duration::from_millis(50),
+            }
+        );
+    }
+
+    #[test]
+    fn test_git_dir_from_flag_and_env() {
+        unsafe {
+            env::set_var("GIT_DIR", "/");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(config.diff_options.git_dir, Some(PathBuf::from("/")));
+
+        let config = from_args(Args {
+            git_dir: Some(".".to_string()),
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.diff_options.git_dir, Some(PathBuf::from(".")));
+
+        let config = from_args(Args {
+            git_dir: Some(".".to_string()),
+            prefer_env: true,
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.diff_options.git_dir, Some(PathBuf::from("/")));
+
+        unsafe {
+            env::remove_var("GIT_DIR");
+        }
+        assert_eq!(from_args(args).unwrap().diff_options.git_dir, None);
+    }
+
+    #[test]
+    fn test_error_git_dir_missing() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["main".to_string()],
+            git_dir: Some("/nonexistent/repo.git".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            from_args(args),
+            Err("Git directory not found: /nonexistent/repo.git".to_string())
+        );
+    }
+
+    #[test]
+    fn test_base_ref_fallback_chain() {
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["origin/main, main".to_string(), "v1.0".to_string()],
+            ..Default::default()
+        };
+
+        let config = from_args(args).unwrap();
+        assert_eq!(config.base_refs, vec!["origin/main", "main", "v1.0"]);
+    }
+
+    #[test]
+    fn test_base_ref_fallback_chain_from_env_var() {
+        unsafe {
+            env::set_var("BASE_REF", "upstream/main,,main");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(args).unwrap();
+        assert_eq!(config.base_refs, vec!["upstream/main", "main"]);
+
+        unsafe {
+            env::remove_var("BASE_REF");
+        }
+    }
+
+    #[test]
+    fn test_github_event_path_from_env() {
+        unsafe {
+            env::set_var("GITHUB_EVENT_PATH", "/tmp/event.json");
+        }
+
+        let args = Args {
+            patterns: vec!["*.rs".to_string()],
+            base_refs: vec!["auto".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(args).unwrap();
+        assert_eq!(config.github_event_path.as_deref(), Some("/tmp/event.json"));
+        assert_eq!(config.base_refs, vec!["auto"]);
+
+        unsafe {
+            env::remove_var("GITHUB_EVENT_PATH");
+        }
+    }
+
+    #[test]
+    fn test_since_replaces_base_ref() {
+        let args = Args {
+            patterns: vec!["services/**".to_string()],
+            since: Some("24 hours ago".to_string()),
+            ..Default::default()
+        };
+        let config = from_args(args).unwrap();
+        assert_eq!(config.since.as_deref(), Some("24 hours ago"));
+        assert!(config.base_refs.is_empty());
+
+        let args = Args {
+            patterns: vec!["services/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            since: Some("24 hours ago".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args),
+            Err("--since cannot be combined with -b/--base-ref".to_string())
+        );
+    }
+
+    #[test]
+    fn test_first_parent_passed_to_diff_options() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["v1.0".to_string()],
+            first_parent: true,
+            ..Default::default()
+        };
+        assert!(from_args(args).unwrap().diff_options.first_parent);
+    }
+
+    #[test]
+    fn test_parse_repos() {
+        let root = std::env::temp_dir().join(format!("gdf_test_repos_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["meta", "libs/core.v2", "libs/other"] {
+            fs::create_dir_all(root.join(dir).join(".git")).unwrap();
+        }
+        let entry = |dir: &str| root.join(dir).to_string_lossy().into_owned();
+
+        let repos =
+            parse_repos(&[entry("meta"), format!("core={}", entry("libs/core.v2"))]).unwrap();
+        assert_eq!(
+            repos,
+            vec![
+                Repository {
+                    name: "meta".to_string(),
+                    git_dir: root.join("meta/.git"),
+                    work_tree: root.join("meta"),
+                },
+                Repository {
+                    name: "core".to_string(),
+                    git_dir: root.join("libs/core.v2/.git"),
+                    work_tree: root.join("libs/core.v2"),
+                },
+            ]
+        );
+        assert_eq!(
+            parse_repos(&[entry("libs/core.v2")]).unwrap()[0].name,
+            "core_v2"
+        );
+
+        assert_eq!(
+            parse_repos(&[entry("meta"), format!("meta={}", entry("libs/other"))]),
+            Err(
+                "Duplicate repository name 'meta'; name each one with --repo <name>=<path>"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            parse_repos(&[format!("a b={}", entry("meta"))]),
+            Err("Invalid repository name 'a b': use letters, digits, '-' and '_'".to_string())
+        );
+        assert!(parse_repos(&[entry("missing")]).is_err());
+        assert!(parse_repos(&[entry("libs")]).is_err());
+
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            repos: vec![entry("meta")],
+            relative: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--repo cannot be combined with --git-dir or --relative".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                relative: false,
+                cargo_workspace: true,
+                ..args
+            }),
+            Err("--repo cannot be combined with --cargo-workspace or --js-workspace".to_string())
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_include_dependents_requires_workspace_mode() {
+        let args = Args {
+            patterns: vec!["crates/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            include_dependents: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--include-dependents requires --cargo-workspace or --js-workspace".to_string())
+        );
+        let config = from_args(Args {
+            js_workspace: true,
+            ..args
+        })
+        .unwrap();
+        assert!(config.include_dependents);
+    }
+
+    #[test]
+    fn test_watch_source() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            watch: true,
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert!(config.watch);
+        assert_eq!(config.diff_options.source, DiffSource::Combined);
+        let config = from_args(Args {
+            source: Some("committed".to_string()),
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.diff_options.source, DiffSource::Committed);
+        assert_eq!(
+            from_args(Args {
+                last: Some("3".to_string()),
+                ..args.clone()
+            }),
+            Err("--watch cannot be combined with --last".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                dotenv: Some("build.env".to_string()),
+                github_output: vec![("src".to_string(), 0)],
+                ..args.clone()
+            }),
+            Err("--watch cannot be combined with --dotenv".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                env_file: Some("changes.sh".to_string()),
+                github_output: vec![("src".to_string(), 0)],
+                ..args.clone()
+            }),
+            Err("--watch cannot be combined with --env-file".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                properties_file: Some("gdf.properties".to_string()),
+                github_output: vec![("src".to_string(), 0)],
+                ..args.clone()
+            }),
+            Err("--watch cannot be combined with --properties-file".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                step_summary: true,
+                ..args
+            }),
+            Err("--watch cannot be combined with --step-summary".to_string())
+        );
+    }
+
+    #[test]
+    fn test_serve_options() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            serve: true,
+            ..Default::default()
+        };
+        // Requests may give the base ref
+        let config = from_args(args.clone()).unwrap();
+        assert!(config.serve);
+        assert!(config.base_refs.is_empty());
+        assert_eq!(
+            from_args(Args {
+                watch: true,
+                ..args.clone()
+            }),
+            Err("--serve cannot be combined with --watch".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                serve: false,
+                socket: Some("gdf.sock".to_string()),
+                ..args
+            }),
+            Err("--socket requires --serve".to_string())
+        );
+    }
+
+    #[test]
+    fn test_github_api_options() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            github_api: true,
+            ..Default::default()
+        };
+        // The pull request's commits replace the base ref
+        let config = from_args(args.clone()).unwrap();
+        assert!(config.github_api);
+        assert_eq!(
+            from_args(Args {
+                last: Some("3".to_string()),
+                ..args.clone()
+            }),
+            Err("--github-api cannot be combined with --last".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                source: Some("combined".to_string()),
+                ..args
+            }),
+            Err("--github-api cannot be combined with --source".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cache_options() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert!(config.cache);
+        assert_eq!(config.cache_dir, None);
+        let config = from_args(Args {
+            no_cache: true,
+            cache_dir: Some(".cache/gdf".to_string()),
+            ..args
+        })
+        .unwrap();
+        assert!(!config.cache);
+        assert_eq!(config.cache_dir.as_deref(), Some(".cache/gdf"));
+    }
+
+    #[test]
+    fn test_generated_attribute() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(from_args(args.clone()).unwrap().generated_attribute, None);
+        let exclude = Args {
+            exclude_generated: true,
+            ..args.clone()
+        };
+        assert_eq!(
+            from_args(exclude.clone())
+                .unwrap()
+                .generated_attribute
+                .as_deref(),
+            Some("linguist-generated")
+        );
+        let config = from_args(Args {
+            generated_attribute: Some("generated".to_string()),
+            ..exclude.clone()
+        })
+        .unwrap();
+        assert_eq!(config.generated_attribute.as_deref(), Some("generated"));
+        assert_eq!(
+            from_args(Args {
+                generated_attribute: Some("generated".to_string()),
+                ..args
+            }),
+            Err("--generated-attribute requires --exclude-generated".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                generated_attribute: Some("-generated".to_string()),
+                ..exclude.clone()
+            }),
+            Err(
+                "Invalid --generated-attribute '-generated': expected an attribute name"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            from_args(Args {
+                github_api: true,
+                base_refs: Vec::new(),
+                ..exclude
+            }),
+            Err("--github-api cannot be combined with --exclude-generated".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let args = Args {
+            patterns: vec!["api/**".to_string(), "docs/**".to_string()],
+            github_output: vec![("api".to_string(), 0), ("docs".to_string(), 1)],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        let with = |expressions: &[&str]| {
+            from_args(Args {
+                expressions: expressions.iter().map(|s| (*s).to_string()).collect(),
+                ..args.clone()
+            })
+        };
+        let config = with(&["deploy=api && !docs"]).unwrap();
+        assert_eq!(config.expressions.len(), 1);
+        assert_eq!(config.expressions[0].0, "deploy");
+        assert_eq!(config.expressions[0].1.to_string(), "api && !docs");
+        assert_eq!(
+            with(&["api || docs"]).unwrap_err(),
+            "Invalid --expr 'api || docs': expected <name>=<expression>"
+        );
+        assert_eq!(
+            with(&["api=docs"]).unwrap_err(),
+            "--expr api reuses the name of an output"
+        );
+        assert_eq!(
+            with(&["x=api", "x=docs"]).unwrap_err(),
+            "--expr x reuses the name of an output"
+        );
+        assert_eq!(
+            with(&["deploy=api && web"]).unwrap_err(),
+            "Unknown filter 'web' in --expr deploy (expected a -g name)"
+        );
+        assert_eq!(
+            with(&["deploy=api &&"]).unwrap_err(),
+            "Invalid expression 'api &&': expected a filter name"
+        );
+        assert!(with(&["1st=api"])
+            .unwrap_err()
+            .starts_with("Invalid output name '1st'"));
+    }
+
+    #[test]
+    fn test_each_base() {
+        let args = Args {
+            patterns: vec!["api/**".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            ..Default::default()
+        };
+        let with = |bases: &[&str]| {
+            from_args(Args {
+                bases: bases.iter().map(|s| (*s).to_string()).collect(),
+                ..args.clone()
+            })
+        };
+        // No -b or BASE_REF is needed, as each base brings its own refs
+        let config = with(&[
+            "main",
+            "origin/release/1.x",
+            "lts=release/0.9,origin/release/0.9",
+        ])
+        .unwrap();
+        let bases: Vec<(&str, &str, Vec<String>)> = config
+            .bases
+            .iter()
+            .map(|base| (base.name.as_str(), base.label.as_str(), base.refs.clone()))
+            .collect();
+        assert_eq!(
+            bases,
+            [
+                ("main", "vs_main", vec!["main".to_string()]),
+                (
+                    "release_1_x",
+                    "vs_release_1_x",
+                    vec!["origin/release/1.x".to_string()]
+                ),
+                (
+                    "lts",
+                    "vs_lts",
+                    vec!["release/0.9".to_string(), "origin/release/0.9".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(
+            with(&["main", "origin/main"]).unwrap_err(),
+            "Duplicate base name 'main'; name each one with --each-base <name>=<ref>"
+        );
+        assert_eq!(
+            with(&["lts="]).unwrap_err(),
+            "Invalid --each-base 'lts=': expected [<name>=]<ref>"
+        );
+        assert_eq!(
+            with(&["v1.x=release/1.x"]).unwrap_err(),
+            "Invalid base name 'v1.x': use letters, digits, '-' and '_'"
+        );
+        assert_eq!(
+            from_args(Args {
+                bases: vec!["main".to_string()],
+                base_refs: vec!["develop".to_string()],
+                ..args.clone()
+            })
+            .unwrap_err(),
+            "--each-base cannot be combined with -b/--base-ref"
+        );
+        assert_eq!(
+            from_args(Args {
+                bases: vec!["main".to_string()],
+                last: Some("5".to_string()),
+                ..args
+            })
+            .unwrap_err(),
+            "--each-base cannot be combined with --last"
+        );
+    }
+
+    #[test]
+    fn test_ci_base_refs() {
+        unsafe {
+            env::set_var("GITHUB_BASE_REF", "release/2.x");
+        }
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            ci: Some("github".to_string()),
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(config.base_refs, ["origin/release/2.x", "release/2.x"]);
+        assert_eq!(config.ci, Some(Provider::GitHubActions));
+
+        // -b wins over the CI system
+        let config = from_args(Args {
+            base_refs: vec!["main".to_string()],
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.base_refs, ["main"]);
+        assert!(from_args(Args {
+            ci: Some("none".to_string()),
+            ..args.clone()
+        })
+        .is_err());
+        assert!(from_args(Args {
+            ci: Some("travis".to_string()),
+            ..args
+        })
+        .unwrap_err()
+        .starts_with("Invalid CI system: travis"));
+        unsafe {
+            env::remove_var("GITHUB_BASE_REF");
+        }
+    }
+
+    #[test]
+    fn test_ci_output_file() {
+        let args = Args {
+            patterns: vec!["api/**".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            base_refs: vec!["main".to_string()],
+            ci: Some("gitlab".to_string()),
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(config.dotenv.as_deref(), Some("gdf.env"));
+        assert_eq!(config.properties_file, None);
+
+        let config = from_args(Args {
+            ci: Some("jenkins".to_string()),
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.properties_file.as_deref(), Some("gdf.properties"));
+        assert_eq!(config.dotenv, None);
+
+        // A given output file, unnamed outputs or another system leave the files alone
+        for args in [
+            Args {
+                dotenv: Some("build.env".to_string()),
+                ..args.clone()
+            },
+            Args {
+                env_file: Some("changes.sh".to_string()),
+                ..args.clone()
+            },
+            Args {
+                github_output: Vec::new(),
+                ..args.clone()
+            },
+            Args {
+                ci: Some("github".to_string()),
+                ..args.clone()
+            },
+            Args {
+                ci: Some("none".to_string()),
+                ..args
+            },
+        ] {
+            let given = args.dotenv.clone();
+            let config = from_args(args).unwrap();
+            assert_eq!(config.dotenv, given);
+            assert_eq!(config.properties_file, None);
+        }
+    }
+
+    #[test]
+    fn test_targets() {
+        let args = Args {
+            patterns: vec!["**".to_string()],
+            base_refs: vec!["main".to_string()],
+            targets: vec!["//web:app=web/**".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(config.targets.len(), 1);
+        assert_eq!(config.targets[0].0, "//web:app");
+        assert_eq!(
+            from_args(Args {
+                targets: vec!["web/**".to_string()],
+                ..args.clone()
+            }),
+            Err("Invalid --target 'web/**': expected <target>=<glob>".to_string())
+        );
+
+        let document = toml::parse(
+            "[targets]\n\"//web:app\" = [\"web/**\", \"!web/**/*.md\"]\n\"//docs\" = \"docs/**\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            args_from_document(&document).unwrap(),
+            [
+                ["--target", "//web:app=web/**"].as_slice(),
+                &["--target", "//web:app=!web/**/*.md"],
+                &["--target", "//docs=docs/**"],
+            ]
+        );
+        let error = |text: &str| args_from_document(&toml::parse(text).unwrap()).unwrap_err();
+        assert_eq!(
+            error("targets = [\"web/**\"]"),
+            "targets must map target names to globs"
+        );
+        assert_eq!(
+            error("[targets]\n\"//web\" = 1"),
+            "targets.//web must be a string or an array of strings"
+        );
+        assert_eq!(
+            error("target = \"//web=web/**\""),
+            "target cannot be set in a config file; use a [targets] table"
+        );
+    }
+
+    #[test]
+    fn test_test_map() {
+        let args = Args {
+            patterns: vec!["**".to_string()],
+            base_refs: vec!["main".to_string()],
+            test_map: vec!["src/auth/**=tests/auth/**".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(config.test_map.len(), 1);
+        assert_eq!(
+            from_args(Args {
+                test_map: vec!["src/auth/**".to_string()],
+                ..args.clone()
+            }),
+            Err("Invalid --test-map 'src/auth/**': expected <source-glob>=<test-glob>".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                repos: vec![".".to_string()],
+                ..args
+            }),
+            Err("--repo cannot be combined with --test-map".to_string())
+        );
+
+        let document = toml::parse(
+            "[tests]\n\"src/auth/**\" = [\"tests/auth/**\", \"tests/integration/login*\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            args_from_document(&document).unwrap(),
+            [
+                ["--test-map", "src/auth/**=tests/auth/**"].as_slice(),
+                &["--test-map", "src/auth/**=tests/integration/login*"],
+            ]
+        );
+        let error = |text: &str| args_from_document(&toml::parse(text).unwrap()).unwrap_err();
+        assert_eq!(
+            error("tests = [\"tests/**\"]"),
+            "tests must map source globs to test globs"
+        );
+        assert_eq!(
+            error("test-map = \"src/**=tests/**\""),
+            "test-map cannot be set in a config file; use a [tests] table"
+        );
+    }
+
+    #[test]
+    fn test_jobs() {
+        let args = Args {
+            patterns: vec!["api/**".to_string(), "docs/**".to_string()],
+            github_output: vec![("api".to_string(), 0), ("docs".to_string(), 1)],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        let with = |jobs: &[&str]| {
+            from_args(Args {
+                jobs: jobs.iter().map(|s| (*s).to_string()).collect(),
+                ..args.clone()
+            })
+        };
+        let config = with(&["unit-tests=api", "deploy=api && !docs", "unit-tests=docs"]).unwrap();
+        let jobs: Vec<(&str, Vec<String>)> = config
+            .jobs
+            .iter()
+            .map(|(job, conditions)| {
+                let conditions = conditions.iter().map(ToString::to_string).collect();
+                (job.as_str(), conditions)
+            })
+            .collect();
+        assert_eq!(
+            jobs,
+            [
+                ("unit-tests", vec!["api".to_string(), "docs".to_string()]),
+                ("deploy", vec!["api && !docs".to_string()]),
+            ]
+        );
+        assert_eq!(
+            with(&["api"]).unwrap_err(),
+            "Invalid --job 'api': expected <job>=<expression>"
+        );
+        assert_eq!(
+            with(&["=api"]).unwrap_err(),
+            "Invalid --job '=api': expected <job>=<expression>"
+        );
+        assert_eq!(
+            with(&["lint=web"]).unwrap_err(),
+            "Unknown filter 'web' in --job lint (expected a -g name)"
+        );
+        assert_eq!(
+            from_args(Args {
+                jobs: vec!["lint=api".to_string()],
+                expressions: vec!["jobs=api".to_string()],
+                ..args.clone()
+            })
+            .unwrap_err(),
+            "--job writes a jobs output, which cannot also name a filter or --expr"
+        );
+        assert_eq!(
+            from_args(Args {
+                patterns: vec!["**".to_string()],
+                base_refs: vec!["main".to_string()],
+                jobs: vec!["lint=api".to_string()],
+                ..Default::default()
+            }),
+            Err("--job requires -g/--github-output to name the filters".to_string())
+        );
+
+        let document =
+            toml::parse("[jobs]\nlint = \"api\"\ne2e = [\"api\", \"docs && !api\"]\n").unwrap();
+        assert_eq!(
+            args_from_document(&document).unwrap(),
+            [
+                ["--job", "lint=api"].as_slice(),
+                &["--job", "e2e=api"],
+                &["--job", "e2e=docs && !api"],
+            ]
+        );
+        let error = |text: &str| args_from_document(&toml::parse(text).unwrap()).unwrap_err();
+        assert_eq!(
+            error("jobs = [\"lint\"]"),
+            "jobs must map job names to filter expressions"
+        );
+        assert_eq!(
+            error("job = \"lint=api\""),
+            "job cannot be set in a config file; use a [jobs] table"
+        );
+    }
+
+    #[test]
+    fn test_presets() {
+        let args = Args {
+            base_refs: vec!["main".to_string()],
+            presets: vec!["docs-only".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(config.groups.len(), 1);
+        assert_eq!(config.groups[0].name.as_deref(), Some("docs_only"));
+        assert_eq!(config.groups[0].min_fraction, Some(1.0));
+
+        let config = from_args(Args {
+            patterns: vec!["api/**".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            status: Some("modified".to_string()),
+            ..args.clone()
+        })
+        .unwrap();
+        let names: Vec<_> = config.groups.iter().map(|g| g.name.as_deref()).collect();
+        assert_eq!(names, [Some("api"), Some("docs_only")]);
+        assert_eq!(config.groups[0].min_fraction, None);
+        assert_eq!(config.groups[1].statuses, [ChangeKind::Modified]);
+
+        assert_eq!(
+            from_args(Args {
+                patterns: vec!["api/**".to_string()],
+                ..args.clone()
+            }),
+            Err(
+                "--preset requires -g/--github-output to name the filter of the -p patterns"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            from_args(Args {
+                patterns: vec!["**/*.md".to_string()],
+                github_output: vec![("docs_only".to_string(), 0)],
+                ..args.clone()
+            }),
+            Err("Duplicate output name 'docs_only'".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                presets: vec!["docs".to_string()],
+                ..args
+            }),
+            Err("Invalid preset: docs (expected docs-only)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_min_fraction() {
+        let args = Args {
+            patterns: vec!["web/**".to_string(), "api/**".to_string()],
+            github_output: vec![("frontend".to_string(), 0), ("backend".to_string(), 1)],
+            base_refs: vec!["main".to_string()],
+            min_fraction: Some("0.5".to_string()),
+            presets: vec!["docs-only".to_string()],
+            ..Default::default()
+        };
+        let config = from_args(Args {
+            group_min_fraction: vec![(1, "1".to_string())],
+            ..args.clone()
+        })
+        .unwrap();
+        let fractions: Vec<Option<f64>> = config
+            .groups
+            .iter()
+            .map(|group| group.min_fraction)
+            .collect();
+        // The preset keeps its own fraction
+        assert_eq!(fractions, [Some(0.5), Some(1.0), Some(1.0)]);
+
+        for fraction in ["0", "1.5", "-0.5", "half"] {
+            assert_eq!(
+                from_args(Args {
+                    min_fraction: Some(fraction.to_string()),
+                    ..args.clone()
+                })
+                .unwrap_err(),
+                format!(
+                    "Invalid --min-fraction: {fraction} (expected a number above 0 and at most 1)"
+                )
+            );
+        }
+        assert_eq!(
+            from_args(Args {
+                group_min_fraction: vec![(0, "50%".to_string())],
+                ..args
+            })
+            .unwrap_err(),
+            "Invalid --group-min-fraction: 50% (expected a number above 0 and at most 1)"
+        );
+
+        let document =
+            toml::parse("[filters.frontend]\npatterns = \"web/**\"\nmin-fraction = 0.5\n").unwrap();
+        assert_eq!(
+            args_from_document(&document).unwrap(),
+            [
+                ["--github-output", "frontend"].as_slice(),
+                &["--pattern", "web/**"],
+                &["--group-min-fraction", "0.5"],
+            ]
+        );
+        assert_eq!(
+            args_from_document(
+                &toml::parse("[filters.frontend]\nmin-fraction = \"half\"\n").unwrap()
+            )
+            .unwrap_err(),
+            "filters.frontend.min-fraction must be a number"
+        );
+    }
+
+    #[test]
+    fn test_required() {
+        let args = Args {
+            patterns: vec!["db/schema.sql".to_string(), "db/migrations/**".to_string()],
+            github_output: vec![("schema".to_string(), 0), ("migrations".to_string(), 1)],
+            base_refs: vec!["main".to_string()],
+            ..Default::default()
+        };
+        let with = |names: &[&str]| {
+            from_args(Args {
+                required: names.iter().map(ToString::to_string).collect(),
+                ..args.clone()
+            })
+        };
+        assert_eq!(
+            with(&["schema", "migrations", "schema"]).unwrap().required,
+            ["schema", "migrations"]
+        );
+        assert!(with(&[]).unwrap().required.is_empty());
+        assert_eq!(
+            with(&["schema", "docs"]).unwrap_err(),
+            "Unknown filter 'docs' in --require (expected a -g name)"
+        );
+        assert_eq!(
+            from_args(Args {
+                patterns: vec!["db/**".to_string()],
+                base_refs: vec!["main".to_string()],
+                required: vec!["db".to_string()],
+                ..Default::default()
+            }),
+            Err("--require requires -g/--github-output to name the filters".to_string())
+        );
+    }
+
+    #[test]
+    fn test_enforce() {
+        let args = Args {
+            patterns: vec!["hotfix/**".to_string()],
+            enforce: true,
+            ..Default::default()
+        };
+        let config = from_args(Args {
+            base_refs: vec!["main".to_string()],
+            ..args.clone()
+        })
+        .unwrap();
+        assert!(config.enforce);
+        assert_eq!(
+            from_args(Args {
+                last: Some("5".to_string()),
+                ..args.clone()
+            }),
+            Err("--enforce cannot be combined with --last".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                serve: true,
+                ..args
+            }),
+            Err("--enforce cannot be combined with --serve".to_string())
+        );
+    }
+
+    #[test]
+    fn test_last_replaces_base_ref() {
+        let args = |last: &str| Args {
+            patterns: vec!["CHANGELOG.md".to_string()],
+            last: Some(last.to_string()),
+            ..Default::default()
+        };
+        let config = from_args(args("10")).unwrap();
+        assert_eq!(config.last, Some(10));
+        assert!(config.base_refs.is_empty());
+
+        assert_eq!(
+            from_args(args("0")),
+            Err("--last must be at least 1".to_string())
+        );
+        assert!(from_args(args("ten")).is_err());
+        assert_eq!(
+            from_args(Args {
+                base_refs: vec!["main".to_string()],
+                ..args("10")
+            }),
+            Err("--last cannot be combined with -b/--base-ref".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                since: Some("yesterday".to_string()),
+                ..args("10")
+            }),
+            Err("--since cannot be combined with --last".to_string())
+        );
+        assert_eq!(
+            from_args(Args {
+                source: Some("combined".to_string()),
+                ..args("10")
+            }),
+            Err(
+                "--last inspects commits and cannot be combined with --source combined".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_list_files_requires_output_name() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            list_files: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--list-files requires -g/--github-output".to_string())
+        );
+        let config = from_args(Args {
+            github_output: vec![("api".to_string(), 0)],
+            ..args
+        })
+        .unwrap();
+        assert!(config.list_files);
+    }
+
+    #[test]
+    fn test_step_summary_file_from_env() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            step_summary: true,
+            ..Default::default()
+        };
+        unsafe {
+            env::remove_var("GITHUB_STEP_SUMMARY");
+        }
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--step-summary requires the GITHUB_STEP_SUMMARY environment variable".to_string())
+        );
+
+        unsafe {
+            env::set_var("GITHUB_STEP_SUMMARY", "/tmp/step_summary.md");
+        }
+        let config = from_args(args.clone()).unwrap();
+        assert_eq!(
+            config.step_summary_filepath,
+            Some("/tmp/step_summary.md".to_string())
+        );
+        let config = from_args(Args {
+            step_summary: false,
+            ..args
+        })
+        .unwrap();
+        assert_eq!(config.step_summary_filepath, None);
+
+        unsafe {
+            env::remove_var("GITHUB_STEP_SUMMARY");
+        }
+    }
+
+    #[test]
+    fn test_dotenv_requires_output_name() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            dotenv: Some("build.env".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--dotenv requires -g/--github-output to name the variables".to_string())
+        );
+        let config = from_args(Args {
+            github_output: vec![("api".to_string(), 0)],
+            ..args
+        })
+        .unwrap();
+        assert_eq!(config.dotenv, Some("build.env".to_string()));
+    }
+
+    #[test]
+    fn test_teamcity_requires_output_name() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            teamcity: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--teamcity requires -g/--github-output to name the parameters".to_string())
+        );
+        let config = from_args(Args {
+            github_output: vec![("api".to_string(), 0)],
+            ..args
+        })
+        .unwrap();
+        assert!(config.teamcity);
+    }
 
     #[test]
-    fn test_base_ref_from_cli_flag() {
+    fn test_properties_file_requires_output_name() {
         let args = Args {
-            patterns: vec!["*.txt".to_string()],
-            base_ref: Some("main".to_string()),
-            github_output: None,
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            properties_file: Some("gdf.properties".to_string()),
+            ..Default::default()
         };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--properties-file requires -g/--github-output to name the properties".to_string())
+        );
+        let config = from_args(Args {
+            github_output: vec![("api".to_string(), 0)],
+            ..args
+        })
+        .unwrap();
+        assert_eq!(config.properties_file, Some("gdf.properties".to_string()));
+    }
 
-        let config = from_args(args).unwrap();
-        assert_eq!(config.base_ref, "main");
-        assert_eq!(config.patterns, vec!["*.txt".to_string()]);
-        assert_eq!(config.github_output_name, None);
+    #[test]
+    fn test_circleci_parameters_requires_output_name() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            circleci_parameters: Some("parameters.json".to_string()),
+            ..Default::default()
+        };
+        assert!(from_args(args.clone()).is_err());
+        let config = from_args(Args {
+            github_output: vec![("api".to_string(), 0)],
+            ..args
+        })
+        .unwrap();
+        assert_eq!(
+            config.circleci_parameters,
+            Some("parameters.json".to_string())
+        );
     }
 
     #[test]
-    fn test_base_ref_from_env_var() {
-        unsafe {
-            env::set_var("BASE_REF", "develop");
-        }
+    fn test_env_file_requires_output_name() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            env_file: Some("changes.sh".to_string()),
+            bitbucket_report: Some("report.json".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--env-file requires -g/--github-output to name the variables".to_string())
+        );
+        let config = from_args(Args {
+            env_file: None,
+            ..args
+        })
+        .unwrap();
+        assert_eq!(config.bitbucket_report, Some("report.json".to_string()));
+    }
 
+    #[test]
+    fn test_matrix_output_requires_output_name() {
         let args = Args {
-            patterns: vec!["*.rs".to_string()],
-            base_ref: None,
-            github_output: None,
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            matrix_output: true,
+            ..Default::default()
         };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--matrix-output requires -g/--github-output".to_string())
+        );
+        let config = from_args(Args {
+            github_output: vec![("api".to_string(), 0)],
+            ..args
+        })
+        .unwrap();
+        assert!(config.matrix_output);
+    }
 
-        let config = from_args(args).unwrap();
-        assert_eq!(config.base_ref, "develop");
+    #[test]
+    fn test_files_output_nul_requires_files_output() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            files_output_nul: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err("--files-output-nul requires --files-output".to_string())
+        );
+        let config = from_args(Args {
+            files_output: Some("changed.txt".to_string()),
+            ..args
+        })
+        .unwrap();
+        assert_eq!(config.files_output, Some("changed.txt".to_string()));
+        assert!(config.files_output_nul);
+    }
 
-        unsafe {
-            env::remove_var("BASE_REF");
-        }
+    #[test]
+    fn test_list_delimiter() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            list_delimiter: Some("space".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            from_args(args.clone()),
+            Err(
+                "--list-delimiter requires --list-files, --properties-file, --env-file, --format, a workspace mode, --target, --test-map or --job"
+                    .to_string()
+            )
+        );
+        let config = from_args(Args {
+            list_files: true,
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(config.list_delimiter, Some(ListDelimiter::Space));
+        assert_eq!(
+            from_args(Args {
+                list_files: true,
+                list_delimiter: Some("tab".to_string()),
+                ..args
+            }),
+            Err("Invalid list delimiter: tab (expected space, comma or json)".to_string())
+        );
     }
 
     #[test]
-    fn test_cli_flag_overrides_env_var() {
-        unsafe {
-            env::set_var("BASE_REF", "develop");
-        }
+    fn test_color() {
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            color: Some("always".to_string()),
+            ..Default::default()
+        };
+        assert!(from_args(args.clone()).unwrap().color);
+        let named = Args {
+            github_output: vec![("api".to_string(), 0)],
+            ..args.clone()
+        };
+        assert!(!from_args(named).unwrap().color);
+        let never = Args {
+            color: Some("never".to_string()),
+            ..args.clone()
+        };
+        assert!(!from_args(never).unwrap().color);
+        assert_eq!(
+            from_args(Args {
+                color: Some("yes".to_string()),
+                ..args
+            }),
+            Err("Invalid color mode: yes (expected auto, always or never)".to_string())
+        );
+    }
 
+    #[test]
+    fn test_jsonl() {
         let args = Args {
-            patterns: vec!["*.rs".to_string()],
-            base_ref: Some("main".to_string()),
-            github_output: None,
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            color: Some("always".to_string()),
+            jsonl: true,
+            ..Default::default()
         };
+        let config = from_args(args.clone()).unwrap();
+        assert!(config.jsonl);
+        assert!(!config.color);
+        assert_eq!(
+            from_args(Args {
+                format: Some("{name}".to_string()),
+                ..args
+            }),
+            Err("--jsonl cannot be combined with --format".to_string())
+        );
+    }
 
-        let config = from_args(args).unwrap();
-        assert_eq!(config.base_ref, "main"); // CLI flag wins
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(
+            "match=0, nomatch=78".parse(),
+            Ok(ExitCodes {
+                on_match: 0,
+                no_match: 78,
+                error: 1,
+            })
+        );
+        let codes: ExitCodes = "error=2,match=10".parse().unwrap();
+        assert_eq!(codes.for_result(true), 10);
+        assert_eq!(codes.for_result(false), 0);
+        assert_eq!(codes.error, 2);
+        assert_eq!(
+            "nomatch=256".parse::<ExitCodes>(),
+            Err("Invalid exit code mapping: nomatch=256 (expected match=<code>, nomatch=<code> or error=<code>, with codes from 0 to 255)".to_string())
+        );
+        assert!("skipped=3".parse::<ExitCodes>().is_err());
+        assert!("match".parse::<ExitCodes>().is_err());
 
-        unsafe {
-            env::remove_var("BASE_REF");
-        }
+        let config = from_args(Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            exit_codes: Some("nomatch=78".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(config.exit_codes.for_result(false), 78);
     }
 
     #[test]
-    fn test_error_when_base_ref_missing() {
-        unsafe {
-            env::remove_var("BASE_REF");
-        }
-
-        let args = Args {
-            patterns: vec!["*.rs".to_string()],
-            base_ref: None,
-            github_output: None,
+    fn test_filter_groups() {
+        let patterns =
+            |list: &[&str]| -> Vec<String> { list.iter().map(ToString::to_string).collect() };
+        let group = |name: &str, list: &[&str]| FilterGroup {
+            name: Some(name.to_string()),
+            patterns: patterns(list),
+            ..FilterGroup::default()
         };
 
-        let result = from_args(args);
+        // A lone name takes every pattern, before or after it
         assert_eq!(
-            result,
+            filter_groups(
+                patterns(&["a/**", "b/**"]),
+                vec![("api".to_string(), 1)],
+                &[]
+            ),
+            Ok(vec![group("api", &["a/**", "b/**"])])
+        );
+        assert_eq!(
+            filter_groups(patterns(&["a/**"]), Vec::new(), &[]),
+            Ok(vec![FilterGroup {
+                name: None,
+                patterns: patterns(&["a/**"]),
+                ..FilterGroup::default()
+            }])
+        );
+
+        // Patterns before the first name are shared
+        let all = patterns(&["!**/*.md", "docs/**", "src/**", "lib/**"]);
+        assert_eq!(
+            filter_groups(
+                all.clone(),
+                vec![("docs".to_string(), 1), ("code".to_string(), 2)],
+                &[]
+            ),
+            Ok(vec![
+                group("docs", &["!**/*.md", "docs/**"]),
+                group("code", &["!**/*.md", "src/**", "lib/**"]),
+            ])
+        );
+
+        assert_eq!(
+            filter_groups(
+                all.clone(),
+                vec![("docs".to_string(), 1), ("code".to_string(), 1)],
+                &[]
+            ),
+            Err("-g docs has no patterns; give them after it with -p".to_string())
+        );
+        assert_eq!(
+            filter_groups(
+                all.clone(),
+                vec![("api".to_string(), 0), ("api".to_string(), 2)],
+                &[]
+            ),
+            Err("Duplicate output name 'api'".to_string())
+        );
+        assert!(filter_groups(all.clone(), vec![("api=true".to_string(), 0)], &[]).is_err());
+
+        // Composed groups take no patterns, not even the shared ones
+        let names = vec![
+            ("docs".to_string(), 1),
+            ("code".to_string(), 2),
+            ("both".to_string(), 4),
+        ];
+        let compose = [(2, "docs + code".to_string())];
+        assert_eq!(
+            filter_groups(all.clone(), names.clone(), &compose),
+            Ok(vec![
+                group("docs", &["!**/*.md", "docs/**"]),
+                group("code", &["!**/*.md", "src/**", "lib/**"]),
+                group("both", &[]),
+            ])
+        );
+        assert_eq!(
+            filter_groups(all, names, &[(1, "docs".to_string())]),
             Err(
-                "BASE_REF must be provided via -b/--base-ref flag or BASE_REF environment variable"
+                "-g code combines other filters with --group-compose, so it takes no patterns"
                     .to_string()
             )
         );
     }
 
     #[test]
-    fn test_error_when_base_ref_empty() {
-        unsafe {
-            env::set_var("BASE_REF", "");
-        }
+    fn test_args_from_document() {
+        let document = toml::parse(
+            r#"
+patterns = ["!**/*.md"]
+base-ref = ["main", "master"]
+find-copies = true
+relative = false
+git-retries = 2
 
-        let args = Args {
-            patterns: vec!["*.rs".to_string()],
-            base_ref: None,
-            github_output: None,
-        };
+[filters.api]
+patterns = ["services/api/**"]
 
-        let result = from_args(args);
-        assert!(result.is_err());
+[filters.web]
+patterns = "web/**"
+"#,
+        )
+        .unwrap();
+        let entries = args_from_document(&document).unwrap();
+        assert_eq!(
+            entries,
+            [
+                ["--pattern", "!**/*.md"].as_slice(),
+                &["--base-ref", "main"],
+                &["--base-ref", "master"],
+                &["--find-copies"],
+                &["--git-retries", "2"],
+                &["--github-output", "api"],
+                &["--pattern", "services/api/**"],
+                &["--github-output", "web"],
+                &["--pattern", "web/**"],
+            ]
+        );
 
-        unsafe {
-            env::remove_var("BASE_REF");
-        }
+        let error = |text: &str| args_from_document(&toml::parse(text).unwrap()).unwrap_err();
+        assert_eq!(
+            error("pattern = \"src/**\""),
+            "pattern cannot be set in a config file; use patterns and [filters.<name>] tables"
+        );
+        assert_eq!(
+            error("[filters.api]\nbase = \"main\""),
+            "Unknown key filters.api.base: did you mean base-ref?"
+        );
+        assert_eq!(
+            error("[filters.api]\nowner = \"web\""),
+            "Unknown key filters.api.owner (expected patterns, output, ignore-case, status, base-ref, branches, compose, default-excludes, min-fraction)"
+        );
+        assert_eq!(
+            error("patterns = [1]"),
+            "patterns must be a string or an array of strings"
+        );
+        assert_eq!(
+            error("owner = { team = \"web\" }"),
+            "owner must be a string, number, boolean or array"
+        );
+        assert_eq!(
+            error("config = \"other.toml\""),
+            "config cannot be set in a config file"
+        );
+
+        // Each filter may set its own output name, case sensitivity and change kinds
+        let document = yaml::parse(
+            "
+filters:
+  infra:
+    output: infra_changed
+    patterns:
+      - terraform/**
+    ignore-case: true
+    status: deleted
+    base-ref: [v2.0, v1.9]
+  docs:
+    patterns: docs/**
+    ignore-case: false
+",
+        )
+        .unwrap();
+        assert_eq!(
+            args_from_document(&document).unwrap(),
+            [
+                ["--github-output", "infra_changed"].as_slice(),
+                &["--pattern", "terraform/**"],
+                &["--group-ignore-case"],
+                &["--group-status", "deleted"],
+                &["--group-base-ref", "v2.0"],
+                &["--group-base-ref", "v1.9"],
+                &["--github-output", "docs"],
+                &["--pattern", "docs/**"],
+            ]
+        );
+        assert_eq!(
+            error("[filters.api]\nignore-case = \"yes\""),
+            "filters.api.ignore-case must be a boolean"
+        );
+        assert_eq!(
+            error("[filters.api]\nstatus = [1]"),
+            "filters.api.status must be a string or an array of strings"
+        );
     }
 
     #[test]
-    fn test_github_output_name_passed_through() {
-        let args = Args {
-            patterns: vec!["*.rs".to_string()],
-            base_ref: Some("main".to_string()),
-            github_output: Some("api".to_string()),
+    fn test_file_args() {
+        let root = std::env::temp_dir().join(format!("gdf_test_config_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("repo/.git")).unwrap();
+        fs::create_dir_all(root.join("repo/services/api")).unwrap();
+        let path = root.join("repo").join(CONFIG_FILES[0]);
+        fs::write(&path, "base-ref = \"main\"\npatterns = [\"src/**\"]\n").unwrap();
+
+        // Found from a subdirectory, but not past the repository root
+        assert_eq!(
+            discover_config(&root.join("repo/services/api")),
+            Some(path.clone())
+        );
+        fs::write(root.join(CONFIG_FILES[0]), "").unwrap();
+        fs::create_dir_all(root.join("other/.git")).unwrap();
+        assert_eq!(discover_config(&root.join("other")), None);
+
+        let entries = file_args(path.to_str(), false, None).unwrap();
+        assert_eq!(entries, [["--pattern", "src/**"], ["--base-ref", "main"]]);
+        assert_eq!(file_args(None, true, None), Ok(Vec::new()));
+
+        fs::write(&path, "base-ref = \"main\"\nunknown-option = true\n").unwrap();
+        assert_eq!(
+            file_args(path.to_str(), false, None),
+            Err(format!(
+                "{}: line 2, column 1: Unknown key unknown-option (expected a long flag without its dashes, patterns, filters, aliases, targets, tests, jobs, profiles or extends)",
+                path.display()
+            ))
+        );
+        fs::write(
+            &path,
+            "[filters.api]\npatterns = [\"api/**\"]\n  stauts = \"added\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            file_args(path.to_str(), false, None),
+            Err(format!(
+                "{}: line 3, column 3: Unknown key filters.api.stauts: did you mean status?",
+                path.display()
+            ))
+        );
+        fs::write(&path, "base-ref = main\n").unwrap();
+        assert_eq!(
+            file_args(path.to_str(), false, None),
+            Err(format!(
+                "{}: Invalid TOML at line 1, column 12: unexpected character",
+                path.display()
+            ))
+        );
+        assert!(file_args(Some("/nonexistent/gdf.toml"), false, None).is_err());
+
+        // A YAML file is found when there is no TOML file
+        fs::remove_file(&path).unwrap();
+        let path = root.join("repo").join(CONFIG_FILES[1]);
+        fs::write(
+            &path,
+            "patterns:\n  - \"!**/*.md\"\nfilters:\n  api:\n    patterns: [services/api/**]\n    status: [added, modified]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            discover_config(&root.join("repo/services/api")),
+            Some(path.clone())
+        );
+        assert_eq!(
+            file_args(path.to_str(), false, None).unwrap(),
+            [
+                ["--pattern", "!**/*.md"].as_slice(),
+                &["--github-output", "api"],
+                &["--pattern", "services/api/**"],
+                &["--group-status", "added,modified"],
+            ]
+        );
+
+        // A profile's table is merged over the rest of the file
+        fs::write(
+            &path,
+            "base-ref: main\npatterns: [src/**]\nprofiles:\n  release:\n    base-ref: v2.0\n    find-copies: true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            file_args(path.to_str(), false, None).unwrap(),
+            [["--pattern", "src/**"], ["--base-ref", "main"]]
+        );
+        assert_eq!(
+            file_args(path.to_str(), false, Some("release")).unwrap(),
+            [
+                ["--pattern", "src/**"].as_slice(),
+                &["--base-ref", "v2.0"],
+                &["--find-copies"],
+            ]
+        );
+        assert_eq!(
+            file_args(path.to_str(), false, Some("ci")),
+            Err(format!(
+                "{}: Unknown profile 'ci' (expected release)",
+                path.display()
+            ))
+        );
+        assert_eq!(profile_names(&path), Ok(vec!["release".to_string()]));
+
+        fs::write(&path, "patterns: *.md\n").unwrap();
+        assert!(file_args(path.to_str(), false, None)
+            .unwrap_err()
+            .starts_with(&format!("{}: Invalid YAML at line 1", path.display())));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let entries = |list: &[&[&str]]| -> Vec<Vec<String>> {
+            list.iter()
+                .map(|entry| entry.iter().map(ToString::to_string).collect())
+                .collect()
         };
+        assert_eq!(check_entries(&entries(&[&["--pattern", "src/**"]])), Ok(()));
+        assert_eq!(
+            check_entries(&entries(&[&["--base-ref", "main"], &["--find-cpies"]])),
+            Err("Unknown key find-cpies: did you mean find-copies?".to_string())
+        );
+        assert_eq!(
+            check_entries(&entries(&[&["--find-copies", "yes"]])),
+            Err("find-copies takes no value; set it to true or false".to_string())
+        );
+        assert_eq!(
+            check_entries(&entries(&[&["--base-ref"]])),
+            Err("base-ref takes a value, not true".to_string())
+        );
+        assert_eq!(
+            check_entries(&entries(&[&["--since", "a"], &["--since", "b"]])),
+            Err("since: --since can only be specified once".to_string())
+        );
+    }
 
-        let config = from_args(args).unwrap();
-        assert_eq!(config.github_output_name, Some("api".to_string()));
+    #[test]
+    fn test_did_you_mean() {
+        let keys = ["base-ref", "status", "find-copies"];
+        assert_eq!(edit_distance("stauts", "status"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(did_you_mean("stauts", keys.into_iter()), Some("status"));
+        assert_eq!(did_you_mean("base", keys.into_iter()), Some("base-ref"));
+        assert_eq!(did_you_mean("owner", keys.into_iter()), None);
     }
 
     #[test]
-    fn test_github_output_file_from_env() {
-        unsafe {
-            env::set_var("GITHUB_OUTPUT", "/tmp/github_output.txt");
-        }
+    fn test_locate_error() {
+        let text = "base-ref = \"main\"\n\n[filters.api]\npatterns = 1\n";
+        let document = toml::parse(text).unwrap();
+        assert_eq!(key_position(text, "filters.api.patterns"), Some((4, 1)));
+        assert_eq!(key_position(text, "filters"), Some((3, 2)));
+        assert_eq!(key_position(text, "owner"), None);
+        assert_eq!(
+            locate_error(
+                text,
+                &document,
+                "filters.api.patterns must be a string".to_string()
+            ),
+            "line 4, column 1: filters.api.patterns must be a string"
+        );
+        assert_eq!(
+            locate_error(text, &document, "Unknown profile 'x'".to_string()),
+            "Unknown profile 'x'"
+        );
+        let text = "filters:\n  api:\n    \"output\": 1\n";
+        let document = yaml::parse(text).unwrap();
+        assert_eq!(
+            locate_error(
+                text,
+                &document,
+                "filters.api.output must be a string".to_string()
+            ),
+            "line 3, column 6: filters.api.output must be a string"
+        );
+    }
 
-        let args = Args {
-            patterns: vec!["*.rs".to_string()],
-            base_ref: Some("main".to_string()),
-            github_output: None,
+    #[test]
+    fn test_expand_entries() {
+        let entries = |list: &[&[&str]]| -> Vec<Vec<String>> {
+            list.iter()
+                .map(|entry| entry.iter().map(ToString::to_string).collect())
+                .collect()
         };
+        let home = env::var("HOME").unwrap_or_default();
+        assert_eq!(
+            expand_entries(entries(&[&["--pattern", "${HOME}/**"], &["--find-copies"]])),
+            Ok(entries(&[
+                &["--pattern", &format!("{home}/**")],
+                &["--find-copies"]
+            ]))
+        );
+        assert!(
+            expand_entries(entries(&[&["--base-ref", "${GDF_TEST_UNDEFINED}"]]))
+                .unwrap_err()
+                .starts_with("Undefined environment variable GDF_TEST_UNDEFINED")
+        );
+    }
 
-        let config = from_args(args).unwrap();
+    #[test]
+    fn test_pattern_aliases() {
+        let document = toml::parse(
+            r#"
+patterns = ["!@generated"]
+
+[aliases]
+rust = ["**/*.rs", "@manifests"]
+manifests = ["Cargo.toml", "Cargo.lock"]
+generated = "**/*.pb.rs"
+
+[filters.core]
+patterns = ["@rust", "\\@types/**"]
+"#,
+        )
+        .unwrap();
         assert_eq!(
-            config.github_output_filepath,
-            Some("/tmp/github_output.txt".to_string())
+            args_from_document(&document).unwrap(),
+            [
+                ["--pattern", "!**/*.pb.rs"].as_slice(),
+                &["--github-output", "core"],
+                &["--pattern", "**/*.rs"],
+                &["--pattern", "Cargo.toml"],
+                &["--pattern", "Cargo.lock"],
+                &["--pattern", "\\@types/**"],
+            ]
         );
 
-        unsafe {
-            env::remove_var("GITHUB_OUTPUT");
-        }
+        let error = |text: &str| args_from_document(&toml::parse(text).unwrap()).unwrap_err();
+        assert_eq!(
+            error("patterns = [\"@rust\"]"),
+            "Unknown pattern alias @rust (write \\@rust for a path starting with @)"
+        );
+        assert_eq!(
+            error("patterns = [\"@a\"]\n[aliases]\na = [\"@b\"]\nb = [\"src/**\", \"@a\"]"),
+            "Pattern aliases refer to each other: @a -> @b -> @a"
+        );
+        assert_eq!(
+            error("patterns = [\"!@a\"]\n[aliases]\na = [\"src/**\", \"!src/*.md\"]"),
+            "!@a cannot negate the exclusion !src/*.md"
+        );
+        assert_eq!(
+            error("[aliases]\n\"rust sources\" = [\"**/*.rs\"]"),
+            "Invalid alias name 'rust sources': use letters, digits, '-' and '_'"
+        );
+        assert_eq!(
+            error("aliases = [\"**/*.rs\"]"),
+            "aliases must hold named pattern lists"
+        );
     }
 
     #[test]
-    fn test_github_output_file_not_set() {
-        unsafe {
-            env::remove_var("GITHUB_OUTPUT");
-        }
+    fn test_load_document_extends() {
+        let root = std::env::temp_dir().join(format!("gdf_test_extends_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("shared")).unwrap();
+        fs::create_dir_all(root.join("packages/web")).unwrap();
+        fs::write(
+            root.join("shared/base.yml"),
+            "base-ref: main\nfind-copies: true\nfilters:\n  api:\n    patterns: [services/api/**]\n    status: added\n  docs:\n    patterns: docs/**\n",
+        )
+        .unwrap();
+        fs::write(root.join("shared/extra.toml"), "git-retries = 2\n").unwrap();
+        let path = root.join("packages/web/.git-diff-filter.toml");
+        fs::write(
+            &path,
+            "extends = [\"../../shared/base.yml\", \"../../shared/extra.toml\"]\nbase-ref = \"develop\"\n\n[filters.api]\npatterns = [\"packages/web/api/**\"]\n\n[filters.web]\npatterns = \"packages/web/**\"\n",
+        )
+        .unwrap();
 
-        let args = Args {
-            patterns: vec!["*.rs".to_string()],
-            base_ref: Some("main".to_string()),
-            github_output: None,
-        };
+        // Tables merge key by key; the extending file's values replace the base's
+        assert_eq!(
+            file_args(path.to_str(), false, None).unwrap(),
+            [
+                ["--base-ref", "develop"].as_slice(),
+                &["--find-copies"],
+                &["--git-retries", "2"],
+                &["--github-output", "api"],
+                &["--pattern", "packages/web/api/**"],
+                &["--group-status", "added"],
+                &["--github-output", "docs"],
+                &["--pattern", "docs/**"],
+                &["--github-output", "web"],
+                &["--pattern", "packages/web/**"],
+            ]
+        );
 
-        let config = from_args(args).unwrap();
-        assert_eq!(config.github_output_filepath, None);
+        fs::write(
+            root.join("shared/extra.toml"),
+            "extends = \"../packages/web/.git-diff-filter.toml\"\n",
+        )
+        .unwrap();
+        let error = file_args(path.to_str(), false, None).unwrap_err();
+        assert!(
+            error.starts_with("Config files extend each other: "),
+            "{error}"
+        );
+        assert!(error.ends_with(".git-diff-filter.toml"), "{error}");
+
+        fs::write(&path, "extends = 1\n").unwrap();
+        assert_eq!(
+            file_args(path.to_str(), false, None),
+            Err(format!(
+                "{}: extends must be a string or an array of strings",
+                path.display()
+            ))
+        );
+        let _ = fs::remove_dir_all(&root);
     }
 
     #[test]
-    fn test_all_config_fields() {
+    fn test_load_document_extends_other_checkout() {
+        let root =
+            std::env::temp_dir().join(format!("gdf_test_extends_checkout_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("policy/.git")).unwrap();
+        fs::create_dir_all(root.join("policy/filters")).unwrap();
+        fs::create_dir_all(root.join("app/.git")).unwrap();
+        fs::create_dir_all(root.join("app/services/web")).unwrap();
+        fs::write(
+            root.join("policy/filters/base.toml"),
+            "extends = \"//common.toml\"\nbase-ref = \"main\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("policy/common.toml"), "git-retries = 2\n").unwrap();
+        fs::write(root.join("app/local.toml"), "find-copies = true\n").unwrap();
+        let path = root.join("app/services/web/.git-diff-filter.toml");
+        fs::write(
+            &path,
+            "extends = [\"${GDF_TEST_POLICY}/filters/base.toml\", \"//local.toml\"]\npatterns = [\"services/web/**\"]\n",
+        )
+        .unwrap();
         unsafe {
-            env::set_var("BASE_REF", "develop");
-            env::set_var("GITHUB_OUTPUT", "/tmp/output");
+            env::set_var("GDF_TEST_POLICY", root.join("policy"));
         }
 
-        let args = Args {
-            patterns: vec!["*.rs".to_string(), "*.md".to_string()],
-            base_ref: None,
-            github_output: Some("my-api".to_string()),
-        };
+        // Each file resolves // against its own checkout
+        assert_eq!(
+            file_args(path.to_str(), false, None).unwrap(),
+            [
+                ["--pattern", "services/web/**"].as_slice(),
+                &["--git-retries", "2"],
+                &["--base-ref", "main"],
+                &["--find-copies"],
+            ]
+        );
 
-        let config = from_args(args).unwrap();
-        assert_eq!(config.patterns, vec!["*.rs", "*.md"]);
-        assert_eq!(config.base_ref, "develop");
-        assert_eq!(config.github_output_name, Some("my-api".to_string()));
+        fs::remove_file(root.join("policy/common.toml")).unwrap();
         assert_eq!(
-            config.github_output_filepath,
-            Some("/tmp/output".to_string())
+            file_args(path.to_str(), false, None),
+            Err(format!(
+                "{}: extends //common.toml: no config file at {}",
+                root.join("policy/filters/base.toml").display(),
+                fs::canonicalize(root.join("policy/filters"))
+                    .unwrap()
+                    .parent()
+                    .unwrap()
+                    .join("common.toml")
+                    .display()
+            ))
         );
 
         unsafe {
-            env::remove_var("BASE_REF");
-            env::remove_var("GITHUB_OUTPUT");
+            env::remove_var("GDF_TEST_POLICY");
         }
+        let error = file_args(path.to_str(), false, None).unwrap_err();
+        assert!(
+            error.ends_with("Undefined environment variable GDF_TEST_POLICY in ${GDF_TEST_POLICY}/filters/base.toml"),
+            "{error}"
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_describe() {
+        let given = |flag: &str, values: &[&str], origin: &str| GivenFlag {
+            flag: flag.to_string(),
+            values: values.iter().map(ToString::to_string).collect(),
+            origin: origin.to_string(),
+        };
+        let args = Args {
+            patterns: vec!["src/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("code".to_string(), 0)],
+            ignore_case: true,
+            print_config: true,
+            config_file: Some("gdf.toml".to_string()),
+            given: vec![
+                given("--base-ref", &["main"], "gdf.toml"),
+                given("-g", &["code"], "command line"),
+                given("-p", &["src/**"], "command line"),
+                given("--ignore-case", &[], "command line"),
+                given("--print-config", &[], "command line"),
+            ],
+            ..Default::default()
+        };
+        let lines = from_args(args).unwrap().describe();
+        assert_eq!(
+            lines[..10],
+            [
+                "Config file: gdf.toml",
+                "Flags:",
+                "  --base-ref main  (gdf.toml)",
+                "  -g code  (command line)",
+                "  -p 'src/**'  (command line)",
+                "  --ignore-case  (command line)",
+                "  --print-config  (command line)",
+                "Resolved:",
+                "  Base refs: main  (gdf.toml)",
+                "  Filter code: src/** | ignore case  (command line)",
+            ]
+        );
+        assert!(lines.contains(&"  Anchoring: prefix  (default)".to_string()));
+    }
+
+    #[test]
+    fn test_shell_word() {
+        assert_eq!(shell_word("main"), "main");
+        assert_eq!(shell_word("src/**"), "'src/**'");
+        assert_eq!(shell_word("it's"), r"'it'\''s'");
+        assert_eq!(shell_word(""), "''");
     }
 }
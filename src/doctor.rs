@@ -0,0 +1,392 @@
+//! `git-diff-filter doctor`: check what a run depends on, git, the checkout, the base
+//! ref and the CI variables, and say how to fix each problem, so a broken CI setup is
+//! diagnosed from one command instead of from a failed run.
+
+use crate::ci::Provider;
+use crate::cli;
+use crate::config;
+use crate::git::{self, DiffOptions, RevisionRange};
+use std::path::{Path, PathBuf};
+
+/// The oldest git with every option gdf passes (`rev-parse --path-format`)
+const MIN_GIT_VERSION: (u32, u32) = (2, 31);
+
+/// How a check went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    /// Works, but some options will not
+    Warn,
+    /// A run will fail
+    Fail,
+}
+
+/// The outcome of one check, with how to fix it when it did not pass
+#[derive(Debug, PartialEq, Eq)]
+struct Check {
+    status: Status,
+    summary: String,
+    remedy: Option<String>,
+}
+
+impl Check {
+    fn ok(summary: impl Into<String>) -> Self {
+        Check {
+            status: Status::Ok,
+            summary: summary.into(),
+            remedy: None,
+        }
+    }
+
+    fn problem(status: Status, summary: impl Into<String>, remedy: impl Into<String>) -> Self {
+        Check {
+            status,
+            summary: summary.into(),
+            remedy: Some(remedy.into()),
+        }
+    }
+}
+
+/// Run every check against the repository and base refs the flags give, printing one
+/// line per check and the remedy under each problem
+///
+/// Returns whether no check failed.
+///
+/// # Errors
+/// Returns an error when the flags are invalid.
+pub fn run(args: &[String]) -> Result<bool, String> {
+    let parsed = cli::parse_flags(args)?;
+    let env = |name: &str| std::env::var(name).ok();
+    let git_dir = parsed.git_dir.clone().or_else(|| env("GIT_DIR"));
+    let options = match git_dir.filter(|dir| !dir.is_empty()) {
+        Some(dir) => {
+            let (git_dir, work_tree) = git::locate_git_dir(Path::new(&dir))?;
+            DiffOptions {
+                git_dir: Some(git_dir),
+                work_tree,
+                ..DiffOptions::default()
+            }
+        }
+        None => DiffOptions::default(),
+    };
+    let checks = checks(&options, &parsed.base_refs, &env);
+    for check in &checks {
+        println!("{}", describe(check));
+    }
+    Ok(checks.iter().all(|check| check.status != Status::Fail))
+}
+
+/// The check lines: the status, the summary and the remedy indented below
+fn describe(check: &Check) -> String {
+    let status = match check.status {
+        Status::Ok => "ok  ",
+        Status::Warn => "warn",
+        Status::Fail => "FAIL",
+    };
+    match &check.remedy {
+        Some(remedy) => format!("{status}  {}\n      {remedy}", check.summary),
+        None => format!("{status}  {}", check.summary),
+    }
+}
+
+/// Check git, then the checkout, then the base refs (`-b`, or else `BASE_REF`), then
+/// `GITHUB_OUTPUT`; checks needing a repository are left out outside one
+fn checks(
+    options: &DiffOptions,
+    base_refs: &[String],
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Vec<Check> {
+    let mut checks = vec![check_git(options)];
+    if checks[0].status == Status::Fail {
+        return checks;
+    }
+    let root = match git::repo_root(options) {
+        Ok(root) => root,
+        Err(e) => {
+            checks.push(Check::problem(
+                Status::Fail,
+                format!("Not in a git work tree: {e}"),
+                "Run gdf inside the checkout, or point --git-dir (or GIT_DIR) at its .git",
+            ));
+            return checks;
+        }
+    };
+    checks.push(Check::ok(format!("Work tree {}", root.display())));
+    checks.push(check_branch(options, env));
+    checks.push(check_shallow(options));
+    checks.push(check_sparse(options));
+    checks.push(check_base_refs(options, base_refs, env));
+    checks.push(check_github_output(env));
+    checks
+}
+
+fn check_git(options: &DiffOptions) -> Check {
+    let version = match git::version(options) {
+        Ok(version) => version,
+        Err(e) => {
+            return Check::problem(
+                Status::Fail,
+                format!("git cannot be run: {e}"),
+                "Install git and make sure it is on PATH",
+            )
+        }
+    };
+    match parse_version(&version) {
+        Some(found) if found < MIN_GIT_VERSION => Check::problem(
+            Status::Warn,
+            format!(
+                "{version} is older than {}.{}",
+                MIN_GIT_VERSION.0, MIN_GIT_VERSION.1
+            ),
+            "Some options fail on older git; upgrade git (e.g. the git-core PPA on Ubuntu)",
+        ),
+        _ => Check::ok(version),
+    }
+}
+
+/// The major and minor version in `git --version` output
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let number = version.strip_prefix("git version ")?;
+    let mut parts = number.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+fn check_branch(options: &DiffOptions, env: &dyn Fn(&str) -> Option<String>) -> Check {
+    match git::current_branch(options, env) {
+        Some(branch) => Check::ok(format!("Building branch {branch}")),
+        None => Check::problem(
+            Status::Warn,
+            "HEAD is detached and no CI variable names the branch being built",
+            "--group-branch and filter branches match no branch; check the branch out, \
+             or set GITHUB_HEAD_REF or GITHUB_REF_NAME",
+        ),
+    }
+}
+
+fn check_shallow(options: &DiffOptions) -> Check {
+    match git::is_shallow(options) {
+        Ok(false) => Check::ok("Full history"),
+        Ok(true) => Check::problem(
+            Status::Warn,
+            "The repository is a shallow clone",
+            "The merge base may be missing; fetch full history (`fetch-depth: 0` with \
+             actions/checkout) or pass --auto-fetch",
+        ),
+        Err(e) => Check::problem(
+            Status::Fail,
+            format!("Cannot tell whether the clone is shallow: {e}"),
+            "Check that the repository is readable by this user",
+        ),
+    }
+}
+
+fn check_sparse(options: &DiffOptions) -> Check {
+    match git::is_sparse_checkout(options) {
+        Ok(false) => Check::ok("Not a sparse checkout"),
+        Ok(true) => Check::problem(
+            Status::Warn,
+            "Sparse checkout: some changed files are not checked out",
+            "They still count; pass --sparse-paths exclude to drop them, and note that \
+             --cargo-workspace and --js-workspace only see checked-out manifests",
+        ),
+        Err(e) => Check::problem(
+            Status::Fail,
+            format!("Cannot read the sparse checkout setting: {e}"),
+            "Check that the repository is readable by this user",
+        ),
+    }
+}
+
+/// Check that one of the base refs resolves, like `-b` and `BASE_REF` fallback lists, or
+/// those of the CI system detected without either; `auto`, `pr-merge` and `event` need
+/// the CI event, so they are only named
+fn check_base_refs(
+    options: &DiffOptions,
+    base_refs: &[String],
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Check {
+    let (base_refs, source) = if base_refs.is_empty() {
+        let from_env = config::split_base_refs(env("BASE_REF").as_deref());
+        match Provider::detect(env) {
+            Some(provider) if from_env.is_empty() => {
+                (provider.base_refs(env), provider.to_string())
+            }
+            _ => (from_env, "BASE_REF".to_string()),
+        }
+    } else {
+        (
+            config::split_base_refs(base_refs.iter().map(String::as_str)),
+            "-b".to_string(),
+        )
+    };
+    if base_refs.is_empty() {
+        return Check::problem(
+            Status::Warn,
+            "No base ref: BASE_REF is not set, -b was not given and no CI system was detected",
+            "Pass -b <ref> (e.g. -b origin/main), set BASE_REF, or give base-ref in the \
+             config file",
+        );
+    }
+    let mut failures = Vec::new();
+    for base_ref in &base_refs {
+        if [git::AUTO_BASE, git::PR_MERGE_BASE, git::EVENT_BASE].contains(&base_ref.as_str()) {
+            return Check::ok(format!(
+                "Base ref {base_ref} ({source}), resolved from the CI event when run"
+            ));
+        }
+        let resolved = RevisionRange::parse(base_ref).and_then(|range| {
+            git::verify_revision(options, &range.base)?;
+            git::verify_revision(options, &range.head)
+        });
+        match resolved {
+            Ok(()) => return Check::ok(format!("Base ref {base_ref} ({source}) resolves")),
+            Err(e) => failures.push(e),
+        }
+    }
+    Check::problem(
+        Status::Fail,
+        format!("No base ref given with {source} resolves"),
+        failures.join("\n      "),
+    )
+}
+
+/// Check that the `GITHUB_OUTPUT` file, when set, can be appended to
+fn check_github_output(env: &dyn Fn(&str) -> Option<String>) -> Check {
+    let Some(path) = env("GITHUB_OUTPUT").filter(|path| !path.is_empty()) else {
+        return Check::ok("GITHUB_OUTPUT is not set: -g outputs go to stdout only");
+    };
+    let path = PathBuf::from(path);
+    let writable = if path.exists() {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    } else {
+        match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            Some(dir) if !dir.is_dir() => Err(format!("{} does not exist", dir.display())),
+            _ => Ok(()),
+        }
+    };
+    match writable {
+        Ok(()) => Check::ok(format!("GITHUB_OUTPUT {} is writable", path.display())),
+        Err(e) => Check::problem(
+            Status::Fail,
+            format!("GITHUB_OUTPUT {} cannot be written: {e}", path.display()),
+            "Unset GITHUB_OUTPUT outside GitHub Actions, or point it at a writable file",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::temp_repo;
+    use std::fs;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("git version 2.39.5"), Some((2, 39)));
+        assert_eq!(parse_version("git version 2.45.1.windows.1"), Some((2, 45)));
+        assert_eq!(parse_version("hub version 2.14"), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(&Check::ok("Full history")), "ok    Full history");
+        assert_eq!(
+            describe(&Check::problem(Status::Warn, "Shallow", "Fetch more")),
+            "warn  Shallow\n      Fetch more"
+        );
+    }
+
+    #[test]
+    fn test_checks() {
+        let (repo, git) = temp_repo("doctor");
+        fs::write(repo.join("README.md"), "# Demo\n").unwrap();
+        git(&["init", "-q", "-b", "main"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "second"]);
+        git(&["checkout", "-q", "--detach"]);
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..DiffOptions::default()
+        };
+        let output = repo.join("missing/output");
+        let env = |name: &str| match name {
+            "BASE_REF" => Some("main~1".to_string()),
+            "GITHUB_OUTPUT" => Some(output.display().to_string()),
+            _ => None,
+        };
+
+        let checks = checks(&options, &[], &env);
+        let statuses: Vec<Status> = checks.iter().map(|check| check.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                checks[0].status,
+                Status::Ok,
+                Status::Warn,
+                Status::Ok,
+                Status::Ok,
+                Status::Ok,
+                Status::Fail,
+            ]
+        );
+        assert!(checks[0].summary.starts_with("git version "));
+        assert_eq!(checks[5].summary, "Base ref main~1 (BASE_REF) resolves");
+        assert_eq!(
+            checks[6].summary,
+            format!(
+                "GITHUB_OUTPUT {} cannot be written: {} does not exist",
+                output.display(),
+                repo.join("missing").display()
+            )
+        );
+
+        // -b replaces BASE_REF; the first that resolves is used
+        let base_refs = ["missing,main".to_string()];
+        assert_eq!(
+            check_base_refs(&options, &base_refs, &env),
+            Check::ok("Base ref main (-b) resolves")
+        );
+        let failed = check_base_refs(&options, &["gone".to_string()], &env);
+        assert_eq!(failed.status, Status::Fail);
+        assert_eq!(failed.summary, "No base ref given with -b resolves");
+        assert!(failed
+            .remedy
+            .unwrap()
+            .starts_with("Cannot resolve revision 'gone' to a commit"));
+        assert_eq!(
+            check_base_refs(&options, &["auto".to_string()], &env).status,
+            Status::Ok
+        );
+        assert_eq!(
+            check_base_refs(&options, &[], &|_| None).status,
+            Status::Warn
+        );
+        // The pull request's target branch, tried as origin/main first
+        let github = |name: &str| match name {
+            "GITHUB_ACTIONS" => Some("true".to_string()),
+            "GITHUB_BASE_REF" => Some("main".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            check_base_refs(&options, &[], &github),
+            Check::ok("Base ref main (GitHub Actions) resolves")
+        );
+
+        let outside = DiffOptions {
+            git_dir: Some(repo.join("missing")),
+            ..DiffOptions::default()
+        };
+        let checks = super::checks(&outside, &[], &|_| None);
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[1].status, Status::Fail);
+        let _ = fs::remove_dir_all(&repo);
+    }
+}
@@ -1,121 +1,3412 @@
 //! Git command execution and output parsing.
 
-use std::process::Command;
+use crate::hunk::{self, FileDiff};
+use crate::json::Value;
+use crate::log::{self, log};
+use crate::matcher::Pathspec;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-/// Get the list of files changed between `base_ref` and HEAD
-pub fn get_changed_files(base_ref: &str) -> Result<Vec<String>, String> {
-    let output = execute_git_diff(base_ref)?;
-    Ok(parse_git_output(&output))
+/// A file reported as changed by `git diff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    /// Path of the file after the change
+    pub path: String,
+    /// Path of the file before the change when git detected a rename, or the source
+    /// file when git detected a copy
+    pub old_path: Option<String>,
+    /// How the file changed
+    pub kind: ChangeKind,
 }
 
-/// Execute git diff command and return stdout
-fn execute_git_diff(base_ref: &str) -> Result<String, String> {
+/// How a file changed, from the status letter reported by `git diff --name-status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    Added,
+    Copied,
+    Deleted,
+    Modified,
+    Renamed,
+    /// The file type changed (e.g. a regular file became a symlink)
+    TypeChanged,
+    Unmerged,
+    Unknown,
+}
+
+impl ChangeKind {
+    /// Map a `--name-status` status field (e.g. `M`, `R100`) to a change kind
+    fn from_status(status: &str) -> Self {
+        match status.as_bytes().first() {
+            Some(b'A') => ChangeKind::Added,
+            Some(b'C') => ChangeKind::Copied,
+            Some(b'D') => ChangeKind::Deleted,
+            Some(b'M') => ChangeKind::Modified,
+            Some(b'R') => ChangeKind::Renamed,
+            Some(b'T') => ChangeKind::TypeChanged,
+            Some(b'U') => ChangeKind::Unmerged,
+            _ => ChangeKind::Unknown,
+        }
+    }
+
+    /// The long name accepted by `--status`, e.g. `type-changed`
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Copied => "copied",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::TypeChanged => "type-changed",
+            ChangeKind::Unmerged => "unmerged",
+            ChangeKind::Unknown => "unknown",
+        }
+    }
+}
+
+impl FromStr for ChangeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "A" | "added" => Ok(ChangeKind::Added),
+            "C" | "copied" => Ok(ChangeKind::Copied),
+            "D" | "deleted" => Ok(ChangeKind::Deleted),
+            "M" | "modified" => Ok(ChangeKind::Modified),
+            "R" | "renamed" => Ok(ChangeKind::Renamed),
+            "T" | "type-changed" => Ok(ChangeKind::TypeChanged),
+            "U" | "unmerged" => Ok(ChangeKind::Unmerged),
+            "X" | "unknown" => Ok(ChangeKind::Unknown),
+            _ => Err(format!(
+                "Invalid status: {s} (expected added, copied, deleted, modified, renamed, type-changed, unmerged or unknown, or one of A, C, D, M, R, T, U, X)"
+            )),
+        }
+    }
+}
+
+impl ChangedFile {
+    /// Paths of this file that should be matched against patterns
+    pub fn match_paths(&self, renames: RenamePaths) -> impl Iterator<Item = &str> {
+        let old_path = match renames {
+            RenamePaths::Both => self.old_path.as_deref(),
+            RenamePaths::New => None,
+        };
+        std::iter::once(self.path.as_str()).chain(old_path)
+    }
+}
+
+/// Options controlling how `git diff` is invoked
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct DiffOptions {
+    /// Detect copied files (`-C`) and report their source path
+    pub find_copies: bool,
+    /// Diff from the merge base of HEAD and the base ref when the base ref is a branch
+    pub merge_base: bool,
+    /// Which changes are reported
+    pub source: DiffSource,
+    /// Suffix appended to the path of a submodule whose recorded commit changed
+    pub submodule_marker: Option<String>,
+    /// Also report the files changed inside submodules whose recorded commit moved
+    pub recurse_submodules: bool,
+    /// How many times to fetch missing revisions or deepen a shallow clone (0 disables)
+    pub fetch_attempts: usize,
+    /// How git commands that fail transiently are retried
+    pub retry: RetryPolicy,
+    /// Git directory to run against instead of discovering it from the working directory
+    pub git_dir: Option<PathBuf>,
+    /// Work tree of `git_dir` when it cannot be inferred by git (see [`locate_git_dir`])
+    pub work_tree: Option<PathBuf>,
+    /// Report paths relative to the current directory, dropping changes outside it
+    pub relative: bool,
+    /// Ignore whitespace and blank-line changes (`-w --ignore-blank-lines`)
+    pub ignore_whitespace: bool,
+    /// Ignore changes whose lines all match one of these POSIX extended regexes (`-I`)
+    pub ignore_content: Vec<String>,
+    /// Whether files whose only change is their mode count as changed
+    pub mode_changes: ModeChanges,
+    /// List a range commit by commit along first-parent history, skipping merges
+    pub first_parent: bool,
+}
+
+impl DiffOptions {
+    /// Whether the changed files come from a single `git diff` run and can be streamed
+    ///
+    /// Combined sources, submodule handling and ignored changes combine the output of
+    /// several commands.
+    pub fn is_single_pass(&self) -> bool {
+        self.source == DiffSource::Committed
+            && self.submodule_marker.is_none()
+            && !self.recurse_submodules
+            && !self.ignores_changes()
+    }
+
+    /// Whether some modifications are ignored, so that a file may have no counted changes
+    fn ignores_changes(&self) -> bool {
+        self.ignore_whitespace
+            || !self.ignore_content.is_empty()
+            || self.mode_changes == ModeChanges::Ignore
+    }
+}
+
+/// Retries with exponential backoff for transient git failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first failure
+    pub retries: usize,
+    /// Delay before the first retry; doubled for each further retry
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            delay: Duration::from_millis(250),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the `attempt`-th (zero-based) retry
+    fn delay_for(&self, attempt: usize) -> Duration {
+        let factor = u32::try_from(attempt).map_or(u32::MAX, |attempt| 1u32 << attempt.min(16));
+        self.delay.saturating_mul(factor)
+    }
+}
+
+/// Which changes make up the changed-file set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffSource {
+    /// Only changes committed in the revision range
+    #[default]
+    Committed,
+    /// Committed changes plus staged, unstaged and untracked files in the working tree
+    Combined,
+}
+
+impl FromStr for DiffSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "committed" => Ok(DiffSource::Committed),
+            "combined" => Ok(DiffSource::Combined),
+            _ => Err(format!(
+                "Invalid source: {s} (expected committed or combined)"
+            )),
+        }
+    }
+}
+
+/// What a single `git diff` invocation compares
+#[derive(Debug, Clone, Copy)]
+enum DiffTarget<'a> {
+    /// Commits in a revision range
+    Range(&'a RevisionRange),
+    /// The index against HEAD
+    Staged,
+    /// The working tree against the index
+    Unstaged,
+}
+
+/// A submodule whose recorded commit (gitlink) changed in the diffed range
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SubmoduleChange {
+    /// Path of the submodule in the superproject
+    path: String,
+    /// Commit recorded before the change, `None` if the submodule was added
+    old_commit: Option<String>,
+    /// Commit recorded after the change, `None` if the submodule was removed
+    new_commit: Option<String>,
+}
+
+/// File mode git uses for submodule entries
+const GITLINK_MODE: &str = "160000";
+
+/// Bounds on the number of lines (added plus deleted) a changed file must touch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineThresholds {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+impl LineThresholds {
+    /// Check whether no bound is set
+    pub fn is_unbounded(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    /// Check whether a file with `lines_changed` lines is within the bounds
+    ///
+    /// Files without a line count (binary or untracked files) are always allowed.
+    pub fn allows(&self, lines_changed: Option<usize>) -> bool {
+        lines_changed.is_none_or(|lines| {
+            self.min.is_none_or(|min| lines >= min) && self.max.is_none_or(|max| lines <= max)
+        })
+    }
+}
+
+/// Whether git treats the content of a changed file as binary or text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Binary,
+    Text,
+}
+
+impl ContentKind {
+    /// Classify a file from its `--numstat` line count, which is `None` for binary files
+    pub fn from_line_count(lines_changed: Option<usize>) -> Self {
+        match lines_changed {
+            Some(_) => ContentKind::Text,
+            None => ContentKind::Binary,
+        }
+    }
+}
+
+/// Whether files whose only change is their mode (e.g. `chmod +x`) count as changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModeChanges {
+    /// Mode-only changes count like any other change
+    #[default]
+    Include,
+    /// Files whose content is unchanged are ignored
+    Ignore,
+}
+
+impl FromStr for ModeChanges {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(ModeChanges::Include),
+            "ignore" => Ok(ModeChanges::Ignore),
+            _ => Err(format!(
+                "Invalid mode changes setting: {s} (expected include or ignore)"
+            )),
+        }
+    }
+}
+
+/// Which paths of a renamed or copied file are matched against patterns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenamePaths {
+    /// Match both the old and the new path
+    #[default]
+    Both,
+    /// Match only the new path
+    New,
+}
+
+impl FromStr for RenamePaths {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "both" => Ok(RenamePaths::Both),
+            "new" => Ok(RenamePaths::New),
+            _ => Err(format!("Invalid renames mode: {s} (expected both or new)")),
+        }
+    }
+}
+
+/// Whether changed files outside the sparse-checkout cone are considered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SparsePaths {
+    /// Consider every changed file
+    #[default]
+    Include,
+    /// Ignore changed files that are not checked out
+    Exclude,
+}
+
+impl FromStr for SparsePaths {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(SparsePaths::Include),
+            "exclude" => Ok(SparsePaths::Exclude),
+            _ => Err(format!(
+                "Invalid sparse paths mode: {s} (expected include or exclude)"
+            )),
+        }
+    }
+}
+
+/// The checked-out part of a sparse checkout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseCheckout {
+    /// Index entries that are not checked out (skip-worktree bit set)
+    skipped: HashSet<String>,
+    /// Directory the paths are relative to
+    root: PathBuf,
+}
+
+impl SparseCheckout {
+    /// Whether a changed path lies inside the sparse-checkout cone
+    ///
+    /// Paths in the index are inside unless git marked them skip-worktree. Paths absent
+    /// from the index (deleted files) are inside when their directory is checked out.
+    pub fn contains(&self, path: &str) -> bool {
+        !self.skipped.contains(path)
+            && Path::new(path)
+                .parent()
+                .is_none_or(|parent| self.root.join(parent).is_dir())
+    }
+}
+
+/// The revisions to diff, parsed from a base ref or range expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionRange {
+    /// Revision the diff starts from
+    pub base: String,
+    /// Revision the diff ends at
+    pub head: String,
+    /// Whether the range was written with `...` (diff from the merge base)
+    pub symmetric: bool,
+}
+
+impl RevisionRange {
+    /// Parse a single revision (`main`, `HEAD~5`) or a range (`v1.2.0..release/2.x`,
+    /// `main...feature`)
+    ///
+    /// A single revision is compared against `HEAD`, as is a range with an empty side.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (base, head, symmetric) = if let Some((base, head)) = spec.split_once("...") {
+            (base, head, true)
+        } else if let Some((base, head)) = spec.split_once("..") {
+            (base, head, false)
+        } else {
+            (spec, "", false)
+        };
+
+        let base = if base.is_empty() { "HEAD" } else { base };
+        let head = if head.is_empty() { "HEAD" } else { head };
+        for revision in [base, head] {
+            if revision.starts_with('-') || revision.contains("..") {
+                return Err(format!("Invalid revision range: {spec}"));
+            }
+        }
+
+        Ok(RevisionRange {
+            base: base.to_string(),
+            head: head.to_string(),
+            symmetric,
+        })
+    }
+}
+
+impl std::fmt::Display for RevisionRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dots = if self.symmetric { "..." } else { ".." };
+        write!(f, "{}{dots}{}", self.base, self.head)
+    }
+}
+
+/// A revision range ready to be diffed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRange {
+    /// The revisions to diff
+    pub range: RevisionRange,
+    /// The merge base that replaced the base ref, if any
+    pub merge_base: Option<String>,
+}
+
+/// Verify both ends of the range and resolve the revision to diff from
+///
+/// When merge-base resolution is enabled and the base names a local or remote-tracking
+/// branch, the base is replaced by `git merge-base <base> <head>`, so only changes
+/// introduced since the branches diverged are reported. Tags, commits and expressions are
+/// kept unchanged, as are `...` ranges, where git already diffs from the merge base.
+///
+/// With `fetch_attempts` set, revisions missing locally are fetched from their remote, and
+/// a shallow history is deepened until the merge base is reachable.
+pub fn resolve_range(
+    range: &RevisionRange,
+    options: &DiffOptions,
+) -> Result<ResolvedRange, String> {
+    let range = RevisionRange {
+        base: ensure_revision(&range.base, options)?,
+        head: ensure_revision(&range.head, options)?,
+        symmetric: range.symmetric,
+    };
+
+    if range.symmetric {
+        if options.fetch_attempts > 0 {
+            // `git diff a...b` needs the merge base to be present in the history
+            with_deepening(options, || {
+                run_git_text(options, &["merge-base", &range.base, &range.head])
+            })?;
+        }
+        return Ok(ResolvedRange {
+            range,
+            merge_base: None,
+        });
+    }
+    if !options.merge_base {
+        return Ok(ResolvedRange {
+            range,
+            merge_base: None,
+        });
+    }
+
+    let full_name = run_git_text(options, &["rev-parse", "--symbolic-full-name", &range.base])?;
+    if !is_branch_ref(&full_name) {
+        return Ok(ResolvedRange {
+            range,
+            merge_base: None,
+        });
+    }
+
+    let merge_base = with_deepening(options, || {
+        run_git_text(options, &["merge-base", &range.base, &range.head])
+    })
+    .map_err(|e| {
+        format!(
+            "Cannot find a merge base of '{}' and '{}': {e}",
+            range.base, range.head
+        )
+    })?;
+    Ok(ResolvedRange {
+        range: RevisionRange {
+            base: merge_base.clone(),
+            ..range
+        },
+        merge_base: Some(merge_base),
+    })
+}
+
+/// Details from the triggering CI event used to resolve special base ref specs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventHints<'a> {
+    /// The repository's default branch
+    pub default_branch: Option<&'a str>,
+    /// Number of the pull request being built
+    pub pull_request: Option<u64>,
+    /// The commit a push or merge queue event builds on
+    pub event_base: Option<&'a str>,
+}
+
+/// Resolve the first base ref spec that parses and resolves, in order
+///
+/// Returns the parsed range alongside its resolution. When no candidate resolves, the
+/// error lists why each one failed.
+///
+/// The spec [`AUTO_BASE`] stands for the repository's default branch (see
+/// [`default_branch`]), [`PR_MERGE_BASE`] for the changes of the pull request's merge
+/// commit (see [`pr_merge_spec`]) and [`EVENT_BASE`] for the commit a push or merge queue
+/// event builds on (see [`event_base_spec`]).
+pub fn resolve_first(
+    specs: &[String],
+    options: &DiffOptions,
+    hints: EventHints,
+) -> Result<(RevisionRange, ResolvedRange), String> {
+    let mut failures = Vec::new();
+    for spec in specs {
+        let spec = match spec.as_str() {
+            AUTO_BASE => default_branch(options, hints.default_branch),
+            PR_MERGE_BASE => pr_merge_spec(options, hints.pull_request),
+            EVENT_BASE => event_base_spec(hints.event_base),
+            _ => Ok(spec.clone()),
+        };
+        match spec
+            .and_then(|spec| RevisionRange::parse(&spec))
+            .and_then(|range| resolve_range(&range, options).map(|resolved| (range, resolved)))
+        {
+            Ok(found) => return Ok(found),
+            Err(e) => failures.push(e),
+        }
+    }
+    match failures.as_slice() {
+        [only] => Err(only.clone()),
+        _ => Err(format!(
+            "None of the base refs resolved: {}",
+            failures.join("; ")
+        )),
+    }
+}
+
+/// Base ref spec that resolves to the repository's default branch
+pub const AUTO_BASE: &str = "auto";
+
+/// Base ref spec that diffs the pull request's merge commit against the base branch
+pub const PR_MERGE_BASE: &str = "pr-merge";
+
+/// Base ref spec that resolves to the commit a push or merge queue event builds on
+pub const EVENT_BASE: &str = "event";
+
+/// The commit a `push` event's branch pointed at before the push, or the base of a
+/// `merge_group` event's queued changes
+///
+/// A push creating a branch names no previous commit, and a forced push may name one
+/// the checkout lacks: the spec then fails, so that the next base ref is tried.
+pub fn event_base_spec(base: Option<&str>) -> Result<String, String> {
+    base.map(str::to_string).ok_or_else(|| {
+        "-b event requires a push or merge_group event naming the previous commit: none in \
+         the GitHub event payload (a push creating a branch names none)"
+            .to_string()
+    })
+}
+
+/// Build the range covering what the pull request's merge commit adds to the base branch
+///
+/// GitHub keeps `refs/pull/<n>/merge` pointing at a commit merging the pull request head
+/// into the current base branch; its first parent is the base branch tip. Diffing the two
+/// reports exactly the files shown in the pull request's "Files changed" tab, with any
+/// conflicts against the base branch resolved as in that merge. The ref is fetched from
+/// `origin` into `refs/remotes/pull/<n>/merge` when it is missing locally.
+pub fn pr_merge_spec(options: &DiffOptions, pull_request: Option<u64>) -> Result<String, String> {
+    let number = pull_request.ok_or(
+        "-b pr-merge requires a pull_request event: no pull request number in the GitHub \
+         event payload",
+    )?;
+    let merge_ref = pr_merge_ref(number);
+    let spec = format!("{merge_ref}^{{commit}}");
+    if run_git_text(options, &["rev-parse", "--verify", "--quiet", &spec]).is_err() {
+        let refspec = format!("+refs/pull/{number}/merge:{merge_ref}");
+        run_git(options, &["fetch", "--no-tags", "origin", &refspec]).map_err(|e| {
+            format!(
+                "Cannot fetch refs/pull/{number}/merge from origin (GitHub does not create \
+                 it while the pull request has merge conflicts): {e}"
+            )
+        })?;
+    }
+    Ok(format!("{merge_ref}^1..{merge_ref}"))
+}
+
+/// Local ref that holds a pull request's merge commit
+fn pr_merge_ref(number: u64) -> String {
+    format!("refs/remotes/pull/{number}/merge")
+}
+
+/// Detect the repository's default branch
+///
+/// Uses `origin/HEAD` when it is set (by `git clone` or `git remote set-head origin
+/// --auto`), otherwise `hint` (e.g. the default branch named in the GitHub event payload),
+/// preferring its remote-tracking branch `origin/<hint>` when that exists.
+pub fn default_branch(options: &DiffOptions, hint: Option<&str>) -> Result<String, String> {
+    if let Ok(origin_head) = run_git_text(
+        options,
+        &[
+            "symbolic-ref",
+            "--quiet",
+            "--short",
+            "refs/remotes/origin/HEAD",
+        ],
+    ) {
+        if !origin_head.is_empty() {
+            return Ok(origin_head);
+        }
+    }
+    let hint = hint.ok_or(
+        "Cannot detect the default branch: origin/HEAD is not set (run `git remote set-head \
+         origin --auto`) and no GitHub event payload names one",
+    )?;
+    let remote_branch = format!("origin/{hint}");
+    let spec = format!("{remote_branch}^{{commit}}");
+    if run_git_text(options, &["rev-parse", "--verify", "--quiet", &spec]).is_ok() {
+        Ok(remote_branch)
+    } else {
+        Ok(hint.to_string())
+    }
+}
+
+/// CI variables naming the branch being built, most specific first: the source branch
+/// of a pull or merge request, then the pushed branch
+const BRANCH_VARIABLES: [&str; 7] = [
+    "GITHUB_HEAD_REF",
+    "CI_MERGE_REQUEST_SOURCE_BRANCH_NAME",
+    "GITHUB_REF_NAME",
+    "CI_COMMIT_BRANCH",
+    "BUILDKITE_BRANCH",
+    "CIRCLE_BRANCH",
+    "BITBUCKET_BRANCH",
+];
+
+/// The branch being built: the one a CI variable names, or else the one checked out
+///
+/// `var` reads an environment variable. Returns `None` when no CI variable names a
+/// branch and HEAD is detached.
+pub fn current_branch(
+    options: &DiffOptions,
+    var: &dyn Fn(&str) -> Option<String>,
+) -> Option<String> {
+    BRANCH_VARIABLES
+        .iter()
+        .find_map(|name| var(name).filter(|branch| !branch.is_empty()))
+        .or_else(|| {
+            run_git_text(options, &["symbolic-ref", "--quiet", "--short", "HEAD"])
+                .ok()
+                .filter(|branch| !branch.is_empty())
+        })
+}
+
+/// Check that a revision resolves to a commit
+///
+/// When it does not, the error suggests remote-tracking branches of the same name and
+/// points out shallow clones, the usual causes in CI.
+pub fn verify_revision(options: &DiffOptions, revision: &str) -> Result<(), String> {
+    let spec = format!("{revision}^{{commit}}");
+    if run_git_text(options, &["rev-parse", "--verify", "--quiet", &spec]).is_ok() {
+        return Ok(());
+    }
+
+    let candidates = if is_plain_ref_name(revision) {
+        let pattern = format!("refs/remotes/*/{revision}");
+        run_git_text(
+            options,
+            &["for-each-ref", "--format=%(refname:short)", &pattern],
+        )
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+    } else {
+        Vec::new()
+    };
+    let shallow = is_shallow(options).unwrap_or(false);
+    Err(missing_revision_message(revision, &candidates, shallow))
+}
+
+/// Whether the repository is a shallow clone, missing the history beyond its edge
+pub fn is_shallow(options: &DiffOptions) -> Result<bool, String> {
+    run_git_text(options, &["rev-parse", "--is-shallow-repository"]).map(|output| output == "true")
+}
+
+/// The `git --version` line, such as `git version 2.39.5`
+pub fn version(options: &DiffOptions) -> Result<String, String> {
+    run_git_text(options, &["--version"])
+}
+
+/// Check whether a revision is a bare ref name rather than an expression like `HEAD~1`
+fn is_plain_ref_name(revision: &str) -> bool {
+    !revision.is_empty()
+        && !revision.starts_with("refs/")
+        && !revision.contains(['~', '^', ':', '@', '{', '*', '?', '[', ' '])
+}
+
+/// Build the error for a revision that does not resolve
+fn missing_revision_message(revision: &str, candidates: &[String], shallow: bool) -> String {
+    let mut hints = Vec::new();
+    let quoted: Vec<String> = candidates
+        .iter()
+        .map(|candidate| format!("'{candidate}'"))
+        .collect();
+    match quoted.as_slice() {
+        [] => {}
+        [only] => hints.push(format!(
+            "A remote-tracking branch exists: did you mean {only}?"
+        )),
+        _ => hints.push(format!(
+            "Remote-tracking branches exist: did you mean one of {}?",
+            quoted.join(", ")
+        )),
+    }
+    if shallow {
+        hints.push(
+            "The repository is a shallow clone, so branches and commits that were not \
+             fetched are missing; fetch full history (e.g. `fetch-depth: 0` with \
+             actions/checkout) or pass --auto-fetch."
+                .to_string(),
+        );
+    } else if candidates.is_empty() {
+        hints.push(format!(
+            "If it exists on the remote, fetch it first (e.g. `git fetch origin {revision}`) \
+             or pass --auto-fetch."
+        ));
+    }
+    let message = format!("Cannot resolve revision '{revision}' to a commit");
+    if hints.is_empty() {
+        message
+    } else {
+        format!("{message}. {}", hints.join(" "))
+    }
+}
+
+/// Verify a revision, fetching it from its remote when it is missing and auto-fetch is on
+///
+/// Returns the revision to use, which is the remote-tracking branch when a branch name was
+/// fetched from `origin`.
+fn ensure_revision(revision: &str, options: &DiffOptions) -> Result<String, String> {
+    let Err(missing) = verify_revision(options, revision) else {
+        return Ok(revision.to_string());
+    };
+    if options.fetch_attempts == 0 {
+        return Err(missing);
+    }
+
+    let remotes = run_git_text(options, &["remote"])?;
+    let remotes: Vec<&str> = remotes.lines().collect();
+    let fetch = match revision.split_once('/') {
+        Some((remote, branch)) if remotes.contains(&remote) => FetchTarget {
+            remote: remote.to_string(),
+            refspec: format!("+refs/heads/{branch}:refs/remotes/{remote}/{branch}"),
+            resolved: revision.to_string(),
+        },
+        _ => {
+            let listing = run_git(options, &["ls-remote", "origin", revision])?;
+            fetch_target_from_ls_remote(revision, &String::from_utf8_lossy(&listing))
+        }
+    };
+
+    log!(
+        Info,
+        "git",
+        "Revision '{revision}' not found locally, fetching {} from {}",
+        fetch.refspec,
+        fetch.remote
+    );
+    let mut last_error = missing;
+    for _ in 0..options.fetch_attempts {
+        match run_git(
+            options,
+            &["fetch", "--no-tags", &fetch.remote, &fetch.refspec],
+        ) {
+            Ok(_) => {
+                verify_revision(options, &fetch.resolved)?;
+                return Ok(fetch.resolved);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(format!("Cannot fetch revision '{revision}': {last_error}"))
+}
+
+/// What to fetch for a missing revision, and the name it resolves as afterwards
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FetchTarget {
+    remote: String,
+    refspec: String,
+    resolved: String,
+}
+
+/// Choose the refspec for a revision from `origin`, given `git ls-remote origin <rev>` output
+///
+/// Branches are fetched into `refs/remotes/origin/` and tags into `refs/tags/`; anything
+/// else (e.g. a commit SHA) is fetched as-is.
+fn fetch_target_from_ls_remote(revision: &str, listing: &str) -> FetchTarget {
+    let refs: Vec<&str> = listing
+        .lines()
+        .filter_map(|line| line.split_once('\t').map(|(_, name)| name))
+        .collect();
+    let (refspec, resolved) = if refs.contains(&format!("refs/heads/{revision}").as_str()) {
+        (
+            format!("+refs/heads/{revision}:refs/remotes/origin/{revision}"),
+            format!("origin/{revision}"),
+        )
+    } else if refs.contains(&format!("refs/tags/{revision}").as_str()) {
+        (
+            format!("+refs/tags/{revision}:refs/tags/{revision}"),
+            revision.to_string(),
+        )
+    } else {
+        (revision.to_string(), revision.to_string())
+    };
+    FetchTarget {
+        remote: "origin".to_string(),
+        refspec,
+        resolved,
+    }
+}
+
+/// Find the last commit on the first-parent history of HEAD made before `date`
+///
+/// `date` is anything git's `--before` accepts (`2024-05-01`, `24 hours ago`). Following
+/// first parents yields the commit the branch pointed at by then, not one from a branch
+/// merged later. With auto-fetch, a shallow clone is deepened until such a commit exists.
+pub fn commit_before(options: &DiffOptions, date: &str) -> Result<String, String> {
+    let before = format!("--before={date}");
+    let found = with_deepening(options, || {
+        let id = run_git_text(
+            options,
+            &["rev-list", "-1", "--first-parent", &before, "HEAD"],
+        )?;
+        if id.is_empty() {
+            Err(format!("No commit on HEAD was made before '{date}'"))
+        } else {
+            Ok(id)
+        }
+    });
+    found.map_err(|e| {
+        if is_shallow(options).unwrap_or(false) {
+            format!(
+                "{e}. The repository is a shallow clone; pass --auto-fetch or fetch more \
+                 history (e.g. `git fetch --shallow-since='{date}'`)"
+            )
+        } else {
+            e
+        }
+    })
+}
+
+/// Ranges covering each of the last `count` commits of HEAD, newest first
+///
+/// Each commit is diffed against its first parent, and a root commit against the empty
+/// tree. In a shallow clone, a listed commit whose parents were not fetched has unknown
+/// changes; with auto-fetch the clone is deepened, otherwise that is an error.
+pub fn recent_commits(options: &DiffOptions, count: usize) -> Result<Vec<RevisionRange>, String> {
+    let max_count = format!("--max-count={count}");
+    let mut args = vec!["rev-list", "--parents", &max_count];
+    if options.first_parent {
+        args.push("--first-parent");
+    }
+    args.push("HEAD");
+    let listing = with_deepening(options, || {
+        let listing = run_git_text(options, &args)?;
+        let shallow = shallow_commits(options)?;
+        match listing.lines().find(|line| shallow.contains(*line)) {
+            Some(commit) => Err(format!(
+                "The parent of commit {commit} was not fetched; pass --auto-fetch or fetch \
+                 at least {} commits",
+                count + 1
+            )),
+            None => Ok(listing),
+        }
+    })?;
+
+    listing
+        .lines()
+        .map(|line| {
+            let mut ids = line.split(' ');
+            let head = ids.next().unwrap_or_default().to_string();
+            let base = match ids.next() {
+                Some(parent) => parent.to_string(),
+                None => run_git_text(options, &["hash-object", "-t", "tree", "/dev/null"])?,
+            };
+            Ok(RevisionRange {
+                base,
+                head,
+                symmetric: false,
+            })
+        })
+        .collect()
+}
+
+/// Resolve a revision that is known to exist to its full object ID
+pub fn object_id(options: &DiffOptions, revision: &str) -> Result<String, String> {
+    run_git_text(options, &["rev-parse", "--verify", "--quiet", revision])
+        .map_err(|_| format!("Cannot resolve revision '{revision}'"))
+}
+
+/// Commits at the edge of a shallow clone, whose parents were not fetched
+fn shallow_commits(options: &DiffOptions) -> Result<HashSet<String>, String> {
+    let path = run_git_text(
+        options,
+        &[
+            "rev-parse",
+            "--path-format=absolute",
+            "--git-path",
+            "shallow",
+        ],
+    )?;
+    Ok(fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Run `op`, deepening a shallow clone and retrying while it fails
+///
+/// Each attempt but the last deepens the history by twice as many commits as the one
+/// before; the last attempt fetches the complete history.
+fn with_deepening<T>(
+    options: &DiffOptions,
+    mut op: impl FnMut() -> Result<T, String>,
+) -> Result<T, String> {
+    let mut result = op();
+    for attempt in 0..options.fetch_attempts {
+        if result.is_ok() || !is_shallow(options)? {
+            break;
+        }
+        let args = deepen_args(attempt, options.fetch_attempts);
+        log!(
+            Info,
+            "git",
+            "Shallow clone is missing history, running git {}",
+            args.join(" ")
+        );
+        run_git(options, &args)?;
+        result = op();
+    }
+    result
+}
+
+/// Arguments for the `attempt`-th (zero-based) fetch that deepens a shallow clone
+fn deepen_args(attempt: usize, attempts: usize) -> Vec<String> {
+    let depth = if attempt + 1 == attempts {
+        "--unshallow".to_string()
+    } else {
+        format!("--deepen={}", DEEPEN_STEP << attempt)
+    };
+    vec!["fetch".to_string(), "--no-tags".to_string(), depth]
+}
+
+/// Number of commits the first deepening fetch adds to a shallow clone
+const DEEPEN_STEP: usize = 50;
+
+/// Check whether a full ref name refers to a branch
+fn is_branch_ref(full_name: &str) -> bool {
+    full_name.starts_with("refs/heads/") || full_name.starts_with("refs/remotes/")
+}
+
+/// Source of the changes in a revision range
+///
+/// [`Subprocess`] runs git; other implementations can supply changes from memory, so the
+/// filtering on top of them can be exercised without a repository.
+pub trait GitBackend {
+    /// Files changed in `range` (see [`get_changed_files`])
+    fn changed_files(
+        &self,
+        range: &RevisionRange,
+        options: &DiffOptions,
+    ) -> Result<Vec<ChangedFile>, String>;
+
+    /// Lines changed per file in `range` (see [`get_line_counts`])
+    fn line_counts(
+        &self,
+        range: &RevisionRange,
+        options: &DiffOptions,
+    ) -> Result<HashMap<String, Option<usize>>, String>;
+
+    /// Added and removed lines per file in `range` (see [`get_file_diffs`])
+    fn file_diffs(
+        &self,
+        range: &RevisionRange,
+        options: &DiffOptions,
+    ) -> Result<Vec<FileDiff>, String>;
+}
+
+/// The default backend, running the `git` executable
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Subprocess;
+
+impl GitBackend for Subprocess {
+    fn changed_files(
+        &self,
+        range: &RevisionRange,
+        options: &DiffOptions,
+    ) -> Result<Vec<ChangedFile>, String> {
+        get_changed_files(range, options)
+    }
+
+    fn line_counts(
+        &self,
+        range: &RevisionRange,
+        options: &DiffOptions,
+    ) -> Result<HashMap<String, Option<usize>>, String> {
+        get_line_counts(range, options)
+    }
+
+    fn file_diffs(
+        &self,
+        range: &RevisionRange,
+        options: &DiffOptions,
+    ) -> Result<Vec<FileDiff>, String> {
+        get_file_diffs(range, options)
+    }
+}
+
+/// Get the list of files changed in a revision range
+///
+/// With [`DiffSource::Combined`], staged, unstaged and untracked files are added to the
+/// committed changes. Each path is reported once, keeping its first occurrence, which with
+/// `--first-parent` is its status in the newest commit changing it.
+pub fn get_changed_files(
+    range: &RevisionRange,
+    options: &DiffOptions,
+) -> Result<Vec<ChangedFile>, String> {
+    let mut files = list_changed_files(options, &[], DiffTarget::Range(range))?;
+
+    if options.submodule_marker.is_some() || options.recurse_submodules {
+        let raw = run_git(options, &raw_args(DiffTarget::Range(range), options))?;
+        let submodules = parse_submodule_changes(&raw)?;
+        if options.recurse_submodules {
+            files.extend(get_submodule_files(&submodules, options)?);
+        }
+        if let Some(marker) = &options.submodule_marker {
+            mark_submodules(&mut files, &submodules, marker);
+        }
+    }
+
+    if options.source == DiffSource::Combined {
+        for target in [DiffTarget::Staged, DiffTarget::Unstaged] {
+            files.extend(list_changed_files(options, &[], target)?);
+        }
+        let untracked = run_git(options, &untracked_args(options))?;
+        files.extend(parse_untracked(&untracked));
+    }
+    if options.first_parent || options.source == DiffSource::Combined {
+        dedup_by_path(&mut files);
+    }
+
+    Ok(files)
+}
+
+/// List the files changed by one `git diff` invocation, prefixed by `global_args`
+///
+/// When changes are ignored (`-w`, `-I`), `git diff --name-status` still lists files whose
+/// changes are all ignored, but `--numstat` leaves them out, so the numstat listing
+/// decides which files remain. Files whose mode changed but content did not are found
+/// in the `--raw` listing.
+fn list_changed_files(
+    options: &DiffOptions,
+    global_args: &[String],
+    target: DiffTarget,
+) -> Result<Vec<ChangedFile>, String> {
+    let run = |args: Vec<String>| run_git(options, &[global_args.to_vec(), args].concat());
+    let mut files = parse_git_output(&run(diff_args(target, options))?)?;
+    if options.ignore_whitespace || !options.ignore_content.is_empty() {
+        let numstat = run(diff_command(&["--numstat", "-z"], target, options))?;
+        let changed: HashSet<String> = parse_numstat(&numstat)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        files.retain(|file| changed.contains(&file.path));
+    }
+    if options.mode_changes == ModeChanges::Ignore {
+        let entries = parse_raw(&run(raw_args(target, options))?)?;
+        let mode_only = mode_only_paths(&entries, options)?;
+        files.retain(|file| !mode_only.contains(&file.path));
+    }
+    Ok(files)
+}
+
+/// Paths of `--raw` entries whose mode changed while their content stayed the same
+///
+/// Working-tree entries carry an all-zero ID for the new side; those files are hashed
+/// with `git hash-object` to compare their content.
+fn mode_only_paths(entries: &[RawEntry], options: &DiffOptions) -> Result<HashSet<String>, String> {
+    let candidates: Vec<&RawEntry> = entries
+        .iter()
+        .filter(|entry| {
+            entry.old_mode != entry.new_mode
+                && ![entry.old_mode.as_str(), entry.new_mode.as_str()]
+                    .iter()
+                    .any(|&mode| is_null_id(mode) || mode == GITLINK_MODE)
+        })
+        .collect();
+    let mut mode_only: HashSet<String> = candidates
+        .iter()
+        .filter(|entry| entry.old_id == entry.new_id)
+        .map(|entry| entry.path.clone())
+        .collect();
+
+    let unhashed: Vec<&RawEntry> = candidates
+        .into_iter()
+        .filter(|entry| is_null_id(&entry.new_id))
+        .collect();
+    if !unhashed.is_empty() {
+        let root = if options.relative {
+            PathBuf::new()
+        } else {
+            repo_root(options)?
+        };
+        let mut args = vec!["hash-object".to_string(), "--".to_string()];
+        args.extend(
+            unhashed
+                .iter()
+                .map(|entry| root.join(&entry.path).to_string_lossy().into_owned()),
+        );
+        let hashes = run_git_text(
+            options,
+            &args.iter().map(String::as_str).collect::<Vec<_>>(),
+        )?;
+        for (entry, hash) in unhashed.iter().zip(hashes.lines()) {
+            if hash == entry.old_id {
+                mode_only.insert(entry.path.clone());
+            }
+        }
+    }
+    // With `--first-parent` a path has an entry per commit; any content change keeps it
+    for entry in entries {
+        if entry.old_id != entry.new_id && !is_null_id(&entry.new_id) {
+            mode_only.remove(&entry.path);
+        }
+    }
+    Ok(mode_only)
+}
+
+/// Whether a mode or object ID from `--raw` output is all zeros (a missing side)
+fn is_null_id(id: &str) -> bool {
+    id.bytes().all(|b| b == b'0')
+}
+
+/// Check whether any file changed in a revision range satisfies `predicate`
+///
+/// Unlike [`get_changed_files`], the output of `git diff` is read as it is produced, so
+/// memory use does not grow with the size of the diff, and git is stopped as soon as a
+/// file is accepted. Only valid when [`DiffOptions::is_single_pass`] holds.
+///
+/// Non-empty `pathspecs` let git skip files outside them. Renames and copies whose other
+/// side falls outside are then reported as additions or deletions.
+pub fn any_changed_file<F>(
+    range: &RevisionRange,
+    options: &DiffOptions,
+    pathspecs: &[Pathspec],
+    mut predicate: F,
+) -> Result<bool, String>
+where
+    F: FnMut(&ChangedFile) -> Result<bool, String>,
+{
+    let mut args = diff_args(DiffTarget::Range(range), options);
+    args.extend(pathspec_args(pathspecs, options));
+    let mut attempt = 0;
+    loop {
+        let mut child = GitChild(
+            git_command(options, &args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Failed to execute git command: {e}"))?,
+        );
+        // Drain stderr concurrently so a chatty git cannot block on a full pipe
+        let mut stderr = child.0.stderr.take().expect("stderr is piped");
+        let stderr_reader = thread::spawn(move || {
+            let mut buf = Vec::new();
+            stderr.read_to_end(&mut buf).map(|_| buf)
+        });
+        let stdout = BufReader::new(child.0.stdout.take().expect("stdout is piped"));
+
+        let mut seen = false;
+        let found = find_changed_file(stdout, |file| {
+            seen = true;
+            predicate(file)
+        })?;
+        if found {
+            return Ok(true);
+        }
+
+        let status = child
+            .0
+            .wait()
+            .map_err(|e| format!("Failed to wait for git command: {e}"))?;
+        let stderr = stderr_reader
+            .join()
+            .ok()
+            .and_then(Result::ok)
+            .unwrap_or_default();
+        if status.success() {
+            return Ok(false);
+        }
+        // Files already passed to the predicate would be seen twice on a retry
+        let retries = if seen { 0 } else { options.retry.retries };
+        retry_after_failure(options, attempt, retries, &stderr)?;
+        attempt += 1;
+    }
+}
+
+/// A running git process that is killed when dropped before it exits
+struct GitChild(Child);
+
+impl Drop for GitChild {
+    fn drop(&mut self) {
+        if matches!(self.0.try_wait(), Ok(None)) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+/// Read `--name-status -z` records until one satisfies `predicate`
+fn find_changed_file<R, F>(reader: R, mut predicate: F) -> Result<bool, String>
+where
+    R: BufRead,
+    F: FnMut(&ChangedFile) -> Result<bool, String>,
+{
+    let fields = reader.split(0).map(|field| {
+        field
+            .map(|field| String::from_utf8_lossy(&field).into_owned())
+            .map_err(|e| format!("Failed to read git output: {e}"))
+    });
+    let records = NameStatusRecords { fields };
+    for file in records {
+        if predicate(&file?)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Get the added and removed lines of each file changed in a revision range
+///
+/// With [`DiffSource::Combined`], staged and unstaged changes are included as well.
+/// Untracked files have no diff and are not reported.
+pub fn get_file_diffs(
+    range: &RevisionRange,
+    options: &DiffOptions,
+) -> Result<Vec<FileDiff>, String> {
+    let mut targets = vec![DiffTarget::Range(range)];
+    if options.source == DiffSource::Combined {
+        targets.extend([DiffTarget::Staged, DiffTarget::Unstaged]);
+    }
+
+    let mut diffs = Vec::new();
+    for target in targets {
+        let output = run_git(options, &patch_args(target, options))?;
+        diffs.extend(hunk::parse_unified_diff(&output)?);
+    }
+    Ok(diffs)
+}
+
+/// Count the lines (added plus deleted) changed in each file, keyed by new path
+///
+/// Binary files map to `None`. With [`DiffSource::Combined`], the counts of staged and
+/// unstaged changes are added to the committed ones.
+pub fn get_line_counts(
+    range: &RevisionRange,
+    options: &DiffOptions,
+) -> Result<HashMap<String, Option<usize>>, String> {
+    let mut targets = vec![DiffTarget::Range(range)];
+    if options.source == DiffSource::Combined {
+        targets.extend([DiffTarget::Staged, DiffTarget::Unstaged]);
+    }
+
+    let mut counts: HashMap<String, Option<usize>> = HashMap::new();
+    for target in targets {
+        let args = diff_command(&["--numstat", "-z"], target, options);
+        for (path, lines) in parse_numstat(&run_git(options, &args)?)? {
+            counts
+                .entry(path)
+                .and_modify(|total| *total = total.zip(lines).map(|(a, b)| a + b))
+                .or_insert(lines);
+        }
+    }
+    Ok(counts)
+}
+
+/// Build the arguments for the git diff command
+fn diff_args(target: DiffTarget, options: &DiffOptions) -> Vec<String> {
+    diff_command(&["--name-status", "-z"], target, options)
+}
+
+/// Build the arguments for a zero-context patch with fixed path prefixes
+fn patch_args(target: DiffTarget, options: &DiffOptions) -> Vec<String> {
+    let format = [
+        "-U0",
+        "--no-color",
+        "--no-ext-diff",
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+    ];
+    diff_command(&format, target, options)
+}
+
+/// Build a `git diff` command line with the given output format flags
+fn diff_command(format: &[&str], target: DiffTarget, options: &DiffOptions) -> Vec<String> {
+    let mut args = subcommand_args(target, options);
+    args.extend(format.iter().map(ToString::to_string));
+    args.push("-M".to_string());
+    if options.find_copies {
+        args.push("-C".to_string());
+    }
+    if options.relative {
+        args.push("--relative".to_string());
+    }
+    if options.ignore_whitespace {
+        args.extend(["-w".to_string(), "--ignore-blank-lines".to_string()]);
+    }
+    args.extend(
+        options
+            .ignore_content
+            .iter()
+            .map(|regex| format!("-I{regex}")),
+    );
+    args.extend(target_arg(target, options));
+    args
+}
+
+/// The command listing the changes of `target`, before any output format flags
+///
+/// With `--first-parent`, a range is listed commit by commit along the first-parent
+/// history of its head with `git log`, skipping merges, so that changes merged in from
+/// other branches are left out. A path changed by several commits is listed once per
+/// commit, newest first.
+fn subcommand_args(target: DiffTarget, options: &DiffOptions) -> Vec<String> {
+    let args: &[&str] = match target {
+        DiffTarget::Range(_) if options.first_parent => {
+            &["log", "--first-parent", "--no-merges", "--format="]
+        }
+        _ => &["diff"],
+    };
+    args.iter().map(ToString::to_string).collect()
+}
+
+/// The argument selecting what is diffed for `target`, if any
+fn target_arg(target: DiffTarget, options: &DiffOptions) -> Option<String> {
+    match target {
+        // `git log base..head` already stops at the merge base, even for `...` ranges
+        DiffTarget::Range(range) if options.first_parent => {
+            Some(format!("{}..{}", range.base, range.head))
+        }
+        DiffTarget::Range(range) => Some(range.to_string()),
+        DiffTarget::Staged => Some("--cached".to_string()),
+        DiffTarget::Unstaged => None,
+    }
+}
+
+/// Build the `-- <pathspec>...` arguments restricting a diff to `specs`
+///
+/// Paths are literal and taken from the top of the work tree, or from the current
+/// directory with `--relative`, like the paths patterns are matched against.
+fn pathspec_args(specs: &[Pathspec], options: &DiffOptions) -> Vec<String> {
+    if specs.is_empty() {
+        return Vec::new();
+    }
+    let top = if options.relative { "" } else { "top," };
+    let mut args = vec!["--".to_string()];
+    args.extend(specs.iter().map(|spec| {
+        let exclude = if spec.exclude { "exclude," } else { "" };
+        format!(":({top}{exclude}literal){}", spec.path)
+    }));
+    args
+}
+
+/// Build the arguments for listing changes with their modes and object IDs
+fn raw_args(target: DiffTarget, options: &DiffOptions) -> Vec<String> {
+    let mut args = subcommand_args(target, options);
+    args.extend(
+        ["--raw", "-z", "--no-abbrev", "--no-renames"]
+            .iter()
+            .map(ToString::to_string),
+    );
+    if options.relative {
+        args.push("--relative".to_string());
+    }
+    args.extend(target_arg(target, options));
+    args
+}
+
+/// Build the arguments for listing untracked files
+///
+/// `git ls-files` prints paths relative to the current directory; `--full-name` makes them
+/// relative to the repository root like `git diff` does, unless `--relative` is in effect.
+fn untracked_args(options: &DiffOptions) -> Vec<&'static str> {
+    let mut args = vec!["ls-files", "--others", "--exclude-standard", "-z"];
+    if !options.relative {
+        args.push("--full-name");
+    }
+    args
+}
+
+/// List the files changed inside each submodule between its old and new recorded commit
+///
+/// Paths are prefixed with the submodule path. Submodules that were added or removed have
+/// no commit range to diff and only their own path is reported. The submodule must be
+/// initialized and contain both commits.
+fn get_submodule_files(
+    submodules: &[SubmoduleChange],
+    options: &DiffOptions,
+) -> Result<Vec<ChangedFile>, String> {
+    let mut files = Vec::new();
+    // Submodule paths are relative to the top of the work tree, or to the current
+    // directory with --relative
+    let root = if options.relative || submodules.is_empty() {
+        PathBuf::new()
+    } else {
+        repo_root(options)?
+    };
+    for submodule in submodules {
+        let (Some(old), Some(new)) = (&submodule.old_commit, &submodule.new_commit) else {
+            continue;
+        };
+        let range = RevisionRange {
+            base: old.clone(),
+            head: new.clone(),
+            symmetric: false,
+        };
+        let dir = root.join(&submodule.path);
+        let global_args = ["-C".to_string(), dir.to_string_lossy().into_owned()];
+        // The submodule has its own git directory, discovered from its path
+        let submodule_options = DiffOptions {
+            git_dir: None,
+            work_tree: None,
+            ..options.clone()
+        };
+        let changed =
+            list_changed_files(&submodule_options, &global_args, DiffTarget::Range(&range))
+                .map_err(|e| format!("Cannot diff submodule '{}': {e}", submodule.path))?;
+        let prefix = |path: String| format!("{}/{path}", submodule.path);
+        files.extend(changed.into_iter().map(|file| ChangedFile {
+            path: prefix(file.path),
+            old_path: file.old_path.map(prefix),
+            kind: file.kind,
+        }));
+    }
+    Ok(files)
+}
+
+/// Append `marker` to the path of every changed file that is a submodule
+fn mark_submodules(files: &mut [ChangedFile], submodules: &[SubmoduleChange], marker: &str) {
+    for file in files {
+        if submodules
+            .iter()
+            .any(|submodule| submodule.path == file.path)
+        {
+            file.path.push_str(marker);
+        }
+    }
+}
+
+/// Parse `git diff --raw -z --no-abbrev --no-renames` output into submodule changes
+///
+/// Only entries with the gitlink mode on either side are kept; an all-zero SHA marks a
+/// side that does not exist.
+fn parse_submodule_changes(output: &[u8]) -> Result<Vec<SubmoduleChange>, String> {
+    let commit =
+        |mode: &str, id: &str| (mode == GITLINK_MODE && !is_null_id(id)).then(|| id.to_string());
+    Ok(parse_raw(output)?
+        .into_iter()
+        .filter(|entry| entry.old_mode == GITLINK_MODE || entry.new_mode == GITLINK_MODE)
+        .map(|entry| SubmoduleChange {
+            old_commit: commit(&entry.old_mode, &entry.old_id),
+            new_commit: commit(&entry.new_mode, &entry.new_id),
+            path: entry.path,
+        })
+        .collect())
+}
+
+/// One entry of `git diff --raw -z --no-abbrev --no-renames` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RawEntry {
+    old_mode: String,
+    new_mode: String,
+    old_id: String,
+    new_id: String,
+    path: String,
+}
+
+/// Parse `git diff --raw -z --no-abbrev --no-renames` output
+///
+/// Each entry is `:<old mode> <new mode> <old id> <new id> <status>\0<path>\0`.
+fn parse_raw(output: &[u8]) -> Result<Vec<RawEntry>, String> {
+    let mut fields = output
+        .split(|&b| b == 0)
+        .map(|field| String::from_utf8_lossy(field).into_owned());
+    let mut entries = Vec::new();
+
+    while let Some(header) = fields.next() {
+        if header.is_empty() {
+            continue;
+        }
+        let path = fields
+            .next()
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| format!("Missing path after git diff entry {header}"))?;
+        let parts: Vec<&str> = header.trim_start_matches(':').split(' ').collect();
+        let [old_mode, new_mode, old_id, new_id, _status] = parts[..] else {
+            return Err(format!("Malformed git diff entry: {header}"));
+        };
+        entries.push(RawEntry {
+            old_mode: old_mode.to_string(),
+            new_mode: new_mode.to_string(),
+            old_id: old_id.to_string(),
+            new_id: new_id.to_string(),
+            path,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Parse `git diff --numstat -z` output into (new path, lines changed) pairs
+///
+/// Entries are `<added>\t<deleted>\t<path>\0`, or `<added>\t<deleted>\t\0<old>\0<new>\0` for
+/// renames and copies. Binary files report `-` for both counts.
+fn parse_numstat(output: &[u8]) -> Result<Vec<(String, Option<usize>)>, String> {
+    let mut fields = output
+        .split(|&b| b == 0)
+        .map(|field| String::from_utf8_lossy(field).into_owned());
+    let mut counts = Vec::new();
+
+    while let Some(entry) = fields.next() {
+        if entry.is_empty() {
+            continue;
+        }
+        let mut parts = entry.splitn(3, '\t');
+        let (Some(added), Some(deleted), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("Malformed git numstat entry: {entry}"));
+        };
+        let path = if path.is_empty() {
+            // Rename or copy: the old and new paths follow as separate fields
+            fields.next();
+            fields
+                .next()
+                .filter(|path| !path.is_empty())
+                .ok_or_else(|| format!("Missing path after git numstat entry {entry}"))?
+        } else {
+            path.to_string()
+        };
+        let lines = match (added.parse::<usize>(), deleted.parse::<usize>()) {
+            (Ok(added), Ok(deleted)) => Some(added + deleted),
+            _ if added == "-" && deleted == "-" => None,
+            _ => return Err(format!("Malformed git numstat entry: {entry}")),
+        };
+        counts.push((path, lines));
+    }
+
+    Ok(counts)
+}
+
+/// Parse `git ls-files -z` output into changed files
+fn parse_untracked(output: &[u8]) -> impl Iterator<Item = ChangedFile> + '_ {
+    output
+        .split(|&b| b == 0)
+        .filter(|field| !field.is_empty())
+        .map(|field| ChangedFile {
+            path: String::from_utf8_lossy(field).into_owned(),
+            old_path: None,
+            kind: ChangeKind::Added,
+        })
+}
+
+/// Remove files whose path was already reported, keeping the first occurrence
+fn dedup_by_path(files: &mut Vec<ChangedFile>) {
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.path.clone()));
+}
+
+/// Execute a git command and return its trimmed stdout as text
+fn run_git_text(options: &DiffOptions, args: &[&str]) -> Result<String, String> {
+    let stdout = run_git(options, args)?;
+    String::from_utf8(stdout)
+        .map(|text| text.trim().to_string())
+        .map_err(|e| format!("Failed to parse git output as UTF-8: {e}"))
+}
+
+/// Execute a git command and return raw stdout
+///
+/// Failures whose stderr looks transient (lock contention, network hiccups, unreadable
+/// objects) are retried according to the retry policy in `options`.
+fn run_git<S: AsRef<std::ffi::OsStr>>(
+    options: &DiffOptions,
+    args: &[S],
+) -> Result<Vec<u8>, String> {
+    let mut attempt = 0;
+    loop {
+        let output = git_command(options, args)
+            .output()
+            .map_err(|e| format!("Failed to execute git command: {e}"))?;
+
+        if output.status.success() {
+            return Ok(output.stdout);
+        }
+        retry_after_failure(options, attempt, options.retry.retries, &output.stderr)?;
+        attempt += 1;
+    }
+}
+
+/// Start building a git command with `args` that runs against the configured git
+/// directory
+fn git_command<S: AsRef<std::ffi::OsStr>>(options: &DiffOptions, args: &[S]) -> Command {
+    let text_args: Vec<String> = args
+        .iter()
+        .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+        .collect();
+    log!(Debug, "git", "Running git {}", text_args.join(" "));
+    log::event("git_command", || {
+        vec![(
+            "args",
+            Value::Array(text_args.into_iter().map(Value::String).collect()),
+        )]
+    });
+    let mut command = Command::new("git");
+    // An inherited GIT_DIR would override repository discovery, including inside
+    // submodules, so the configured git directory is always passed explicitly
+    command.env_remove("GIT_DIR");
+    if let Some(git_dir) = &options.git_dir {
+        command.arg("--git-dir").arg(git_dir);
+    }
+    if let Some(work_tree) = &options.work_tree {
+        command.arg("--work-tree").arg(work_tree);
+    }
+    command.args(args);
+    command
+}
+
+/// Resolve a `--git-dir` path to a git directory and, for linked worktrees, its work tree
+///
+/// `path` may be a git directory or a `.git` file (`gitdir: <path>`) as found at the top
+/// of linked worktrees and submodules. Given an explicit git directory, git takes the
+/// current directory as the top of the work tree unless the repository configures one;
+/// the administrative directory of a linked worktree never does, but records the `.git`
+/// file of its checkout in `gitdir`, whose parent is returned as the work tree.
+pub fn locate_git_dir(path: &Path) -> Result<(PathBuf, Option<PathBuf>), String> {
+    let git_dir = if path.is_file() {
+        read_gitfile(path)?
+    } else if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        return Err(format!("Git directory not found: {}", path.display()));
+    };
+
+    let work_tree = if git_dir.join("commondir").is_file() {
+        read_pointer(&git_dir.join("gitdir"))
+            .ok()
+            .and_then(|gitfile| gitfile.parent().map(Path::to_path_buf))
+    } else {
+        None
+    };
+    Ok((git_dir, work_tree))
+}
+
+/// Follow a `.git` file to the git directory it names
+fn read_gitfile(path: &Path) -> Result<PathBuf, String> {
+    let invalid = || format!("Not a git directory or .git file: {}", path.display());
+    let contents = fs::read_to_string(path).map_err(|_| invalid())?;
+    let target = contents
+        .trim_end()
+        .strip_prefix("gitdir: ")
+        .ok_or_else(invalid)?;
+    let git_dir = path.parent().unwrap_or(Path::new(".")).join(target);
+    if git_dir.is_dir() {
+        Ok(git_dir)
+    } else {
+        Err(format!(
+            "Git directory not found: {} (named by {})",
+            git_dir.display(),
+            path.display()
+        ))
+    }
+}
+
+/// Read a file holding a single path, resolved against the file's directory
+fn read_pointer(path: &Path) -> Result<PathBuf, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    Ok(path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(contents.trim_end()))
+}
+
+/// Whether the work tree is a sparse checkout (`core.sparseCheckout`)
+pub fn is_sparse_checkout(options: &DiffOptions) -> Result<bool, String> {
+    let enabled = run_git_text(
+        options,
+        &[
+            "config",
+            "--type=bool",
+            "--default=false",
+            "core.sparseCheckout",
+        ],
+    )?;
+    Ok(enabled == "true")
+}
+
+/// The files tracked in the index, across the whole work tree
+///
+/// Paths are relative to the top of the work tree, or with `--relative`, relative to the
+/// current directory and limited to the files under it, matching the paths reported by
+/// the diff.
+pub fn tracked_files(options: &DiffOptions) -> Result<Vec<String>, String> {
+    let mut args = vec!["ls-files", "-z"];
+    if !options.relative {
+        args.extend(["--full-name", ":/"]);
+    }
+    let listing = run_git(options, &args)?;
+    Ok(listing
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| String::from_utf8_lossy(path).into_owned())
+        .collect())
+}
+
+/// Read which files a sparse checkout leaves out
+///
+/// Paths are relative to the top of the work tree, or to the current directory with
+/// `--relative`, matching the paths reported by the diff.
+pub fn sparse_checkout(options: &DiffOptions) -> Result<SparseCheckout, String> {
+    let mut args = vec!["ls-files", "-t", "-z"];
+    if !options.relative {
+        args.push("--full-name");
+    }
+    let listing = run_git(options, &args)?;
+    let root = if options.relative {
+        PathBuf::new()
+    } else {
+        repo_root(options)?
+    };
+    Ok(SparseCheckout {
+        skipped: parse_skipped_entries(&listing).collect(),
+        root,
+    })
+}
+
+/// Paths tagged skip-worktree (`S`) in `git ls-files -t -z` output
+fn parse_skipped_entries(output: &[u8]) -> impl Iterator<Item = String> + '_ {
+    output
+        .split(|&b| b == 0)
+        .filter_map(|entry| entry.strip_prefix(b"S "))
+        .map(|path| String::from_utf8_lossy(path).into_owned())
+}
+
+/// The paths among `paths` for which `attribute` is set (`<attribute>` or
+/// `<attribute>=true` in `.gitattributes`), as the checked-out work tree and the index
+/// define it
+///
+/// Paths are relative to the top of the work tree, or to the current directory with
+/// `--relative`, matching the paths reported by the diff.
+pub fn paths_with_attribute(
+    options: &DiffOptions,
+    attribute: &str,
+    paths: &[&str],
+) -> Result<HashSet<String>, String> {
+    if paths.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let root = if options.relative {
+        PathBuf::new()
+    } else {
+        repo_root(options)?
+    };
+    let mut input = Vec::new();
+    for path in paths {
+        input.extend(root.join(path).to_string_lossy().as_bytes());
+        input.push(0);
+    }
+    let mut child = git_command(options, &["check-attr", "-z", "--stdin", attribute])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute git command: {e}"))?;
+    // Written from another thread, as git answers while still reading
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or("Failed to write to git check-attr")?;
+    let writer = thread::spawn(move || std::io::Write::write_all(&mut stdin, &input));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to execute git command: {e}"))?;
+    let written = writer
+        .join()
+        .map_err(|_| "Failed to write to git check-attr")?;
+    if !output.status.success() {
+        return Err(format!(
+            "git check-attr failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    written.map_err(|e| format!("Failed to write to git check-attr: {e}"))?;
+    parse_attribute_values(&output.stdout, paths)
+}
+
+/// The `paths` whose value is `set` or `true` in `git check-attr -z` output, which
+/// holds a `<path> NUL <attribute> NUL <value> NUL` record per path, in order
+fn parse_attribute_values(output: &[u8], paths: &[&str]) -> Result<HashSet<String>, String> {
+    let fields: Vec<&[u8]> = output.split(|&b| b == 0).collect();
+    let records: Vec<&[&[u8]]> = fields.chunks_exact(3).collect();
+    if records.len() != paths.len() {
+        return Err(format!(
+            "git check-attr answered {} of {} paths",
+            records.len(),
+            paths.len()
+        ));
+    }
+    Ok(paths
+        .iter()
+        .zip(records)
+        .filter(|(_, record)| matches!(record[2], b"set" | b"true"))
+        .map(|(path, _)| (*path).to_string())
+        .collect())
+}
+
+/// The git directory shared by every worktree of the repository
+pub fn common_dir(options: &DiffOptions) -> Result<PathBuf, String> {
+    run_git_text(
+        options,
+        &["rev-parse", "--path-format=absolute", "--git-common-dir"],
+    )
+    .map(PathBuf::from)
+}
+
+/// Top-level directory of the work tree that git commands run against
+pub fn repo_root(options: &DiffOptions) -> Result<PathBuf, String> {
+    run_git_text(options, &["rev-parse", "--show-toplevel"]).map(PathBuf::from)
+}
+
+/// A snapshot of what a combined diff sees, which changes whenever its result could:
+/// HEAD, the staged blobs, and the size and modification time of every modified,
+/// deleted or untracked file
+///
+/// Comparing snapshots is much cheaper than diffing, which only `--watch` reruns when
+/// they differ.
+pub fn work_tree_state(options: &DiffOptions) -> Result<Vec<u8>, String> {
+    let mut state = run_git(options, &["rev-parse", "HEAD"])?;
+    state.extend(run_git(options, &["diff", "--cached", "--raw", "-z"])?);
+    let listing = run_git(
+        options,
+        &[
+            "ls-files",
+            "--modified",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "-z",
+            "--",
+            ":/",
+        ],
+    )?;
+    let root = repo_root(options)?;
+    for path in listing.split(|&b| b == 0).filter(|path| !path.is_empty()) {
+        state.extend_from_slice(path);
+        let metadata = fs::symlink_metadata(root.join(String::from_utf8_lossy(path).as_ref()));
+        if let Ok(metadata) = metadata {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok());
+            state.extend(format!(" {} {modified:?}\0", metadata.len()).into_bytes());
+        }
+    }
+    Ok(state)
+}
+
+/// Wait before retrying a failed git command, or return the failure as an error
+///
+/// Only transient failures are retried, at most `retries` times.
+fn retry_after_failure(
+    options: &DiffOptions,
+    attempt: usize,
+    retries: usize,
+    stderr: &[u8],
+) -> Result<(), String> {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr_trimmed = stderr.trim();
+    if attempt >= retries || !is_transient_failure(stderr_trimmed) {
+        return Err(format!("Git command failed: {stderr_trimmed}"));
+    }
+    let delay = options.retry.delay_for(attempt);
+    log!(
+        Warn,
+        "git",
+        "Git command failed with a transient error, retrying in {}ms: {stderr_trimmed}",
+        delay.as_millis()
+    );
+    thread::sleep(delay);
+    Ok(())
+}
+
+/// Stderr fragments of failures that are never worth retrying
+const FATAL_PATTERNS: &[&str] = &[
+    "not a git repository",
+    "unknown revision",
+    "bad revision",
+    "ambiguous argument",
+    "invalid object name",
+    "Not a valid object name",
+];
+
+/// Stderr fragments of failures that may succeed when retried
+const TRANSIENT_PATTERNS: &[&str] = &[
+    "index.lock",
+    "Unable to create",
+    "cannot lock ref",
+    "Resource temporarily unavailable",
+    "Connection reset",
+    "Connection timed out",
+    "Operation timed out",
+    "Could not resolve host",
+    "early EOF",
+    "RPC failed",
+    "remote end hung up unexpectedly",
+    "packfile",
+    "failed to read object",
+];
+
+/// Check whether a git failure is likely transient
+fn is_transient_failure(stderr: &str) -> bool {
+    !FATAL_PATTERNS
+        .iter()
+        .any(|pattern| stderr.contains(pattern))
+        && TRANSIENT_PATTERNS
+            .iter()
+            .any(|pattern| stderr.contains(pattern))
+}
+
+/// Parse `git diff --name-status -z` output into a list of changed files
+///
+/// Fields are NUL-terminated: `<status>\0<path>\0`, or `<status>\0<old path>\0<new path>\0`
+/// for renames and copies. With `-z` git writes paths verbatim (no `core.quotePath`
+/// escaping), so names containing newlines, tabs, quotes or non-ASCII bytes survive intact.
+/// Bytes that are not valid UTF-8 are replaced with `U+FFFD`.
+fn parse_git_output(output: &[u8]) -> Result<Vec<ChangedFile>, String> {
+    let fields = output
+        .split(|&b| b == 0)
+        .map(|field| Ok(String::from_utf8_lossy(field).into_owned()));
+    NameStatusRecords { fields }.collect()
+}
+
+/// Changed files parsed from the NUL-separated fields of `git diff --name-status -z`
+struct NameStatusRecords<I> {
+    fields: I,
+}
+
+impl<I> Iterator for NameStatusRecords<I>
+where
+    I: Iterator<Item = Result<String, String>>,
+{
+    type Item = Result<ChangedFile, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let status = loop {
+            match self.fields.next()? {
+                // Trailing terminator
+                Ok(status) if status.is_empty() => {}
+                status => break status,
+            }
+        };
+        Some(status.and_then(|status| self.record(&status)))
+    }
+}
+
+impl<I> NameStatusRecords<I>
+where
+    I: Iterator<Item = Result<String, String>>,
+{
+    /// Read the path fields following a status field
+    fn record(&mut self, status: &str) -> Result<ChangedFile, String> {
+        let mut next_path = || {
+            self.fields
+                .next()
+                .transpose()?
+                .filter(|path| !path.is_empty())
+                .ok_or_else(|| format!("Missing path after git diff status {status}"))
+        };
+
+        let kind = ChangeKind::from_status(status);
+        let file = if matches!(kind, ChangeKind::Renamed | ChangeKind::Copied) {
+            let old_path = next_path()?;
+            ChangedFile {
+                path: next_path()?,
+                old_path: Some(old_path),
+                kind,
+            }
+        } else {
+            ChangedFile {
+                path: next_path()?,
+                old_path: None,
+                kind,
+            }
+        };
+        Ok(file)
+    }
+}
+
+/// Run git in `dir` with a fixed identity, panicking on failure
+#[cfg(test)]
+pub(crate) fn git_in(dir: &Path, args: &[&str]) {
     let output = Command::new("git")
-        .args(["diff", "--name-only", &format!("{base_ref}..HEAD")])
+        .env_remove("GIT_DIR")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
         .output()
-        .map_err(|e| format!("Failed to execute git command: {e}"))?;
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {args:?}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Create an empty directory for a test repository named after `name`, returning it
+/// and a function running git in it with [`git_in`]
+#[cfg(test)]
+pub(crate) fn temp_repo(name: &str) -> (PathBuf, impl Fn(&[&str])) {
+    let repo = std::env::temp_dir().join(format!("gdf_test_{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&repo);
+    fs::create_dir_all(&repo).unwrap();
+    let dir = repo.clone();
+    (repo, move |args: &[&str]| git_in(&dir, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(files: &[ChangedFile]) -> Vec<&str> {
+        files.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_git_output_single_file() {
+        let output = b"M\0file.txt\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["file.txt"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_multiple_files() {
+        let output = b"M\0file1.txt\0A\0file2.rs\0D\0file3.md\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["file1.txt", "file2.rs", "file3.md"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_with_paths() {
+        let output = b"M\0src/main.rs\0M\0README.md\0A\0docs/guide.md\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(
+            paths(&result),
+            vec!["src/main.rs", "README.md", "docs/guide.md"]
+        );
+    }
+
+    #[test]
+    fn test_parse_git_output_empty() {
+        let output = b"";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(result, Vec::<ChangedFile>::new());
+    }
+
+    #[test]
+    fn test_parse_git_output_keeps_whitespace_in_paths() {
+        let output = b"M\0  file1.txt  \0M\0file2.rs\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["  file1.txt  ", "file2.rs"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_no_trailing_terminator() {
+        let output = b"M\0file1.txt\0M\0file2.rs";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["file1.txt", "file2.rs"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_newlines_in_paths() {
+        let output = b"M\0line\nbreak.txt\0M\0crlf\r\n.txt\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["line\nbreak.txt", "crlf\r\n.txt"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_quotes_and_tabs_in_paths() {
+        let output = b"A\0say \"hi\".txt\0A\0tab\there.txt\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["say \"hi\".txt", "tab\there.txt"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_non_ascii_paths() {
+        let output = "M\0docs/café.md\0M\0日本語/ファイル.rs\0".as_bytes();
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["docs/café.md", "日本語/ファイル.rs"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_invalid_utf8_replaced() {
+        let output = b"M\0bad\xff.txt\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["bad\u{fffd}.txt"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_deep_paths() {
+        let output = b"M\0a/b/c/d/file.txt\0M\0x/y/z/file.rs\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(paths(&result), vec!["a/b/c/d/file.txt", "x/y/z/file.rs"]);
+    }
+
+    #[test]
+    fn test_parse_git_output_special_characters_in_path() {
+        let output = b"M\0file-name.txt\0M\0file_name.rs\0M\0file.test.md\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(
+            paths(&result),
+            vec!["file-name.txt", "file_name.rs", "file.test.md"]
+        );
+    }
+
+    #[test]
+    fn test_parse_git_output_rename() {
+        let output = b"R100\0src/a.rs\0lib/a.rs\0M\0README.md\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                ChangedFile {
+                    path: "lib/a.rs".to_string(),
+                    old_path: Some("src/a.rs".to_string()),
+                    kind: ChangeKind::Renamed,
+                },
+                ChangedFile {
+                    path: "README.md".to_string(),
+                    old_path: None,
+                    kind: ChangeKind::Modified,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_git_output_missing_path() {
+        let result = parse_git_output(b"M\0");
+        assert_eq!(
+            result,
+            Err("Missing path after git diff status M".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_git_output_rename_missing_new_path() {
+        let result = parse_git_output(b"R100\0src/a.rs\0");
+        assert!(result.unwrap_err().contains("R100"));
+    }
+
+    #[test]
+    fn test_match_paths_for_rename() {
+        let file = ChangedFile {
+            path: "lib/a.rs".to_string(),
+            old_path: Some("src/a.rs".to_string()),
+            kind: ChangeKind::Renamed,
+        };
+        assert_eq!(
+            file.match_paths(RenamePaths::Both).collect::<Vec<_>>(),
+            vec!["lib/a.rs", "src/a.rs"]
+        );
+        assert_eq!(
+            file.match_paths(RenamePaths::New).collect::<Vec<_>>(),
+            vec!["lib/a.rs"]
+        );
+    }
+
+    #[test]
+    fn test_rename_paths_from_str() {
+        assert_eq!("both".parse(), Ok(RenamePaths::Both));
+        assert_eq!("new".parse(), Ok(RenamePaths::New));
+        assert!("old".parse::<RenamePaths>().is_err());
+    }
+
+    #[test]
+    fn test_parse_git_output_copy() {
+        let output = b"C075\0templates/base.yml\0services/api/deploy.yml\0";
+        let result = parse_git_output(output).unwrap();
+        assert_eq!(
+            result,
+            vec![ChangedFile {
+                path: "services/api/deploy.yml".to_string(),
+                old_path: Some("templates/base.yml".to_string()),
+                kind: ChangeKind::Copied,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_args_default() {
+        let args = diff_args(DiffTarget::Range(&range("main")), &DiffOptions::default());
+        assert_eq!(
+            args,
+            vec!["diff", "--name-status", "-z", "-M", "main..HEAD"]
+        );
+    }
+
+    #[test]
+    fn test_diff_args_find_copies() {
+        let options = DiffOptions {
+            find_copies: true,
+            ..Default::default()
+        };
+        let args = diff_args(DiffTarget::Range(&range("main")), &options);
+        assert_eq!(
+            args,
+            vec!["diff", "--name-status", "-z", "-M", "-C", "main..HEAD"]
+        );
+    }
+
+    #[test]
+    fn test_diff_args_ignore_whitespace() {
+        let options = DiffOptions {
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        let args = diff_args(DiffTarget::Range(&range("main")), &options);
+        assert_eq!(
+            args,
+            vec![
+                "diff",
+                "--name-status",
+                "-z",
+                "-M",
+                "-w",
+                "--ignore-blank-lines",
+                "main..HEAD"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_args_ignore_content() {
+        let options = DiffOptions {
+            ignore_content: vec!["^version = ".to_string(), "Copyright".to_string()],
+            ..Default::default()
+        };
+        let args = diff_args(DiffTarget::Staged, &options);
+        assert_eq!(
+            args,
+            vec![
+                "diff",
+                "--name-status",
+                "-z",
+                "-M",
+                "-I^version = ",
+                "-ICopyright",
+                "--cached"
+            ]
+        );
+        assert!(!options.is_single_pass());
+    }
+
+    #[test]
+    fn test_pathspec_args() {
+        let specs = [
+            Pathspec {
+                path: "src".to_string(),
+                exclude: false,
+            },
+            Pathspec {
+                path: "src/vendor/".to_string(),
+                exclude: true,
+            },
+        ];
+        assert_eq!(
+            pathspec_args(&specs, &DiffOptions::default()),
+            vec![
+                "--",
+                ":(top,literal)src",
+                ":(top,exclude,literal)src/vendor/"
+            ]
+        );
+        let relative = DiffOptions {
+            relative: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            pathspec_args(&specs[..1], &relative),
+            vec!["--", ":(literal)src"]
+        );
+        assert!(pathspec_args(&[], &relative).is_empty());
+    }
+
+    #[test]
+    fn test_content_kind_from_line_count() {
+        assert_eq!(ContentKind::from_line_count(Some(0)), ContentKind::Text);
+        assert_eq!(ContentKind::from_line_count(Some(12)), ContentKind::Text);
+        assert_eq!(ContentKind::from_line_count(None), ContentKind::Binary);
+        let counts: HashMap<_, _> = parse_numstat(b"-\t-\tlogo.png\x003\t1\tmain.rs\0")
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            ContentKind::from_line_count(counts["logo.png"]),
+            ContentKind::Binary
+        );
+        assert_eq!(
+            ContentKind::from_line_count(counts["main.rs"]),
+            ContentKind::Text
+        );
+    }
+
+    #[test]
+    fn test_is_branch_ref() {
+        assert!(is_branch_ref("refs/heads/main"));
+        assert!(is_branch_ref("refs/remotes/origin/main"));
+        assert!(!is_branch_ref("refs/tags/v1.0"));
+        assert!(!is_branch_ref(""));
+    }
+
+    fn range(spec: &str) -> RevisionRange {
+        RevisionRange::parse(spec).unwrap()
+    }
+
+    #[test]
+    fn test_revision_range_single_revision() {
+        assert_eq!(
+            range("HEAD~5"),
+            RevisionRange {
+                base: "HEAD~5".to_string(),
+                head: "HEAD".to_string(),
+                symmetric: false,
+            }
+        );
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stderr_trimmed = stderr.trim();
-        return Err(format!("Git command failed: {stderr_trimmed}"));
+    #[test]
+    fn test_revision_range_two_dots() {
+        let parsed = range("v1.2.0..release/2.x");
+        assert_eq!(parsed.base, "v1.2.0");
+        assert_eq!(parsed.head, "release/2.x");
+        assert!(!parsed.symmetric);
+        assert_eq!(parsed.to_string(), "v1.2.0..release/2.x");
     }
 
-    String::from_utf8(output.stdout)
-        .map_err(|e| format!("Failed to parse git output as UTF-8: {e}"))
-}
+    #[test]
+    fn test_revision_range_three_dots() {
+        let parsed = range("main...feature");
+        assert_eq!(parsed.base, "main");
+        assert_eq!(parsed.head, "feature");
+        assert!(parsed.symmetric);
+        assert_eq!(parsed.to_string(), "main...feature");
+    }
 
-/// Parse git diff output into a list of file paths
-fn parse_git_output(output: &str) -> Vec<String> {
-    output
-        .lines()
-        .map(str::trim)
-        .filter(|s| !s.is_empty())
-        .map(str::to_string)
-        .collect()
-}
+    #[test]
+    fn test_revision_range_sha_pair() {
+        let parsed = range("1a2b3c4..5d6e7f8");
+        assert_eq!(parsed.to_string(), "1a2b3c4..5d6e7f8");
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_revision_range_empty_side_defaults_to_head() {
+        assert_eq!(range("main..").to_string(), "main..HEAD");
+        assert_eq!(range("..feature").to_string(), "HEAD..feature");
+    }
 
     #[test]
-    fn test_parse_git_output_single_file() {
-        let output = "file.txt\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["file.txt"]);
+    fn test_revision_range_rejects_options_and_extra_dots() {
+        assert!(RevisionRange::parse("--output=x").is_err());
+        assert!(RevisionRange::parse("a..b..c").is_err());
     }
 
     #[test]
-    fn test_parse_git_output_multiple_files() {
-        let output = "file1.txt\nfile2.rs\nfile3.md\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["file1.txt", "file2.rs", "file3.md"]);
+    fn test_diff_args_range() {
+        let args = diff_args(DiffTarget::Range(&range("v1..v2")), &DiffOptions::default());
+        assert_eq!(args.last().unwrap(), "v1..v2");
     }
 
     #[test]
-    fn test_parse_git_output_with_paths() {
-        let output = "src/main.rs\nREADME.md\ndocs/guide.md\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["src/main.rs", "README.md", "docs/guide.md"]);
+    fn test_diff_args_first_parent() {
+        let options = DiffOptions {
+            first_parent: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            diff_args(DiffTarget::Range(&range("main...feature")), &options),
+            vec![
+                "log",
+                "--first-parent",
+                "--no-merges",
+                "--format=",
+                "--name-status",
+                "-z",
+                "-M",
+                "main..feature"
+            ]
+        );
+        assert_eq!(
+            raw_args(DiffTarget::Range(&range("v1")), &options),
+            vec![
+                "log",
+                "--first-parent",
+                "--no-merges",
+                "--format=",
+                "--raw",
+                "-z",
+                "--no-abbrev",
+                "--no-renames",
+                "v1..HEAD"
+            ]
+        );
+        // Local changes are not commits and are still diffed
+        assert_eq!(
+            diff_args(DiffTarget::Staged, &options),
+            vec!["diff", "--name-status", "-z", "-M", "--cached"]
+        );
     }
 
     #[test]
-    fn test_parse_git_output_empty() {
-        let output = "";
-        let result = parse_git_output(output);
-        assert_eq!(result, Vec::<String>::new());
+    fn test_diff_args_local_targets() {
+        let options = DiffOptions::default();
+        assert_eq!(
+            diff_args(DiffTarget::Staged, &options),
+            vec!["diff", "--name-status", "-z", "-M", "--cached"]
+        );
+        assert_eq!(
+            diff_args(DiffTarget::Unstaged, &options),
+            vec!["diff", "--name-status", "-z", "-M"]
+        );
     }
 
     #[test]
-    fn test_parse_git_output_only_newlines() {
-        let output = "\n\n\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, Vec::<String>::new());
+    fn test_parse_untracked() {
+        let files: Vec<ChangedFile> = parse_untracked(b"new.rs\0dir/other.md\0").collect();
+        assert_eq!(paths(&files), vec!["new.rs", "dir/other.md"]);
+        assert!(files.iter().all(|f| f.old_path.is_none()));
+        assert!(files.iter().all(|f| f.kind == ChangeKind::Added));
     }
 
     #[test]
-    fn test_parse_git_output_with_whitespace() {
-        let output = "  file1.txt  \n  file2.rs\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["file1.txt", "file2.rs"]);
+    fn test_dedup_by_path_keeps_first() {
+        let mut files = parse_git_output(b"R100\0old.rs\0a.rs\0M\0b.rs\0M\0a.rs\0").unwrap();
+        dedup_by_path(&mut files);
+        assert_eq!(paths(&files), vec!["a.rs", "b.rs"]);
+        assert_eq!(files[0].old_path.as_deref(), Some("old.rs"));
+    }
+
+    #[test]
+    fn test_diff_source_from_str() {
+        assert_eq!("committed".parse(), Ok(DiffSource::Committed));
+        assert_eq!("combined".parse(), Ok(DiffSource::Combined));
+        assert!("staged".parse::<DiffSource>().is_err());
+    }
+
+    const OLD: &str = "1111111111111111111111111111111111111111";
+    const NEW: &str = "2222222222222222222222222222222222222222";
+    const ZERO: &str = "0000000000000000000000000000000000000000";
+
+    fn raw_entry(old_mode: &str, new_mode: &str, old: &str, new: &str, path: &str) -> String {
+        format!(":{old_mode} {new_mode} {old} {new} M\0{path}\0")
     }
 
     #[test]
-    fn test_parse_git_output_mixed_whitespace() {
-        let output = "file1.txt\n\nfile2.rs\n  \nfile3.md\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["file1.txt", "file2.rs", "file3.md"]);
+    fn test_parse_submodule_changes_pointer_moved() {
+        let output = raw_entry("160000", "160000", OLD, NEW, "vendor/lib")
+            + &raw_entry("100644", "100644", OLD, NEW, "README.md");
+        let result = parse_submodule_changes(output.as_bytes()).unwrap();
+        assert_eq!(
+            result,
+            vec![SubmoduleChange {
+                path: "vendor/lib".to_string(),
+                old_commit: Some(OLD.to_string()),
+                new_commit: Some(NEW.to_string()),
+            }]
+        );
     }
 
     #[test]
-    fn test_parse_git_output_no_trailing_newline() {
-        let output = "file1.txt\nfile2.rs";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["file1.txt", "file2.rs"]);
+    fn test_parse_submodule_changes_added_and_removed() {
+        let output = raw_entry("000000", "160000", ZERO, NEW, "added")
+            + &raw_entry("160000", "000000", OLD, ZERO, "removed");
+        let result = parse_submodule_changes(output.as_bytes()).unwrap();
+        assert_eq!(result[0].old_commit, None);
+        assert_eq!(result[0].new_commit.as_deref(), Some(NEW));
+        assert_eq!(result[1].old_commit.as_deref(), Some(OLD));
+        assert_eq!(result[1].new_commit, None);
     }
 
     #[test]
-    fn test_parse_git_output_windows_newlines() {
-        let output = "file1.txt\r\nfile2.rs\r\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["file1.txt", "file2.rs"]);
+    fn test_parse_submodule_changes_replaced_by_file() {
+        let output = raw_entry("160000", "100644", OLD, NEW, "lib");
+        let result = parse_submodule_changes(output.as_bytes()).unwrap();
+        assert_eq!(result[0].old_commit.as_deref(), Some(OLD));
+        assert_eq!(result[0].new_commit, None);
     }
 
     #[test]
-    fn test_parse_git_output_deep_paths() {
-        let output = "a/b/c/d/file.txt\nx/y/z/file.rs\n";
-        let result = parse_git_output(output);
-        assert_eq!(result, vec!["a/b/c/d/file.txt", "x/y/z/file.rs"]);
+    fn test_parse_submodule_changes_malformed() {
+        assert!(parse_submodule_changes(b":160000 160000\0lib\0").is_err());
+        assert!(parse_submodule_changes(b":160000 160000 a b M\0").is_err());
     }
 
     #[test]
-    fn test_parse_git_output_special_characters_in_path() {
-        let output = "file-name.txt\nfile_name.rs\nfile.test.md\n";
-        let result = parse_git_output(output);
+    fn test_mode_only_paths() {
+        let output = raw_entry("100644", "100755", OLD, OLD, "run.sh")
+            + &raw_entry("100644", "100755", OLD, NEW, "edited.sh")
+            + &raw_entry("100644", "100644", OLD, NEW, "README.md")
+            + &raw_entry("000000", "100755", ZERO, NEW, "new.sh")
+            + &raw_entry("120000", "100644", OLD, OLD, "link");
+        let entries = parse_raw(output.as_bytes()).unwrap();
+        let mode_only = mode_only_paths(&entries, &DiffOptions::default()).unwrap();
         assert_eq!(
-            result,
-            vec!["file-name.txt", "file_name.rs", "file.test.md"]
+            mode_only,
+            HashSet::from(["run.sh".to_string(), "link".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_mode_only_paths_across_commits() {
+        // One commit changed the mode, another the content
+        let output = raw_entry("100644", "100755", OLD, OLD, "run.sh")
+            + &raw_entry("100755", "100755", OLD, NEW, "run.sh")
+            + &raw_entry("100644", "100755", OLD, OLD, "build.sh");
+        let entries = parse_raw(output.as_bytes()).unwrap();
+        let mode_only = mode_only_paths(&entries, &DiffOptions::default()).unwrap();
+        assert_eq!(mode_only, HashSet::from(["build.sh".to_string()]));
+    }
+
+    #[test]
+    fn test_mode_changes_from_str() {
+        assert_eq!("include".parse(), Ok(ModeChanges::Include));
+        assert_eq!("ignore".parse(), Ok(ModeChanges::Ignore));
+        assert!("only".parse::<ModeChanges>().is_err());
+    }
+
+    #[test]
+    fn test_mark_submodules() {
+        let mut files = parse_git_output(b"M\0vendor/lib\0M\0vendor/lib.rs\0").unwrap();
+        let submodules = vec![SubmoduleChange {
+            path: "vendor/lib".to_string(),
+            old_commit: Some(OLD.to_string()),
+            new_commit: Some(NEW.to_string()),
+        }];
+        mark_submodules(&mut files, &submodules, "@");
+        assert_eq!(paths(&files), vec!["vendor/lib@", "vendor/lib.rs"]);
+    }
+
+    #[test]
+    fn test_patch_args() {
+        let options = DiffOptions {
+            find_copies: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            patch_args(DiffTarget::Range(&range("main")), &options),
+            vec![
+                "diff",
+                "-U0",
+                "--no-color",
+                "--no-ext-diff",
+                "--src-prefix=a/",
+                "--dst-prefix=b/",
+                "-M",
+                "-C",
+                "main..HEAD"
+            ]
+        );
+        assert_eq!(
+            patch_args(DiffTarget::Staged, &DiffOptions::default())
+                .last()
+                .unwrap(),
+            "--cached"
+        );
+    }
+
+    #[test]
+    fn test_parse_numstat() {
+        let output = b"3\t1\tsrc/main.rs\0-\t-\timg.png\0\x30\t0\t\0old.rs\0new.rs\0";
+        assert_eq!(
+            parse_numstat(output).unwrap(),
+            vec![
+                ("src/main.rs".to_string(), Some(4)),
+                ("img.png".to_string(), None),
+                ("new.rs".to_string(), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_numstat_path_with_tab() {
+        let output = b"1\t0\ttab\there.txt\0";
+        assert_eq!(
+            parse_numstat(output).unwrap(),
+            vec![("tab\there.txt".to_string(), Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_parse_numstat_malformed() {
+        assert!(parse_numstat(b"3\tsrc/main.rs\0").is_err());
+        assert!(parse_numstat(b"x\t1\tsrc/main.rs\0").is_err());
+        assert!(parse_numstat(b"1\t1\t\0old.rs\0").is_err());
+    }
+
+    #[test]
+    fn test_line_thresholds() {
+        let unbounded = LineThresholds::default();
+        assert!(unbounded.is_unbounded());
+        assert!(unbounded.allows(Some(0)));
+
+        let bounded = LineThresholds {
+            min: Some(2),
+            max: Some(10),
+        };
+        assert!(!bounded.is_unbounded());
+        assert!(!bounded.allows(Some(1)));
+        assert!(bounded.allows(Some(2)));
+        assert!(bounded.allows(Some(10)));
+        assert!(!bounded.allows(Some(11)));
+        assert!(bounded.allows(None));
+    }
+
+    #[test]
+    fn test_parse_git_output_change_kinds() {
+        let output = b"A\0a\0D\0d\0M\0m\0T\0t\0U\0u\0X\0x\0";
+        let kinds: Vec<ChangeKind> = parse_git_output(output)
+            .unwrap()
+            .iter()
+            .map(|f| f.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChangeKind::Added,
+                ChangeKind::Deleted,
+                ChangeKind::Modified,
+                ChangeKind::TypeChanged,
+                ChangeKind::Unmerged,
+                ChangeKind::Unknown,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_change_kind_from_str() {
+        assert_eq!("A".parse(), Ok(ChangeKind::Added));
+        assert_eq!("deleted".parse(), Ok(ChangeKind::Deleted));
+        assert_eq!("type-changed".parse(), Ok(ChangeKind::TypeChanged));
+        assert!("a".parse::<ChangeKind>().is_err());
+        assert!("moved".parse::<ChangeKind>().is_err());
+        assert_eq!(
+            ChangeKind::TypeChanged.name().parse(),
+            Ok(ChangeKind::TypeChanged)
+        );
+    }
+
+    #[test]
+    fn test_fetch_target_branch_and_tag() {
+        let listing = "1111\trefs/heads/main\n2222\trefs/tags/v1\n";
+        assert_eq!(
+            fetch_target_from_ls_remote("main", listing),
+            FetchTarget {
+                remote: "origin".to_string(),
+                refspec: "+refs/heads/main:refs/remotes/origin/main".to_string(),
+                resolved: "origin/main".to_string(),
+            }
+        );
+        let tag = fetch_target_from_ls_remote("v1", listing);
+        assert_eq!(tag.refspec, "+refs/tags/v1:refs/tags/v1");
+        assert_eq!(tag.resolved, "v1");
+    }
+
+    #[test]
+    fn test_fetch_target_commit() {
+        let target = fetch_target_from_ls_remote("1a2b3c4d", "");
+        assert_eq!(target.refspec, "1a2b3c4d");
+        assert_eq!(target.resolved, "1a2b3c4d");
+    }
+
+    #[test]
+    fn test_fetch_target_ignores_similar_refs() {
+        let listing = "1111\trefs/heads/release/main\n";
+        assert_eq!(fetch_target_from_ls_remote("main", listing).refspec, "main");
+    }
+
+    #[test]
+    fn test_deepen_args_schedule() {
+        assert_eq!(deepen_args(0, 3), vec!["fetch", "--no-tags", "--deepen=50"]);
+        assert_eq!(
+            deepen_args(1, 3),
+            vec!["fetch", "--no-tags", "--deepen=100"]
+        );
+        assert_eq!(deepen_args(2, 3), vec!["fetch", "--no-tags", "--unshallow"]);
+        assert_eq!(deepen_args(0, 1), vec!["fetch", "--no-tags", "--unshallow"]);
+    }
+
+    #[test]
+    fn test_is_transient_failure() {
+        assert!(is_transient_failure(
+            "fatal: Unable to create '/repo/.git/index.lock': File exists."
+        ));
+        assert!(is_transient_failure(
+            "error: RPC failed; curl 56 Recv failure: Connection reset by peer"
+        ));
+        assert!(is_transient_failure("fatal: early EOF"));
+        assert!(!is_transient_failure(
+            "fatal: ambiguous argument 'nope..HEAD': unknown revision or path not in the working tree."
+        ));
+        assert!(!is_transient_failure(
+            "fatal: not a git repository (or any of the parent directories): .git"
+        ));
+        assert!(!is_transient_failure(""));
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy {
+            retries: 3,
+            delay: Duration::from_millis(100),
+        };
+        assert_eq!(policy.delay_for(0), Duration::from_millis(100));
+        assert_eq!(policy.delay_for(1), Duration::from_millis(200));
+        assert_eq!(policy.delay_for(2), Duration::from_millis(400));
+        assert_eq!(RetryPolicy::default().retries, 2);
+    }
+
+    #[test]
+    fn test_relative_args() {
+        let options = DiffOptions {
+            relative: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            diff_args(DiffTarget::Range(&range("main")), &options),
+            vec![
+                "diff",
+                "--name-status",
+                "-z",
+                "-M",
+                "--relative",
+                "main..HEAD"
+            ]
+        );
+        assert!(raw_args(DiffTarget::Range(&range("main")), &options)
+            .contains(&"--relative".to_string()));
+        assert!(!untracked_args(&options).contains(&"--full-name"));
+        assert!(untracked_args(&DiffOptions::default()).contains(&"--full-name"));
+    }
+
+    #[test]
+    fn test_missing_revision_message_suggests_remote_branch() {
+        let message = missing_revision_message("main", &["origin/main".to_string()], false);
+        assert_eq!(
+            message,
+            "Cannot resolve revision 'main' to a commit. A remote-tracking branch exists: \
+             did you mean 'origin/main'?"
+        );
+
+        let candidates = vec!["origin/main".to_string(), "upstream/main".to_string()];
+        let message = missing_revision_message("main", &candidates, false);
+        assert!(message.contains("one of 'origin/main', 'upstream/main'?"));
+    }
+
+    #[test]
+    fn test_missing_revision_message_shallow_clone() {
+        let message = missing_revision_message("main", &[], true);
+        assert!(message.starts_with("Cannot resolve revision 'main' to a commit. "));
+        assert!(message.contains("shallow clone"));
+        assert!(message.contains("--auto-fetch"));
+    }
+
+    #[test]
+    fn test_missing_revision_message_suggests_fetch() {
+        let message = missing_revision_message("release", &[], false);
+        assert!(message.contains("git fetch origin release"));
+    }
+
+    #[test]
+    fn test_is_plain_ref_name() {
+        assert!(is_plain_ref_name("main"));
+        assert!(is_plain_ref_name("release/2.x"));
+        assert!(!is_plain_ref_name("HEAD~1"));
+        assert!(!is_plain_ref_name("main@{1}"));
+        assert!(!is_plain_ref_name("refs/heads/main"));
+        assert!(!is_plain_ref_name(""));
+    }
+
+    #[test]
+    fn test_pr_merge_ref() {
+        assert_eq!(pr_merge_ref(42), "refs/remotes/pull/42/merge");
+        let parsed = range(&format!("{0}^1..{0}", pr_merge_ref(42)));
+        assert_eq!(parsed.base, "refs/remotes/pull/42/merge^1");
+        assert_eq!(parsed.head, "refs/remotes/pull/42/merge");
+    }
+
+    #[test]
+    fn test_pr_merge_spec_requires_pull_request() {
+        let err = pr_merge_spec(&DiffOptions::default(), None).unwrap_err();
+        assert!(err.starts_with("-b pr-merge requires a pull_request event"));
+    }
+
+    #[test]
+    fn test_event_base_spec() {
+        assert_eq!(event_base_spec(Some("1a2b3c")), Ok("1a2b3c".to_string()));
+        let err = event_base_spec(None).unwrap_err();
+        assert!(err.starts_with("-b event requires a push or merge_group event"));
+    }
+
+    #[test]
+    fn test_find_changed_file_stops_at_first_accepted() {
+        let output = b"M\0a.txt\0R100\0old.rs\0new.rs\0D\0z.md\0";
+        let mut visited = Vec::new();
+        let found = find_changed_file(&output[..], |file| {
+            visited.push(file.path.clone());
+            Ok(file.kind == ChangeKind::Renamed)
+        })
+        .unwrap();
+        assert!(found);
+        assert_eq!(visited, vec!["a.txt", "new.rs"]);
+    }
+
+    #[test]
+    fn test_find_changed_file_reads_small_chunks() {
+        // A one-byte buffer forces every field to span several reads
+        let output: &[u8] = b"A\0src/lib.rs\0C75\0a.rs\0b.rs\0";
+        let reader = BufReader::with_capacity(1, output);
+        let mut files = Vec::new();
+        let found = find_changed_file(reader, |file| {
+            files.push(file.clone());
+            Ok(false)
+        })
+        .unwrap();
+        assert!(!found);
+        assert_eq!(files, parse_git_output(output).unwrap());
+    }
+
+    #[test]
+    fn test_find_changed_file_errors() {
+        let err = find_changed_file(&b"R100\0old.rs\0"[..], |_| Ok(false)).unwrap_err();
+        assert_eq!(err, "Missing path after git diff status R100");
+        let err = find_changed_file(&b"M\0a\0"[..], |_| Err("boom".to_string())).unwrap_err();
+        assert_eq!(err, "boom");
+    }
+
+    #[test]
+    fn test_is_single_pass() {
+        assert!(DiffOptions::default().is_single_pass());
+        for options in [
+            DiffOptions {
+                source: DiffSource::Combined,
+                ..Default::default()
+            },
+            DiffOptions {
+                submodule_marker: Some("/".to_string()),
+                ..Default::default()
+            },
+            DiffOptions {
+                recurse_submodules: true,
+                ..Default::default()
+            },
+        ] {
+            assert!(!options.is_single_pass());
+        }
+    }
+
+    #[test]
+    fn test_work_tree_state() {
+        let (main, _) = worktree_fixture("watch");
+        let options = DiffOptions {
+            git_dir: Some(main.join(".git")),
+            work_tree: Some(main.clone()),
+            ..Default::default()
+        };
+        let mut state = work_tree_state(&options).unwrap();
+        assert_eq!(work_tree_state(&options).unwrap(), state);
+        let mut assert_changed = || {
+            let changed = work_tree_state(&options).unwrap();
+            assert_ne!(changed, state);
+            state = changed;
+        };
+        fs::write(main.join("pkg/new.txt"), "untracked\n").unwrap();
+        assert_changed();
+        fs::write(main.join("pkg/new.txt"), "untracked, edited\n").unwrap();
+        assert_changed();
+        fs::write(main.join("pkg/file.txt"), "modified\n").unwrap();
+        assert_changed();
+        git_in(&main, &["add", "pkg/file.txt"]);
+        assert_changed();
+        git_in(&main, &["commit", "-q", "-m", "edit"]);
+        assert_changed();
+    }
+
+    // ========== Linked worktrees ==========
+
+    /// Create a repository on `main` with a linked worktree whose branch modifies
+    /// `pkg/file.txt`, returning the main checkout and the worktree
+    fn worktree_fixture(name: &str) -> (PathBuf, PathBuf) {
+        let (base, _) = temp_repo(name);
+        let main = base.join("main");
+        let linked = base.join("linked");
+        fs::create_dir_all(main.join("pkg")).unwrap();
+        fs::write(main.join("pkg/file.txt"), "1\n").unwrap();
+        git_in(&main, &["init", "-q"]);
+        git_in(&main, &["add", "."]);
+        git_in(&main, &["commit", "-q", "-m", "initial"]);
+        git_in(&main, &["branch", "-M", "main"]);
+        git_in(
+            &main,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "-b",
+                "feature",
+                linked.to_str().unwrap(),
+            ],
+        );
+        fs::write(linked.join("pkg/file.txt"), "2\n").unwrap();
+        git_in(&linked, &["commit", "-q", "-a", "-m", "change"]);
+        (main, linked)
+    }
+
+    fn canonical(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap()
+    }
+
+    #[test]
+    fn test_locate_git_dir_linked_worktree() {
+        let (main, linked) = worktree_fixture("locate");
+        let (git_dir, work_tree) = locate_git_dir(&linked.join(".git")).unwrap();
+        assert_eq!(
+            canonical(&git_dir),
+            canonical(&main.join(".git/worktrees/linked"))
+        );
+        assert_eq!(canonical(&work_tree.unwrap()), canonical(&linked));
+
+        // The administrative directory itself resolves to the same work tree
+        let (_, work_tree) = locate_git_dir(&git_dir).unwrap();
+        assert_eq!(canonical(&work_tree.unwrap()), canonical(&linked));
+
+        // The main repository's git directory leaves the work tree to git
+        let (git_dir, work_tree) = locate_git_dir(&main.join(".git")).unwrap();
+        assert_eq!(git_dir, main.join(".git"));
+        assert_eq!(work_tree, None);
+    }
+
+    #[test]
+    fn test_changed_files_in_linked_worktree() {
+        let (_, linked) = worktree_fixture("diff");
+        fs::write(linked.join("pkg/new.txt"), "untracked\n").unwrap();
+        let (git_dir, work_tree) = locate_git_dir(&linked.join(".git")).unwrap();
+        let options = DiffOptions {
+            git_dir: Some(git_dir),
+            work_tree,
+            ..Default::default()
+        };
+
+        assert_eq!(canonical(&repo_root(&options).unwrap()), canonical(&linked));
+
+        let resolved = resolve_range(&range("main"), &options).unwrap();
+        let files = get_changed_files(&resolved.range, &options).unwrap();
+        assert_eq!(paths(&files), vec!["pkg/file.txt"]);
+        let streamed = any_changed_file(&resolved.range, &options, &[], |file| {
+            Ok(file.path == "pkg/file.txt")
+        });
+        assert_eq!(streamed, Ok(true));
+
+        // Local changes are read from the worktree's checkout, not the current directory
+        let combined = DiffOptions {
+            source: DiffSource::Combined,
+            ..options
+        };
+        let files = get_changed_files(&resolved.range, &combined).unwrap();
+        assert_eq!(paths(&files), vec!["pkg/file.txt", "pkg/new.txt"]);
+    }
+
+    #[test]
+    fn test_locate_git_dir_errors() {
+        let (dir, _) = temp_repo("gitfile");
+        let gitfile = dir.join(".git");
+
+        fs::write(&gitfile, "not a gitfile\n").unwrap();
+        let err = locate_git_dir(&gitfile).unwrap_err();
+        assert!(err.starts_with("Not a git directory or .git file"), "{err}");
+
+        fs::write(&gitfile, "gitdir: missing\n").unwrap();
+        let err = locate_git_dir(&gitfile).unwrap_err();
+        assert!(err.starts_with("Git directory not found"), "{err}");
+
+        let err = locate_git_dir(&dir.join("absent")).unwrap_err();
+        assert!(err.starts_with("Git directory not found"), "{err}");
+    }
+
+    // ========== Sparse checkout ==========
+
+    #[test]
+    fn test_parse_skipped_entries() {
+        let output = b"H\0top.txt\0S\0docs/a.md\0S docs/b.md\0H src/lib.rs\0S web/x y.js\0";
+        let skipped: Vec<String> = parse_skipped_entries(output).collect();
+        assert_eq!(skipped, vec!["docs/b.md", "web/x y.js"]);
+    }
+
+    #[test]
+    fn test_sparse_paths_from_str() {
+        assert_eq!("include".parse(), Ok(SparsePaths::Include));
+        assert_eq!("exclude".parse(), Ok(SparsePaths::Exclude));
+        assert!("all".parse::<SparsePaths>().is_err());
+    }
+
+    #[test]
+    fn test_sparse_checkout_cone() {
+        let (repo, git) = temp_repo("sparse");
+        for dir in ["app", "docs"] {
+            fs::create_dir_all(repo.join(dir)).unwrap();
+            fs::write(repo.join(dir).join("file.txt"), dir).unwrap();
+        }
+        fs::write(repo.join("README"), "top").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        assert_eq!(is_sparse_checkout(&options), Ok(false));
+
+        git(&["sparse-checkout", "set", "--cone", "app"]);
+        assert_eq!(is_sparse_checkout(&options), Ok(true));
+        let sparse = sparse_checkout(&options).unwrap();
+        assert!(sparse.contains("app/file.txt"));
+        assert!(sparse.contains("README"));
+        assert!(!sparse.contains("docs/file.txt"));
+        // Paths missing from the index are judged by their directory
+        assert!(sparse.contains("app/deleted.txt"));
+        assert!(!sparse.contains("docs/deleted.txt"));
+        assert!(!sparse.contains("gone/deleted.txt"));
+    }
+
+    #[test]
+    fn test_parse_attribute_values() {
+        let output = b"gen.pb.go\0linguist-generated\0set\0lock.json\0linguist-generated\0true\0\
+main.go\0linguist-generated\0unspecified\0vendor.js\0linguist-generated\0false\0";
+        let paths = ["gen.pb.go", "lock.json", "main.go", "vendor.js"];
+        let generated = parse_attribute_values(output, &paths).unwrap();
+        assert_eq!(
+            generated,
+            HashSet::from(["gen.pb.go".to_string(), "lock.json".to_string()])
+        );
+        assert_eq!(
+            parse_attribute_values(b"", &paths),
+            Err("git check-attr answered 0 of 4 paths".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tracked_files() {
+        let (repo, git) = temp_repo("tracked");
+        fs::create_dir_all(repo.join("tests/auth")).unwrap();
+        fs::write(repo.join("README.md"), "# Demo\n").unwrap();
+        fs::write(repo.join("tests/auth/login.rs"), "").unwrap();
+        fs::write(repo.join("untracked.rs"), "").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "README.md", "tests"]);
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            tracked_files(&options),
+            Ok(vec![
+                "README.md".to_string(),
+                "tests/auth/login.rs".to_string()
+            ])
+        );
+        let _ = fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_paths_with_attribute() {
+        let (repo, git) = temp_repo("attr");
+        fs::create_dir_all(repo.join("api")).unwrap();
+        fs::write(
+            repo.join(".gitattributes"),
+            "*.pb.go linguist-generated\npackage-lock.json linguist-generated=true\n\
+api/keep.pb.go -linguist-generated\n",
+        )
+        .unwrap();
+        git(&["init", "-q"]);
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        let paths = [
+            "api/service.pb.go",
+            "api/keep.pb.go",
+            "api/main.go",
+            "package-lock.json",
+            "deleted/old.pb.go",
+        ];
+        let generated = paths_with_attribute(&options, "linguist-generated", &paths).unwrap();
+        let mut generated: Vec<&str> = generated.iter().map(String::as_str).collect();
+        generated.sort_unstable();
+        assert_eq!(
+            generated,
+            [
+                "api/service.pb.go",
+                "deleted/old.pb.go",
+                "package-lock.json"
+            ]
+        );
+        assert!(paths_with_attribute(&options, "custom-generated", &paths)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            paths_with_attribute(&options, "linguist-generated", &[]),
+            Ok(HashSet::new())
+        );
+        let _ = fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_changed_files_ignore_whitespace() {
+        let (repo, git) = temp_repo("whitespace");
+        fs::write(repo.join("fmt.rs"), "fn a() {}\n").unwrap();
+        fs::write(repo.join("code.rs"), "fn b() {}\n").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        fs::write(repo.join("fmt.rs"), "fn a()  {}\n\n").unwrap();
+        fs::write(repo.join("code.rs"), "fn c() {}\n").unwrap();
+        git(&["commit", "-q", "-a", "-m", "format"]);
+
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        let files = get_changed_files(&range("HEAD~1"), &options).unwrap();
+        assert_eq!(paths(&files), vec!["code.rs", "fmt.rs"]);
+
+        let whitespace = DiffOptions {
+            ignore_whitespace: true,
+            ..options.clone()
+        };
+        let files = get_changed_files(&range("HEAD~1"), &whitespace).unwrap();
+        assert_eq!(paths(&files), vec!["code.rs"]);
+
+        let content = DiffOptions {
+            ignore_content: vec!["^fn [bc]".to_string()],
+            ..options
+        };
+        let files = get_changed_files(&range("HEAD~1"), &content).unwrap();
+        assert_eq!(paths(&files), vec!["fmt.rs"]);
+    }
+
+    #[test]
+    fn test_any_changed_file_with_pathspecs() {
+        let (repo, git) = temp_repo("pathspec");
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "initial"]);
+        for path in ["src/a.rs", "src/vendor/b.rs", "docs/c.md", "[x]/d"] {
+            let path = repo.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "add"]);
+
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        let visited = |specs: &[Pathspec]| {
+            let mut seen = Vec::new();
+            any_changed_file(&range("HEAD~1"), &options, specs, |file| {
+                seen.push(file.path.clone());
+                Ok(false)
+            })
+            .unwrap();
+            seen
+        };
+        let spec = |path: &str, exclude| Pathspec {
+            path: path.to_string(),
+            exclude,
+        };
+
+        assert_eq!(
+            visited(&[spec("src", false), spec("src/vendor/", true)]),
+            vec!["src/a.rs"]
+        );
+        // Pathspecs are literal, so glob characters in directory names are plain text
+        assert_eq!(visited(&[spec("[x]", false)]), vec!["[x]/d"]);
+        assert_eq!(visited(&[]).len(), 4);
+    }
+
+    #[test]
+    fn test_changed_files_ignore_mode_changes() {
+        let (repo, git) = temp_repo("mode");
+        for name in ["run.sh", "build.sh", "dirty.sh"] {
+            fs::write(repo.join(name), "echo hi\n").unwrap();
+        }
+        git(&["init", "-q"]);
+        git(&["config", "core.fileMode", "true"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        git(&["update-index", "--chmod=+x", "run.sh"]);
+        fs::write(repo.join("build.sh"), "echo bye\n").unwrap();
+        git(&["add", "build.sh"]);
+        git(&["update-index", "--chmod=+x", "build.sh"]);
+        git(&["commit", "-q", "-m", "chmod"]);
+
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        let files = get_changed_files(&range("HEAD~1"), &options).unwrap();
+        assert_eq!(paths(&files), vec!["build.sh", "run.sh"]);
+
+        let ignore = DiffOptions {
+            mode_changes: ModeChanges::Ignore,
+            ..options.clone()
+        };
+        let files = get_changed_files(&range("HEAD~1"), &ignore).unwrap();
+        assert_eq!(paths(&files), vec!["build.sh"]);
+
+        // Working-tree mode changes are compared by hashing the file
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // The committed mode changes were made in the index only
+            for name in ["run.sh", "build.sh", "dirty.sh"] {
+                let executable = fs::Permissions::from_mode(0o755);
+                fs::set_permissions(repo.join(name), executable).unwrap();
+            }
+            let combined = DiffOptions {
+                source: DiffSource::Combined,
+                ..options
+            };
+            let files = get_changed_files(&range("HEAD"), &combined).unwrap();
+            assert_eq!(paths(&files), vec!["dirty.sh"]);
+            let files = get_changed_files(
+                &range("HEAD"),
+                &DiffOptions {
+                    mode_changes: ModeChanges::Ignore,
+                    ..combined
+                },
+            )
+            .unwrap();
+            assert!(files.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_commit_before() {
+        let (repo, git) = temp_repo("since");
+        git(&["init", "-q"]);
+        for (date, message) in [
+            ("2024-01-01T00:00:00Z", "first"),
+            ("2024-02-01T00:00:00Z", "second"),
+            ("2024-03-01T00:00:00Z", "third"),
+        ] {
+            let output = Command::new("git")
+                .env_remove("GIT_DIR")
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date)
+                .arg("-C")
+                .arg(&repo)
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(["commit", "-q", "--allow-empty", "-m", message])
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+        }
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        let subject = |id: &str| run_git_text(&options, &["log", "-1", "--format=%s", id]).unwrap();
+
+        let id = commit_before(&options, "2024-02-15").unwrap();
+        assert_eq!(subject(&id), "second");
+        let id = commit_before(&options, "2025-01-01").unwrap();
+        assert_eq!(subject(&id), "third");
+        let err = commit_before(&options, "2023-06-01").unwrap_err();
+        assert_eq!(err, "No commit on HEAD was made before '2023-06-01'");
+    }
+
+    #[test]
+    fn test_changed_files_first_parent() {
+        let (repo, git) = temp_repo("first_parent");
+        git(&["init", "-q", "-b", "main"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "initial"]);
+        git(&["tag", "base"]);
+        git(&["checkout", "-q", "-b", "feature"]);
+        fs::write(repo.join("feature.txt"), "feature\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "feature"]);
+        git(&["checkout", "-q", "main"]);
+        fs::write(repo.join("main.txt"), "main\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "main"]);
+        git(&["checkout", "-q", "feature"]);
+        git(&["merge", "-q", "--no-edit", "main"]);
+        fs::write(repo.join("feature.txt"), "feature, again\n").unwrap();
+        git(&["commit", "-q", "-am", "feature again"]);
+
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        let files = get_changed_files(&range("base"), &options).unwrap();
+        assert_eq!(paths(&files), vec!["feature.txt", "main.txt"]);
+
+        let first_parent = DiffOptions {
+            first_parent: true,
+            ..options
+        };
+        let files = get_changed_files(&range("base"), &first_parent).unwrap();
+        assert_eq!(paths(&files), vec!["feature.txt"]);
+        assert_eq!(files[0].kind, ChangeKind::Modified); // from the newest commit
+        let mut seen = Vec::new();
+        any_changed_file(&range("base"), &first_parent, &[], |file| {
+            seen.push(file.path.clone());
+            Ok(false)
+        })
+        .unwrap();
+        assert_eq!(seen, vec!["feature.txt", "feature.txt"]);
+    }
+
+    #[test]
+    fn test_recent_commits() {
+        let (repo, git) = temp_repo("recent");
+        let (clone, _) = temp_repo("recent_shallow");
+        git(&["init", "-q"]);
+        for name in ["a", "b", "c"] {
+            fs::write(repo.join(name), name).unwrap();
+            git(&["add", "."]);
+            git(&["commit", "-q", "-m", name]);
+        }
+        let options = DiffOptions {
+            git_dir: Some(repo.join(".git")),
+            work_tree: Some(repo.clone()),
+            ..Default::default()
+        };
+        let changed =
+            |range: &RevisionRange| paths(&get_changed_files(range, &options).unwrap()).join(",");
+
+        let head = object_id(&options, "HEAD").unwrap();
+        assert_eq!(head.len(), 40);
+        assert_eq!(object_id(&options, "HEAD~0").unwrap(), head);
+        assert!(object_id(&options, "HEAD~9").is_err());
+
+        let commits = recent_commits(&options, 2).unwrap();
+        assert_eq!(
+            commits.iter().map(changed).collect::<Vec<_>>(),
+            vec!["c", "b"]
+        );
+        // The root commit is diffed against the empty tree
+        let commits = recent_commits(&options, 5).unwrap();
+        assert_eq!(
+            commits.iter().map(changed).collect::<Vec<_>>(),
+            vec!["c", "b", "a"]
+        );
+
+        let url = format!("file://{}", repo.display());
+        git(&["clone", "-q", "--depth=2", &url, clone.to_str().unwrap()]);
+        let shallow = DiffOptions {
+            git_dir: Some(clone.join(".git")),
+            work_tree: Some(clone.clone()),
+            ..Default::default()
+        };
+        assert_eq!(recent_commits(&shallow, 1).unwrap().len(), 1);
+        let err = recent_commits(&shallow, 2).unwrap_err();
+        assert!(err.contains("was not fetched"), "{err}");
+        let deepened = DiffOptions {
+            fetch_attempts: 1,
+            ..shallow
+        };
+        assert_eq!(recent_commits(&deepened, 3).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_current_branch_from_ci_variables() {
+        let options = DiffOptions::default();
+        let var = |name: &str| match name {
+            "GITHUB_HEAD_REF" => Some(String::new()),
+            "GITHUB_REF_NAME" => Some("release/2.0".to_string()),
+            "CIRCLE_BRANCH" => Some("main".to_string()),
+            _ => None,
+        };
+        // An empty variable, as GitHub sets outside pull requests, names no branch
+        assert_eq!(
+            current_branch(&options, &var),
+            Some("release/2.0".to_string())
         );
     }
 }
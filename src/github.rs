@@ -0,0 +1,511 @@
+//! Access to the GitHub Actions event payload (`GITHUB_EVENT_PATH`), and to the files
+//! a pull request changed through the GitHub REST API, for `--github-api`.
+
+use crate::git::{ChangeKind, ChangedFile, DiffOptions, GitBackend, RevisionRange};
+use crate::hunk::{ChangedLine, FileDiff, LineKind};
+use crate::json;
+use crate::log::log;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Files listed per page of the pull request files endpoint, its maximum
+const FILES_PER_PAGE: usize = 100;
+
+/// Pages read at most: the endpoint lists no more than 3000 files
+const MAX_FILES_PAGES: usize = 30;
+
+/// The webhook payload of the event that triggered a workflow run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    payload: json::Value,
+}
+
+impl Event {
+    /// Read and parse the payload file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read GitHub event payload '{path}': {e}"))?;
+        Self::parse(&text)
+            .map_err(|e| format!("Failed to parse GitHub event payload '{path}': {e}"))
+    }
+
+    /// Parse a payload document
+    pub fn parse(text: &str) -> Result<Self, String> {
+        json::parse(text).map(|payload| Event { payload })
+    }
+
+    /// The repository's default branch (`repository.default_branch`)
+    pub fn default_branch(&self) -> Option<&str> {
+        self.payload
+            .pointer(&["repository", "default_branch"])
+            .and_then(json::Value::as_str)
+    }
+
+    /// The pull request number (`pull_request.number`) for pull request events
+    pub fn pull_request_number(&self) -> Option<u64> {
+        self.payload
+            .pointer(&["pull_request", "number"])
+            .and_then(json::Value::as_u64)
+    }
+
+    /// The commit the event's changes build on: the merge queue's base
+    /// (`merge_group.base_sha`), or else the commit a push moved the branch from
+    /// (`before`), unless the push created the branch and it is all zeros
+    pub fn base_commit(&self) -> Option<&str> {
+        let sha = |path: &[&str]| self.payload.pointer(path).and_then(json::Value::as_str);
+        sha(&["merge_group", "base_sha"])
+            .or_else(|| sha(&["before"]))
+            .filter(|sha| !sha.is_empty() && !sha.bytes().all(|b| b == b'0'))
+    }
+
+    /// The repository's `owner/name` (`repository.full_name`)
+    pub fn repository(&self) -> Option<&str> {
+        self.payload
+            .pointer(&["repository", "full_name"])
+            .and_then(json::Value::as_str)
+    }
+
+    /// The commits the pull request compares (`pull_request.base.sha` and
+    /// `pull_request.head.sha`)
+    pub fn pull_request_commits(&self) -> Option<(&str, &str)> {
+        let sha = |end: &str| {
+            self.payload
+                .pointer(&["pull_request", end, "sha"])
+                .and_then(json::Value::as_str)
+        };
+        Some((sha("base")?, sha("head")?))
+    }
+}
+
+/// A pull request whose changed files are listed by the GitHub REST API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    /// `GITHUB_API_URL`, which differs on GitHub Enterprise Server
+    api_url: String,
+    /// `owner/name`
+    repository: String,
+    number: u64,
+}
+
+impl PullRequest {
+    /// The pull request of the workflow run: the repository is `GITHUB_REPOSITORY`, or
+    /// the payload's, and the number the payload's
+    ///
+    /// # Errors
+    /// Returns an error outside a pull request event or without a repository.
+    pub fn of_run(
+        event: Option<&Event>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let number = event
+            .and_then(Event::pull_request_number)
+            .ok_or("--github-api needs the payload of a pull request event (GITHUB_EVENT_PATH)")?;
+        let repository = env("GITHUB_REPOSITORY")
+            .filter(|repository| !repository.is_empty())
+            .or_else(|| event.and_then(Event::repository).map(str::to_string))
+            .ok_or("--github-api needs GITHUB_REPOSITORY to name the repository")?;
+        let api_url = env("GITHUB_API_URL")
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| "https://api.github.com".to_string());
+        Ok(PullRequest {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            repository,
+            number,
+        })
+    }
+
+    /// Fetch the changed files, a page at a time, with `curl`
+    ///
+    /// The token is given to curl on stdin, so it never shows in the process list.
+    ///
+    /// # Errors
+    /// Returns an error when curl cannot be run, a request fails, a page is not the
+    /// expected JSON, or the pull request changes more files than the API lists.
+    pub fn files(&self, token: &str) -> Result<PullRequestFiles, String> {
+        let files = self.list_files(|url| fetch(url, token))?;
+        log!(Debug, "gdf", "{self} changed {} files", files.len());
+        Ok(PullRequestFiles(files))
+    }
+
+    /// Read the pages of changed files returned by `fetch_page` for each page's URL
+    ///
+    /// When the last page the API serves is full, more files may follow that it does not
+    /// list; deciding on part of the changes would be wrong, so that is an error.
+    fn list_files(
+        &self,
+        mut fetch_page: impl FnMut(&str) -> Result<String, String>,
+    ) -> Result<Vec<PullRequestFile>, String> {
+        let mut files = Vec::new();
+        for page in 1..=MAX_FILES_PAGES {
+            let url = format!(
+                "{}/repos/{}/pulls/{}/files?per_page={FILES_PER_PAGE}&page={page}",
+                self.api_url, self.repository, self.number
+            );
+            let listed = parse_files_page(&fetch_page(&url)?)?;
+            let last = listed.len() < FILES_PER_PAGE;
+            files.extend(listed);
+            if last {
+                return Ok(files);
+            }
+        }
+        Err(format!(
+            "{self} changes more than the {} files the GitHub API lists; check the repository out and run without --github-api",
+            MAX_FILES_PAGES * FILES_PER_PAGE
+        ))
+    }
+}
+
+impl std::fmt::Display for PullRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.repository, self.number)
+    }
+}
+
+/// GET `url` with the token as a bearer, returning the body
+fn fetch(url: &str, token: &str) -> Result<String, String> {
+    log!(Debug, "gdf", "Fetching {url}");
+    let mut child = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--retry",
+            "3",
+            "--header",
+            "@-",
+            "--header",
+            "Accept: application/vnd.github+json",
+            "--header",
+            "X-GitHub-Api-Version: 2022-11-28",
+            url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run curl: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "Authorization: Bearer {token}")
+            .map_err(|e| format!("Failed to run curl: {e}"))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to run curl: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "GitHub API request failed: {url}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|e| format!("Invalid GitHub API response: {e}"))
+}
+
+/// A file changed by a pull request, as the API lists it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PullRequestFile {
+    path: String,
+    /// `previous_filename`, for renamed files
+    old_path: Option<String>,
+    kind: ChangeKind,
+    /// Lines added plus removed
+    changes: usize,
+    /// The unified diff hunks, left out for binary files and very large diffs
+    patch: Option<String>,
+}
+
+/// Read one page of the pull request files endpoint
+fn parse_files_page(text: &str) -> Result<Vec<PullRequestFile>, String> {
+    let invalid = |e: &str| format!("Invalid GitHub API response: {e}");
+    let Ok(json::Value::Array(items)) = json::parse(text) else {
+        return Err(invalid("expected an array of files"));
+    };
+    items
+        .iter()
+        .map(|item| {
+            let text = |key: &str| item.get(key).and_then(json::Value::as_str);
+            let path = text("filename").ok_or_else(|| invalid("a file has no filename"))?;
+            // `changed` is a change of mode or type alone
+            let kind = match text("status") {
+                Some("added") => ChangeKind::Added,
+                Some("removed") => ChangeKind::Deleted,
+                Some("modified") => ChangeKind::Modified,
+                Some("renamed") => ChangeKind::Renamed,
+                Some("copied") => ChangeKind::Copied,
+                Some("changed") => ChangeKind::TypeChanged,
+                _ => ChangeKind::Unknown,
+            };
+            Ok(PullRequestFile {
+                path: path.to_string(),
+                old_path: text("previous_filename").map(str::to_string),
+                kind,
+                changes: item
+                    .get("changes")
+                    .and_then(json::Value::as_u64)
+                    .and_then(|changes| usize::try_from(changes).ok())
+                    .unwrap_or_default(),
+                patch: text("patch").map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// The added and removed lines of a patch's hunks; context lines are left out
+fn patch_lines(patch: &str) -> Vec<ChangedLine> {
+    patch
+        .lines()
+        .filter(|line| !line.starts_with("@@ "))
+        .filter_map(|line| {
+            let kind = match line.chars().next() {
+                Some('+') => LineKind::Added,
+                Some('-') => LineKind::Removed,
+                _ => return None,
+            };
+            Some(ChangedLine {
+                kind,
+                text: line[1..].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The files a pull request changed, standing in for git when there is no checkout
+///
+/// Every range is answered with the pull request's changes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PullRequestFiles(Vec<PullRequestFile>);
+
+impl GitBackend for PullRequestFiles {
+    fn changed_files(
+        &self,
+        _range: &RevisionRange,
+        _options: &DiffOptions,
+    ) -> Result<Vec<ChangedFile>, String> {
+        Ok(self
+            .0
+            .iter()
+            .map(|file| ChangedFile {
+                path: file.path.clone(),
+                old_path: file.old_path.clone(),
+                kind: file.kind,
+            })
+            .collect())
+    }
+
+    /// Files without a patch and without changed lines are taken as binary
+    fn line_counts(
+        &self,
+        _range: &RevisionRange,
+        _options: &DiffOptions,
+    ) -> Result<HashMap<String, Option<usize>>, String> {
+        Ok(self
+            .0
+            .iter()
+            .map(|file| {
+                let binary = file.patch.is_none() && file.changes == 0;
+                (file.path.clone(), (!binary).then_some(file.changes))
+            })
+            .collect())
+    }
+
+    fn file_diffs(
+        &self,
+        _range: &RevisionRange,
+        _options: &DiffOptions,
+    ) -> Result<Vec<FileDiff>, String> {
+        Ok(self
+            .0
+            .iter()
+            .filter_map(|file| {
+                Some(FileDiff {
+                    path: file.path.clone(),
+                    old_path: file.old_path.clone(),
+                    lines: patch_lines(file.patch.as_deref()?),
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_branch() {
+        let event = Event::parse(r#"{"repository": {"default_branch": "develop"}}"#).unwrap();
+        assert_eq!(event.default_branch(), Some("develop"));
+    }
+
+    #[test]
+    fn test_default_branch_missing() {
+        let event = Event::parse(r#"{"ref": "refs/heads/main"}"#).unwrap();
+        assert_eq!(event.default_branch(), None);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let err = Event::load("/nonexistent/event.json").unwrap_err();
+        assert!(err.starts_with("Failed to read GitHub event payload"));
+
+        let path = std::env::temp_dir().join("gdf_test_event_invalid.json");
+        fs::write(&path, "{not json").unwrap();
+        let err = Event::load(path.to_str().unwrap()).unwrap_err();
+        assert!(err.starts_with("Failed to parse GitHub event payload"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_pull_request_number() {
+        let event = Event::parse(r#"{"number": 7, "pull_request": {"number": 7}}"#).unwrap();
+        assert_eq!(event.pull_request_number(), Some(7));
+
+        let push = Event::parse(r#"{"ref": "refs/heads/main"}"#).unwrap();
+        assert_eq!(push.pull_request_number(), None);
+    }
+
+    #[test]
+    fn test_base_commit() {
+        let push = Event::parse(r#"{"before": "1a2b3c", "after": "4d5e6f"}"#).unwrap();
+        assert_eq!(push.base_commit(), Some("1a2b3c"));
+        let queued = Event::parse(
+            r#"{"merge_group": {"base_sha": "7a8b9c", "head_sha": "4d5e6f"}, "before": "1a2b3c"}"#,
+        )
+        .unwrap();
+        assert_eq!(queued.base_commit(), Some("7a8b9c"));
+
+        // A push creating the branch has no previous commit
+        let created = Event::parse(&format!(r#"{{"before": "{}"}}"#, "0".repeat(40))).unwrap();
+        assert_eq!(created.base_commit(), None);
+        let dispatch = Event::parse(r#"{"inputs": {}}"#).unwrap();
+        assert_eq!(dispatch.base_commit(), None);
+    }
+
+    #[test]
+    fn test_pull_request_of_run() {
+        let event = Event::parse(
+            r#"{"pull_request": {"number": 12, "base": {"sha": "b1"}, "head": {"sha": "h2"}},
+                "repository": {"full_name": "acme/app"}}"#,
+        )
+        .unwrap();
+        assert_eq!(event.pull_request_commits(), Some(("b1", "h2")));
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| (*value).to_string())
+            }
+        };
+        let pull_request = PullRequest::of_run(Some(&event), &vars(&[])).unwrap();
+        assert_eq!(pull_request.api_url, "https://api.github.com");
+        assert_eq!(pull_request.to_string(), "acme/app#12");
+
+        let pull_request = PullRequest::of_run(
+            Some(&event),
+            &vars(&[
+                ("GITHUB_REPOSITORY", "acme/fork"),
+                ("GITHUB_API_URL", "https://ghe.example.com/api/v3/"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(pull_request.api_url, "https://ghe.example.com/api/v3");
+        assert_eq!(pull_request.to_string(), "acme/fork#12");
+
+        let push = Event::parse(r#"{"ref": "refs/heads/main"}"#).unwrap();
+        assert_eq!(
+            PullRequest::of_run(Some(&push), &vars(&[("GITHUB_REPOSITORY", "acme/app")])),
+            Err(
+                "--github-api needs the payload of a pull request event (GITHUB_EVENT_PATH)"
+                    .to_string()
+            )
+        );
+        assert!(PullRequest::of_run(None, &vars(&[])).is_err());
+    }
+
+    #[test]
+    fn test_pull_request_files_pages() {
+        let pull_request = PullRequest {
+            api_url: "https://api.github.com".to_string(),
+            repository: "octo/repo".to_string(),
+            number: 7,
+        };
+        let page = |count: usize| {
+            let files: Vec<String> = (0..count)
+                .map(|i| format!(r#"{{"filename": "f{i}", "status": "added"}}"#))
+                .collect();
+            format!("[{}]", files.join(","))
+        };
+        let mut urls = Vec::new();
+        let files = pull_request
+            .list_files(|url| {
+                urls.push(url.to_string());
+                Ok(page(if urls.len() == 1 { 100 } else { 5 }))
+            })
+            .unwrap();
+        assert_eq!(files.len(), 105);
+        assert_eq!(
+            urls[1],
+            "https://api.github.com/repos/octo/repo/pulls/7/files?per_page=100&page=2"
+        );
+
+        // Every page full: the list may go on past what the API serves
+        assert_eq!(
+            pull_request.list_files(|_| Ok(page(100))),
+            Err("octo/repo#7 changes more than the 3000 files the GitHub API lists; check the repository out and run without --github-api".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pull_request_files() {
+        let page = r#"[
+            {"filename": "src/lib.rs", "status": "modified", "changes": 3,
+             "patch": "@@ -1,3 +1,4 @@\n use std::fs;\n-fn old() {}\n+fn new() {}\n+fn more() {}\n context"},
+            {"filename": "docs/new.md", "status": "renamed", "previous_filename": "docs/old.md",
+             "changes": 0},
+            {"filename": "logo.png", "status": "added", "changes": 0},
+            {"filename": "gone.txt", "status": "removed", "changes": 2, "patch": "@@ -1,2 +0,0 @@\n-a\n-b"}
+        ]"#;
+        let files = PullRequestFiles(parse_files_page(page).unwrap());
+        let range = RevisionRange {
+            base: "base".to_string(),
+            head: "head".to_string(),
+            symmetric: false,
+        };
+        let options = DiffOptions::default();
+        let changed = files.changed_files(&range, &options).unwrap();
+        assert_eq!(
+            changed[1],
+            ChangedFile {
+                path: "docs/new.md".to_string(),
+                old_path: Some("docs/old.md".to_string()),
+                kind: ChangeKind::Renamed,
+            }
+        );
+        assert_eq!(changed[3].kind, ChangeKind::Deleted);
+
+        let counts = files.line_counts(&range, &options).unwrap();
+        assert_eq!(counts["src/lib.rs"], Some(3));
+        assert_eq!(counts["logo.png"], None);
+
+        let diffs = files.file_diffs(&range, &options).unwrap();
+        assert_eq!(diffs.len(), 2);
+        let texts: Vec<(LineKind, &str)> = diffs[0]
+            .lines
+            .iter()
+            .map(|line| (line.kind, line.text.as_str()))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (LineKind::Removed, "fn old() {}"),
+                (LineKind::Added, "fn new() {}"),
+                (LineKind::Added, "fn more() {}"),
+            ]
+        );
+
+        assert!(parse_files_page(r#"{"message": "Not Found"}"#).is_err());
+        assert!(parse_files_page(r#"[{"status": "added"}]"#).is_err());
+    }
+}
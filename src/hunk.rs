@@ -0,0 +1,333 @@
+//! Parsing of zero-context unified diffs (`git diff -U0`) into changed lines.
+
+/// Whether a changed line was added or removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Added,
+    Removed,
+}
+
+/// A single added or removed line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+/// The changed lines of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    /// Path of the file after the change (the old path for deleted files)
+    pub path: String,
+    /// Path of the file before the change, when it differs from `path`
+    pub old_path: Option<String>,
+    pub lines: Vec<ChangedLine>,
+}
+
+/// Parse `git diff -U0 --src-prefix=a/ --dst-prefix=b/` output
+///
+/// Files are delimited by their `---`/`+++` headers; binary files and changes without
+/// content (mode changes, pure renames) have no headers and are not reported. Hunk bodies
+/// are read using the line counts from the `@@` header, so removed lines that happen to
+/// start with `--` are never mistaken for headers. Lines that are not valid UTF-8 are
+/// decoded lossily.
+pub fn parse_unified_diff(output: &[u8]) -> Result<Vec<FileDiff>, String> {
+    let mut files: Vec<FileDiff> = Vec::new();
+    let mut old_path: Option<String> = None;
+    let mut lines = output
+        .split(|&b| b == b'\n')
+        .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)));
+
+    while let Some(line) = lines.next() {
+        if let Some(header) = line.strip_prefix("--- ") {
+            old_path = parse_header_path(header, "a/")?;
+        } else if let Some(header) = line.strip_prefix("+++ ") {
+            let new_path = parse_header_path(header, "b/")?;
+            let (path, old_path) = match (new_path, old_path.take()) {
+                (Some(new), Some(old)) if old != new => (new, Some(old)),
+                (Some(path), _) | (None, Some(path)) => (path, None),
+                (None, None) => return Err("Diff header without a path".to_string()),
+            };
+            files.push(FileDiff {
+                path,
+                old_path,
+                lines: Vec::new(),
+            });
+        } else if line.starts_with("@@ ") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| format!("Hunk without a file header: {line}"))?;
+            let (removed, added) = parse_hunk_header(&line)?;
+            for _ in 0..removed + added {
+                let body = lines
+                    .by_ref()
+                    .find(|body| !body.starts_with('\\'))
+                    .ok_or_else(|| format!("Truncated hunk in {}", file.path))?;
+                let kind = match body.chars().next() {
+                    Some('+') => LineKind::Added,
+                    Some('-') => LineKind::Removed,
+                    _ => return Err(format!("Unexpected line in hunk of {}: {body}", file.path)),
+                };
+                file.lines.push(ChangedLine {
+                    kind,
+                    text: body[1..].to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Parse the counts of removed and added lines from `@@ -a[,b] +c[,d] @@`
+fn parse_hunk_header(line: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("Invalid hunk header: {line}");
+    let mut ranges = line
+        .strip_prefix("@@ ")
+        .and_then(|rest| rest.split(" @@").next())
+        .ok_or_else(invalid)?
+        .split(' ');
+    let mut count = |sign: char| {
+        let range = ranges
+            .next()
+            .and_then(|range| range.strip_prefix(sign))
+            .ok_or_else(invalid)?;
+        match range.split_once(',') {
+            Some((_, count)) => count.parse().map_err(|_| invalid()),
+            None => Ok(1),
+        }
+    };
+    let removed = count('-')?;
+    let added = count('+')?;
+    Ok((removed, added))
+}
+
+/// Extract the path from a `---`/`+++` header, or `None` for `/dev/null`
+///
+/// Git appends a tab to names containing spaces and C-quotes names containing special
+/// characters; both are undone here.
+fn parse_header_path(header: &str, prefix: &str) -> Result<Option<String>, String> {
+    let header = header.strip_suffix('\t').unwrap_or(header);
+    if header == "/dev/null" {
+        return Ok(None);
+    }
+    let path = if header.starts_with('"') {
+        unquote(header)?
+    } else {
+        header.to_string()
+    };
+    path.strip_prefix(prefix)
+        .map(|path| Some(path.to_string()))
+        .ok_or_else(|| format!("Unexpected path in diff header: {header}"))
+}
+
+/// Undo git's C-style quoting of a path
+fn unquote(quoted: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid quoted path: {quoted}");
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let byte = match chars.next().ok_or_else(invalid)? {
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'n' => b'\n',
+            'r' => b'\r',
+            't' => b'\t',
+            'v' => 0x0b,
+            digit @ '0'..='3' => {
+                let mut value = digit.to_digit(8).ok_or_else(invalid)?;
+                for _ in 0..2 {
+                    let digit = chars
+                        .next()
+                        .and_then(|c| c.to_digit(8))
+                        .ok_or_else(invalid)?;
+                    value = value * 8 + digit;
+                }
+                u8::try_from(value).map_err(|_| invalid())?
+            }
+            c @ ('"' | '\\') => c as u8,
+            _ => return Err(invalid()),
+        };
+        bytes.push(byte);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn added(text: &str) -> ChangedLine {
+        ChangedLine {
+            kind: LineKind::Added,
+            text: text.to_string(),
+        }
+    }
+
+    fn removed(text: &str) -> ChangedLine {
+        ChangedLine {
+            kind: LineKind::Removed,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_modified_file() {
+        let output = b"diff --git a/main.tf b/main.tf
+index 1111111..2222222 100644
+--- a/main.tf
++++ b/main.tf
+@@ -3 +3 @@ terraform {
+-  region = \"us-east-1\"
++  region = \"eu-west-1\"
+@@ -10,0 +11,2 @@
++provider \"aws\" {
++}
+";
+        let result = parse_unified_diff(output).unwrap();
+        assert_eq!(
+            result,
+            vec![FileDiff {
+                path: "main.tf".to_string(),
+                old_path: None,
+                lines: vec![
+                    removed("  region = \"us-east-1\""),
+                    added("  region = \"eu-west-1\""),
+                    added("provider \"aws\" {"),
+                    added("}"),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_added_and_deleted_files() {
+        let output = b"diff --git a/new.rs b/new.rs
+new file mode 100644
+--- /dev/null
++++ b/new.rs
+@@ -0,0 +1 @@
++fn main() {}
+diff --git a/old.rs b/old.rs
+deleted file mode 100644
+--- a/old.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-fn old() {}
+";
+        let result = parse_unified_diff(output).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].path, "new.rs");
+        assert_eq!(result[0].lines, vec![added("fn main() {}")]);
+        assert_eq!(result[1].path, "old.rs");
+        assert_eq!(result[1].old_path, None);
+        assert_eq!(result[1].lines, vec![removed("fn old() {}")]);
+    }
+
+    #[test]
+    fn test_parse_rename_with_changes() {
+        let output = b"diff --git a/src/a.rs b/lib/a.rs
+similarity index 90%
+rename from src/a.rs
+rename to lib/a.rs
+--- a/src/a.rs
++++ b/lib/a.rs
+@@ -1 +1 @@
+-old
++new
+";
+        let result = parse_unified_diff(output).unwrap();
+        assert_eq!(result[0].path, "lib/a.rs");
+        assert_eq!(result[0].old_path.as_deref(), Some("src/a.rs"));
+    }
+
+    #[test]
+    fn test_parse_removed_line_looking_like_header() {
+        let output = b"--- a/notes.md
++++ b/notes.md
+@@ -1,2 +0,0 @@
+--- a/fake
+-+++ b/fake
+";
+        let result = parse_unified_diff(output).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            result[0].lines,
+            vec![removed("-- a/fake"), removed("+++ b/fake")]
+        );
+    }
+
+    #[test]
+    fn test_parse_no_newline_marker_skipped() {
+        let output = b"--- a/f
++++ b/f
+@@ -1 +1 @@
+-a
+\\ No newline at end of file
++b
+\\ No newline at end of file
+";
+        let result = parse_unified_diff(output).unwrap();
+        assert_eq!(result[0].lines, vec![removed("a"), added("b")]);
+    }
+
+    #[test]
+    fn test_parse_binary_and_mode_changes_skipped() {
+        let output = b"diff --git a/img.png b/img.png
+index 1111111..2222222 100644
+Binary files a/img.png and b/img.png differ
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+";
+        assert_eq!(parse_unified_diff(output).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_parse_quoted_and_tab_suffixed_paths() {
+        let output = b"--- \"a/caf\\303\\251 \\\"x\\\".md\"
++++ \"b/caf\\303\\251 \\\"x\\\".md\"
+@@ -1 +1 @@
+-a
++b
+--- a/with space.txt\t
++++ b/with space.txt\t
+@@ -1 +1 @@
+-a
++b
+";
+        let result = parse_unified_diff(output).unwrap();
+        assert_eq!(result[0].path, "caf\u{e9} \"x\".md");
+        assert_eq!(result[1].path, "with space.txt");
+    }
+
+    #[test]
+    fn test_parse_truncated_hunk() {
+        let output = b"--- a/f\n+++ b/f\n@@ -1,2 +1 @@\n-a\n";
+        assert!(parse_unified_diff(output).is_err());
+    }
+
+    #[test]
+    fn test_parse_hunk_header() {
+        assert_eq!(parse_hunk_header("@@ -3 +3 @@"), Ok((1, 1)));
+        assert_eq!(parse_hunk_header("@@ -10,0 +11,2 @@ fn x()"), Ok((0, 2)));
+        assert!(parse_hunk_header("@@ bogus @@").is_err());
+    }
+
+    #[test]
+    fn test_unquote_escapes() {
+        assert_eq!(unquote(r#""a\tb\nc\\d""#), Ok("a\tb\nc\\d".to_string()));
+        assert!(unquote(r#""bad\q""#).is_err());
+        assert!(unquote(r#""\40""#).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! `${VAR}` expansion in config file values and command-line patterns, so one filter
+//! definition like `services/${SERVICE}/**` serves every job that sets `SERVICE`.
+
+use std::env;
+
+/// Replace each `${NAME}` with the environment variable `NAME`
+///
+/// `$${` stands for a literal `${`; a `$` not followed by `{` is kept as is.
+///
+/// # Errors
+/// Returns an error for an undefined variable, an invalid name or a missing `}`.
+pub fn expand(text: &str) -> Result<String, String> {
+    expand_with(text, &|name| env::var(name).ok())
+}
+
+/// Replace each `${NAME}` with the value `lookup` gives for `NAME`
+fn expand_with(text: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        if let Some(escaped) = after.strip_prefix("${") {
+            expanded.push_str("${");
+            rest = escaped;
+        } else if let Some(reference) = after.strip_prefix('{') {
+            let end = reference
+                .find('}')
+                .ok_or_else(|| format!("Missing }} after ${{ in {text}"))?;
+            let name = &reference[..end];
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(format!("Invalid variable name '{name}' in {text}"));
+            }
+            let value = lookup(name)
+                .ok_or_else(|| format!("Undefined environment variable {name} in {text}"))?;
+            expanded.push_str(&value);
+            rest = &reference[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = after;
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "SERVICE" => Some("billing".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_with() {
+        let expand = |text: &str| expand_with(text, &lookup);
+        assert_eq!(
+            expand("services/${SERVICE}/**"),
+            Ok("services/billing/**".to_string())
+        );
+        assert_eq!(
+            expand("${SERVICE}-${SERVICE}${EMPTY}"),
+            Ok("billing-billing".to_string())
+        );
+        assert_eq!(
+            expand("$${SERVICE} $HOME a$"),
+            Ok("${SERVICE} $HOME a$".to_string())
+        );
+        assert_eq!(
+            expand("services/${TEAM}/**"),
+            Err("Undefined environment variable TEAM in services/${TEAM}/**".to_string())
+        );
+        assert_eq!(
+            expand("${1X}"),
+            Err("Invalid variable name '1X' in ${1X}".to_string())
+        );
+        assert_eq!(
+            expand("a/${SERVICE"),
+            Err("Missing } after ${ in a/${SERVICE".to_string())
+        );
+    }
+}
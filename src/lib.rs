@@ -0,0 +1,3394 @@
+//! Decide whether the files changed since a base ref match glob filters, and write the
+//! result for CI systems.
+//!
+//! The `gdf` binary is a thin wrapper around [`run_cli`]; other Rust tools can resolve
+//! a [`Config`] from [`Args`] and call [`DiffFilter::evaluate`] instead of spawning it
+//! and parsing its output.
+
+use crate::log::log;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+pub use cli::Args;
+pub use config::Config;
+
+mod cache;
+mod cargo;
+mod ci;
+mod cli;
+mod compose;
+mod condition;
+mod config;
+mod doctor;
+mod git;
+mod github;
+mod hunk;
+mod interpolate;
+mod json;
+mod log;
+mod matcher;
+mod node;
+mod output;
+mod preset;
+mod regex;
+mod report;
+mod serve;
+mod stats;
+mod targets;
+mod test_map;
+mod toml;
+mod tui;
+mod validate;
+mod workspace;
+mod yaml;
+
+/// Evaluates filters without writing any output
+#[derive(Debug, Clone, Copy)]
+pub struct DiffFilter;
+
+impl DiffFilter {
+    /// Resolve `args` into a configuration, reading the environment variables the
+    /// `gdf` binary reads (`BASE_REF`, `GIT_DIR`, `GITHUB_EVENT_PATH`, ...)
+    ///
+    /// `args` is taken as given: no config file is read.
+    ///
+    /// # Errors
+    /// Returns an error when the arguments are invalid or conflict.
+    pub fn config(args: Args) -> Result<Config, String> {
+        config::from_args(args)
+    }
+
+    /// Evaluate every filter of `config` against the repository (or each `--repo`
+    /// checkout) it points at, and each `--each-base` base
+    ///
+    /// Every matching file is listed. Diagnostics go to stderr as for the binary;
+    /// outputs such as `GITHUB_OUTPUT` or reports are not written.
+    ///
+    /// # Errors
+    /// Returns an error when a pattern is invalid or a git command fails.
+    pub fn evaluate(config: &Config) -> Result<EvaluationResult, String> {
+        evaluate_compiled(config, &CompiledFilters::new(config)?)
+    }
+}
+
+/// [`DiffFilter::evaluate`] with filters already compiled from `config`, or from a
+/// configuration differing only in its base refs
+pub(crate) fn evaluate_compiled(
+    config: &Config,
+    compiled: &CompiledFilters,
+) -> Result<EvaluationResult, String> {
+    let started = Instant::now();
+    let mut evaluations = evaluate_filters(config, &compiled.filters(config, true))?;
+    let results = group_results(config, &mut evaluations);
+    let has_match = run_matched(config, &results);
+    let result = |name: Option<&str>, outcome: Outcome| FilterResult {
+        name: name.map(str::to_string),
+        has_match: outcome.has_match,
+        commit: outcome.commit,
+        files: outcome.files,
+        repositories: Vec::new(),
+    };
+    let filters: Vec<FilterResult> = results
+        .into_iter()
+        .map(|group_result| FilterResult {
+            repositories: group_result
+                .repo_outcomes
+                .into_iter()
+                .map(|(repo, outcome)| result(Some(repo), outcome))
+                .collect(),
+            ..result(group_result.group.name.as_deref(), group_result.outcome)
+        })
+        .collect();
+    Ok(EvaluationResult {
+        has_match,
+        filters,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// What [`DiffFilter::evaluate`] found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvaluationResult {
+    /// Whether any filter matched, or with `--require`, every required one
+    pub has_match: bool,
+    /// One result per filter, in the order they were given
+    pub filters: Vec<FilterResult>,
+    pub elapsed: Duration,
+}
+
+/// What one filter found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterResult {
+    /// The `-g` name, if any; also the checkout's name in `repositories`
+    pub name: Option<String>,
+    pub has_match: bool,
+    /// With `--last`, the newest commit with a matching change
+    pub commit: Option<String>,
+    /// Matching files; empty with `--repo`, where each checkout lists its own
+    pub files: Vec<String>,
+    /// With `--repo`, the result in each checkout, in order; with `--each-base`, the
+    /// result against each base, named `vs_<base>`
+    pub repositories: Vec<FilterResult>,
+}
+
+/// Run the `gdf` command line and exit with the code for its result
+pub fn run_cli() -> ! {
+    let started = Instant::now();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "validate") {
+        match validate::run(&args[1..]) {
+            Ok(summary) => println!("{summary}"),
+            Err(problems) => {
+                for problem in problems {
+                    log::fatal(&problem);
+                }
+                process::exit(config::ExitCodes::default().error);
+            }
+        }
+        process::exit(0);
+    }
+    if args.first().is_some_and(|arg| arg == "doctor") {
+        match doctor::run(&args[1..]) {
+            Ok(true) => process::exit(0),
+            Ok(false) => process::exit(config::ExitCodes::default().error),
+            Err(e) => {
+                log::fatal(&e);
+                process::exit(config::ExitCodes::default().error);
+            }
+        }
+    }
+    if args.first().is_some_and(|arg| arg == "tui") {
+        if let Err(e) = tui::run(&args[1..]) {
+            log::fatal(&e);
+            process::exit(config::ExitCodes::default().error);
+        }
+        process::exit(0);
+    }
+    // Until the flags are read, errors exit with the default code
+    let config = match cli::parse_args().and_then(config::from_args) {
+        Ok(config) => config,
+        Err(e) => {
+            log::fatal(&e);
+            process::exit(config::ExitCodes::default().error);
+        }
+    };
+    if config.print_resolved {
+        for line in config.describe() {
+            println!("{line}");
+        }
+        process::exit(0);
+    }
+    if config.watch {
+        watch(&config);
+    }
+    if config.serve {
+        let served = match &config.socket {
+            Some(path) => serve::serve_socket(&config, path),
+            None => serve::serve_stdio(&config),
+        };
+        if let Err(e) = served {
+            log::fatal(&e);
+            process::exit(config.exit_codes.error);
+        }
+        process::exit(0);
+    }
+
+    match run(&config, started) {
+        Ok(has_match) => process::exit(config.exit_codes.for_result(has_match)),
+        Err(e) => {
+            log::fatal(&e);
+            process::exit(config.exit_codes.error);
+        }
+    }
+}
+
+/// How often `--watch` looks for changes in the work tree
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Evaluate and write the outputs, then again each time the work tree changes, until
+/// interrupted
+///
+/// Outputs are printed rather than appended to `GITHUB_OUTPUT`, and options writing other
+/// files that grow on each run are rejected, so watching leaves no growing files behind.
+///
+/// Errors are printed without ending the loop, and only when they change, so a broken
+/// state can be fixed while watching.
+fn watch(config: &config::Config) -> ! {
+    let mut last_state = None;
+    loop {
+        let state = git::work_tree_state(&config.diff_options);
+        if last_state.as_ref() != Some(&state) {
+            if last_state.is_some() {
+                eprintln!();
+            }
+            let evaluated = state
+                .as_ref()
+                .map_err(String::clone)
+                .and_then(|_| run(config, Instant::now()));
+            if let Err(e) = evaluated {
+                log::fatal(&e);
+            }
+            last_state = Some(state);
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Evaluate every filter and write the outputs, returning whether the run matched
+fn run(config: &config::Config, started: Instant) -> Result<bool, String> {
+    if config.trace_json {
+        log::enable_trace_json();
+    }
+    if config.format.is_some() || config.jsonl {
+        output::disable_echo();
+    }
+
+    let mut evaluations = evaluate_repositories(config, lists_all_files(config))?;
+    let results = group_results(config, &mut evaluations);
+    let has_match = run_matched(config, &results);
+
+    // With --relative, the paths are relative to the current directory
+    let workspace = config.cargo_workspace || config.js_workspace;
+    let workspace_root = match (workspace, config.diff_options.relative) {
+        (false, _) => None,
+        (true, false) => Some(git::repo_root(&config.diff_options)?),
+        (true, true) => Some(std::env::current_dir().map_err(|e| e.to_string())?),
+    };
+    let tracked = if config.test_map.is_empty() {
+        None
+    } else {
+        Some(git::tracked_files(&config.diff_options)?)
+    };
+    for result in &results {
+        if let Some(root) = &workspace_root {
+            write_workspace_members(config, root, result)?;
+        }
+        if !config.targets.is_empty() {
+            write_targets(config, result)?;
+        }
+        if let Some(tracked) = &tracked {
+            write_tests(config, result, tracked)?;
+        }
+        write_outputs(config, result)?;
+        if let Some(name) = &result.group.name {
+            write_key_values(config, name, result)?;
+        }
+        if config.annotations {
+            write_annotations(result);
+        }
+    }
+    write_expressions(config, &results)?;
+    write_jobs(config, &results)?;
+    write_summaries(config, &results)?;
+    if let Some(template) = &config.format {
+        write_formatted(config, template, &results, &evaluations);
+    }
+    if config.stats {
+        print_stats(&evaluations, &results);
+    }
+    if let Some(path) = &config.files_output {
+        output::write_file_list(
+            &matching_files(config, &results),
+            config.files_output_nul,
+            path,
+        )?;
+    }
+
+    if config.report_json.is_some() || config.report_yaml.is_some() || config.report_md.is_some() {
+        let report = report::Report {
+            has_match,
+            elapsed: started.elapsed(),
+            repositories: evaluations
+                .iter()
+                .enumerate()
+                .map(|(index, (name, evaluation))| {
+                    repository_report(*name, evaluation, &results, index)
+                })
+                .collect(),
+        };
+        if let Some(path) = &config.report_json {
+            report.write(path)?;
+        }
+        if let Some(path) = &config.report_yaml {
+            report.write_yaml(path)?;
+        }
+        if let Some(path) = &config.report_md {
+            report.write_markdown(path)?;
+        }
+    }
+
+    if config.enforce {
+        let outside = files_outside(config, &evaluations, &results);
+        if !outside.is_empty() {
+            return Err(format!(
+                "Changed files outside the allowed patterns ({}):\n  {}",
+                outside.len(),
+                outside.join("\n  ")
+            ));
+        }
+    }
+
+    Ok(has_match)
+}
+
+/// Whether the run matched: when any filter did, or with `--require`, every required one
+fn run_matched(config: &config::Config, results: &[GroupResult]) -> bool {
+    if config.required.is_empty() {
+        return results.iter().any(|result| result.outcome.has_match);
+    }
+    let matched = config.required.iter().all(|name| {
+        results
+            .iter()
+            .any(|result| result.group.name.as_ref() == Some(name) && result.outcome.has_match)
+    });
+    log!(
+        Info,
+        "gdf",
+        "Required filters {}: {}",
+        config.required.join(", "),
+        if matched {
+            "all matched"
+        } else {
+            "not all matched"
+        }
+    );
+    matched
+}
+
+/// Evaluate every filter in each repository, listing every matching file when
+/// `list_all` is set, or else only deciding whether each filter matched
+fn evaluate_repositories(
+    config: &config::Config,
+    list_all: bool,
+) -> Result<Vec<(Option<&str>, Evaluation)>, String> {
+    let compiled = CompiledFilters::new(config)?;
+    evaluate_filters(config, &compiled.filters(config, list_all))
+}
+
+/// Evaluate `filters` in each repository, or against each base
+fn evaluate_filters<'a>(
+    config: &'a config::Config,
+    filters: &Filters,
+) -> Result<Vec<(Option<&'a str>, Evaluation)>, String> {
+    // Each --repo checkout is evaluated on its own, with the same filters
+    let mut evaluations = Vec::new();
+    for repo in &config.repos {
+        let repo_config = config::Config {
+            diff_options: git::DiffOptions {
+                git_dir: Some(repo.git_dir.clone()),
+                work_tree: Some(repo.work_tree.clone()),
+                ..config.diff_options.clone()
+            },
+            ..config.clone()
+        };
+        log!(Info, "gdf", "Repository: {}", repo.name);
+        evaluations.push((
+            Some(repo.name.as_str()),
+            evaluate_groups(&repo_config, filters)?,
+        ));
+    }
+    // And so is each --each-base base
+    for base in &config.bases {
+        let base_config = config::Config {
+            base_refs: base.refs.clone(),
+            ..config.clone()
+        };
+        log!(
+            Info,
+            "gdf",
+            "Base: {} ({})",
+            base.name,
+            base.refs.join(", ")
+        );
+        evaluations.push((
+            Some(base.label.as_str()),
+            evaluate_groups(&base_config, filters)?,
+        ));
+    }
+    if config.repos.is_empty() && config.bases.is_empty() {
+        evaluations.push((None, evaluate_groups(config, filters)?));
+    }
+    Ok(evaluations)
+}
+
+/// Output a group's result, preceded by its matching files and job matrix when asked
+/// for
+///
+/// A named group's result is written with its per-repository results, matching commit
+/// and file counts in one append, so a reader of `GITHUB_OUTPUT` never sees some of
+/// these keys without the others.
+fn write_outputs(config: &config::Config, result: &GroupResult) -> Result<(), String> {
+    let GroupResult {
+        group,
+        outcome,
+        repo_outcomes,
+    } = result;
+    let filepath = config.github_output_filepath.as_deref();
+    let Some(name) = &group.name else {
+        if config.color {
+            write_terminal_summary(config, result);
+            return Ok(());
+        }
+        return output::write_output(outcome.has_match, None, filepath);
+    };
+    // With a delimiter, the lists are written on one line with the results instead
+    let files = config.list_delimiter.filter(|_| config.list_files);
+    if config.list_files && files.is_none() {
+        for (repo, repo_outcome) in repo_outcomes {
+            let files_name = format!("{name}_{repo}_files");
+            output::write_multiline_output(&files_name, &repo_outcome.files, filepath)?;
+        }
+        if config.repos.is_empty() {
+            let files_name = format!("{name}_files");
+            output::write_multiline_output(&files_name, &outcome.files, filepath)?;
+        }
+    }
+    if config.matrix_output {
+        let include = matrix_include(name, outcome, repo_outcomes, &config.bases).to_string();
+        output::write_named_output(&format!("{name}_matrix"), &include, filepath)?;
+    }
+    let fields = Fields {
+        commit: true,
+        count: true,
+        files,
+    };
+    let values = named_values(name, outcome, repo_outcomes, fields);
+    output::write_named_outputs(&values, filepath)
+}
+
+/// Write the result of each `--expr` condition over the named groups' results, to the
+/// outputs the groups' results were written to
+fn write_expressions(config: &config::Config, results: &[GroupResult]) -> Result<(), String> {
+    if config.expressions.is_empty() {
+        return Ok(());
+    }
+    let matched = |name: &str| {
+        results
+            .iter()
+            .any(|result| result.group.name.as_deref() == Some(name) && result.outcome.has_match)
+    };
+    let values: Vec<(String, bool)> = config
+        .expressions
+        .iter()
+        .map(|(name, condition)| {
+            let holds = condition.holds(&matched);
+            log!(Info, "gdf", "Expression {name} ({condition}): {holds}");
+            (name.clone(), holds)
+        })
+        .collect();
+    let text_values: Vec<(String, String)> = values
+        .iter()
+        .map(|(name, holds)| (name.clone(), holds.to_string()))
+        .collect();
+    write_text_values(config, &text_values)?;
+    if let Some(path) = &config.circleci_parameters {
+        output::write_json_parameters(&values, path)?;
+    }
+    Ok(())
+}
+
+/// Write single-line values to `GITHUB_OUTPUT` (or stdout) and to the dotenv, shell,
+/// properties and `##teamcity` outputs that were asked for
+fn write_text_values(config: &config::Config, values: &[(String, String)]) -> Result<(), String> {
+    output::write_named_outputs(values, config.github_output_filepath.as_deref())?;
+    if let Some(path) = &config.dotenv {
+        output::write_dotenv(values, path)?;
+    }
+    if let Some(path) = &config.env_file {
+        output::write_env_file(values, path)?;
+    }
+    if let Some(path) = &config.properties_file {
+        output::write_properties(values, path)?;
+    }
+    if config.teamcity {
+        output::write_teamcity_parameters(values);
+    }
+    Ok(())
+}
+
+/// Write the `jobs` output: the `--job`s any of whose conditions over the named groups'
+/// results hold, in the order given, joined by the `--list-delimiter`, to the outputs
+/// the groups' results were written to
+fn write_jobs(config: &config::Config, results: &[GroupResult]) -> Result<(), String> {
+    if config.jobs.is_empty() {
+        return Ok(());
+    }
+    let matched = |name: &str| {
+        results
+            .iter()
+            .any(|result| result.group.name.as_deref() == Some(name) && result.outcome.has_match)
+    };
+    let jobs: Vec<String> = config
+        .jobs
+        .iter()
+        .filter(|(_, conditions)| conditions.iter().any(|condition| condition.holds(&matched)))
+        .map(|(job, _)| job.clone())
+        .collect();
+    if jobs.is_empty() {
+        log!(Info, "gdf", "Jobs to run: none");
+    } else {
+        log!(Info, "gdf", "Jobs to run: {}", jobs.join(", "));
+    }
+    let value = config.list_delimiter.unwrap_or_default().join(&jobs);
+    write_text_values(config, &[(config::JOBS_OUTPUT.to_string(), value)])
+}
+
+/// Write the `<name>_crates` and `<name>_packages` outputs (`crates` and `packages`
+/// without `-g`): the Cargo crates and JavaScript packages owning the group's matching
+/// files, joined by the `--list-delimiter`
+fn write_workspace_members(
+    config: &config::Config,
+    root: &std::path::Path,
+    result: &GroupResult,
+) -> Result<(), String> {
+    let files = &result.outcome.files;
+    let mut members = Vec::new();
+    if config.cargo_workspace {
+        let mut crates = cargo::affected_crates(root, files);
+        if config.include_dependents && !crates.is_empty() {
+            crates = workspace::with_dependents(crates, &cargo::dependency_graph(root)?);
+        }
+        members.push(("crates", crates));
+    }
+    if config.js_workspace {
+        let mut packages = node::affected_packages(root, files)?;
+        if config.include_dependents && !packages.is_empty() {
+            packages = workspace::with_dependents(packages, &node::dependency_graph(root)?);
+        }
+        members.push(("packages", packages));
+    }
+    for (kind, names) in members {
+        let key = match &result.group.name {
+            Some(name) => format!("{name}_{kind}"),
+            None => kind.to_string(),
+        };
+        let value = config.list_delimiter.unwrap_or_default().join(&names);
+        output::write_named_output(&key, &value, config.github_output_filepath.as_deref())?;
+    }
+    Ok(())
+}
+
+/// Write the `<name>_targets` output (`targets` without `-g`): the `--target`s whose
+/// globs select any of the group's matching files, joined by the `--list-delimiter`, to
+/// the outputs the group's result is written to
+fn write_targets(config: &config::Config, result: &GroupResult) -> Result<(), String> {
+    let names = targets::affected(&config.targets, &result.outcome.files)?;
+    let key = match &result.group.name {
+        Some(name) => format!("{name}_targets"),
+        None => "targets".to_string(),
+    };
+    let value = config.list_delimiter.unwrap_or_default().join(&names);
+    write_text_values(config, &[(key, value)])
+}
+
+/// Write the `<name>_tests` output (`tests` without `-g`): the tracked test files the
+/// `--test-map` rules call for given the group's matching files, joined by the
+/// `--list-delimiter`, to the outputs the group's result is written to
+fn write_tests(
+    config: &config::Config,
+    result: &GroupResult,
+    tracked: &[String],
+) -> Result<(), String> {
+    let tests = test_map::implied_tests(&config.test_map, &result.outcome.files, tracked)?;
+    let key = match &result.group.name {
+        Some(name) => format!("{name}_tests"),
+        None => "tests".to_string(),
+    };
+    let value = config.list_delimiter.unwrap_or_default().join(&tests);
+    write_text_values(config, &[(key, value)])
+}
+
+/// A `strategy.matrix` include list with an entry per matching filter, naming the
+/// repository too with `--repo`, or the base with `--each-base`
+fn matrix_include(
+    name: &str,
+    outcome: &Outcome,
+    repo_outcomes: &[(&str, Outcome)],
+    bases: &[config::Base],
+) -> json::Value {
+    let entry = |repo: Option<&str>| {
+        let mut members = vec![("filter".to_string(), json::Value::String(name.to_string()))];
+        if let Some(repo) = repo {
+            let member = match bases.iter().find(|base| base.label == repo) {
+                Some(base) => ("base", base.name.as_str()),
+                None => ("repository", repo),
+            };
+            members.push((
+                member.0.to_string(),
+                json::Value::String(member.1.to_string()),
+            ));
+        }
+        json::Value::Object(members)
+    };
+    let entries = if repo_outcomes.is_empty() {
+        [outcome]
+            .into_iter()
+            .filter(|outcome| outcome.has_match)
+            .map(|_| entry(None))
+            .collect()
+    } else {
+        repo_outcomes
+            .iter()
+            .filter(|(_, repo_outcome)| repo_outcome.has_match)
+            .map(|(repo, _)| entry(Some(repo)))
+            .collect()
+    };
+    json::Value::Array(entries)
+}
+
+/// Name each reported outcome of a group: its `-g` output name (or its patterns),
+/// qualified by the repository with `--repo`
+fn named_outcomes<'a>(result: &'a GroupResult) -> Vec<(String, &'a Outcome)> {
+    let filter = result
+        .group
+        .name
+        .clone()
+        .unwrap_or_else(|| result.group.patterns.join(", "));
+    if result.repo_outcomes.is_empty() {
+        vec![(filter, &result.outcome)]
+    } else {
+        result
+            .repo_outcomes
+            .iter()
+            .map(|(repo, repo_outcome)| (format!("{filter} ({repo})"), repo_outcome))
+            .collect()
+    }
+}
+
+/// Write the step summary table and the Bitbucket report, one row per group and
+/// repository
+fn write_summaries(config: &config::Config, results: &[GroupResult]) -> Result<(), String> {
+    let filters: Vec<(String, &Outcome)> = results.iter().flat_map(named_outcomes).collect();
+    let rows: Vec<output::SummaryRow> = filters
+        .iter()
+        .map(|(filter, outcome)| output::SummaryRow {
+            filter,
+            has_match: outcome.has_match,
+            files: &outcome.files,
+        })
+        .collect();
+    if let Some(path) = &config.step_summary_filepath {
+        output::write_step_summary(&rows, path)?;
+    }
+    if let Some(path) = &config.bitbucket_report {
+        output::write_code_insights_report(&rows, path)?;
+    }
+    Ok(())
+}
+
+/// Every file any group matched, once each, prefixed with its checkout's path with
+/// `--repo` so that it can be opened from the current directory
+fn matching_files(config: &config::Config, results: &[GroupResult]) -> Vec<String> {
+    let mut files: Vec<String> = results
+        .iter()
+        .flat_map(|result| checkout_files(config, result, |outcome| &outcome.files))
+        .collect();
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    files
+}
+
+/// The changed files no group selected, for `--enforce`, prefixed with their checkout's
+/// path with `--repo`
+fn files_outside(
+    config: &config::Config,
+    evaluations: &[(Option<&str>, Evaluation)],
+    results: &[GroupResult],
+) -> Vec<String> {
+    let mut outside = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, (_, evaluation)) in evaluations.iter().enumerate() {
+        let selected: HashSet<&str> = results
+            .iter()
+            .flat_map(|result| &result.repository_outcome(index).files)
+            .map(String::as_str)
+            .collect();
+        for path in &evaluation.changed {
+            if selected.contains(path.as_str()) {
+                continue;
+            }
+            let path = match config.repos.get(index) {
+                Some(repo) => repo.work_tree.join(path).display().to_string(),
+                None => path.clone(),
+            };
+            if seen.insert(path.clone()) {
+                outside.push(path);
+            }
+        }
+    }
+    outside
+}
+
+/// The files `list` picks from a group's outcome, prefixed with their checkout's path
+/// with `--repo`
+fn checkout_files(
+    config: &config::Config,
+    result: &GroupResult,
+    list: impl Fn(&Outcome) -> &[String],
+) -> Vec<String> {
+    if config.repos.is_empty() {
+        return list(&result.outcome).to_vec();
+    }
+    let mut files = Vec::new();
+    for (repo, (_, outcome)) in config.repos.iter().zip(&result.repo_outcomes) {
+        let paths = list(outcome).iter().map(|file| repo.work_tree.join(file));
+        files.extend(paths.map(|path| path.display().to_string()));
+    }
+    files
+}
+
+/// Print the colored summary of an unnamed group's result
+fn write_terminal_summary(config: &config::Config, result: &GroupResult) {
+    let outcomes: Vec<&Outcome> = if result.repo_outcomes.is_empty() {
+        vec![&result.outcome]
+    } else {
+        result
+            .repo_outcomes
+            .iter()
+            .map(|(_, outcome)| outcome)
+            .collect()
+    };
+    let mut pattern_hits = vec![0; result.group.patterns.len()];
+    for outcome in outcomes {
+        for (total, hits) in pattern_hits.iter_mut().zip(&outcome.pattern_hits) {
+            *total += hits;
+        }
+    }
+    let patterns: Vec<(&str, usize)> = result
+        .group
+        .patterns
+        .iter()
+        .map(String::as_str)
+        .zip(pattern_hits)
+        .collect();
+    output::write_terminal_summary(&output::TerminalSummary {
+        has_match: result.outcome.has_match,
+        files: &checkout_files(config, result, |outcome| &outcome.files),
+        excluded: &checkout_files(config, result, |outcome| &outcome.excluded),
+        patterns: &patterns,
+    });
+}
+
+/// Print the `--stats` tables to stderr, over the changed files of every range, for each
+/// checkout
+fn print_stats(evaluations: &[(Option<&str>, Evaluation)], results: &[GroupResult]) {
+    for (index, (name, evaluation)) in evaluations.iter().enumerate() {
+        let changed: Vec<&str> = evaluation.changed.iter().map(String::as_str).collect();
+        let matched: HashSet<&str> = results
+            .iter()
+            .flat_map(|result| &result.repository_outcome(index).files)
+            .map(String::as_str)
+            .collect();
+        if let Some(name) = name {
+            log!(Info, "gdf", "Repository: {name}");
+        }
+        for line in stats::Stats::new(&changed, &matched).lines() {
+            log!(Info, "gdf", "{line}");
+        }
+    }
+}
+
+/// Print the `--format` line of each filter; the range is the first checkout's, spanning
+/// every commit inspected with `--last`
+fn write_formatted(
+    config: &config::Config,
+    template: &output::Template,
+    results: &[GroupResult],
+    evaluations: &[(Option<&str>, Evaluation)],
+) {
+    let ranges = evaluations
+        .first()
+        .map(|(_, evaluation)| evaluation.ranges.as_slice())
+        .unwrap_or_default();
+    let base = ranges
+        .last()
+        .map_or("", |resolved| resolved.range.base.as_str());
+    let head = ranges
+        .first()
+        .map_or("", |resolved| resolved.range.head.as_str());
+    for result in results {
+        let commit = result.outcome.commit.as_deref().or_else(|| {
+            result
+                .repo_outcomes
+                .iter()
+                .find_map(|(_, outcome)| outcome.commit.as_deref())
+        });
+        let line = template.render(&output::TemplateValues {
+            name: result.group.name.as_deref().unwrap_or_default(),
+            has_match: result.outcome.has_match,
+            files: &checkout_files(config, result, |outcome| &outcome.files),
+            delimiter: config
+                .list_delimiter
+                .unwrap_or(output::ListDelimiter::Space),
+            base,
+            head,
+            commit: commit.unwrap_or_default(),
+        });
+        println!("{line}");
+    }
+}
+
+/// Annotate the workflow run with a notice per matching filter and a warning per
+/// pattern of the group that matched no changed file in any repository
+fn write_annotations(result: &GroupResult) {
+    let filters = named_outcomes(result);
+    let mut pattern_hits = vec![0; result.group.patterns.len()];
+    for (filter, outcome) in &filters {
+        if outcome.has_match {
+            let message = format!(
+                "Matched {} changed file(s): {}",
+                outcome.files.len(),
+                output::sample(&outcome.files).join(", ")
+            );
+            output::write_annotation(output::Annotation::Notice, filter, &message);
+        }
+        for (total, hits) in pattern_hits.iter_mut().zip(&outcome.pattern_hits) {
+            *total += hits;
+        }
+    }
+
+    let title = result.group.name.as_deref().unwrap_or("gdf");
+    for (pattern, hits) in result.group.patterns.iter().zip(pattern_hits) {
+        if hits == 0 {
+            let message = format!("Pattern '{pattern}' matched no changed files");
+            output::write_annotation(output::Annotation::Warning, title, &message);
+        }
+    }
+}
+
+/// Describe the evaluation of the `index`th repository for the JSON report
+fn repository_report<'a>(
+    name: Option<&'a str>,
+    evaluation: &Evaluation,
+    results: &'a [GroupResult],
+    index: usize,
+) -> report::RepositoryReport<'a> {
+    let ranges = evaluation
+        .ranges
+        .iter()
+        .zip(&evaluation.range_ids)
+        .map(|(resolved, (base_id, head_id))| report::RangeReport {
+            base: resolved.range.base.clone(),
+            head: resolved.range.head.clone(),
+            base_id: base_id.clone(),
+            head_id: head_id.clone(),
+            merge_base: resolved.merge_base.clone(),
+        })
+        .collect();
+    let groups: Vec<report::GroupReport> = results
+        .iter()
+        .map(|result| {
+            let outcome = result.repository_outcome(index);
+            report::GroupReport {
+                name: result.group.name.as_deref(),
+                patterns: &result.group.patterns,
+                has_match: outcome.has_match,
+                commit: outcome.commit.as_deref(),
+                files: &outcome.files,
+                pattern_hits: &outcome.pattern_hits,
+            }
+        })
+        .collect();
+    // The newest commit any group matched in
+    let commit = groups
+        .iter()
+        .filter_map(|group| group.commit)
+        .min_by_key(|commit| {
+            evaluation
+                .ranges
+                .iter()
+                .position(|resolved| resolved.range.head == *commit)
+        });
+    report::RepositoryReport {
+        name,
+        ranges,
+        commit,
+        elapsed: evaluation.elapsed,
+        groups,
+    }
+}
+
+/// The result of evaluating one repository
+#[derive(Debug, Default)]
+struct Evaluation {
+    /// Ranges diffed, in order, until every group matched
+    ranges: Vec<git::ResolvedRange>,
+    /// Object IDs of the base and head of each range, resolved only for reports
+    range_ids: Vec<(String, String)>,
+    /// One outcome per filter group, in group order
+    outcomes: Vec<Outcome>,
+    /// Paths of the changed files left after filtering, in each range diffed; listed
+    /// only for reports
+    changed: Vec<String>,
+    elapsed: Duration,
+}
+
+impl Evaluation {
+    /// The evaluation as a cache entry, without its duration
+    fn to_cache(&self) -> json::Value {
+        let strings = |values: &[String]| {
+            json::Value::Array(values.iter().cloned().map(json::Value::String).collect())
+        };
+        let optional =
+            |value: &Option<String>| value.clone().map_or(json::Value::Null, json::Value::String);
+        let object = |members: Vec<(&str, json::Value)>| {
+            json::Value::Object(
+                members
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect(),
+            )
+        };
+        let ranges = self.ranges.iter().map(|resolved| {
+            object(vec![
+                ("base", json::Value::String(resolved.range.base.clone())),
+                ("head", json::Value::String(resolved.range.head.clone())),
+                ("symmetric", json::Value::Bool(resolved.range.symmetric)),
+                ("merge_base", optional(&resolved.merge_base)),
+            ])
+        });
+        let range_ids = self
+            .range_ids
+            .iter()
+            .map(|(base, head)| strings(&[base.clone(), head.clone()]));
+        #[allow(clippy::cast_precision_loss)]
+        let outcomes = self.outcomes.iter().map(|outcome| {
+            object(vec![
+                ("match", json::Value::Bool(outcome.has_match)),
+                ("commit", optional(&outcome.commit)),
+                ("files", strings(&outcome.files)),
+                (
+                    "pattern_hits",
+                    json::Value::Array(
+                        outcome
+                            .pattern_hits
+                            .iter()
+                            .map(|&hits| json::Value::Number(hits as f64))
+                            .collect(),
+                    ),
+                ),
+                ("excluded", strings(&outcome.excluded)),
+            ])
+        });
+        object(vec![
+            ("ranges", json::Value::Array(ranges.collect())),
+            ("range_ids", json::Value::Array(range_ids.collect())),
+            ("outcomes", json::Value::Array(outcomes.collect())),
+            ("changed", strings(&self.changed)),
+        ])
+    }
+
+    /// An evaluation read back from a cache entry, or `None` for an unreadable one
+    fn from_cache(entry: &json::Value) -> Option<Self> {
+        let array = |value: &json::Value, key: &str| match value.get(key) {
+            Some(json::Value::Array(items)) => Some(items.clone()),
+            _ => None,
+        };
+        let strings = |value: &json::Value, key: &str| -> Option<Vec<String>> {
+            array(value, key)?
+                .iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect()
+        };
+        let optional = |value: &json::Value, key: &str| {
+            value
+                .get(key)
+                .and_then(json::Value::as_str)
+                .map(str::to_string)
+        };
+        let text = |value: &json::Value, key: &str| optional(value, key);
+        let ranges = array(entry, "ranges")?
+            .iter()
+            .map(|range| {
+                Some(git::ResolvedRange {
+                    range: git::RevisionRange {
+                        base: text(range, "base")?,
+                        head: text(range, "head")?,
+                        symmetric: matches!(range.get("symmetric"), Some(json::Value::Bool(true))),
+                    },
+                    merge_base: optional(range, "merge_base"),
+                })
+            })
+            .collect::<Option<_>>()?;
+        let range_ids = array(entry, "range_ids")?
+            .iter()
+            .map(|ids| match ids {
+                json::Value::Array(ids) => match ids.as_slice() {
+                    [base, head] => Some((base.as_str()?.to_string(), head.as_str()?.to_string())),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        let outcomes = array(entry, "outcomes")?
+            .iter()
+            .map(|outcome| {
+                Some(Outcome {
+                    has_match: matches!(outcome.get("match"), Some(json::Value::Bool(true))),
+                    commit: optional(outcome, "commit"),
+                    files: strings(outcome, "files")?,
+                    pattern_hits: array(outcome, "pattern_hits")?
+                        .iter()
+                        .map(|hits| usize::try_from(hits.as_u64()?).ok())
+                        .collect::<Option<_>>()?,
+                    excluded: strings(outcome, "excluded")?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Evaluation {
+            ranges,
+            range_ids,
+            outcomes,
+            changed: strings(entry, "changed")?,
+            elapsed: Duration::ZERO,
+        })
+    }
+}
+
+/// What one filter group found in a repository
+#[derive(Debug, Default)]
+struct Outcome {
+    has_match: bool,
+    /// With `--last`, the newest commit with a matching change
+    commit: Option<String>,
+    /// Matching files, listed only for reports
+    files: Vec<String>,
+    /// Changed paths each pattern matched over the ranges, counted only for reports
+    pattern_hits: Vec<usize>,
+    /// Files an inclusion pattern matched but an exclusion dropped, listed only for
+    /// reports
+    excluded: Vec<String>,
+}
+
+/// A filter group's outcomes, gathered from every repository
+#[derive(Debug)]
+struct GroupResult<'a> {
+    group: &'a config::FilterGroup,
+    /// Overall outcome; with `--repo`, only whether any checkout matched, and with
+    /// `--each-base`, whether any base matched and the files matching against any
+    outcome: Outcome,
+    /// Outcome in each `--repo` checkout or against each `--each-base` base, in order
+    repo_outcomes: Vec<(&'a str, Outcome)>,
+}
+
+impl GroupResult<'_> {
+    /// The outcome in the `index`th evaluated repository
+    fn repository_outcome(&self, index: usize) -> &Outcome {
+        self.repo_outcomes
+            .get(index)
+            .map_or(&self.outcome, |(_, outcome)| outcome)
+    }
+}
+
+/// Regroup the outcomes of each evaluated repository by filter group
+fn group_results<'a>(
+    config: &'a config::Config,
+    evaluations: &mut [(Option<&'a str>, Evaluation)],
+) -> Vec<GroupResult<'a>> {
+    let mut results = Vec::new();
+    for (index, group) in config.groups.iter().enumerate() {
+        let mut outcomes = evaluations
+            .iter_mut()
+            .map(|(name, evaluation)| (*name, std::mem::take(&mut evaluation.outcomes[index])));
+        let result = if config.repos.is_empty() && config.bases.is_empty() {
+            GroupResult {
+                group,
+                outcome: outcomes
+                    .next()
+                    .map(|(_, outcome)| outcome)
+                    .unwrap_or_default(),
+                repo_outcomes: Vec::new(),
+            }
+        } else {
+            let repo_outcomes: Vec<(&str, Outcome)> = outcomes
+                .map(|(name, outcome)| (name.unwrap_or_default(), outcome))
+                .collect();
+            // The files matching against any base are in the same checkout
+            let mut files = Vec::new();
+            if !config.bases.is_empty() {
+                let mut seen = HashSet::new();
+                files = repo_outcomes
+                    .iter()
+                    .flat_map(|(_, outcome)| &outcome.files)
+                    .filter(|file| seen.insert(*file))
+                    .cloned()
+                    .collect();
+            }
+            GroupResult {
+                group,
+                outcome: Outcome {
+                    has_match: repo_outcomes.iter().any(|(_, outcome)| outcome.has_match),
+                    files,
+                    ..Outcome::default()
+                },
+                repo_outcomes,
+            }
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// Write the dotenv, shell, properties, pipeline parameter and `##teamcity` outputs
+/// that were asked for
+fn write_key_values(
+    config: &config::Config,
+    name: &str,
+    result: &GroupResult,
+) -> Result<(), String> {
+    let GroupResult {
+        outcome,
+        repo_outcomes,
+        ..
+    } = result;
+    let values = |fields| named_values(name, outcome, repo_outcomes, fields);
+    let delimiter = config.list_delimiter.unwrap_or_default();
+    if let Some(path) = &config.dotenv {
+        // Dotenv values cannot span lines, so file lists need a delimiter
+        let fields = Fields {
+            commit: true,
+            files: config.list_delimiter.filter(|_| config.list_files),
+            ..Fields::default()
+        };
+        output::write_dotenv(&values(fields), path)?;
+    }
+    if let Some(path) = &config.env_file {
+        let fields = Fields {
+            commit: true,
+            count: true,
+            files: Some(delimiter),
+        };
+        output::write_env_file(&values(fields), path)?;
+    }
+    if let Some(path) = &config.properties_file {
+        let fields = Fields {
+            commit: true,
+            count: true,
+            files: Some(delimiter),
+        };
+        output::write_properties(&values(fields), path)?;
+    }
+    if let Some(path) = &config.circleci_parameters {
+        let parameters: Vec<(String, bool)> = repo_outcomes
+            .iter()
+            .map(|(repo, repo_outcome)| (format!("{name}_{repo}"), repo_outcome.has_match))
+            .chain([(name.to_string(), outcome.has_match)])
+            .collect();
+        output::write_json_parameters(&parameters, path)?;
+    }
+    if config.teamcity {
+        let fields = Fields {
+            count: true,
+            ..Fields::default()
+        };
+        output::write_teamcity_parameters(&values(fields));
+    }
+    Ok(())
+}
+
+/// Which values a key-value output reports for each named outcome
+#[derive(Debug, Clone, Copy, Default)]
+struct Fields {
+    /// `<key>_commit`, when `--last` found a matching commit
+    commit: bool,
+    /// `<key>_count`, the number of matching files
+    count: bool,
+    /// `<key>_files`, the matching files joined by the delimiter
+    files: Option<output::ListDelimiter>,
+}
+
+/// The `<name>` result of each repository, then overall, each preceded by the
+/// requested `fields`
+fn named_values(
+    name: &str,
+    outcome: &Outcome,
+    repo_outcomes: &[(&str, Outcome)],
+    fields: Fields,
+) -> Vec<(String, String)> {
+    let named = repo_outcomes
+        .iter()
+        .map(|(repo, repo_outcome)| (format!("{name}_{repo}"), repo_outcome))
+        .chain([(name.to_string(), outcome)]);
+    let mut values = Vec::new();
+    for (key, outcome) in named {
+        if let (true, Some(commit)) = (fields.commit, &outcome.commit) {
+            values.push((format!("{key}_commit"), commit.clone()));
+        }
+        if fields.count {
+            values.push((format!("{key}_count"), outcome.files.len().to_string()));
+        }
+        if let Some(delimiter) = fields.files {
+            values.push((format!("{key}_files"), delimiter.join(&outcome.files)));
+        }
+        values.push((key, outcome.has_match.to_string()));
+    }
+    values
+}
+
+/// Whether any output needs every matching file, so the diff cannot stop at the first
+/// match
+fn lists_all_files(config: &config::Config) -> bool {
+    config.groups.iter().any(|group| group.name.is_some())
+        || config.report_json.is_some()
+        || config.report_yaml.is_some()
+        || config.report_md.is_some()
+        || config.list_files
+        || config.step_summary_filepath.is_some()
+        || config.annotations
+        || config.teamcity
+        || config.properties_file.is_some()
+        || config.env_file.is_some()
+        || config.bitbucket_report.is_some()
+        || config.files_output.is_some()
+        || config.color
+        || config.jsonl
+        || config.stats
+        || config.cargo_workspace
+        || config.js_workspace
+        || !config.targets.is_empty()
+        || !config.test_map.is_empty()
+        || config.enforce
+        || config
+            .format
+            .as_ref()
+            .is_some_and(output::Template::lists_files)
+}
+
+/// The patterns, content regexes and event payload of a configuration, compiled and
+/// loaded once for any number of evaluations
+pub(crate) struct CompiledFilters {
+    pattern_sets: Vec<matcher::PatternSet>,
+    content_regexes: Vec<regex::Regex>,
+    event: Option<github::Event>,
+}
+
+impl CompiledFilters {
+    pub(crate) fn new(config: &config::Config) -> Result<Self, String> {
+        let content_regexes = config
+            .content_patterns
+            .iter()
+            .map(|pattern| regex::Regex::new(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pattern_sets = config
+            .groups
+            .iter()
+            .map(|group| {
+                matcher::PatternSet::new(&group.patterns, config.anchoring)
+                    .map(|pattern_set| pattern_set.ignoring_case(group.ignore_case))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CompiledFilters {
+            pattern_sets,
+            content_regexes,
+            event: load_event(config)?,
+        })
+    }
+
+    fn filters(&self, config: &config::Config, list_all: bool) -> Filters<'_> {
+        let event = self.event.as_ref();
+        Filters {
+            pattern_sets: &self.pattern_sets,
+            content_regexes: &self.content_regexes,
+            event,
+            hints: git::EventHints {
+                default_branch: event.and_then(github::Event::default_branch),
+                pull_request: event.and_then(github::Event::pull_request_number),
+                event_base: event.and_then(github::Event::base_commit),
+            },
+            list_all,
+            labeled: config.groups.len() > 1,
+        }
+    }
+}
+
+/// Filters shared by every repository evaluated in one run
+struct Filters<'a> {
+    /// Patterns of each filter group, in group order
+    pattern_sets: &'a [matcher::PatternSet],
+    content_regexes: &'a [regex::Regex],
+    /// The payload of the event that triggered the run, when a base ref or
+    /// `--github-api` needs it
+    event: Option<&'a github::Event>,
+    hints: git::EventHints<'a>,
+    /// List every matching file rather than stopping at the first one
+    list_all: bool,
+    /// Prefix each group's comparison line with its name, as there are several groups
+    labeled: bool,
+}
+
+/// Evaluate the groups comparing against the same base refs together, diffing once per
+/// distinct list of base refs, and merge their outcomes back in group order
+fn evaluate_groups(config: &config::Config, filters: &Filters) -> Result<Evaluation, String> {
+    let active = active_groups(config)?;
+    if active.iter().all(|&active| active)
+        && config.groups.iter().all(|group| group.base_refs.is_empty())
+    {
+        return evaluate(config, filters);
+    }
+    let mut partitions: Vec<(&[String], Vec<usize>)> = Vec::new();
+    for (index, group) in config.groups.iter().enumerate() {
+        // Groups that do not apply on this branch keep an outcome without a match
+        if !active[index] {
+            continue;
+        }
+        let base_refs = if group.base_refs.is_empty() {
+            &config.base_refs
+        } else {
+            &group.base_refs
+        };
+        match partitions
+            .iter_mut()
+            .find(|(refs, _)| *refs == base_refs.as_slice())
+        {
+            Some((_, indexes)) => indexes.push(index),
+            None => partitions.push((base_refs, vec![index])),
+        }
+    }
+
+    let mut merged = Evaluation {
+        outcomes: config.groups.iter().map(|_| Outcome::default()).collect(),
+        ..Evaluation::default()
+    };
+    for (base_refs, indexes) in partitions {
+        let partition_config = config::Config {
+            base_refs: base_refs.to_vec(),
+            groups: indexes
+                .iter()
+                .map(|&index| config.groups[index].clone())
+                .collect(),
+            ..config.clone()
+        };
+        let pattern_sets: Vec<matcher::PatternSet> = indexes
+            .iter()
+            .map(|&index| filters.pattern_sets[index].clone())
+            .collect();
+        let partition_filters = Filters {
+            pattern_sets: &pattern_sets,
+            ..*filters
+        };
+        let evaluation = evaluate(&partition_config, &partition_filters)?;
+        for (index, outcome) in indexes.into_iter().zip(evaluation.outcomes) {
+            merged.outcomes[index] = outcome;
+        }
+        merged.ranges.extend(evaluation.ranges);
+        merged.range_ids.extend(evaluation.range_ids);
+        merged.changed.extend(evaluation.changed);
+        merged.elapsed += evaluation.elapsed;
+    }
+    Ok(merged)
+}
+
+/// Whether each group applies on the branch being built, by its branch conditions
+fn active_groups(config: &config::Config) -> Result<Vec<bool>, String> {
+    if config.groups.iter().all(|group| group.branches.is_empty()) {
+        return Ok(vec![true; config.groups.len()]);
+    }
+    let branch = git::current_branch(&config.diff_options, &|name| std::env::var(name).ok())
+        .ok_or(
+            "Cannot tell which branch is being built for the filters' branch conditions: \
+             HEAD is detached and no CI variable names the branch",
+        )?;
+    config
+        .groups
+        .iter()
+        .map(|group| {
+            let active = group.applies_on(&branch)?;
+            if !active {
+                log!(
+                    Info,
+                    "gdf",
+                    "{}Skipped: branch {branch} does not match {}",
+                    group
+                        .name
+                        .as_deref()
+                        .map_or_else(String::new, |name| format!("{name}: ")),
+                    group.branches.join(", ")
+                );
+            }
+            Ok(active)
+        })
+        .collect()
+}
+
+/// Decide which filter groups have a matching change in the repository `config` points at
+fn evaluate(config: &config::Config, filters: &Filters) -> Result<Evaluation, String> {
+    if config.github_api {
+        return evaluate_pull_request(config, filters);
+    }
+    let started = Instant::now();
+
+    // Get the ranges to diff: each recent commit with --last, otherwise one range from the
+    // base ref; a date resolves to the last commit before it on the current branch
+    let (ranges, range_label) = if let Some(count) = config.last {
+        let ranges = git::recent_commits(&config.diff_options, count)?
+            .into_iter()
+            .map(|range| git::ResolvedRange {
+                range,
+                merge_base: None,
+            })
+            .collect();
+        (ranges, format!("each of the last {count} commits"))
+    } else {
+        let base_refs = match &config.since {
+            Some(date) => vec![git::commit_before(&config.diff_options, date)?],
+            None => config.base_refs.clone(),
+        };
+        let (range, resolved) =
+            git::resolve_first(&base_refs, &config.diff_options, filters.hints)?;
+        let range_label = match &resolved.merge_base {
+            Some(merge_base) => {
+                format!("{} (merge-base {merge_base})..{}", range.base, range.head)
+            }
+            None => range.to_string(),
+        };
+        let range_label = match &config.since {
+            Some(date) => format!("{range_label} (last commit before {date})"),
+            None => range_label,
+        };
+        (vec![resolved], range_label)
+    };
+
+    // Files outside a sparse checkout cannot be built; they are dropped when requested
+    let sparse = git::is_sparse_checkout(&config.diff_options)?;
+    let cone = if sparse && config.sparse_paths == git::SparsePaths::Exclude {
+        Some(git::sparse_checkout(&config.diff_options)?)
+    } else {
+        None
+    };
+    let file_selected = |file: &git::ChangedFile| {
+        (config.statuses.is_empty() || config.statuses.contains(&file.kind))
+            && cone.as_ref().is_none_or(|cone| cone.contains(&file.path))
+    };
+
+    let cache_entry = cache_entry(config, filters, &ranges, cone.is_some())?;
+    let cached = cache_entry
+        .as_ref()
+        .and_then(|(dir, key)| cache::load(dir, key));
+    if let Some(mut evaluation) = cached.and_then(|entry| Evaluation::from_cache(&entry)) {
+        evaluation.elapsed = started.elapsed();
+        print_evaluation(config, filters, &range_label, sparse, &evaluation);
+        return Ok(evaluation);
+    }
+
+    let mut evaluation = Evaluation {
+        outcomes: filters
+            .pattern_sets
+            .iter()
+            .map(|_| Outcome::default())
+            .collect(),
+        ..Evaluation::default()
+    };
+    for resolved in ranges {
+        let listing = match_range(config, filters, &resolved.range, &file_selected)?;
+        if filters.list_all {
+            evaluation.changed.extend(listing.changed);
+            let ids = (
+                git::object_id(&config.diff_options, &resolved.range.base)?,
+                git::object_id(&config.diff_options, &resolved.range.head)?,
+            );
+            evaluation.range_ids.push(ids);
+        }
+        for (outcome, selection) in evaluation.outcomes.iter_mut().zip(listing.selections) {
+            // With --last, a group is done at its newest matching commit
+            if outcome.has_match {
+                continue;
+            }
+            if filters.list_all {
+                outcome.files.extend(selection.files);
+                outcome.excluded.extend(selection.excluded);
+                outcome.pattern_hits.resize(selection.pattern_hits.len(), 0);
+                for (total, hits) in outcome.pattern_hits.iter_mut().zip(selection.pattern_hits) {
+                    *total += hits;
+                }
+            }
+            if selection.has_match {
+                outcome.has_match = true;
+                if config.last.is_some() {
+                    outcome.commit = Some(resolved.range.head.clone());
+                }
+            }
+        }
+        evaluation.ranges.push(resolved);
+        if evaluation.outcomes.iter().all(|outcome| outcome.has_match) {
+            break;
+        }
+    }
+    evaluation.elapsed = started.elapsed();
+    let stored = cache_entry.map(|(dir, key)| cache::store(&dir, &key, &evaluation.to_cache()));
+    if let Some(Err(e)) = stored {
+        log!(Warn, "gdf", "{e}");
+    }
+
+    print_evaluation(config, filters, &range_label, sparse, &evaluation);
+    Ok(evaluation)
+}
+
+/// The cache directory and key of the result of diffing `ranges`, unless caching is
+/// off or the result depends on more than the commits: local changes, the files a
+/// sparse checkout leaves out, or `--jsonl`, which prints as it matches
+fn cache_entry(
+    config: &config::Config,
+    filters: &Filters,
+    ranges: &[git::ResolvedRange],
+    sparse_excluded: bool,
+) -> Result<Option<(PathBuf, String)>, String> {
+    let cacheable = config.cache
+        && config.last.is_none()
+        && config.diff_options.source == git::DiffSource::Committed
+        && !config.jsonl
+        && !sparse_excluded;
+    if !cacheable {
+        return Ok(None);
+    }
+    let dir = match &config.cache_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => git::common_dir(&config.diff_options)?.join("gdf-cache"),
+    };
+    let mut inputs = Vec::new();
+    for resolved in ranges {
+        inputs.push(git::object_id(&config.diff_options, &resolved.range.base)?);
+        inputs.push(git::object_id(&config.diff_options, &resolved.range.head)?);
+        inputs.push(format!("{resolved:?}"));
+    }
+    // Paths are relative to the current directory with --relative
+    let current_dir = std::env::current_dir().map_err(|e| e.to_string())?;
+    inputs.extend([
+        format!("{:?}", config.groups),
+        format!("{:?}", config.statuses),
+        format!("{:?}", config.content_patterns),
+        format!("{:?}", config.diff_options),
+        format!(
+            "{:?} {:?} {:?} {:?} {:?}",
+            config.anchoring,
+            config.renames,
+            config.line_thresholds,
+            config.content_kind,
+            config.generated_attribute
+        ),
+        format!("{} {}", filters.list_all, current_dir.display()),
+    ]);
+    let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+    Ok(Some((dir, cache::key(&inputs))))
+}
+
+/// Decide which filter groups have a matching change in the pull request of the run,
+/// listed by the GitHub API rather than by git
+fn evaluate_pull_request(config: &config::Config, filters: &Filters) -> Result<Evaluation, String> {
+    let started = Instant::now();
+    let env = |name: &str| std::env::var(name).ok();
+    let pull_request = github::PullRequest::of_run(filters.event, &env)?;
+    let token = env("GITHUB_TOKEN")
+        .filter(|token| !token.is_empty())
+        .ok_or("--github-api needs a GITHUB_TOKEN environment variable")?;
+    let files = pull_request.files(&token)?;
+
+    // The payload names the commits compared, for reports
+    let (base, head) = filters
+        .event
+        .and_then(github::Event::pull_request_commits)
+        .unwrap_or_default();
+    let range = git::RevisionRange {
+        base: base.to_string(),
+        head: head.to_string(),
+        symmetric: true,
+    };
+    let file_selected = |file: &git::ChangedFile| {
+        config.statuses.is_empty() || config.statuses.contains(&file.kind)
+    };
+    let listing = list_and_match(
+        &files,
+        config,
+        &range,
+        filters.pattern_sets,
+        filters.content_regexes,
+        &file_selected,
+    )?;
+    let mut evaluation = Evaluation {
+        outcomes: listing
+            .selections
+            .into_iter()
+            .map(|selection| Outcome {
+                has_match: selection.has_match,
+                files: selection.files,
+                pattern_hits: selection.pattern_hits,
+                excluded: selection.excluded,
+                ..Outcome::default()
+            })
+            .collect(),
+        changed: listing.changed,
+        range_ids: vec![(base.to_string(), head.to_string())],
+        ranges: vec![git::ResolvedRange {
+            range,
+            merge_base: None,
+        }],
+        ..Evaluation::default()
+    };
+    evaluation.elapsed = started.elapsed();
+    print_evaluation(
+        config,
+        filters,
+        &format!("pull request {pull_request}"),
+        false,
+        &evaluation,
+    );
+    Ok(evaluation)
+}
+
+/// Print the range, filters and result of each group to stderr
+fn print_evaluation(
+    config: &config::Config,
+    filters: &Filters,
+    range_label: &str,
+    sparse: bool,
+    evaluation: &Evaluation,
+) {
+    let content_label = if filters.content_regexes.is_empty() {
+        String::new()
+    } else {
+        let sources: Vec<&str> = filters
+            .content_regexes
+            .iter()
+            .map(regex::Regex::as_str)
+            .collect();
+        format!(" | Content: {}", sources.join(", "))
+    };
+    let kind_label = match config.content_kind {
+        None => "",
+        Some(git::ContentKind::Binary) => " | Only: binary files",
+        Some(git::ContentKind::Text) => " | Only: text files",
+    };
+    let sparse_label = match (sparse, config.sparse_paths) {
+        (false, _) => "",
+        (true, git::SparsePaths::Include) => " | Sparse checkout: out-of-cone paths included",
+        (true, git::SparsePaths::Exclude) => " | Sparse checkout: out-of-cone paths excluded",
+    };
+    for (group, outcome) in config.groups.iter().zip(&evaluation.outcomes) {
+        // Several groups are told apart by name
+        let group_label = match &group.name {
+            Some(name) if filters.labeled => format!("{name}: "),
+            _ => String::new(),
+        };
+        let commit_label = match &outcome.commit {
+            Some(commit) => format!(" (in commit {commit})"),
+            None => String::new(),
+        };
+        log!(
+            Info,
+            "gdf",
+            "{group_label}Comparing: {range_label} | Patterns: {}{content_label}{kind_label}{sparse_label} | Match: {}{commit_label}",
+            group.selection(),
+            outcome.has_match
+        );
+    }
+}
+
+/// The changed files of one range and what each filter group selected among them
+#[derive(Debug, Default)]
+struct Listing {
+    /// Paths of the changed files left after filtering; empty when the diff was only
+    /// streamed up to a match
+    changed: Vec<String>,
+    /// One selection per filter group, in group order
+    selections: Vec<Selection>,
+}
+
+/// What matched in one range
+#[derive(Debug, Default)]
+struct Selection {
+    has_match: bool,
+    /// Paths of the matching files; empty when the diff was only streamed up to a match
+    files: Vec<String>,
+    /// How many changed paths each pattern matched, ignoring negation; empty when streamed
+    pattern_hits: Vec<usize>,
+    /// Files an inclusion pattern matched but an exclusion dropped; empty when streamed
+    excluded: Vec<String>,
+}
+
+/// Decide, for each filter group, whether a file changed in `range` is selected and
+/// matches its patterns
+fn match_range(
+    config: &config::Config,
+    filters: &Filters,
+    range: &git::RevisionRange,
+    file_selected: &dyn Fn(&git::ChangedFile) -> bool,
+) -> Result<Listing, String> {
+    let streamable = !filters.list_all
+        && config
+            .groups
+            .iter()
+            .all(|group| group.min_fraction.is_none())
+        && filters.content_regexes.is_empty()
+        && config.line_thresholds.is_unbounded()
+        && config.content_kind.is_none()
+        && config.generated_attribute.is_none()
+        && config.diff_options.is_single_pass();
+    match filters.pattern_sets {
+        [pattern_set] if streamable => {
+            // Stream the diff, stopping git at the first selected file. Git can prune
+            // paths up front unless the kind of change or the rename pairing matters, as
+            // pathspecs turn renames across their boundary into additions and deletions.
+            let pathspecs =
+                if config.statuses.is_empty() && config.renames == git::RenamePaths::Both {
+                    pattern_set.pathspecs().unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+            let has_match =
+                git::any_changed_file(range, &config.diff_options, &pathspecs, |file| {
+                    let group = config
+                        .groups
+                        .first()
+                        .and_then(|group| group.name.as_deref());
+                    if !file_selected(file) {
+                        trace_classified(&file.path, None, "filtered");
+                        return Ok(false);
+                    }
+                    let matched = pattern_set
+                        .filter_iter(file.match_paths(config.renames))
+                        .next()
+                        .transpose()?
+                        .is_some();
+                    trace_classified(
+                        &file.path,
+                        group,
+                        if matched { "match" } else { "unmatched" },
+                    );
+                    Ok(matched)
+                })?;
+            Ok(Listing {
+                changed: Vec::new(),
+                selections: vec![Selection {
+                    has_match,
+                    ..Selection::default()
+                }],
+            })
+        }
+        // Several groups share one listing of the changed files
+        pattern_sets => list_and_match(
+            &git::Subprocess,
+            config,
+            range,
+            pattern_sets,
+            filters.content_regexes,
+            file_selected,
+        ),
+    }
+}
+
+/// Find the matching files of each filter group in the full list of changed files
+///
+/// Used when filters need more than one git command: line thresholds, binary or text
+/// files, content patterns, generated files, local changes or submodules, when a report lists every
+/// matching file, or when several groups are evaluated at once.
+fn list_and_match(
+    backend: &dyn git::GitBackend,
+    config: &config::Config,
+    range: &git::RevisionRange,
+    pattern_sets: &[matcher::PatternSet],
+    content_regexes: &[regex::Regex],
+    file_selected: &dyn Fn(&git::ChangedFile) -> bool,
+) -> Result<Listing, String> {
+    let changed_files = wanted_changes(backend, config, range, file_selected)?;
+
+    // Resolve inclusion and exclusion patterns against the changed files, remembering
+    // the file each path (new or old) belongs to
+    let (paths, owners): (Vec<&str>, Vec<usize>) = changed_files
+        .iter()
+        .enumerate()
+        .flat_map(|(index, file)| {
+            file.match_paths(config.renames)
+                .map(move |path| (path, index))
+        })
+        .unzip();
+    let group_rows = matcher::match_set_rows(pattern_sets, &paths)?;
+    let mut selections: Vec<Selection> = pattern_sets
+        .iter()
+        .zip(&group_rows)
+        .zip(&config.groups)
+        .map(|((pattern_set, rows), group)| {
+            // A group may consider fewer kinds of change than the others
+            let owned_paths = |bits: matcher::BitSet| -> Vec<String> {
+                bits.ones()
+                    .map(|index| &changed_files[owners[index]])
+                    .filter(|file| group.considers(file.kind))
+                    .map(|file| file.path.clone())
+                    .collect()
+            };
+            // Each path is kept once, and excluded only when no pattern selected it
+            let mut seen = HashSet::new();
+            let files: Vec<String> = owned_paths(pattern_set.resolve(rows))
+                .into_iter()
+                .filter(|path| seen.insert(path.clone()))
+                .collect();
+            let excluded: Vec<String> = owned_paths(pattern_set.resolve_excluded(rows))
+                .into_iter()
+                .filter(|path| seen.insert(path.clone()))
+                .collect();
+            log!(
+                Debug,
+                "matcher",
+                "Selected {} of {} changed files, {} dropped by exclusions",
+                files.len(),
+                changed_files.len(),
+                excluded.len()
+            );
+            Selection {
+                has_match: false,
+                files,
+                pattern_hits: rows.iter().map(matcher::BitSet::count).collect(),
+                excluded,
+            }
+        })
+        .collect();
+
+    // The diffs are only read when some group has files left to check
+    if !content_regexes.is_empty() && selections.iter().any(|s| !s.files.is_empty()) {
+        let diffs = backend.file_diffs(range, &config.diff_options)?;
+        let matching = content_matching_paths(content_regexes, &diffs);
+        for selection in &mut selections {
+            selection
+                .files
+                .retain(|path| matching.contains(path.as_str()));
+        }
+    }
+    compose_selections(config, &changed_files, &mut selections);
+    for (group, selection) in config.groups.iter().zip(&mut selections) {
+        selection.has_match = !selection.files.is_empty()
+            && group.min_fraction.is_none_or(|fraction| {
+                let considered = changed_files
+                    .iter()
+                    .filter(|file| group.considers(file.kind))
+                    .count();
+                reaches_fraction(selection.files.len(), considered, fraction)
+            });
+    }
+    trace_selections(config, &changed_files, &selections);
+    if config.jsonl {
+        let records = file_records(
+            config,
+            &changed_files,
+            &owners,
+            pattern_sets,
+            &group_rows,
+            &selections,
+        );
+        for record in records {
+            println!("{record}");
+        }
+    }
+    Ok(Listing {
+        changed: changed_files.into_iter().map(|file| file.path).collect(),
+        selections,
+    })
+}
+
+/// Whether `selected` of `total` changed files make at least `fraction` of them
+#[allow(clippy::cast_precision_loss)]
+fn reaches_fraction(selected: usize, total: usize, fraction: f64) -> bool {
+    selected as f64 >= fraction * total as f64
+}
+
+/// Select the files of each composed group from the files of the groups it combines
+fn compose_selections(
+    config: &config::Config,
+    changed_files: &[git::ChangedFile],
+    selections: &mut [Selection],
+) {
+    let selected: HashMap<&str, HashSet<String>> = config
+        .groups
+        .iter()
+        .zip(selections.iter())
+        .filter(|(group, _)| group.composition.is_none())
+        .filter_map(|(group, selection)| {
+            Some((
+                group.name.as_deref()?,
+                selection.files.iter().cloned().collect(),
+            ))
+        })
+        .collect();
+    for (group, selection) in config.groups.iter().zip(selections) {
+        let Some(composition) = &group.composition else {
+            continue;
+        };
+        selection.files = changed_files
+            .iter()
+            .filter(|file| group.considers(file.kind))
+            .map(|file| &file.path)
+            .filter(|path| composition.contains(path, &|name| selected.get(name)))
+            .cloned()
+            .collect();
+        dedup_paths(&mut selection.files);
+    }
+}
+
+/// Drop repeated paths, keeping the first occurrence of each
+fn dedup_paths(paths: &mut Vec<String>) {
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+}
+
+/// List the files changed in `range`, dropping those whose change size, content kind,
+/// status or sparse checkout location is not wanted, and generated files
+fn wanted_changes(
+    backend: &dyn git::GitBackend,
+    config: &config::Config,
+    range: &git::RevisionRange,
+    file_selected: &dyn Fn(&git::ChangedFile) -> bool,
+) -> Result<Vec<git::ChangedFile>, String> {
+    let mut changed_files = backend.changed_files(range, &config.diff_options)?;
+    let line_counts = if config.line_thresholds.is_unbounded() && config.content_kind.is_none() {
+        None
+    } else {
+        Some(backend.line_counts(range, &config.diff_options)?)
+    };
+    let size_and_kind_wanted = |path: &str| {
+        line_counts
+            .as_ref()
+            .is_none_or(|counts| match counts.get(path) {
+                Some(&lines) => {
+                    config.line_thresholds.allows(lines)
+                        && config
+                            .content_kind
+                            .is_none_or(|kind| kind == git::ContentKind::from_line_count(lines))
+                }
+                // Untracked files have no diff, so their content kind is unknown
+                None => config.content_kind.is_none(),
+            })
+    };
+    changed_files.retain(|file| {
+        let kept = size_and_kind_wanted(&file.path) && file_selected(file);
+        if !kept {
+            trace_classified(&file.path, None, "filtered");
+        }
+        kept
+    });
+    if let Some(attribute) = &config.generated_attribute {
+        let paths: Vec<&str> = changed_files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        let generated = git::paths_with_attribute(&config.diff_options, attribute, &paths)?;
+        changed_files.retain(|file| {
+            let kept = !generated.contains(&file.path);
+            if !kept {
+                trace_classified(&file.path, None, "filtered");
+            }
+            kept
+        });
+    }
+    Ok(changed_files)
+}
+
+/// Describe each changed file for each filter group, as printed by `--jsonl`: whether
+/// the group selected the file and which of its inclusion and exclusion patterns
+/// matched its paths
+///
+/// `owners` maps each matched path (new and old paths of renames) to its changed file,
+/// and `group_rows` holds each group's match rows over those paths.
+fn file_records(
+    config: &config::Config,
+    changed_files: &[git::ChangedFile],
+    owners: &[usize],
+    pattern_sets: &[matcher::PatternSet],
+    group_rows: &[Vec<matcher::BitSet>],
+    selections: &[Selection],
+) -> Vec<json::Value> {
+    // A file's paths sit next to each other among the matched paths
+    let mut spans = vec![0..0; changed_files.len()];
+    for (path, &owner) in owners.iter().enumerate() {
+        if spans[owner].is_empty() {
+            spans[owner] = path..path + 1;
+        } else {
+            spans[owner].end = path + 1;
+        }
+    }
+    let selected: Vec<HashSet<&str>> = selections
+        .iter()
+        .map(|selection| selection.files.iter().map(String::as_str).collect())
+        .collect();
+    let mut records = Vec::new();
+    for (file, span) in changed_files.iter().zip(&spans) {
+        let groups = config.groups.iter().zip(pattern_sets).zip(group_rows);
+        for (((group, pattern_set), rows), selected) in groups.zip(&selected) {
+            let matched_by = |negated: bool| -> Vec<&str> {
+                pattern_set
+                    .patterns()
+                    .iter()
+                    .zip(rows)
+                    .filter(|(pattern, row)| {
+                        pattern.is_negated() == negated && span.clone().any(|path| row.get(path))
+                    })
+                    .map(|(pattern, _)| pattern.glob())
+                    .collect()
+            };
+            records.push(file_record(
+                file,
+                group.name.as_deref(),
+                selected.contains(file.path.as_str()),
+                &matched_by(false),
+                &matched_by(true),
+            ));
+        }
+    }
+    records
+}
+
+/// The `--jsonl` object describing how a filter group classified a changed file
+fn file_record(
+    file: &git::ChangedFile,
+    group: Option<&str>,
+    matched: bool,
+    matched_by: &[&str],
+    excluded_by: &[&str],
+) -> json::Value {
+    let string = |s: &str| json::Value::String(s.to_string());
+    let list = |items: &[&str]| json::Value::Array(items.iter().map(|s| string(s)).collect());
+    json::Value::Object(vec![
+        ("path".to_string(), string(&file.path)),
+        (
+            "old_path".to_string(),
+            file.old_path.as_deref().map_or(json::Value::Null, string),
+        ),
+        ("status".to_string(), string(file.kind.name())),
+        ("group".to_string(), group.map_or(json::Value::Null, string)),
+        ("matched".to_string(), json::Value::Bool(matched)),
+        ("matched_by".to_string(), list(matched_by)),
+        ("excluded_by".to_string(), list(excluded_by)),
+    ])
+}
+
+/// Record with `--trace-json` how each filter group classified each changed file
+fn trace_selections(
+    config: &config::Config,
+    changed_files: &[git::ChangedFile],
+    selections: &[Selection],
+) {
+    if !log::trace_json() {
+        return;
+    }
+    for (group, selection) in config.groups.iter().zip(selections) {
+        let files: HashSet<&str> = selection.files.iter().map(String::as_str).collect();
+        let excluded: HashSet<&str> = selection.excluded.iter().map(String::as_str).collect();
+        for file in changed_files {
+            let result = if files.contains(file.path.as_str()) {
+                "match"
+            } else if excluded.contains(file.path.as_str()) {
+                "excluded"
+            } else {
+                "unmatched"
+            };
+            trace_classified(&file.path, group.name.as_deref(), result);
+        }
+    }
+}
+
+/// Record with `--trace-json` how a changed file was classified for a filter group (or
+/// for every group when it was filtered out before matching)
+fn trace_classified(path: &str, group: Option<&str>, result: &str) {
+    log::event("file_classified", || {
+        vec![
+            ("path", json::Value::String(path.to_string())),
+            (
+                "group",
+                group.map_or(json::Value::Null, |name| {
+                    json::Value::String(name.to_string())
+                }),
+            ),
+            ("result", json::Value::String(result.to_string())),
+        ]
+    });
+}
+
+/// Load the GitHub event payload when a base ref needs it
+///
+/// The payload names the default branch for `-b auto`, the pull request for
+/// `-b pr-merge` and the previous commit for `-b event`.
+fn load_event(config: &config::Config) -> Result<Option<github::Event>, String> {
+    let groups = config.groups.iter().map(|group| &group.base_refs);
+    let bases = config.bases.iter().map(|base| &base.refs);
+    // A --serve request may give any base ref
+    let needs_event = config.github_api
+        || config.serve
+        || std::iter::once(&config.base_refs)
+            .chain(groups)
+            .chain(bases)
+            .flatten()
+            .any(|r| [git::AUTO_BASE, git::PR_MERGE_BASE, git::EVENT_BASE].contains(&r.as_str()));
+    match &config.github_event_path {
+        Some(path) if needs_event => github::Event::load(path).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Paths of the diffs with a changed line matching any regex
+fn content_matching_paths<'a>(
+    regexes: &[regex::Regex],
+    diffs: &'a [hunk::FileDiff],
+) -> HashSet<&'a str> {
+    diffs
+        .iter()
+        .filter(|diff| {
+            diff.lines
+                .iter()
+                .any(|line| regexes.iter().any(|regex| regex.is_match(&line.text)))
+        })
+        .map(|diff| diff.path.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    // Helper to test the orchestration logic without running full integration
+    fn test_orchestration(files: &[String], patterns: &[String]) -> Result<bool, String> {
+        let paths: Vec<&str> = files.iter().map(String::as_str).collect();
+        matcher::PatternSet::new(patterns, matcher::Anchoring::default())?.any_match(&paths)
+    }
+
+    #[test]
+    fn test_single_inclusion_pattern() {
+        let files = vec![
+            "file.txt".to_string(),
+            "test.txt".to_string(),
+            "main.rs".to_string(),
+        ];
+        let patterns = vec!["*.txt".to_string()];
+        assert!(test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_multiple_inclusion_patterns() {
+        let files = vec![
+            "file.txt".to_string(),
+            "test.rs".to_string(),
+            "main.js".to_string(),
+        ];
+        let patterns = vec!["*.txt".to_string(), "*.rs".to_string()];
+        assert!(test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_deduplication() {
+        let files = vec!["file.txt".to_string()];
+        let patterns = vec!["*.txt".to_string(), "file.*".to_string()];
+        assert!(test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_simple_exclusion() {
+        let files = vec![
+            "src/main.rs".to_string(),
+            "src/lib.rs".to_string(),
+            "src/README.md".to_string(),
+        ];
+        let patterns = vec!["src/**".to_string(), "!*.md".to_string()];
+        assert!(test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_exclusion_removes_all() {
+        let files = vec!["file.txt".to_string(), "test.txt".to_string()];
+        let patterns = vec!["*.txt".to_string(), "!*.txt".to_string()];
+        assert!(!test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_order_independent_exclusions() {
+        let files = vec![
+            "src/main.rs".to_string(),
+            "src/test.rs".to_string(),
+            "src/README.md".to_string(),
+        ];
+
+        let patterns1 = vec!["!*.md".to_string(), "src/**".to_string()];
+        let result1 = test_orchestration(&files, &patterns1).unwrap();
+
+        let patterns2 = vec!["src/**".to_string(), "!*.md".to_string()];
+        let result2 = test_orchestration(&files, &patterns2).unwrap();
+        assert_eq!(result1, result2);
+        assert!(result1);
+    }
+
+    #[test]
+    fn test_exclusion_only_affects_matched() {
+        let files = vec!["file.txt".to_string(), "README.md".to_string()];
+        let patterns = vec!["!*.md".to_string()];
+        assert!(!test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_multiple_exclusions() {
+        let files = vec![
+            "src/main.rs".to_string(),
+            "src/test.rs".to_string(),
+            "src/README.md".to_string(),
+            "src/notes.txt".to_string(),
+        ];
+        let patterns = vec![
+            "src/**".to_string(),
+            "!*.md".to_string(),
+            "!*.txt".to_string(),
+        ];
+        assert!(test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_empty_pattern_list() {
+        let files = vec!["file.txt".to_string()];
+        let patterns = vec![];
+        assert!(!test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_empty_file_list() {
+        let files = vec![];
+        let patterns = vec!["*.txt".to_string()];
+        assert!(!test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_complex_inclusion_exclusion() {
+        let files = vec![
+            "libs/core/src/main.rs".to_string(),
+            "libs/core/test/unit.rs".to_string(),
+            "libs/utils/src/helper.rs".to_string(),
+            "apps/web/src/app.js".to_string(),
+            "apps/api/README.md".to_string(),
+        ];
+        let patterns = vec![
+            "libs/**".to_string(),
+            "apps/**".to_string(),
+            "!**/test/**".to_string(),
+            "!*.md".to_string(),
+        ];
+        assert!(test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_only_exclusions() {
+        let files = vec!["file.txt".to_string(), "test.rs".to_string()];
+        let patterns = vec!["!*.md".to_string(), "!*.js".to_string()];
+        assert!(!test_orchestration(&files, &patterns).unwrap());
+    }
+
+    #[test]
+    fn test_no_inclusions_match() {
+        let files = vec!["file.js".to_string(), "test.py".to_string()];
+        let patterns = vec!["*.txt".to_string(), "!*.js".to_string()];
+        assert!(!test_orchestration(&files, &patterns).unwrap());
+    }
+
+    fn file_diff(path: &str, old_path: Option<&str>, lines: &[&str]) -> hunk::FileDiff {
+        hunk::FileDiff {
+            path: path.to_string(),
+            old_path: old_path.map(str::to_string),
+            lines: lines
+                .iter()
+                .map(|text| hunk::ChangedLine {
+                    kind: hunk::LineKind::Added,
+                    text: (*text).to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn content_match(
+        patterns: &[&str],
+        regex: &str,
+        diffs: &[hunk::FileDiff],
+        renames: git::RenamePaths,
+    ) -> bool {
+        let backend = FakeBackend {
+            files: diffs
+                .iter()
+                .map(|diff| git::ChangedFile {
+                    path: diff.path.clone(),
+                    old_path: diff.old_path.clone(),
+                    kind: if diff.old_path.is_some() {
+                        git::ChangeKind::Renamed
+                    } else {
+                        git::ChangeKind::Modified
+                    },
+                })
+                .collect(),
+            diffs: diffs.to_vec(),
+            ..Default::default()
+        };
+        let renames = match renames {
+            git::RenamePaths::Both => "both",
+            git::RenamePaths::New => "new",
+        };
+        let args = cli::Args {
+            patterns: patterns.iter().map(|p| (*p).to_string()).collect(),
+            content_patterns: vec![regex.to_string()],
+            renames: Some(renames.to_string()),
+            ..Default::default()
+        };
+        backend_match(&backend, args).unwrap()
+    }
+
+    #[test]
+    fn test_content_match_requires_matching_line() {
+        let diffs = vec![file_diff("infra/main.tf", None, &["  region = \"eu\""])];
+        let both = git::RenamePaths::Both;
+        assert!(!content_match(&["**/*.tf"], "^provider", &diffs, both));
+
+        let diffs = vec![file_diff("infra/main.tf", None, &["provider \"aws\" {"])];
+        assert!(content_match(&["**/*.tf"], "^provider", &diffs, both));
+    }
+
+    #[test]
+    fn test_content_match_ignores_unselected_files() {
+        let diffs = vec![
+            file_diff("README.md", None, &["provider docs"]),
+            file_diff("infra/main.tf", None, &["# comment"]),
+        ];
+        assert!(!content_match(
+            &["**/*.tf"],
+            "provider",
+            &diffs,
+            git::RenamePaths::Both
+        ));
+    }
+
+    #[test]
+    fn test_content_match_respects_exclusions_and_renames() {
+        let diffs = vec![file_diff("lib/a.tf", Some("infra/a.tf"), &["provider"])];
+        assert!(content_match(
+            &["infra/**"],
+            "provider",
+            &diffs,
+            git::RenamePaths::Both
+        ));
+        assert!(!content_match(
+            &["infra/**"],
+            "provider",
+            &diffs,
+            git::RenamePaths::New
+        ));
+        assert!(!content_match(
+            &["**/*.tf", "!lib/**"],
+            "provider",
+            &diffs,
+            git::RenamePaths::New
+        ));
+    }
+
+    /// Backend serving fixed changes, so the filtering runs without git
+    #[derive(Default)]
+    struct FakeBackend {
+        files: Vec<git::ChangedFile>,
+        line_counts: HashMap<String, Option<usize>>,
+        diffs: Vec<hunk::FileDiff>,
+    }
+
+    impl git::GitBackend for FakeBackend {
+        fn changed_files(
+            &self,
+            _: &git::RevisionRange,
+            _: &git::DiffOptions,
+        ) -> Result<Vec<git::ChangedFile>, String> {
+            Ok(self.files.clone())
+        }
+
+        fn line_counts(
+            &self,
+            _: &git::RevisionRange,
+            _: &git::DiffOptions,
+        ) -> Result<HashMap<String, Option<usize>>, String> {
+            Ok(self.line_counts.clone())
+        }
+
+        fn file_diffs(
+            &self,
+            _: &git::RevisionRange,
+            _: &git::DiffOptions,
+        ) -> Result<Vec<hunk::FileDiff>, String> {
+            Ok(self.diffs.clone())
+        }
+    }
+
+    #[test]
+    fn test_named_values() {
+        let outcome = |has_match: bool, commit: Option<&str>, files: &[&str]| Outcome {
+            has_match,
+            commit: commit.map(str::to_string),
+            files: files.iter().map(ToString::to_string).collect(),
+            ..Outcome::default()
+        };
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            named_values("api", &outcome(false, None, &[]), &[], Fields::default()),
+            pairs(&[("api", "false")])
+        );
+
+        let repo_outcomes = [
+            ("core", outcome(true, Some("abc123"), &["a.rs", "b.rs"])),
+            ("web", outcome(false, None, &[])),
+        ];
+        let overall = outcome(true, None, &[]);
+        let commits = Fields {
+            commit: true,
+            ..Fields::default()
+        };
+        assert_eq!(
+            named_values("api", &overall, &repo_outcomes, commits),
+            pairs(&[
+                ("api_core_commit", "abc123"),
+                ("api_core", "true"),
+                ("api_web", "false"),
+                ("api", "true"),
+            ])
+        );
+        let files = Fields {
+            count: true,
+            files: Some(output::ListDelimiter::Comma),
+            ..Fields::default()
+        };
+        assert_eq!(
+            named_values("api", &overall, &repo_outcomes[..1], files),
+            pairs(&[
+                ("api_core_count", "2"),
+                ("api_core_files", "a.rs,b.rs"),
+                ("api_core", "true"),
+                ("api_count", "0"),
+                ("api_files", ""),
+                ("api", "true"),
+            ])
+        );
+        let json_files = Fields {
+            files: Some(output::ListDelimiter::Json),
+            ..Fields::default()
+        };
+        assert_eq!(
+            named_values("api", &overall, &[], json_files),
+            pairs(&[("api_files", "[]"), ("api", "true")])
+        );
+    }
+
+    #[test]
+    fn test_matrix_include() {
+        let outcome = |has_match: bool| Outcome {
+            has_match,
+            ..Outcome::default()
+        };
+        assert_eq!(
+            matrix_include("api", &outcome(true), &[], &[]).to_string(),
+            r#"[{"filter":"api"}]"#
+        );
+        assert_eq!(
+            matrix_include("api", &outcome(false), &[], &[]).to_string(),
+            "[]"
+        );
+
+        let repo_outcomes = [
+            ("core", outcome(true)),
+            ("web", outcome(false)),
+            ("cli", outcome(true)),
+        ];
+        assert_eq!(
+            matrix_include("api", &outcome(true), &repo_outcomes, &[]).to_string(),
+            r#"[{"filter":"api","repository":"core"},{"filter":"api","repository":"cli"}]"#
+        );
+
+        let bases = [config::Base {
+            name: "main".to_string(),
+            label: "vs_main".to_string(),
+            refs: vec!["origin/main".to_string()],
+        }];
+        let base_outcomes = [("vs_main", outcome(true))];
+        assert_eq!(
+            matrix_include("api", &outcome(true), &base_outcomes, &bases).to_string(),
+            r#"[{"filter":"api","base":"main"}]"#
+        );
+    }
+
+    #[test]
+    fn test_matching_files() {
+        let config = config::from_args(cli::Args {
+            patterns: vec!["src/**".to_string(), "docs/**".to_string()],
+            base_refs: vec!["main".to_string()],
+            github_output: vec![("src".to_string(), 0), ("docs".to_string(), 1)],
+            ..Default::default()
+        })
+        .unwrap();
+        let outcome = |files: &[&str]| Outcome {
+            has_match: !files.is_empty(),
+            files: files.iter().map(ToString::to_string).collect(),
+            ..Outcome::default()
+        };
+        let results = vec![
+            GroupResult {
+                group: &config.groups[0],
+                outcome: outcome(&["src/a.rs", "src/b.rs"]),
+                repo_outcomes: Vec::new(),
+            },
+            GroupResult {
+                group: &config.groups[1],
+                outcome: outcome(&["docs/a.md", "src/a.rs"]),
+                repo_outcomes: Vec::new(),
+            },
+        ];
+        assert_eq!(
+            matching_files(&config, &results),
+            vec!["src/a.rs", "src/b.rs", "docs/a.md"]
+        );
+
+        let repos = vec![
+            config::Repository {
+                name: "core".to_string(),
+                git_dir: PathBuf::from("libs/core/.git"),
+                work_tree: PathBuf::from("libs/core"),
+            },
+            config::Repository {
+                name: "web".to_string(),
+                git_dir: PathBuf::from("web/.git"),
+                work_tree: PathBuf::from("web"),
+            },
+        ];
+        let results = vec![GroupResult {
+            group: &config.groups[0],
+            outcome: outcome(&[]),
+            repo_outcomes: vec![("core", outcome(&["src/a.rs"])), ("web", outcome(&[]))],
+        }];
+        let repo_config = config::Config {
+            repos,
+            ..config.clone()
+        };
+        assert_eq!(
+            matching_files(&repo_config, &results),
+            vec!["libs/core/src/a.rs"]
+        );
+    }
+
+    #[test]
+    fn test_files_outside() {
+        let config = config::from_args(cli::Args {
+            patterns: vec!["hotfix/**".to_string(), "CHANGELOG.md".to_string()],
+            base_refs: vec!["release/1.x".to_string()],
+            github_output: vec![("hotfix".to_string(), 0), ("changelog".to_string(), 1)],
+            enforce: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let outcome = |files: &[&str]| Outcome {
+            has_match: !files.is_empty(),
+            files: files.iter().map(ToString::to_string).collect(),
+            ..Outcome::default()
+        };
+        let evaluation = |changed: &[&str]| Evaluation {
+            changed: changed.iter().map(ToString::to_string).collect(),
+            ..Evaluation::default()
+        };
+        let results = vec![
+            GroupResult {
+                group: &config.groups[0],
+                outcome: outcome(&["hotfix/fix.rs"]),
+                repo_outcomes: Vec::new(),
+            },
+            GroupResult {
+                group: &config.groups[1],
+                outcome: outcome(&["CHANGELOG.md"]),
+                repo_outcomes: Vec::new(),
+            },
+        ];
+        let evaluations = vec![(
+            None,
+            evaluation(&["CHANGELOG.md", "hotfix/fix.rs", "src/lib.rs", "Cargo.toml"]),
+        )];
+        assert_eq!(
+            files_outside(&config, &evaluations, &results),
+            ["src/lib.rs", "Cargo.toml"]
+        );
+        let evaluations = vec![(None, evaluation(&["CHANGELOG.md", "hotfix/fix.rs"]))];
+        assert!(files_outside(&config, &evaluations, &results).is_empty());
+
+        // Each checkout's changes are checked against its own outcomes
+        let repo_config = config::Config {
+            repos: vec![
+                config::Repository {
+                    name: "core".to_string(),
+                    git_dir: PathBuf::from("libs/core/.git"),
+                    work_tree: PathBuf::from("libs/core"),
+                },
+                config::Repository {
+                    name: "web".to_string(),
+                    git_dir: PathBuf::from("web/.git"),
+                    work_tree: PathBuf::from("web"),
+                },
+            ],
+            ..config.clone()
+        };
+        let results = vec![GroupResult {
+            group: &config.groups[0],
+            outcome: outcome(&[]),
+            repo_outcomes: vec![("core", outcome(&["hotfix/fix.rs"])), ("web", outcome(&[]))],
+        }];
+        let evaluations = vec![
+            (Some("core"), evaluation(&["hotfix/fix.rs"])),
+            (Some("web"), evaluation(&["hotfix/fix.rs"])),
+        ];
+        assert_eq!(
+            files_outside(&repo_config, &evaluations, &results),
+            ["web/hotfix/fix.rs"]
+        );
+    }
+
+    fn changed(path: &str, kind: git::ChangeKind) -> git::ChangedFile {
+        git::ChangedFile {
+            path: path.to_string(),
+            old_path: None,
+            kind,
+        }
+    }
+
+    /// Run the full-listing match against `backend` with the given flags, returning each
+    /// group's selection
+    fn backend_selections(
+        backend: &FakeBackend,
+        args: cli::Args,
+    ) -> Result<Vec<Selection>, String> {
+        let config = config::from_args(cli::Args {
+            base_refs: vec!["main".to_string()],
+            ..args
+        })?;
+        let pattern_sets = config
+            .groups
+            .iter()
+            .map(|group| {
+                matcher::PatternSet::new(&group.patterns, config.anchoring)
+                    .map(|pattern_set| pattern_set.ignoring_case(group.ignore_case))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let content_regexes = config
+            .content_patterns
+            .iter()
+            .map(|pattern| regex::Regex::new(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+        let range = git::RevisionRange::parse("main")?;
+        list_and_match(
+            backend,
+            &config,
+            &range,
+            &pattern_sets,
+            &content_regexes,
+            &|_| true,
+        )
+        .map(|listing| listing.selections)
+    }
+
+    /// Run the full-listing match of a single group against `backend`
+    fn backend_match(backend: &FakeBackend, args: cli::Args) -> Result<bool, String> {
+        backend_selections(backend, args).map(|selections| selections[0].has_match)
+    }
+
+    #[test]
+    fn test_docs_only_preset() {
+        let docs_only = |files: &[(&str, git::ChangeKind)], args: cli::Args| {
+            let backend = FakeBackend {
+                files: files
+                    .iter()
+                    .map(|&(path, kind)| changed(path, kind))
+                    .collect(),
+                ..FakeBackend::default()
+            };
+            backend_selections(&backend, args).map(|selections| {
+                let selection = &selections[selections.len() - 1];
+                (selection.has_match, selection.files.clone())
+            })
+        };
+        let preset = cli::Args {
+            presets: vec!["docs-only".to_string()],
+            ..Default::default()
+        };
+        let modified = git::ChangeKind::Modified;
+        assert_eq!(
+            docs_only(
+                &[("README.md", modified), ("docs/img/flow.png", modified)],
+                preset.clone()
+            ),
+            Ok((
+                true,
+                vec!["README.md".to_string(), "docs/img/flow.png".to_string()]
+            ))
+        );
+        assert_eq!(
+            docs_only(
+                &[("README.md", modified), ("src/main.rs", modified)],
+                preset.clone()
+            ),
+            Ok((false, vec!["README.md".to_string()]))
+        );
+        assert_eq!(docs_only(&[], preset.clone()), Ok((false, Vec::new())));
+        // Only the kinds of change the filter considers have to be documentation
+        assert_eq!(
+            docs_only(
+                &[
+                    ("README.md", modified),
+                    ("src/old.rs", git::ChangeKind::Deleted)
+                ],
+                cli::Args {
+                    status: Some("modified".to_string()),
+                    ..preset.clone()
+                }
+            ),
+            Ok((true, vec!["README.md".to_string()]))
+        );
+        // Other filters are unaffected
+        let selections = backend_selections(
+            &FakeBackend {
+                files: vec![
+                    changed("README.md", modified),
+                    changed("src/main.rs", modified),
+                ],
+                ..FakeBackend::default()
+            },
+            cli::Args {
+                patterns: vec!["src/**".to_string()],
+                github_output: vec![("code".to_string(), 0)],
+                ..preset
+            },
+        )
+        .unwrap();
+        assert!(selections[0].has_match);
+        assert!(!selections[1].has_match);
+    }
+
+    #[test]
+    fn test_min_fraction() {
+        let modified = git::ChangeKind::Modified;
+        let backend = FakeBackend {
+            files: vec![
+                changed("web/app.ts", modified),
+                changed("web/style.css", modified),
+                changed("api/main.go", modified),
+                changed("README.md", git::ChangeKind::Added),
+            ],
+            ..FakeBackend::default()
+        };
+        let args = |fraction: &str| cli::Args {
+            patterns: vec!["web/**".to_string()],
+            min_fraction: Some(fraction.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(backend_match(&backend, args("0.5")), Ok(true));
+        assert_eq!(backend_match(&backend, args("0.51")), Ok(false));
+        // Only the kinds of change the filter considers count
+        assert_eq!(
+            backend_match(
+                &backend,
+                cli::Args {
+                    status: Some("modified".to_string()),
+                    ..args("0.6")
+                }
+            ),
+            Ok(true)
+        );
+        // Each filter has its own fraction
+        let selections = backend_selections(
+            &backend,
+            cli::Args {
+                patterns: vec!["web/**".to_string(), "api/**".to_string()],
+                github_output: vec![("frontend".to_string(), 0), ("backend".to_string(), 1)],
+                group_min_fraction: vec![(0, "0.5".to_string())],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(selections[0].has_match);
+        assert!(selections[1].has_match);
+        assert!(!reaches_fraction(1, 3, 0.5));
+        assert!(reaches_fraction(1, 3, 1.0 / 3.0));
+    }
+
+    #[test]
+    fn test_list_and_match_with_backend() {
+        let backend = FakeBackend {
+            files: vec![
+                changed("src/main.rs", git::ChangeKind::Modified),
+                changed("assets/logo.png", git::ChangeKind::Added),
+            ],
+            line_counts: HashMap::from([
+                ("src/main.rs".to_string(), Some(3)),
+                ("assets/logo.png".to_string(), None),
+            ]),
+            diffs: vec![hunk::FileDiff {
+                path: "src/main.rs".to_string(),
+                old_path: None,
+                lines: vec![hunk::ChangedLine {
+                    kind: hunk::LineKind::Added,
+                    text: "// TODO".to_string(),
+                }],
+            }],
+        };
+        let args = |patterns: &[&str]| cli::Args {
+            patterns: patterns.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(backend_match(&backend, args(&["src/**"])), Ok(true));
+        assert_eq!(backend_match(&backend, args(&["docs/**"])), Ok(false));
+        assert_eq!(
+            backend_match(
+                &backend,
+                cli::Args {
+                    min_lines_changed: Some("5".to_string()),
+                    ..args(&["src/**"])
+                }
+            ),
+            Ok(false)
+        );
+        assert_eq!(
+            backend_match(
+                &backend,
+                cli::Args {
+                    binary_only: true,
+                    ..args(&["**"])
+                }
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            backend_match(
+                &backend,
+                cli::Args {
+                    binary_only: true,
+                    ..args(&["src/**"])
+                }
+            ),
+            Ok(false)
+        );
+        assert_eq!(
+            backend_match(
+                &backend,
+                cli::Args {
+                    content_patterns: vec!["TODO".to_string()],
+                    ..args(&["src/**"])
+                }
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            backend_match(
+                &backend,
+                cli::Args {
+                    content_patterns: vec!["FIXME".to_string()],
+                    ..args(&["**"])
+                }
+            ),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_list_and_match_selection() {
+        let backend = FakeBackend {
+            files: vec![
+                changed("src/main.rs", git::ChangeKind::Modified),
+                changed("src/README.md", git::ChangeKind::Modified),
+                git::ChangedFile {
+                    path: "lib/util.rs".to_string(),
+                    old_path: Some("src/util.rs".to_string()),
+                    kind: git::ChangeKind::Renamed,
+                },
+            ],
+            ..Default::default()
+        };
+        let selections = backend_selections(
+            &backend,
+            cli::Args {
+                patterns: vec!["src/**".to_string(), "!**/*.md".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let selection = &selections[0];
+        assert!(selection.has_match);
+        assert_eq!(selection.files, vec!["src/main.rs", "lib/util.rs"]);
+        // Hits count paths, including the old path of the renamed file
+        assert_eq!(selection.pattern_hits, vec![3, 1]);
+    }
+
+    #[test]
+    fn test_file_records() {
+        let changed_files = vec![
+            changed("src/main.rs", git::ChangeKind::Modified),
+            changed("src/README.md", git::ChangeKind::Added),
+            git::ChangedFile {
+                path: "lib/util.rs".to_string(),
+                old_path: Some("src/util.rs".to_string()),
+                kind: git::ChangeKind::Renamed,
+            },
+        ];
+        let config = config::from_args(cli::Args {
+            patterns: vec!["src/**".to_string(), "!**/*.md".to_string()],
+            base_refs: vec!["main".to_string()],
+            jsonl: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let pattern_sets =
+            [matcher::PatternSet::new(&config.groups[0].patterns, config.anchoring).unwrap()];
+        let paths = ["src/main.rs", "src/README.md", "lib/util.rs", "src/util.rs"];
+        let owners = [0, 1, 2, 2];
+        let group_rows = [pattern_sets[0].match_rows(&paths).unwrap()];
+        let selections = [Selection {
+            has_match: true,
+            files: vec!["src/main.rs".to_string(), "lib/util.rs".to_string()],
+            ..Selection::default()
+        }];
+        let records: Vec<String> = file_records(
+            &config,
+            &changed_files,
+            &owners,
+            &pattern_sets,
+            &group_rows,
+            &selections,
+        )
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        assert_eq!(
+            records,
+            [
+                r#"{"path":"src/main.rs","old_path":null,"status":"modified","group":null,"matched":true,"matched_by":["src/**"],"excluded_by":[]}"#,
+                r#"{"path":"src/README.md","old_path":null,"status":"added","group":null,"matched":false,"matched_by":["src/**"],"excluded_by":["**/*.md"]}"#,
+                r#"{"path":"lib/util.rs","old_path":"src/util.rs","status":"renamed","group":null,"matched":true,"matched_by":["src/**"],"excluded_by":[]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_and_match_group_options() {
+        let backend = FakeBackend {
+            files: vec![
+                changed("Docs/Guide.md", git::ChangeKind::Added),
+                changed("docs/old.md", git::ChangeKind::Deleted),
+                changed("src/main.rs", git::ChangeKind::Modified),
+            ],
+            ..Default::default()
+        };
+        let args = cli::Args {
+            patterns: ["docs/**", "**/*.md"].map(str::to_string).to_vec(),
+            github_output: vec![("docs".to_string(), 0), ("removed".to_string(), 1)],
+            group_ignore_case: vec![0],
+            group_status: vec![(1, "deleted".to_string())],
+            ..Default::default()
+        };
+        let selections = backend_selections(&backend, args).unwrap();
+        let files: Vec<&[String]> = selections.iter().map(|s| s.files.as_slice()).collect();
+        assert_eq!(
+            files,
+            [
+                &["Docs/Guide.md".to_string(), "docs/old.md".to_string()][..],
+                &["docs/old.md".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_and_match_composed_groups() {
+        let backend = FakeBackend {
+            files: vec![
+                changed("api/main.rs", git::ChangeKind::Modified),
+                changed("api/tests/main.rs", git::ChangeKind::Added),
+                changed("web/tests/app.ts", git::ChangeKind::Added),
+            ],
+            ..Default::default()
+        };
+        let args = cli::Args {
+            patterns: ["api/**", "**/tests/**"].map(str::to_string).to_vec(),
+            github_output: vec![
+                ("backend".to_string(), 0),
+                ("tests".to_string(), 1),
+                ("code".to_string(), 2),
+                ("new".to_string(), 2),
+            ],
+            group_compose: vec![
+                (2, "backend - tests".to_string()),
+                (3, "backend | tests".to_string()),
+            ],
+            group_status: vec![(3, "added".to_string())],
+            ..Default::default()
+        };
+        let selections = backend_selections(&backend, args).unwrap();
+        assert_eq!(selections[2].files, ["api/main.rs"]);
+        assert!(selections[2].has_match);
+        assert_eq!(
+            selections[3].files,
+            ["api/tests/main.rs", "web/tests/app.ts"]
+        );
+    }
+
+    #[test]
+    fn test_list_and_match_several_groups() {
+        let backend = FakeBackend {
+            files: vec![
+                changed("src/main.rs", git::ChangeKind::Modified),
+                changed("docs/guide.md", git::ChangeKind::Added),
+                changed("docs/logo.png", git::ChangeKind::Added),
+            ],
+            diffs: vec![file_diff("src/main.rs", None, &["fn main() {}"])],
+            ..Default::default()
+        };
+        // The shared exclusion applies to every group
+        let args = cli::Args {
+            patterns: ["!**/*.png", "docs/**", "src/**", "web/**"]
+                .map(str::to_string)
+                .to_vec(),
+            github_output: vec![
+                ("docs".to_string(), 1),
+                ("code".to_string(), 2),
+                ("web".to_string(), 3),
+            ],
+            ..Default::default()
+        };
+        let selections = backend_selections(&backend, args.clone()).unwrap();
+        let files: Vec<&[String]> = selections.iter().map(|s| s.files.as_slice()).collect();
+        assert_eq!(
+            files,
+            [
+                &["docs/guide.md".to_string()][..],
+                &["src/main.rs".to_string()],
+                &[]
+            ]
+        );
+        assert_eq!(
+            selections.iter().map(|s| s.has_match).collect::<Vec<_>>(),
+            [true, true, false]
+        );
+
+        // Content patterns are checked against each group's files
+        let selections = backend_selections(
+            &backend,
+            cli::Args {
+                content_patterns: vec!["fn main".to_string()],
+                ..args
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            selections.iter().map(|s| s.has_match).collect::<Vec<_>>(),
+            [false, true, false]
+        );
+    }
+
+    #[test]
+    fn test_diff_filter_evaluate() {
+        let (repo, git) = git::temp_repo("library");
+        std::fs::create_dir_all(repo.join("api")).unwrap();
+        std::fs::create_dir_all(repo.join("docs")).unwrap();
+        std::fs::write(repo.join("api/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(repo.join("docs/guide.md"), "# Guide\n").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        std::fs::write(repo.join("api/lib.rs"), "pub fn f() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "api"]);
+
+        let config = DiffFilter::config(Args {
+            patterns: vec!["api/**".to_string(), "docs/**".to_string()],
+            github_output: vec![("api".to_string(), 0), ("docs".to_string(), 1)],
+            base_refs: vec!["HEAD~1".to_string()],
+            git_dir: Some(repo.join(".git").display().to_string()),
+            ..Args::default()
+        })
+        .unwrap();
+        let result = DiffFilter::evaluate(&config).unwrap();
+        assert!(result.has_match);
+        assert_eq!(
+            result.filters,
+            [
+                FilterResult {
+                    name: Some("api".to_string()),
+                    has_match: true,
+                    files: vec!["api/lib.rs".to_string()],
+                    ..FilterResult::default()
+                },
+                FilterResult {
+                    name: Some("docs".to_string()),
+                    ..FilterResult::default()
+                },
+            ]
+        );
+
+        // With --require, every required filter has to match
+        let required = |names: &[&str]| {
+            let config = config::Config {
+                required: names.iter().map(ToString::to_string).collect(),
+                ..config.clone()
+            };
+            DiffFilter::evaluate(&config).unwrap().has_match
+        };
+        assert!(required(&["api"]));
+        assert!(!required(&["api", "docs"]));
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_evaluate_cached() {
+        let (repo, git) = git::temp_repo("cached");
+        std::fs::create_dir_all(repo.join("api")).unwrap();
+        std::fs::write(repo.join("README.md"), "# Demo\n").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        std::fs::write(repo.join("api/main.rs"), "fn main() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "api"]);
+
+        let args = Args {
+            patterns: vec!["api/**".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            base_refs: vec!["HEAD~1".to_string()],
+            git_dir: Some(repo.join(".git").display().to_string()),
+            ..Args::default()
+        };
+        let files = |args: &Args| {
+            let config = DiffFilter::config(args.clone()).unwrap();
+            DiffFilter::evaluate(&config).unwrap().filters[0]
+                .files
+                .clone()
+        };
+        assert_eq!(files(&args), ["api/main.rs"]);
+        let cache = repo.join(".git/gdf-cache");
+        let entries: Vec<_> = std::fs::read_dir(&cache)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(entries.len(), 1);
+
+        // The second run reads the stored result instead of diffing
+        let entry = std::fs::read_to_string(&entries[0]).unwrap();
+        std::fs::write(&entries[0], entry.replace("api/main.rs", "api/cached.rs")).unwrap();
+        assert_eq!(files(&args), ["api/cached.rs"]);
+        let no_cache = Args {
+            no_cache: true,
+            ..args.clone()
+        };
+        assert_eq!(files(&no_cache), ["api/main.rs"]);
+
+        // Other filters have another entry
+        let other = Args {
+            patterns: vec!["api/**".to_string(), "!**/main.rs".to_string()],
+            ..args.clone()
+        };
+        assert!(files(&other).is_empty());
+        assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 2);
+
+        // Local changes are never cached
+        let local = Args {
+            cache_dir: Some(repo.join("local-cache").display().to_string()),
+            source: Some("combined".to_string()),
+            ..args
+        };
+        assert_eq!(files(&local), ["api/main.rs"]);
+        assert!(!repo.join("local-cache").exists());
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_evaluate_each_base() {
+        let (repo, git) = git::temp_repo("each_base");
+        std::fs::create_dir_all(repo.join("api")).unwrap();
+        std::fs::write(repo.join("README.md"), "# Demo\n").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        git(&["branch", "release"]);
+        std::fs::write(repo.join("api/main.rs"), "fn main() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "api"]);
+        git(&["branch", "stable"]);
+        std::fs::write(repo.join("api/lib.rs"), "pub fn api() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "lib"]);
+
+        let config = DiffFilter::config(Args {
+            patterns: vec!["api/main.rs".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            bases: vec!["release=missing,release".to_string(), "stable".to_string()],
+            git_dir: Some(repo.join(".git").display().to_string()),
+            no_cache: true,
+            ..Args::default()
+        })
+        .unwrap();
+        let result = DiffFilter::evaluate(&config).unwrap();
+        let filter = &result.filters[0];
+        assert!(filter.has_match);
+        assert_eq!(filter.files, ["api/main.rs"]);
+        let bases: Vec<(Option<&str>, bool)> = filter
+            .repositories
+            .iter()
+            .map(|base| (base.name.as_deref(), base.has_match))
+            .collect();
+        // The first ref of a base that resolves is compared against, as with -b
+        assert_eq!(
+            bases,
+            [(Some("vs_release"), true), (Some("vs_stable"), false)]
+        );
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_evaluate_exclude_generated() {
+        let (repo, git) = git::temp_repo("generated");
+        std::fs::create_dir_all(repo.join("api")).unwrap();
+        std::fs::write(
+            repo.join(".gitattributes"),
+            "*.pb.go linguist-generated\npackage-lock.json generated\n",
+        )
+        .unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        std::fs::write(repo.join("api/service.pb.go"), "package api\n").unwrap();
+        std::fs::write(repo.join("package-lock.json"), "{}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "regenerate"]);
+
+        let args = Args {
+            patterns: vec!["**".to_string()],
+            base_refs: vec!["HEAD~1".to_string()],
+            git_dir: Some(repo.join(".git").display().to_string()),
+            no_cache: true,
+            ..Args::default()
+        };
+        let files = |args: Args| {
+            let config = DiffFilter::config(args).unwrap();
+            DiffFilter::evaluate(&config).unwrap().filters[0]
+                .files
+                .clone()
+        };
+        assert_eq!(
+            files(args.clone()),
+            ["api/service.pb.go", "package-lock.json"]
+        );
+        let exclude = Args {
+            exclude_generated: true,
+            ..args
+        };
+        assert_eq!(files(exclude.clone()), ["package-lock.json"]);
+        let custom = Args {
+            generated_attribute: Some("generated".to_string()),
+            ..exclude
+        };
+        assert_eq!(files(custom), ["api/service.pb.go"]);
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_write_expressions() {
+        let dir = std::env::temp_dir().join(format!("gdf_test_expr_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = DiffFilter::config(Args {
+            patterns: vec![
+                "api/**".to_string(),
+                "lib/**".to_string(),
+                "**/*.md".to_string(),
+            ],
+            github_output: vec![
+                ("backend".to_string(), 0),
+                ("shared".to_string(), 1),
+                ("docs_only".to_string(), 2),
+            ],
+            base_refs: vec!["main".to_string()],
+            expressions: vec![
+                "deploy=(backend || shared) && !docs_only".to_string(),
+                "docs=docs_only".to_string(),
+            ],
+            ci: Some("none".to_string()),
+            ..Args::default()
+        })
+        .unwrap();
+        let config = config::Config {
+            github_output_filepath: Some(dir.join("output").display().to_string()),
+            dotenv: Some(dir.join("env").display().to_string()),
+            ..config
+        };
+        let results: Vec<GroupResult> = config
+            .groups
+            .iter()
+            .zip([true, false, false])
+            .map(|(group, has_match)| GroupResult {
+                group,
+                outcome: Outcome {
+                    has_match,
+                    ..Outcome::default()
+                },
+                repo_outcomes: Vec::new(),
+            })
+            .collect();
+        write_expressions(&config, &results).unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("output"), "deploy=true\ndocs=false\n");
+        assert_eq!(read("env"), "deploy=true\ndocs=false\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_write_jobs() {
+        let dir = std::env::temp_dir().join(format!("gdf_test_jobs_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = DiffFilter::config(Args {
+            patterns: vec!["api/**".to_string(), "web/**".to_string()],
+            github_output: vec![("backend".to_string(), 0), ("frontend".to_string(), 1)],
+            base_refs: vec!["main".to_string()],
+            jobs: vec![
+                "lint=backend".to_string(),
+                "e2e=frontend".to_string(),
+                "deploy=backend && !frontend".to_string(),
+                "e2e=backend".to_string(),
+            ],
+            list_delimiter: Some("json".to_string()),
+            list_files: true,
+            ci: Some("none".to_string()),
+            ..Args::default()
+        })
+        .unwrap();
+        let config = config::Config {
+            github_output_filepath: Some(dir.join("output").display().to_string()),
+            env_file: Some(dir.join("env").display().to_string()),
+            ..config
+        };
+        let results = |matched: [bool; 2]| -> Vec<GroupResult> {
+            config
+                .groups
+                .iter()
+                .zip(matched)
+                .map(|(group, has_match)| GroupResult {
+                    group,
+                    outcome: Outcome {
+                        has_match,
+                        ..Outcome::default()
+                    },
+                    repo_outcomes: Vec::new(),
+                })
+                .collect()
+        };
+        write_jobs(&config, &results([true, false])).unwrap();
+        write_jobs(&config, &results([false, false])).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("output")).unwrap(),
+            "jobs=[\"lint\",\"e2e\",\"deploy\"]\njobs=[]\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("env")).unwrap(),
+            "export jobs='[\"lint\",\"e2e\",\"deploy\"]'\nexport jobs='[]'\n"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
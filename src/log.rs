@@ -0,0 +1,259 @@
+//! Leveled diagnostics on stderr, filtered per target with `RUST_LOG`.
+//!
+//! `RUST_LOG` holds comma-separated directives in the style of `env_logger`: a level
+//! sets the default (`warn`), `<target>=<level>` overrides it for one target
+//! (`git=debug`), and a bare target enables everything it logs. Targets are `gdf` (the
+//! run itself), `git`, `matcher` and `output`. Without `RUST_LOG`, `info` and above are
+//! shown.
+//!
+//! With `--trace-json`, stderr carries JSON lines instead: one object per event (git
+//! command issued, pattern compiled, file classified, output written), and the log
+//! messages themselves as `log` events.
+
+use crate::json::Value;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Severity of a message, from most to least severe
+///
+/// `Off` only appears in filters, where it shows nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(s: &str) -> Option<Level> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// The most verbose level shown by default and for each target
+#[derive(Debug, PartialEq, Eq)]
+struct Filter {
+    default: Level,
+    targets: Vec<(String, Level)>,
+}
+
+impl Filter {
+    /// Parse `RUST_LOG` directives; ones naming an unknown level are ignored
+    fn parse(spec: &str) -> Filter {
+        let mut filter = Filter {
+            default: Level::Info,
+            targets: Vec::new(),
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Some(level) = Level::parse(level.trim()) {
+                        filter.targets.push((target.trim().to_string(), level));
+                    }
+                }
+                None => match Level::parse(directive) {
+                    Some(level) => filter.default = level,
+                    None => filter.targets.push((directive.to_string(), Level::Trace)),
+                },
+            }
+        }
+        filter
+    }
+
+    fn enabled(&self, level: Level, target: &str) -> bool {
+        // The last directive for a target wins
+        let max = self
+            .targets
+            .iter()
+            .rev()
+            .find(|(name, _)| name == target)
+            .map_or(self.default, |&(_, max)| max);
+        level != Level::Off && level <= max
+    }
+}
+
+fn filter() -> &'static Filter {
+    static FILTER: OnceLock<Filter> = OnceLock::new();
+    FILTER.get_or_init(|| Filter::parse(&env::var("RUST_LOG").unwrap_or_default()))
+}
+
+/// Whether `RUST_LOG` shows messages of `level` from `target`
+pub fn enabled(level: Level, target: &str) -> bool {
+    filter().enabled(level, target)
+}
+
+/// Print a message to stderr; `info` messages are printed as they are, others are
+/// prefixed with their level and target
+pub fn write(level: Level, target: &str, message: &str) {
+    if trace_json() {
+        event("log", || {
+            vec![
+                ("level", Value::String(level.label().to_ascii_lowercase())),
+                ("target", Value::String(target.to_string())),
+                ("message", Value::String(message.to_string())),
+            ]
+        });
+        return;
+    }
+    eprintln!("{}", format_message(level, target, message));
+}
+
+/// Print the error that ends the run, as an `error` event with `--trace-json`
+pub fn fatal(message: &str) {
+    if trace_json() {
+        event("error", || {
+            vec![("message", Value::String(message.to_string()))]
+        });
+    } else {
+        eprintln!("Error: {message}");
+    }
+}
+
+static TRACE_JSON: AtomicBool = AtomicBool::new(false);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Switch stderr to JSON lines, timing events from now
+pub fn enable_trace_json() {
+    STARTED.get_or_init(Instant::now);
+    TRACE_JSON.store(true, Ordering::Relaxed);
+}
+
+/// Whether events are emitted, for callers with costly work to do before emitting them
+pub fn trace_json() -> bool {
+    TRACE_JSON.load(Ordering::Relaxed)
+}
+
+/// Emit a `name` event with the given fields as a JSON line on stderr, with
+/// `--trace-json`; the fields are only built when the event is emitted
+pub fn event(name: &str, fields: impl FnOnce() -> Vec<(&'static str, Value)>) {
+    if !trace_json() {
+        return;
+    }
+    let elapsed = STARTED
+        .get()
+        .map_or(0, |started| started.elapsed().as_millis());
+    eprintln!("{}", event_json(name, elapsed, fields()));
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn event_json(name: &str, elapsed_ms: u128, fields: Vec<(&'static str, Value)>) -> Value {
+    let mut members = vec![
+        ("event".to_string(), Value::String(name.to_string())),
+        ("elapsed_ms".to_string(), Value::Number(elapsed_ms as f64)),
+    ];
+    members.extend(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value)),
+    );
+    Value::Object(members)
+}
+
+fn format_message(level: Level, target: &str, message: &str) -> String {
+    match level {
+        Level::Info => message.to_string(),
+        _ => format!("[{} {target}] {message}", level.label()),
+    }
+}
+
+/// Log a formatted message at a level (`Warn`, `Info`, `Debug`, ...) for a target
+macro_rules! log {
+    ($level:ident, $target:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Level::$level, $target) {
+            $crate::log::write($crate::log::Level::$level, $target, &format!($($arg)+));
+        }
+    };
+}
+
+pub(crate) use log;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_defaults_to_info() {
+        let filter = Filter::parse("");
+        assert!(filter.enabled(Level::Info, "git"));
+        assert!(filter.enabled(Level::Warn, "gdf"));
+        assert!(!filter.enabled(Level::Debug, "git"));
+    }
+
+    #[test]
+    fn test_filter_per_target() {
+        let filter = Filter::parse("warn, git=debug,output=off");
+        assert!(!filter.enabled(Level::Info, "gdf"));
+        assert!(filter.enabled(Level::Warn, "gdf"));
+        assert!(filter.enabled(Level::Debug, "git"));
+        assert!(!filter.enabled(Level::Trace, "git"));
+        assert!(!filter.enabled(Level::Error, "output"));
+    }
+
+    #[test]
+    fn test_filter_bare_target_and_unknown_level() {
+        let filter = Filter::parse("off,matcher,git=loud");
+        assert!(filter.enabled(Level::Trace, "matcher"));
+        assert!(!filter.enabled(Level::Error, "git"));
+        assert_eq!(
+            Filter::parse("DEBUG"),
+            Filter {
+                default: Level::Debug,
+                targets: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_filter_last_directive_wins() {
+        let filter = Filter::parse("git=trace,git=warn");
+        assert!(!filter.enabled(Level::Info, "git"));
+    }
+
+    #[test]
+    fn test_event_json() {
+        let fields = vec![(
+            "args",
+            Value::Array(vec![Value::String("diff".to_string())]),
+        )];
+        assert_eq!(
+            event_json("git_command", 12, fields).to_string(),
+            r#"{"event":"git_command","elapsed_ms":12,"args":["diff"]}"#
+        );
+    }
+
+    #[test]
+    fn test_format_message() {
+        assert_eq!(
+            format_message(Level::Info, "gdf", "Repository: core"),
+            "Repository: core"
+        );
+        assert_eq!(
+            format_message(Level::Debug, "git", "Running git diff"),
+            "[DEBUG git] Running git diff"
+        );
+    }
+}
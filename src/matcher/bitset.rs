@@ -0,0 +1,121 @@
+//! Fixed-size bit set used to record which paths a pattern matched.
+
+const WORD_BITS: usize = 64;
+
+/// A fixed-length set of bits, one per path in a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    /// Create a bit set of `len` bits, all cleared
+    pub fn new(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(WORD_BITS)],
+            len,
+        }
+    }
+
+    /// Number of bits (not the number of set bits)
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, idx: usize) -> bool {
+        idx < self.len && self.words[idx / WORD_BITS] & (1 << (idx % WORD_BITS)) != 0
+    }
+
+    /// Set the bit at `idx`
+    ///
+    /// # Panics
+    /// Panics if `idx` is out of bounds.
+    pub fn set(&mut self, idx: usize) {
+        assert!(idx < self.len, "bit index {idx} out of range {}", self.len);
+        self.words[idx / WORD_BITS] |= 1 << (idx % WORD_BITS);
+    }
+
+    /// Number of set bits
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Check whether any bit is set
+    pub fn any(&self) -> bool {
+        self.words.iter().any(|&w| w != 0)
+    }
+
+    /// Set every bit that is set in `other`
+    pub fn union_with(&mut self, other: &BitSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    /// Clear every bit that is set in `other`
+    pub fn difference_with(&mut self, other: &BitSet) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= !other;
+        }
+    }
+
+    /// Iterate over the indices of set bits in ascending order
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|&idx| self.get(idx))
+    }
+}
+
+impl From<&[bool]> for BitSet {
+    fn from(bools: &[bool]) -> Self {
+        let mut bits = BitSet::new(bools.len());
+        for (idx, _) in bools.iter().enumerate().filter(|(_, &b)| b) {
+            bits.set(idx);
+        }
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_is_empty() {
+        let bits = BitSet::new(130);
+        assert_eq!(bits.len(), 130);
+        assert_eq!(bits.count(), 0);
+        assert!(!bits.any());
+    }
+
+    #[test]
+    fn test_set_and_get_across_words() {
+        let mut bits = BitSet::new(130);
+        bits.set(0);
+        bits.set(64);
+        bits.set(129);
+        assert!(bits.get(0) && bits.get(64) && bits.get(129));
+        assert!(!bits.get(1) && !bits.get(128));
+        assert!(!bits.get(500));
+        assert_eq!(bits.ones().collect::<Vec<_>>(), vec![0, 64, 129]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_set_out_of_range_panics() {
+        BitSet::new(3).set(3);
+    }
+
+    #[test]
+    fn test_union_and_difference() {
+        let mut a = BitSet::from(&[true, false, true, false][..]);
+        let b = BitSet::from(&[false, true, true, false][..]);
+
+        a.union_with(&b);
+        assert_eq!(a.ones().collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        a.difference_with(&BitSet::from(&[false, false, true, false][..]));
+        assert_eq!(a.ones().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(a.count(), 2);
+    }
+}
@@ -0,0 +1,691 @@
+//! Pattern matching orchestration and public matcher API.
+//!
+//! Patterns are compiled once into a [`PatternSet`], which resolves inclusion and
+//! exclusion patterns against a batch of paths using the state machine in `pattern`.
+
+mod bitset;
+mod pattern;
+
+use crate::json::Value;
+use crate::log::{self, log};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub use bitset::BitSet;
+
+use pattern::{match_batch_anchored, validate_glob};
+
+/// Controls where in a path a pattern is allowed to match
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Anchoring {
+    /// Pattern must match the whole path, starting at the repository root
+    Anchored,
+    /// Pattern may match any run of whole path segments (e.g. `*.md` matches `docs/a.md`)
+    Unanchored,
+    /// Pattern must match from the repository root, either the whole path or a leading
+    /// directory of it (e.g. `src/bin` matches `src/bin/main.rs`)
+    #[default]
+    Prefix,
+}
+
+impl FromStr for Anchoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anchored" => Ok(Anchoring::Anchored),
+            "unanchored" => Ok(Anchoring::Unanchored),
+            "prefix" => Ok(Anchoring::Prefix),
+            _ => Err(format!(
+                "Invalid anchoring mode: {s} (expected anchored, unanchored or prefix)"
+            )),
+        }
+    }
+}
+
+/// A compiled glob pattern that knows whether it includes or excludes paths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// Paths matching the glob are included
+    Glob(String),
+    /// Paths matching the glob are excluded (written with a leading `!`)
+    Negated(String),
+}
+
+impl Pattern {
+    /// Parse a raw pattern, treating a leading `!` as negation
+    ///
+    /// # Errors
+    /// Returns an error if the glob contains unsupported syntax.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let pattern = match raw.strip_prefix('!') {
+            Some(glob) => Pattern::Negated(glob.to_string()),
+            None => Pattern::Glob(raw.to_string()),
+        };
+        validate_glob(pattern.glob().as_bytes())?;
+        Ok(pattern)
+    }
+
+    /// The glob without any negation prefix
+    pub fn glob(&self) -> &str {
+        match self {
+            Pattern::Glob(glob) | Pattern::Negated(glob) => glob,
+        }
+    }
+
+    /// Whether the pattern excludes the paths it matches
+    pub fn is_negated(&self) -> bool {
+        matches!(self, Pattern::Negated(_))
+    }
+
+    /// Match paths against the glob, ignoring negation
+    pub fn match_batch(&self, paths: &[&str], anchoring: Anchoring) -> Result<BitSet, String> {
+        let results = match_batch_anchored(self.glob(), paths, anchoring)?;
+        Ok(BitSet::from(results.as_slice()))
+    }
+
+    /// Match a single path against the glob, ignoring negation
+    pub fn is_match(&self, path: &str, anchoring: Anchoring) -> Result<bool, String> {
+        Ok(match_batch_anchored(self.glob(), &[path], anchoring)?[0])
+    }
+}
+
+/// Match every pattern against every path in one pass
+///
+/// Returns one [`BitSet`] per pattern (in order) with a bit set for each matched path.
+/// Negation is ignored, and patterns sharing the same glob are only evaluated once, so
+/// callers can concatenate the patterns of several filter groups and resolve each group
+/// from the shared rows.
+pub fn match_matrix(
+    patterns: &[Pattern],
+    paths: &[&str],
+    anchoring: Anchoring,
+) -> Result<Vec<BitSet>, String> {
+    let mut evaluated: HashMap<&str, BitSet> = HashMap::new();
+    let mut rows = Vec::with_capacity(patterns.len());
+
+    for pattern in patterns {
+        let row = if let Some(row) = evaluated.get(pattern.glob()) {
+            row.clone()
+        } else {
+            let row = pattern.match_batch(paths, anchoring)?;
+            evaluated.insert(pattern.glob(), row.clone());
+            row
+        };
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Match the patterns of several sets against the same paths, one list of rows per set
+/// (see [`PatternSet::match_rows`])
+///
+/// The patterns of every set matched case-sensitively with the same anchoring go through
+/// a single [`match_matrix`] call, so a glob shared by several sets is only evaluated
+/// once; sets ignoring case are matched on their own.
+pub fn match_set_rows(sets: &[PatternSet], paths: &[&str]) -> Result<Vec<Vec<BitSet>>, String> {
+    let mut rows: Vec<Vec<BitSet>> = vec![Vec::new(); sets.len()];
+    let mut shared: Vec<(Anchoring, Vec<usize>)> = Vec::new();
+    for (index, set) in sets.iter().enumerate() {
+        if set.folded.is_some() {
+            rows[index] = set.match_rows(paths)?;
+            continue;
+        }
+        match shared
+            .iter_mut()
+            .find(|(anchoring, _)| *anchoring == set.anchoring)
+        {
+            Some((_, indexes)) => indexes.push(index),
+            None => shared.push((set.anchoring, vec![index])),
+        }
+    }
+    for (anchoring, indexes) in shared {
+        let patterns: Vec<Pattern> = indexes
+            .iter()
+            .flat_map(|&index| sets[index].patterns.iter().cloned())
+            .collect();
+        let mut matrix = match_matrix(&patterns, paths, anchoring)?.into_iter();
+        for index in indexes {
+            rows[index] = matrix.by_ref().take(sets[index].patterns.len()).collect();
+        }
+    }
+    Ok(rows)
+}
+
+/// A literal path prefix that git can use to narrow a diff before patterns are matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pathspec {
+    /// Path relative to the directory patterns are matched from; a trailing `/` restricts
+    /// it to the contents of a directory
+    pub path: String,
+    /// Whether paths under `path` are excluded rather than included
+    pub exclude: bool,
+}
+
+/// Split a glob into its literal leading directories, and whether it has no wildcards
+///
+/// `src/**/*.rs` gives `("src", false)` and `/docs/` gives `("docs", true)`.
+fn literal_prefix(glob: &str) -> (&str, bool) {
+    let glob = glob.strip_prefix('/').unwrap_or(glob);
+    match glob.find(['*', '?', '[', '\\']) {
+        Some(wildcard) => {
+            let dirs = glob[..wildcard].rfind('/').map_or("", |end| &glob[..end]);
+            (dirs, false)
+        }
+        None => (glob.strip_suffix('/').unwrap_or(glob), true),
+    }
+}
+
+/// A list of inclusion and exclusion patterns resolved together
+///
+/// A path is selected when it matches at least one inclusion pattern and no exclusion
+/// pattern. Exclusions are order-independent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternSet {
+    patterns: Vec<Pattern>,
+    anchoring: Anchoring,
+    /// The patterns lowercased, matched against lowercased paths, when case is ignored
+    folded: Option<Vec<Pattern>>,
+}
+
+impl PatternSet {
+    /// Compile raw patterns into a set
+    ///
+    /// # Errors
+    /// Returns an error if any pattern contains unsupported syntax.
+    pub fn new(raw_patterns: &[String], anchoring: Anchoring) -> Result<Self, String> {
+        let patterns: Vec<Pattern> = raw_patterns
+            .iter()
+            .map(|raw| Pattern::parse(raw))
+            .collect::<Result<_, _>>()?;
+        log!(
+            Debug,
+            "matcher",
+            "Compiled {} patterns with {anchoring:?} anchoring",
+            patterns.len()
+        );
+        for pattern in &patterns {
+            log::event("pattern_compiled", || {
+                vec![
+                    ("pattern", Value::String(pattern.glob().to_string())),
+                    ("negated", Value::Bool(pattern.is_negated())),
+                    (
+                        "anchoring",
+                        Value::String(format!("{anchoring:?}").to_ascii_lowercase()),
+                    ),
+                ]
+            });
+        }
+        Ok(PatternSet {
+            patterns,
+            anchoring,
+            folded: None,
+        })
+    }
+
+    /// Match paths regardless of case when `ignore_case` is set
+    #[must_use]
+    pub fn ignoring_case(mut self, ignore_case: bool) -> Self {
+        self.folded = ignore_case.then(|| {
+            self.patterns
+                .iter()
+                .map(|pattern| match pattern {
+                    Pattern::Glob(glob) => Pattern::Glob(glob.to_lowercase()),
+                    Pattern::Negated(glob) => Pattern::Negated(glob.to_lowercase()),
+                })
+                .collect()
+        });
+        self
+    }
+
+    /// The patterns of the set, in order
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// Match every pattern against every path, one row per pattern (see [`match_matrix`])
+    pub fn match_rows(&self, paths: &[&str]) -> Result<Vec<BitSet>, String> {
+        match &self.folded {
+            Some(folded) => {
+                let lowercased: Vec<String> =
+                    paths.iter().map(|path| path.to_lowercase()).collect();
+                let paths: Vec<&str> = lowercased.iter().map(String::as_str).collect();
+                match_matrix(folded, &paths, self.anchoring)
+            }
+            None => match_matrix(&self.patterns, paths, self.anchoring),
+        }
+    }
+
+    /// Resolve inclusions and exclusions from precomputed match rows
+    ///
+    /// `rows` must hold one row per pattern in the set, as produced by [`match_matrix`].
+    pub fn resolve(&self, rows: &[BitSet]) -> BitSet {
+        let (mut included, excluded) = self.split_rows(rows);
+        included.difference_with(&excluded);
+        included
+    }
+
+    /// Find the paths an inclusion selects but an exclusion drops, from precomputed
+    /// match rows
+    pub fn resolve_excluded(&self, rows: &[BitSet]) -> BitSet {
+        let (included, excluded) = self.split_rows(rows);
+        let mut kept = included.clone();
+        kept.difference_with(&excluded);
+        let mut dropped = included;
+        dropped.difference_with(&kept);
+        dropped
+    }
+
+    /// Union the rows of inclusion patterns and of exclusion patterns separately
+    fn split_rows(&self, rows: &[BitSet]) -> (BitSet, BitSet) {
+        let len = rows.first().map_or(0, BitSet::len);
+        let mut included = BitSet::new(len);
+        let mut excluded = BitSet::new(len);
+
+        for (pattern, row) in self.patterns.iter().zip(rows) {
+            if pattern.is_negated() {
+                excluded.union_with(row);
+            } else {
+                included.union_with(row);
+            }
+        }
+
+        (included, excluded)
+    }
+
+    /// Resolve inclusions and exclusions for each path
+    ///
+    /// Returns a [`BitSet`] with a bit set for each path selected by the set
+    pub fn match_batch(&self, paths: &[&str]) -> Result<BitSet, String> {
+        let rows = self.match_rows(paths)?;
+        if rows.is_empty() {
+            return Ok(BitSet::new(paths.len()));
+        }
+        Ok(self.resolve(&rows))
+    }
+
+    /// Resolve inclusions and exclusions for a single path
+    pub fn is_match(&self, path: &str) -> Result<bool, String> {
+        let (patterns, path) = match &self.folded {
+            Some(folded) => (folded, Cow::Owned(path.to_lowercase())),
+            None => (&self.patterns, Cow::Borrowed(path)),
+        };
+        let mut included = false;
+        for pattern in patterns {
+            if pattern.is_match(&path, self.anchoring)? {
+                if pattern.is_negated() {
+                    return Ok(false);
+                }
+                included = true;
+            }
+        }
+        Ok(included)
+    }
+
+    /// Lazily yield the paths selected by the set
+    ///
+    /// Paths are matched one at a time as the iterator is consumed, so the input never
+    /// has to be collected into memory.
+    pub fn filter_iter<'a, 'p, I>(
+        &'a self,
+        paths: I,
+    ) -> impl Iterator<Item = Result<&'p str, String>> + 'a
+    where
+        I: IntoIterator<Item = &'p str>,
+        I::IntoIter: 'a,
+    {
+        paths
+            .into_iter()
+            .filter_map(move |path| match self.is_match(path) {
+                Ok(true) => Some(Ok(path)),
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            })
+    }
+
+    /// Translate the set into git pathspecs that select a superset of the paths it selects
+    ///
+    /// Each inclusion becomes the literal leading directories of its glob. Exclusions are
+    /// only translated when git would exclude no more than the pattern does (a literal
+    /// path matched as a directory prefix, or `dir/**`), since over-excluding would hide
+    /// changes; the set still decides the final match. Returns `None` when an inclusion
+    /// may match anywhere in the tree, as with unanchored or leading-wildcard globs, or
+    /// when case is ignored, since pathspecs are matched case-sensitively.
+    pub fn pathspecs(&self) -> Option<Vec<Pathspec>> {
+        if self.folded.is_some() {
+            return None;
+        }
+        let mut specs = Vec::new();
+        for pattern in &self.patterns {
+            let glob = pattern.glob();
+            let floating = self.anchoring == Anchoring::Unanchored && !glob.starts_with('/');
+            let (prefix, literal) = literal_prefix(glob);
+
+            let path = if !pattern.is_negated() {
+                if floating || prefix.is_empty() {
+                    return None;
+                }
+                prefix.to_string()
+            } else if floating || prefix.is_empty() {
+                continue;
+            } else if literal && (self.anchoring != Anchoring::Anchored || glob.ends_with('/')) {
+                prefix.to_string()
+            } else if glob.strip_prefix('/').unwrap_or(glob) == format!("{prefix}/**") {
+                format!("{prefix}/")
+            } else {
+                continue;
+            };
+            specs.push(Pathspec {
+                path,
+                exclude: pattern.is_negated(),
+            });
+        }
+        specs.iter().any(|spec| !spec.exclude).then_some(specs)
+    }
+
+    /// Check whether any path is selected by the set
+    #[cfg(test)]
+    pub fn any_match(&self, paths: &[&str]) -> Result<bool, String> {
+        Ok(self.match_batch(paths)?.any())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchoring_from_str() {
+        assert_eq!("anchored".parse(), Ok(Anchoring::Anchored));
+        assert_eq!("unanchored".parse(), Ok(Anchoring::Unanchored));
+        assert_eq!("prefix".parse(), Ok(Anchoring::Prefix));
+        assert!("other".parse::<Anchoring>().is_err());
+    }
+
+    // ========== Pattern and PatternSet ==========
+
+    fn set(patterns: &[&str]) -> PatternSet {
+        let raw: Vec<String> = patterns.iter().map(|&p| p.to_string()).collect();
+        PatternSet::new(&raw, Anchoring::default()).unwrap()
+    }
+
+    fn ones(bits: &BitSet) -> Vec<usize> {
+        bits.ones().collect()
+    }
+
+    fn spec(path: &str, exclude: bool) -> Pathspec {
+        Pathspec {
+            path: path.to_string(),
+            exclude,
+        }
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        assert_eq!(literal_prefix("src/**/*.rs"), ("src", false));
+        assert_eq!(
+            literal_prefix("/services/api/*.go"),
+            ("services/api", false)
+        );
+        assert_eq!(literal_prefix("docs/"), ("docs", true));
+        assert_eq!(literal_prefix("Cargo.toml"), ("Cargo.toml", true));
+        assert_eq!(literal_prefix("*.md"), ("", false));
+        assert_eq!(literal_prefix("src/lib[0-9].rs"), ("src", false));
+        assert_eq!(literal_prefix("a/b\\*c"), ("a", false));
+    }
+
+    #[test]
+    fn test_pathspecs_from_inclusions_and_exclusions() {
+        let specs = set(&[
+            "src/**",
+            "/lib/core/*.rs",
+            "!docs/**",
+            "!src/vendor",
+            "!**/*.md",
+        ])
+        .pathspecs()
+        .unwrap();
+        assert_eq!(
+            specs,
+            vec![
+                spec("src", false),
+                spec("lib/core", false),
+                spec("docs/", true),
+                spec("src/vendor", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pathspecs_unavailable() {
+        // An inclusion that may match anywhere needs the full file list
+        assert_eq!(set(&["src/**", "*.md"]).pathspecs(), None);
+        assert_eq!(set(&["!docs/**"]).pathspecs(), None);
+        let unanchored = PatternSet::new(&["src/**".to_string()], Anchoring::Unanchored).unwrap();
+        assert_eq!(unanchored.pathspecs(), None);
+        let rooted = PatternSet::new(&["/src/**".to_string()], Anchoring::Unanchored).unwrap();
+        assert_eq!(rooted.pathspecs(), Some(vec![spec("src", false)]));
+    }
+
+    #[test]
+    fn test_pathspecs_anchored_exclusions() {
+        // Anchored `!docs` excludes only the file `docs`, not the directory's contents
+        let raw: Vec<String> = ["src/**", "!docs", "!vendor/"]
+            .iter()
+            .map(|&p| p.to_string())
+            .collect();
+        let anchored = PatternSet::new(&raw, Anchoring::Anchored).unwrap();
+        assert_eq!(
+            anchored.pathspecs(),
+            Some(vec![spec("src", false), spec("vendor", true)])
+        );
+    }
+
+    #[test]
+    fn test_pattern_parse_negation() {
+        assert_eq!(
+            Pattern::parse("src/**").unwrap(),
+            Pattern::Glob("src/**".to_string())
+        );
+        let negated = Pattern::parse("!*.md").unwrap();
+        assert_eq!(negated, Pattern::Negated("*.md".to_string()));
+        assert!(negated.is_negated());
+        assert_eq!(negated.glob(), "*.md");
+    }
+
+    #[test]
+    fn test_pattern_parse_rejects_invalid_syntax() {
+        assert!(Pattern::parse("foo[").unwrap_err().contains("Unclosed"));
+        assert!(Pattern::parse("!foo\\").unwrap_err().contains("backslash"));
+        assert!(Pattern::parse("[z-a]")
+            .unwrap_err()
+            .contains("Invalid range"));
+    }
+
+    #[test]
+    fn test_negated_pattern_matches_its_glob() {
+        let pattern = Pattern::parse("!*.md").unwrap();
+        let result = pattern
+            .match_batch(&["README.md", "main.rs"], Anchoring::default())
+            .unwrap();
+        assert_eq!(ones(&result), vec![0]);
+    }
+
+    #[test]
+    fn test_pattern_set_resolves_exclusions() {
+        let result = set(&["src/**", "!**/*.md", "!**/test/**"])
+            .match_batch(&["src/main.rs", "src/README.md", "src/test/a.rs", "lib.rs"])
+            .unwrap();
+        assert_eq!(ones(&result), vec![0]);
+    }
+
+    #[test]
+    fn test_pattern_set_exclusions_are_order_independent() {
+        let paths = ["src/main.rs", "src/README.md"];
+        assert_eq!(
+            set(&["!*.md", "src/**"]).match_batch(&paths).unwrap(),
+            set(&["src/**", "!*.md"]).match_batch(&paths).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pattern_set_only_exclusions_matches_nothing() {
+        assert!(!set(&["!*.md"]).any_match(&["a.rs", "b.md"]).unwrap());
+    }
+
+    #[test]
+    fn test_pattern_set_uses_anchoring() {
+        let raw = vec!["*.md".to_string()];
+        let prefix = PatternSet::new(&raw, Anchoring::Prefix).unwrap();
+        let unanchored = PatternSet::new(&raw, Anchoring::Unanchored).unwrap();
+        assert!(!prefix.any_match(&["docs/a.md"]).unwrap());
+        assert!(unanchored.any_match(&["docs/a.md"]).unwrap());
+    }
+
+    #[test]
+    fn test_pattern_set_empty() {
+        let result = set(&[]).match_batch(&["a.rs", "b.rs"]).unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(!result.any());
+    }
+
+    // ========== Bulk matrix matching ==========
+
+    fn compile(patterns: &[&str]) -> Vec<Pattern> {
+        patterns
+            .iter()
+            .map(|p| Pattern::parse(p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_match_matrix_one_row_per_pattern() {
+        let paths = ["src/main.rs", "docs/a.md", "README.md"];
+        let rows = match_matrix(
+            &compile(&["src/**", "*.md", "!**/*.md"]),
+            &paths,
+            Anchoring::default(),
+        )
+        .unwrap();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(ones(&rows[0]), vec![0]);
+        assert_eq!(ones(&rows[1]), vec![2]);
+        assert_eq!(ones(&rows[2]), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_match_matrix_reuses_duplicate_globs() {
+        let rows = match_matrix(
+            &compile(&["*.md", "!*.md"]),
+            &["README.md", "main.rs"],
+            Anchoring::default(),
+        )
+        .unwrap();
+        assert_eq!(rows[0], rows[1]);
+    }
+
+    #[test]
+    fn test_match_matrix_propagates_errors() {
+        let patterns = vec![Pattern::Glob("foo[".to_string())];
+        let result = match_matrix(&patterns, &["foo"], Anchoring::default());
+        assert!(result.unwrap_err().contains("Unclosed"));
+    }
+
+    #[test]
+    fn test_resolve_groups_from_shared_matrix() {
+        let paths = ["api/main.rs", "api/README.md", "web/app.ts"];
+        let backend = set(&["api/**", "!**/*.md"]);
+        let frontend = set(&["web/**"]);
+
+        let all: Vec<Pattern> = [backend.patterns(), frontend.patterns()].concat();
+        let rows = match_matrix(&all, &paths, Anchoring::default()).unwrap();
+        let (backend_rows, frontend_rows) = rows.split_at(backend.patterns().len());
+
+        assert_eq!(ones(&backend.resolve(backend_rows)), vec![0]);
+        assert_eq!(ones(&frontend.resolve(frontend_rows)), vec![2]);
+    }
+
+    #[test]
+    fn test_resolve_excluded() {
+        let paths = ["api/main.rs", "api/README.md", "docs/guide.md"];
+        let set = set(&["api/**", "!**/*.md"]);
+        let rows = set.match_rows(&paths).unwrap();
+
+        assert_eq!(ones(&set.resolve_excluded(&rows)), vec![1]);
+    }
+
+    #[test]
+    fn test_match_set_rows_matches_each_set() {
+        let paths = ["src/a.rs", "docs/README.md", "SRC/B.RS"];
+        let sets = [
+            set(&["src/**", "!**/*.md"]),
+            set(&["**/*.md"]),
+            set(&["src/**"]).ignoring_case(true),
+            PatternSet::new(&["*.rs".to_string()], Anchoring::Unanchored).unwrap(),
+        ];
+        let rows = match_set_rows(&sets, &paths).unwrap();
+        assert_eq!(rows.len(), sets.len());
+        for (set, rows) in sets.iter().zip(&rows) {
+            assert_eq!(rows, &set.match_rows(&paths).unwrap());
+        }
+        assert_eq!(ones(&sets[2].resolve(&rows[2])), vec![0, 2]);
+    }
+
+    // ========== Streaming matching ==========
+
+    #[test]
+    fn test_pattern_set_filter_iter_yields_matches_in_order() {
+        let patterns = set(&["src/**/*.rs"]);
+        let paths = ["src/a.rs", "README.md", "src/x/b.rs", "src/c.txt"];
+        let matched: Result<Vec<&str>, String> =
+            patterns.filter_iter(paths.iter().copied()).collect();
+        assert_eq!(matched.unwrap(), vec!["src/a.rs", "src/x/b.rs"]);
+    }
+
+    #[test]
+    fn test_pattern_set_filter_iter_is_lazy() {
+        let patterns = set(&["*.md"]);
+        let mut consumed = 0;
+        let paths = ["a.rs", "b.md", "c.md"]
+            .into_iter()
+            .inspect(|_| consumed += 1);
+
+        let first = patterns.filter_iter(paths).next().unwrap();
+        assert_eq!(first, Ok("b.md"));
+        assert_eq!(consumed, 2);
+    }
+
+    #[test]
+    fn test_pattern_set_filter_iter_applies_exclusions() {
+        let patterns = set(&["src/**", "!**/*.md"]);
+        let lines = "src/main.rs\nsrc/README.md\ndocs/a.rs\nsrc/lib.rs\n";
+        let matched: Result<Vec<&str>, String> = patterns.filter_iter(lines.lines()).collect();
+        assert_eq!(matched.unwrap(), vec!["src/main.rs", "src/lib.rs"]);
+    }
+
+    #[test]
+    fn test_pattern_set_is_match() {
+        let patterns = set(&["src/**", "!**/*.md"]);
+        assert!(patterns.is_match("src/main.rs").unwrap());
+        assert!(!patterns.is_match("src/README.md").unwrap());
+        assert!(!patterns.is_match("docs/a.rs").unwrap());
+    }
+
+    #[test]
+    fn test_pattern_set_ignoring_case() {
+        let patterns = set(&["docs/**", "!**/*.MD"]).ignoring_case(true);
+        let paths = ["Docs/Guide.txt", "DOCS/README.md", "src/docs/a.txt"];
+        assert_eq!(ones(&patterns.match_batch(&paths).unwrap()), vec![0]);
+        assert!(patterns.is_match("docs/INDEX.html").unwrap());
+        assert!(!patterns.is_match("docs/index.Md").unwrap());
+        assert_eq!(patterns.patterns()[1].glob(), "**/*.MD");
+        assert_eq!(patterns.pathspecs(), None);
+
+        let exact = set(&["docs/**"]).ignoring_case(false);
+        assert!(!exact.is_match("Docs/Guide.txt").unwrap());
+        assert!(exact.pathspecs().is_some());
+    }
+}
@@ -3,19 +3,46 @@
 //! This implementation processes multiple strings against a single pattern,
 //! maintaining only the active (still-matching) strings for optimal performance.
 
-/// Check if a single path matches any of the provided patterns.
-/// Returns true if ANY pattern matches the path.
+use super::Anchoring;
+
+/// Match multiple strings against a single glob pattern using the given anchoring mode
+///
+/// A leading `/` always anchors the pattern to the repository root, and a trailing `/`
+/// always allows the pattern to match a directory and its contents, whatever the mode.
 ///
-/// # Errors
-/// Returns an error if any pattern contains unsupported syntax.
-pub fn matches_any(path: &str, patterns: &[String]) -> Result<bool, String> {
-    for pattern in patterns {
-        let results = match_batch(pattern, &[path])?;
-        if results.first() == Some(&true) {
-            return Ok(true);
+/// Returns a `Vec<bool>` indicating which strings matched (`true`) or failed (`false`)
+pub(super) fn match_batch_anchored(
+    pattern: &str,
+    strings: &[&str],
+    anchoring: Anchoring,
+) -> Result<Vec<bool>, String> {
+    let root_anchored = pattern.starts_with('/');
+    let dir_prefix = anchoring != Anchoring::Anchored || pattern.ends_with('/');
+
+    if anchoring != Anchoring::Unanchored || root_anchored {
+        return match_from_root(pattern, strings, dir_prefix);
+    }
+
+    // Unanchored: try the pattern against every suffix that starts at a segment boundary
+    let mut owners = Vec::new();
+    let mut suffixes = Vec::new();
+    for (idx, s) in strings.iter().enumerate() {
+        owners.push(idx);
+        suffixes.push(*s);
+        for (pos, _) in s.match_indices('/') {
+            owners.push(idx);
+            suffixes.push(&s[pos + 1..]);
         }
     }
-    Ok(false)
+
+    let mut results = vec![false; strings.len()];
+    for (owner, matched) in owners
+        .into_iter()
+        .zip(match_from_root(pattern, &suffixes, dir_prefix)?)
+    {
+        results[owner] |= matched;
+    }
+    Ok(results)
 }
 
 /// Active string being matched against the pattern
@@ -71,15 +98,22 @@ enum PatternState {
     InSuperWild,          // Confirmed **/*,  ready to match
 }
 
-/// Match multiple strings against a single glob pattern
+/// Match multiple strings against a single glob pattern using [`Anchoring::Prefix`]
+#[cfg(test)]
+fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String> {
+    match_batch_anchored(pattern, strings, Anchoring::Prefix)
+}
+
+/// Run the pattern state machine against strings, starting at the first byte of each
 ///
 /// Matching is done on byte arrays as control characters are all single-byte ASCII
 /// characters. Any other characters will need to match the pattern segments byte
 /// for byte anyway, so we can avoid converting strings to chars.
 ///
-/// Returns a `Vec<bool>` indicating which strings matched (`true`) or failed (`false`)
+/// When `dir_prefix` is set, a string also matches if the pattern is exhausted at a `/`
+/// (i.e. the pattern named a leading directory of the path).
 #[allow(clippy::too_many_lines)]
-pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String> {
+fn match_from_root(pattern: &str, strings: &[&str], dir_prefix: bool) -> Result<Vec<bool>, String> {
     if strings.is_empty() {
         return Ok(Vec::new());
     }
@@ -157,6 +191,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             false, // wildcard mode
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -196,8 +231,12 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                         pattern_idx += 1;
                         let escaped: u8 = pattern_bytes[pattern_idx];
 
-                        // Match literal byte against all active strings
-                        consume_byte(&mut active, &mut results, |b| b == Some(escaped));
+                        // Match literal byte (or shorthand class) against all active strings
+                        consume_byte(
+                            &mut active,
+                            &mut results,
+                            |b| matches!(b, Some(c) if escape_matches(escaped, c)),
+                        );
                         pattern_idx += 1;
                     }
                     PatternState::InWildcard | PatternState::InPossibleGlobstar => {
@@ -209,6 +248,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             false, // wildcard mode
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -223,6 +263,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             true, // globstar mode
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -237,6 +278,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             true, // use globstar mode for now
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -263,6 +305,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             false, // wildcard mode
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -277,6 +320,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             true, // globstar mode
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -291,6 +335,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             true, // use globstar mode for now
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -314,6 +359,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             false, // wildcard mode
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -328,6 +374,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             true, // globstar mode
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -342,6 +389,7 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
                             &mut results,
                             true, // use globstar mode for now
                             question_count,
+                            dir_prefix,
                         )?;
                         pattern_idx = next_pattern_idx;
                         pattern_state = PatternState::Literal;
@@ -358,27 +406,24 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
             // Normal completion - mark remaining active strings based on completion state
             for string in active {
                 // String must be exhausted OR next character is b'/' (directory match)
-                results[string.original_idx] = match string.current_byte() {
-                    Some(b'/') | None => true,
-                    Some(_) => false,
-                };
+                results[string.original_idx] = at_match_end(&string, string.position, dir_prefix);
             }
         }
-        PatternState::InWildcard | PatternState::InPossibleGlobstar => {
+        PatternState::InWildcard => {
             // Pattern ends with wildcard - match remaining string (no /)
-            for string in active.iter_mut() {
+            for string in &mut active {
                 loop {
                     match string.current_byte() {
                         Some(b'/') | None => break,
                         _ => string.advance(),
                     }
                 }
-                results[string.original_idx] = true;
+                results[string.original_idx] = at_match_end(string, string.position, dir_prefix);
             }
         }
-        PatternState::InGlobstar | PatternState::InSuperWild => {
+        PatternState::InPossibleGlobstar | PatternState::InGlobstar | PatternState::InSuperWild => {
             // Pattern ends with globstar or super-wild - match everything
-            for string in active.iter_mut() {
+            for string in &mut active {
                 string.position = string.bytes.len();
                 results[string.original_idx] = true;
             }
@@ -398,8 +443,12 @@ pub fn match_batch(pattern: &str, strings: &[&str]) -> Result<Vec<bool>, String>
 /// `required_chars` specifies the minimum number of non-slash characters that must be
 /// consumed by the wildcard before the pattern segment starts matching.
 ///
+/// When the segment is the last one in the pattern, a trial position only succeeds if it
+/// leaves the string at a valid match end (see `dir_prefix` in `match_from_root`).
+///
 /// Failed strings are swap-removed from active and marked false in results.
 /// Returns the pattern index after consuming the segment.
+#[allow(clippy::too_many_lines)]
 fn match_wildcard_segment(
     pattern: &[u8],
     pattern_start: usize,
@@ -407,6 +456,7 @@ fn match_wildcard_segment(
     results: &mut [bool],
     globstar: bool,
     required_chars: usize,
+    dir_prefix: bool,
 ) -> Result<usize, String> {
     // Patterns ending in globstar or wild
     if pattern_start >= pattern.len() {
@@ -497,7 +547,8 @@ fn match_wildcard_segment(
                         pattern_idx += 1;
                         let escaped = pattern[pattern_idx];
 
-                        if string.bytes.get(string_idx).copied() == Some(escaped) {
+                        if matches!(string.bytes.get(string_idx), Some(&c) if escape_matches(escaped, c))
+                        {
                             string_idx += 1;
                             pattern_idx += 1;
                         } else {
@@ -542,9 +593,14 @@ fn match_wildcard_segment(
                 }
             }
 
-            // If we exhausted pattern (no * found) and segment matched, that's success
-            if segment_matched && pattern_idx >= pattern.len() && next_pattern_idx.is_none() {
-                next_pattern_idx = Some(pattern_idx);
+            // If we exhausted pattern (no * found), the segment must also end the match
+            if segment_matched && pattern_idx >= pattern.len() {
+                if !at_match_end(string, string_idx, dir_prefix) {
+                    continue;
+                }
+                if next_pattern_idx.is_none() {
+                    next_pattern_idx = Some(pattern_idx);
+                }
             }
 
             // Check if this trial succeeded
@@ -570,6 +626,38 @@ fn match_wildcard_segment(
     Ok(next_pattern_idx.unwrap_or(pattern.len()))
 }
 
+/// Check whether a match may end at `position` in the string
+fn at_match_end(string: &ActiveString, position: usize, dir_prefix: bool) -> bool {
+    match string.bytes.get(position) {
+        None => true,
+        Some(b'/') => dir_prefix,
+        Some(_) => false,
+    }
+}
+
+/// Check glob syntax without matching anything
+///
+/// Reports the same errors the state machine would raise when reaching the offending byte.
+pub(super) fn validate_glob(pattern: &[u8]) -> Result<(), String> {
+    let mut idx = 0;
+    while idx < pattern.len() {
+        match pattern[idx] {
+            b'\\' => {
+                if idx + 1 >= pattern.len() {
+                    return Err("Pattern ends with backslash".to_string());
+                }
+                idx += 2;
+            }
+            b'[' => {
+                let (_, class_end) = extract_charset(pattern, idx)?;
+                idx = class_end;
+            }
+            _ => idx += 1,
+        }
+    }
+    Ok(())
+}
+
 /// Extract character set from pattern starting at '['
 ///
 /// Returns the extracted character set and the next pattern index after the closing bracket
@@ -600,7 +688,10 @@ fn extract_charset(pattern: &[u8], start_idx: usize) -> Result<(CharSet, usize),
                 }
                 idx += 1;
                 let escaped = pattern[idx];
-                items.push(CharSetItem::Single(escaped));
+                match shorthand_class(escaped) {
+                    Some(class) => items.extend_from_slice(class),
+                    None => items.push(CharSetItem::Single(escaped)),
+                }
                 idx += 1;
             }
             b']' => {
@@ -641,12 +732,51 @@ fn extract_charset(pattern: &[u8], start_idx: usize) -> Result<(CharSet, usize),
     Err("Unclosed character class".to_string())
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum CharSetItem {
     Single(u8),
     Range(u8, u8),
 }
 
+impl CharSetItem {
+    fn matches(self, b: u8) -> bool {
+        match self {
+            CharSetItem::Single(c) => c == b,
+            CharSetItem::Range(start, end) => b >= start && b <= end,
+        }
+    }
+}
+
+const DIGIT_CLASS: &[CharSetItem] = &[CharSetItem::Range(b'0', b'9')];
+const WORD_CLASS: &[CharSetItem] = &[
+    CharSetItem::Range(b'a', b'z'),
+    CharSetItem::Range(b'A', b'Z'),
+    CharSetItem::Range(b'0', b'9'),
+    CharSetItem::Single(b'_'),
+];
+const SPACE_CLASS: &[CharSetItem] = &[
+    CharSetItem::Single(b' '),
+    CharSetItem::Range(b'\t', b'\r'), // \t \n \x0b \x0c \r
+];
+
+/// Byte ranges for shorthand escapes (`\d`, `\w`, `\s`), or `None` for a plain escape
+fn shorthand_class(escaped: u8) -> Option<&'static [CharSetItem]> {
+    match escaped {
+        b'd' => Some(DIGIT_CLASS),
+        b'w' => Some(WORD_CLASS),
+        b's' => Some(SPACE_CLASS),
+        _ => None,
+    }
+}
+
+/// Check a byte against an escaped pattern byte, expanding shorthand classes
+fn escape_matches(escaped: u8, b: u8) -> bool {
+    match shorthand_class(escaped) {
+        Some(class) => class.iter().any(|item| item.matches(b)),
+        None => b == escaped,
+    }
+}
+
 #[derive(Debug)]
 struct CharSet {
     items: Vec<CharSetItem>,
@@ -655,10 +785,7 @@ struct CharSet {
 
 impl CharSet {
     fn matches(&self, b: u8) -> bool {
-        let contains = self.items.iter().any(|item| match item {
-            CharSetItem::Single(c) => *c == b,
-            CharSetItem::Range(start, end) => b >= *start && b <= *end,
-        });
+        let contains = self.items.iter().any(|item| item.matches(b));
 
         if self.negated {
             !contains
@@ -1464,4 +1591,162 @@ mod tests {
         .unwrap();
         assert_eq!(result, vec![true, true, true, false]);
     }
+
+    // ========== Anchoring modes ==========
+
+    #[test]
+    fn test_anchored_requires_whole_path() {
+        let result = match_batch_anchored(
+            "src/bin",
+            &["src/bin", "src/bin/main.rs", "lib/src/bin"],
+            Anchoring::Anchored,
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_anchored_wildcard_stops_at_directory() {
+        let result =
+            match_batch_anchored("src/*", &["src/a.rs", "src/a/b.rs"], Anchoring::Anchored)
+                .unwrap();
+        assert_eq!(result, vec![true, false]);
+    }
+
+    #[test]
+    fn test_anchored_trailing_globstar_matches_nested_paths() {
+        let result = match_batch_anchored(
+            "src/**",
+            &["src/a.rs", "src/a/b.rs", "src/a/b/c.rs", "lib/src/a.rs"],
+            Anchoring::Anchored,
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, true, true, false]);
+    }
+
+    #[test]
+    fn test_anchored_trailing_slash_still_matches_contents() {
+        let result = match_batch_anchored(
+            "build/",
+            &["build", "build/out.js", "buildx/out.js"],
+            Anchoring::Anchored,
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, true, false]);
+    }
+
+    #[test]
+    fn test_unanchored_matches_any_segment() {
+        let result = match_batch_anchored(
+            "*.md",
+            &["README.md", "docs/guide.md", "docs/a.rs", "notes.md/x.txt"],
+            Anchoring::Unanchored,
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, true, false, true]);
+    }
+
+    #[test]
+    fn test_unanchored_multi_segment_pattern() {
+        let result = match_batch_anchored(
+            "test/*.rs",
+            &["test/a.rs", "crates/core/test/b.rs", "crates/latest/c.rs"],
+            Anchoring::Unanchored,
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, true, false]);
+    }
+
+    #[test]
+    fn test_unanchored_leading_slash_anchors_to_root() {
+        let result = match_batch_anchored(
+            "/README.md",
+            &["README.md", "docs/README.md"],
+            Anchoring::Unanchored,
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, false]);
+    }
+
+    #[test]
+    fn test_prefix_is_default_behaviour() {
+        let paths = ["src/bin", "src/bin/main.rs", "src/binary"];
+        assert_eq!(
+            match_batch_anchored("src/bin", &paths, Anchoring::default()).unwrap(),
+            match_batch("src/bin", &paths).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_final_segment_retries_later_positions() {
+        let result = match_batch("*.rs", &["a.rs.rs", "a.rs.txt"]).unwrap();
+        assert_eq!(result, vec![true, false]);
+
+        let result = match_batch("a*b", &["abab", "aba"]).unwrap();
+        assert_eq!(result, vec![true, false]);
+    }
+
+    // ========== Shorthand escape classes ==========
+
+    #[test]
+    fn test_shorthand_digit() {
+        let result = match_batch(
+            "v\\d.\\d.\\d.tar",
+            &["v1.2.3.tar", "v1.x.3.tar", "vd.d.d.tar"],
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_shorthand_word() {
+        let result =
+            match_batch("\\w\\w.rs", &["ab.rs", "a_.rs", "Z9.rs", "a-.rs", "a/.rs"]).unwrap();
+        assert_eq!(result, vec![true, true, true, false, false]);
+    }
+
+    #[test]
+    fn test_shorthand_space() {
+        let result = match_batch("my\\sfile", &["my file", "my\tfile", "my_file"]).unwrap();
+        assert_eq!(result, vec![true, true, false]);
+    }
+
+    #[test]
+    fn test_shorthand_after_wildcard() {
+        let result = match_batch(
+            "*-\\d\\d.log",
+            &["build-01.log", "build-1a.log", "a/build-01.log"],
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_shorthand_in_charset() {
+        let result =
+            match_batch("[\\d.]*.json", &["1.json", "..json", "a.json", "9abc.json"]).unwrap();
+        assert_eq!(result, vec![true, true, false, true]);
+    }
+
+    #[test]
+    fn test_shorthand_in_negated_charset() {
+        let result = match_batch("[!\\d]*", &["a1", "1a", "_x"]).unwrap();
+        assert_eq!(result, vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_shorthand_hash_like_filename() {
+        let result = match_batch(
+            "dist/app.[\\da-f][\\da-f][\\da-f][\\da-f].js",
+            &["dist/app.0a9f.js", "dist/app.0a9g.js"],
+        )
+        .unwrap();
+        assert_eq!(result, vec![true, false]);
+    }
+
+    #[test]
+    fn test_other_escapes_remain_literal() {
+        let result = match_batch("\\a\\*", &["a*", "ab"]).unwrap();
+        assert_eq!(result, vec![true, false]);
+    }
 }
@@ -0,0 +1,311 @@
+//! `--js-workspace`: the packages of an npm, yarn or pnpm workspace whose files
+//! changed, so a Node monorepo can build and test those alone.
+//!
+//! The workspace globs come from `pnpm-workspace.yaml` when there is one, or else from
+//! the `workspaces` of the root `package.json`. A file belongs to the deepest workspace
+//! directory above it that holds a named `package.json`, and depends on the workspace
+//! packages its `dependencies`, `devDependencies`, `peerDependencies` and
+//! `optionalDependencies` name.
+
+use crate::json::{self, Value};
+use crate::matcher::{Anchoring, PatternSet};
+use crate::yaml;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The names of the packages owning `files`, given relative to the workspace `root`,
+/// sorted and once each
+///
+/// # Errors
+/// Returns an error when no workspace globs are found or a manifest cannot be parsed.
+pub fn affected_packages(root: &Path, files: &[String]) -> Result<Vec<String>, String> {
+    let members = member_manifests(root)?;
+    // The package declared in each directory looked at, if any
+    let mut packages: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut names = BTreeSet::new();
+    for file in files {
+        let mut dir = Path::new(file).parent();
+        while let Some(current) = dir {
+            if !packages.contains_key(current) {
+                let package = member_manifest(root, &members, current)?
+                    .and_then(|manifest| package_name(&manifest));
+                packages.insert(current.to_path_buf(), package);
+            }
+            if let Some(name) = &packages[current] {
+                names.insert(name.clone());
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+    Ok(names.into_iter().collect())
+}
+
+/// Each workspace package with the names of the packages it depends on, found by
+/// walking the directories under `root`, except `node_modules` and hidden ones
+///
+/// # Errors
+/// Returns an error when no workspace globs are found, a directory cannot be read or a
+/// manifest cannot be parsed.
+pub fn dependency_graph(root: &Path) -> Result<Vec<(String, Vec<String>)>, String> {
+    let members = member_manifests(root)?;
+    let mut graph = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(root.join(&dir))
+            .map_err(|e| format!("Failed to read {}: {e}", root.join(&dir).display()))?;
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            if is_dir && name != "node_modules" && !name.starts_with('.') {
+                dirs.push(dir.join(name));
+            }
+        }
+        let Some(manifest) = member_manifest(root, &members, &dir)? else {
+            continue;
+        };
+        let Some(name) = package_name(&manifest) else {
+            continue;
+        };
+        let dependencies = DEPENDENCY_TABLES
+            .iter()
+            .filter_map(|table| match manifest.get(table) {
+                Some(Value::Object(entries)) => Some(entries),
+                _ => None,
+            })
+            .flatten()
+            .map(|(dependency, _)| dependency.clone())
+            .collect();
+        graph.push((name, dependencies));
+    }
+    Ok(graph)
+}
+
+/// The `package.json` tables naming dependencies
+const DEPENDENCY_TABLES: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "optionalDependencies",
+];
+
+/// The `package.json` paths the workspace globs select
+fn member_manifests(root: &Path) -> Result<PatternSet, String> {
+    let manifests: Vec<String> = workspace_globs(root)?
+        .iter()
+        .map(|glob| format!("{glob}/package.json"))
+        .collect();
+    PatternSet::new(&manifests, Anchoring::Anchored)
+}
+
+/// The parsed `package.json` of `dir`, when the workspace includes it
+fn member_manifest(root: &Path, members: &PatternSet, dir: &Path) -> Result<Option<Value>, String> {
+    let manifest = dir.join("package.json");
+    let path = root.join(&manifest);
+    match manifest.to_str() {
+        Some(relative) if dir != Path::new("") && members.is_match(relative)? && path.is_file() => {
+            json::parse(&read(&path)?)
+                .map(Some)
+                .map_err(|e| in_file(&path, &e))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The workspace package globs, without trailing slashes or a leading `./`
+fn workspace_globs(root: &Path) -> Result<Vec<String>, String> {
+    let pnpm = root.join("pnpm-workspace.yaml");
+    let (path, globs) = if pnpm.is_file() {
+        let document = yaml::parse(&read(&pnpm)?).map_err(|e| in_file(&pnpm, &e))?;
+        (pnpm, document.get("packages").cloned())
+    } else {
+        let manifest = root.join("package.json");
+        let document = json::parse(&read(&manifest)?).map_err(|e| in_file(&manifest, &e))?;
+        // Yarn also takes `{ "packages": [...] }`
+        let workspaces = document
+            .get("workspaces")
+            .map(|workspaces| workspaces.get("packages").unwrap_or(workspaces).clone());
+        (manifest, workspaces)
+    };
+    let globs: Vec<String> = match globs {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| item.as_str().map(normalize_glob))
+            .collect::<Option<_>>()
+            .ok_or_else(|| in_file(&path, "workspace packages must be strings"))?,
+        _ => Vec::new(),
+    };
+    if globs.is_empty() {
+        return Err(in_file(&path, "no workspace packages are listed"));
+    }
+    Ok(globs)
+}
+
+/// `./packages/*/` as `packages/*`, keeping a leading `!`
+fn normalize_glob(glob: &str) -> String {
+    let (negation, glob) = match glob.strip_prefix('!') {
+        Some(glob) => ("!", glob),
+        None => ("", glob),
+    };
+    let glob = glob.trim_start_matches("./").trim_end_matches('/');
+    format!("{negation}{glob}")
+}
+
+/// The `name` of a `package.json`, if it has one
+fn package_name(manifest: &Value) -> Option<String> {
+    manifest
+        .get("name")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))
+}
+
+fn in_file(path: &Path, message: &str) -> String {
+    format!("{}: {message}", path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("gdf_test_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, text) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_normalize_glob() {
+        assert_eq!(normalize_glob("./packages/*/"), "packages/*");
+        assert_eq!(normalize_glob("!packages/internal"), "!packages/internal");
+        assert_eq!(normalize_glob("apps/**"), "apps/**");
+    }
+
+    #[test]
+    fn test_affected_packages_npm() {
+        let root = workspace(
+            "npm_workspace",
+            &[
+                (
+                    "package.json",
+                    r#"{"name": "root", "workspaces": ["packages/*"]}"#,
+                ),
+                ("packages/web/package.json", r#"{"name": "@acme/web"}"#),
+                ("packages/api/package.json", r#"{"name": "@acme/api"}"#),
+                ("packages/unnamed/package.json", "{}"),
+                ("tools/package.json", r#"{"name": "tools"}"#),
+            ],
+        );
+        let files = [
+            "packages/web/src/index.ts",
+            "packages/web/package.json",
+            "packages/unnamed/index.js",
+            "tools/build.js",
+            "README.md",
+        ]
+        .map(str::to_string);
+        assert_eq!(
+            affected_packages(&root, &files),
+            Ok(vec!["@acme/web".to_string()])
+        );
+
+        fs::write(
+            root.join("package.json"),
+            r#"{"workspaces": {"packages": ["packages/*", "tools"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            affected_packages(&root, &files),
+            Ok(vec!["@acme/web".to_string(), "tools".to_string()])
+        );
+
+        fs::write(root.join("package.json"), r#"{"name": "root"}"#).unwrap();
+        assert_eq!(
+            affected_packages(&root, &files),
+            Err(format!(
+                "{}: no workspace packages are listed",
+                root.join("package.json").display()
+            ))
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_affected_packages_pnpm() {
+        let root = workspace(
+            "pnpm_workspace",
+            &[
+                ("package.json", r#"{"name": "root"}"#),
+                (
+                    "pnpm-workspace.yaml",
+                    "packages:\n  - 'apps/**'\n  - '!apps/legacy'\n",
+                ),
+                ("apps/site/package.json", r#"{"name": "site"}"#),
+                ("apps/site/plugins/seo/package.json", r#"{"name": "seo"}"#),
+                ("apps/legacy/package.json", r#"{"name": "legacy"}"#),
+            ],
+        );
+        let files = [
+            "apps/site/plugins/seo/index.ts",
+            "apps/site/pages/home.tsx",
+            "apps/legacy/index.js",
+        ]
+        .map(str::to_string);
+        // Nested packages own their files; excluded directories are no package
+        assert_eq!(
+            affected_packages(&root, &files),
+            Ok(vec!["seo".to_string(), "site".to_string()])
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let root = workspace(
+            "npm_dependencies",
+            &[
+                ("package.json", r#"{"workspaces": ["packages/*"]}"#),
+                (
+                    "packages/web/package.json",
+                    r#"{"name": "web", "dependencies": {"ui": "*", "react": "^18"},
+                        "devDependencies": {"test-utils": "workspace:*"}}"#,
+                ),
+                ("packages/ui/package.json", r#"{"name": "ui"}"#),
+                (
+                    "packages/web/node_modules/ui/package.json",
+                    r#"{"name": "ui", "dependencies": {"web": "*"}}"#,
+                ),
+                (
+                    "docs/package.json",
+                    r#"{"name": "docs", "dependencies": {"ui": "*"}}"#,
+                ),
+            ],
+        );
+        let mut graph = dependency_graph(&root).unwrap();
+        graph.sort();
+        assert_eq!(
+            graph,
+            [
+                ("ui".to_string(), Vec::new()),
+                (
+                    "web".to_string(),
+                    vec![
+                        "ui".to_string(),
+                        "react".to_string(),
+                        "test-utils".to_string()
+                    ]
+                ),
+            ]
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -1,7 +1,53 @@
 //! Output handling for stdout, stderr, and GitHub Actions output files.
 
-use std::fs::OpenOptions;
+use crate::json::{self, Value};
+use crate::log::{self, log};
+use std::collections::hash_map::RandomState;
+use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
 use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How a list of files is joined into a single-line value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListDelimiter {
+    /// Separated by spaces, for word splitting in shells
+    Space,
+    /// Separated by commas
+    #[default]
+    Comma,
+    /// A JSON array of strings, for `fromJSON` and paths with any character
+    Json,
+}
+
+impl FromStr for ListDelimiter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "space" => Ok(ListDelimiter::Space),
+            "comma" => Ok(ListDelimiter::Comma),
+            "json" => Ok(ListDelimiter::Json),
+            _ => Err(format!(
+                "Invalid list delimiter: {s} (expected space, comma or json)"
+            )),
+        }
+    }
+}
+
+impl ListDelimiter {
+    /// Join `values` into one value
+    pub fn join(self, values: &[String]) -> String {
+        match self {
+            ListDelimiter::Space => values.join(" "),
+            ListDelimiter::Comma => values.join(","),
+            ListDelimiter::Json => {
+                Value::Array(values.iter().cloned().map(Value::String).collect()).to_string()
+            }
+        }
+    }
+}
 
 /// Write the match result to stdout and optionally to `GITHUB_OUTPUT` file
 pub fn write_output(
@@ -13,34 +59,695 @@ pub fn write_output(
 
     if let Some(name) = output_name {
         // GitHub Actions output mode: <name>=<result>
-        let output_line = format!("{name}={result}");
-        println!("{output_line}");
+        write_named_output(name, result, github_output_filepath)?;
+    } else {
+        // Plain output mode: just true/false
+        echo(result);
+    }
+
+    Ok(())
+}
+
+/// Write a `<name>=<value>` line to stdout and optionally to the `GITHUB_OUTPUT` file
+pub fn write_named_output(
+    name: &str,
+    value: &str,
+    github_output_filepath: Option<&str>,
+) -> Result<(), String> {
+    check_output_name(name)?;
+    check_line(name, value)?;
+    let output_line = format!("{name}={value}");
+    echo(&output_line);
 
-        // Write to GITHUB_OUTPUT file if path is set
-        if let Some(filepath) = github_output_filepath {
-            write_to_file(filepath, &output_line)?;
+    // Write to GITHUB_OUTPUT file if path is set
+    if let Some(filepath) = github_output_filepath {
+        write_to_file(filepath, &output_line)?;
+    }
+
+    Ok(())
+}
+
+/// A `--format` template: text with `{placeholder}` fields, and `{{`/`}}` for braces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Name,
+    Result,
+    Count,
+    Files,
+    Base,
+    Head,
+    Commit,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("Unclosed placeholder in format: {s}"))?;
+                    let field = match &rest[..end] {
+                        "name" => Field::Name,
+                        "result" => Field::Result,
+                        "count" => Field::Count,
+                        "files" => Field::Files,
+                        "base" => Field::Base,
+                        "head" => Field::Head,
+                        "commit" => Field::Commit,
+                        other => {
+                            return Err(format!(
+                                "Unknown placeholder {{{other}}} in format (expected name, result, count, files, base, head or commit)"
+                            ))
+                        }
+                    };
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                    segments.push(Segment::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("Unmatched }} in format: {s}")),
+                c => text.push(c),
+            }
+        }
+        segments.push(Segment::Text(text));
+        segments.retain(|segment| *segment != Segment::Text(String::new()));
+        Ok(Template(segments))
+    }
+}
+
+/// The values a template line is rendered from
+pub struct TemplateValues<'a> {
+    /// The `-g` name, empty without one
+    pub name: &'a str,
+    pub has_match: bool,
+    pub files: &'a [String],
+    /// How `{files}` joins the files
+    pub delimiter: ListDelimiter,
+    pub base: &'a str,
+    pub head: &'a str,
+    /// With `--last`, the newest matching commit; empty otherwise
+    pub commit: &'a str,
+}
+
+impl Template {
+    /// Whether the template shows the matching files or their number
+    pub fn lists_files(&self) -> bool {
+        self.0
+            .iter()
+            .any(|segment| matches!(segment, Segment::Field(Field::Count | Field::Files)))
+    }
+
+    /// Fill in the placeholders
+    pub fn render(&self, values: &TemplateValues) -> String {
+        let mut line = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Text(text) => line.push_str(text),
+                Segment::Field(Field::Name) => line.push_str(values.name),
+                Segment::Field(Field::Result) => line.push_str(&values.has_match.to_string()),
+                Segment::Field(Field::Count) => line.push_str(&values.files.len().to_string()),
+                Segment::Field(Field::Files) => line.push_str(&values.delimiter.join(values.files)),
+                Segment::Field(Field::Base) => line.push_str(values.base),
+                Segment::Field(Field::Head) => line.push_str(values.head),
+                Segment::Field(Field::Commit) => line.push_str(values.commit),
+            }
         }
+        line
+    }
+}
+
+static ECHO: AtomicBool = AtomicBool::new(true);
+
+/// Stop printing the outputs written to `GITHUB_OUTPUT` on stdout, which then only
+/// holds what the caller prints itself (`--format`)
+pub fn disable_echo() {
+    ECHO.store(false, Ordering::Relaxed);
+}
+
+fn echo(text: &str) {
+    if ECHO.load(Ordering::Relaxed) {
+        println!("{text}");
+    }
+}
+
+/// When to print the colored terminal summary instead of the plain result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// When stdout is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!(
+                "Invalid color mode: {s} (expected auto, always or never)"
+            )),
+        }
+    }
+}
+
+pub const GREEN: &str = "\x1b[1;32m";
+pub const RED: &str = "\x1b[1;31m";
+pub const YELLOW: &str = "\x1b[1;33m";
+pub const DIM: &str = "\x1b[2m";
+pub const RESET: &str = "\x1b[0m";
+
+/// What the terminal summary shows
+pub struct TerminalSummary<'a> {
+    pub has_match: bool,
+    pub files: &'a [String],
+    /// Files an inclusion pattern matched but an exclusion dropped
+    pub excluded: &'a [String],
+    /// Each pattern with the number of changed paths it matched
+    pub patterns: &'a [(&'a str, usize)],
+}
+
+/// Print the result in color to stdout, followed by the matching files, the excluded
+/// files dimmed and the patterns, highlighting those that matched a changed path
+pub fn write_terminal_summary(summary: &TerminalSummary) {
+    print!("{}", terminal_summary(summary));
+}
+
+fn terminal_summary(summary: &TerminalSummary) -> String {
+    let mut text = if summary.has_match {
+        format!("{GREEN}true{RESET}\n")
     } else {
-        // Plain output mode: just true/false
-        println!("{result}");
+        format!("{RED}false{RESET}\n")
+    };
+    for file in summary.files {
+        let line = format!("  {file}\n");
+        text.push_str(&line);
+    }
+    for file in summary.excluded {
+        let line = format!("  {DIM}{file} (excluded){RESET}\n");
+        text.push_str(&line);
+    }
+    let patterns: Vec<String> = summary
+        .patterns
+        .iter()
+        .map(|&(pattern, hits)| match hits {
+            0 => format!("{DIM}{pattern}{RESET}"),
+            _ => format!("{YELLOW}{pattern}{RESET} ({hits})"),
+        })
+        .collect();
+    let line = format!("Patterns: {}\n", patterns.join(", "));
+    text.push_str(&line);
+    text
+}
+
+/// Write related `<name>=<value>` lines to stdout and optionally to the `GITHUB_OUTPUT`
+/// file
+///
+/// The lines are appended to the file in a single write, so a step reading it never
+/// sees some of the keys without the others.
+pub fn write_named_outputs(
+    entries: &[(String, String)],
+    github_output_filepath: Option<&str>,
+) -> Result<(), String> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    for (name, _) in entries {
+        check_output_name(name)?;
+    }
+    check_single_line(entries)?;
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    let output = lines.join("\n");
+    echo(&output);
+
+    if let Some(filepath) = github_output_filepath {
+        write_to_file(filepath, &output)?;
     }
 
     Ok(())
 }
 
+/// Write a multiline `<name>` output, one value per line, to stdout and optionally to
+/// the `GITHUB_OUTPUT` file
+///
+/// Uses the `<name><<<delimiter>` heredoc syntax of GitHub Actions, with a random
+/// delimiter that no value contains, so values cannot end the output early or inject
+/// other outputs.
+pub fn write_multiline_output(
+    name: &str,
+    values: &[String],
+    github_output_filepath: Option<&str>,
+) -> Result<(), String> {
+    check_output_name(name)?;
+    if values.iter().any(|value| value.contains(['\n', '\r'])) {
+        return Err(format!(
+            "Cannot write {name} one value per line: a value contains a line break (a file list can use --list-delimiter json)"
+        ));
+    }
+    let delimiter = heredoc_delimiter(values);
+    let mut output = format!("{name}<<{delimiter}\n");
+    for value in values {
+        output.push_str(value);
+        output.push('\n');
+    }
+    output.push_str(&delimiter);
+    echo(&output);
+
+    if let Some(filepath) = github_output_filepath {
+        write_to_file(filepath, &output)?;
+    }
+
+    Ok(())
+}
+
+/// Reject values that would end their line early and start another output
+fn check_single_line(entries: &[(String, String)]) -> Result<(), String> {
+    entries
+        .iter()
+        .try_for_each(|(name, value)| check_line(name, value))
+}
+
+fn check_line(name: &str, value: &str) -> Result<(), String> {
+    if value.contains(['\n', '\r']) {
+        return Err(format!(
+            "Cannot write {name} on one line: its value contains a line break (a file list can use --list-delimiter json)"
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a character may appear in a GitHub Actions output name
+pub fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Reject output names GitHub Actions cannot read back (`[A-Za-z_][A-Za-z0-9_-]*`),
+/// such as names containing `=` or `<<` that would change the meaning of the line
+pub fn check_output_name(name: &str) -> Result<(), String> {
+    if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(is_name_char)
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid output name '{name}': it must start with a letter or '_' and contain only letters, digits, '_' and '-'"
+        ))
+    }
+}
+
+/// Generate a random heredoc delimiter that does not occur in any value
+fn heredoc_delimiter(values: &[String]) -> String {
+    loop {
+        // Each RandomState is seeded with fresh random keys
+        let random = || RandomState::new().build_hasher().finish();
+        let delimiter = format!("ghadelimiter_{:016x}{:016x}", random(), random());
+        if !values.iter().any(|value| value.contains(&delimiter)) {
+            return delimiter;
+        }
+    }
+}
+
+/// Number of matching files listed per filter in summaries and annotations
+const SAMPLE_SIZE: usize = 5;
+
+/// The first few of `files`, followed by a count of the rest
+pub fn sample(files: &[String]) -> Vec<String> {
+    let mut sample = files[..files.len().min(SAMPLE_SIZE)].to_vec();
+    if files.len() > SAMPLE_SIZE {
+        sample.push(format!("and {} more", files.len() - SAMPLE_SIZE));
+    }
+    sample
+}
+
+/// One filter's result, as shown in the step summary
+#[derive(Debug)]
+pub struct SummaryRow<'a> {
+    /// Output name or patterns identifying the filter
+    pub filter: &'a str,
+    pub has_match: bool,
+    pub files: &'a [String],
+}
+
+/// Append a Markdown table of filter results to the `GITHUB_STEP_SUMMARY` file
+pub fn write_step_summary(rows: &[SummaryRow], filepath: &str) -> Result<(), String> {
+    write_to_file(filepath, &step_summary(rows))
+}
+
+/// Render the step summary table, listing a sample of each filter's matching files
+fn step_summary(rows: &[SummaryRow]) -> String {
+    let mut table = String::from(
+        "### Changed files\n\n| Filter | Result | Files | Matching files |\n| --- | --- | --- | --- |\n",
+    );
+    for row in rows {
+        let files: Vec<String> = row.files.iter().map(|file| code_span(file)).collect();
+        let line = format!(
+            "| {} | {} | {} | {} |\n",
+            code_span(row.filter),
+            if row.has_match {
+                "✅ true"
+            } else {
+                "➖ false"
+            },
+            row.files.len(),
+            sample(&files).join("<br>"),
+        );
+        table.push_str(&line);
+    }
+    table
+}
+
+/// Most data items Bitbucket Code Insights accepts in one report
+const CODE_INSIGHTS_DATA_LIMIT: usize = 10;
+
+/// Write a Bitbucket Code Insights report of filter results to `filepath`, ready to be
+/// sent to the reports API
+///
+/// Each filter contributes its result and matching file count; filters past the
+/// service's data item limit are only counted in the details.
+pub fn write_code_insights_report(rows: &[SummaryRow], filepath: &str) -> Result<(), String> {
+    let content = format!("{}\n", code_insights_report(rows));
+    fs::write(filepath, &content).map_err(|e| format!("Failed to write to {filepath}: {e}"))?;
+    trace_written(filepath, content.len(), false);
+    Ok(())
+}
+
+fn code_insights_report(rows: &[SummaryRow]) -> Value {
+    let item = |title: String, kind: &str, value: Value| {
+        Value::Object(vec![
+            ("title".to_string(), Value::String(title)),
+            ("type".to_string(), Value::String(kind.to_string())),
+            ("value".to_string(), value),
+        ])
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let data = rows
+        .iter()
+        .flat_map(|row| {
+            [
+                item(
+                    row.filter.to_string(),
+                    "BOOLEAN",
+                    Value::Bool(row.has_match),
+                ),
+                item(
+                    format!("{} files", row.filter),
+                    "NUMBER",
+                    Value::Number(row.files.len() as f64),
+                ),
+            ]
+        })
+        .take(CODE_INSIGHTS_DATA_LIMIT)
+        .collect();
+    let matched = rows.iter().filter(|row| row.has_match).count();
+    let details = format!("{matched} of {} filter(s) matched", rows.len());
+    Value::Object(vec![
+        (
+            "title".to_string(),
+            Value::String("Changed files".to_string()),
+        ),
+        ("details".to_string(), Value::String(details)),
+        (
+            "report_type".to_string(),
+            Value::String("BUILD".to_string()),
+        ),
+        ("reporter".to_string(), Value::String("gdf".to_string())),
+        ("data".to_string(), Value::Array(data)),
+    ])
+}
+
+/// Format text as an inline code span that is safe inside a table cell
+pub fn code_span(text: &str) -> String {
+    inline_code(&text.replace('|', "\\|"))
+}
+
+/// Format text as an inline code span, fencing it with double backticks when it
+/// contains one
+pub fn inline_code(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {text} ``")
+    } else {
+        format!("`{text}`")
+    }
+}
+
+/// Severity of a workflow annotation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Annotation {
+    Notice,
+    Warning,
+}
+
+/// Write a GitHub Actions `::notice` or `::warning` workflow command to stderr, keeping
+/// stdout for the result; the runner picks up commands from both streams
+pub fn write_annotation(kind: Annotation, title: &str, message: &str) {
+    eprintln!("{}", annotation(kind, title, message));
+}
+
+/// Format a workflow command, escaping the title and message
+fn annotation(kind: Annotation, title: &str, message: &str) -> String {
+    let command = match kind {
+        Annotation::Notice => "notice",
+        Annotation::Warning => "warning",
+    };
+    let title = escape_command_data(title)
+        .replace(':', "%3A")
+        .replace(',', "%2C");
+    format!(
+        "::{command} title={title}::{}",
+        escape_command_data(message)
+    )
+}
+
+/// Escape the characters that would end or corrupt a workflow command
+fn escape_command_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Append `KEY=value` lines to a dotenv file, as read by GitLab's
+/// `artifacts:reports:dotenv`
+///
+/// GitLab variable names cannot contain `-`, so it is replaced with `_`.
+pub fn write_dotenv(entries: &[(String, String)], filepath: &str) -> Result<(), String> {
+    check_single_line(entries)?;
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| format!("{}={value}", key.replace('-', "_")))
+        .collect();
+    write_to_file(filepath, &lines.join("\n"))
+}
+
+/// Append `export key='value'` lines to a shell script, to be sourced by later steps
+/// (e.g. in Bitbucket Pipelines, where steps share files but not variables)
+///
+/// Shell variable names cannot contain `-`, so it is replaced with `_`.
+pub fn write_env_file(entries: &[(String, String)], filepath: &str) -> Result<(), String> {
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "export {}='{}'",
+                key.replace('-', "_"),
+                value.replace('\'', "'\\''")
+            )
+        })
+        .collect();
+    write_to_file(filepath, &lines.join("\n"))
+}
+
+/// Write a list of paths to a file, replacing it, each ended by a newline or, with
+/// `nul`, a NUL byte for paths that contain newlines (`xargs -0`)
+pub fn write_file_list(files: &[String], nul: bool, filepath: &str) -> Result<(), String> {
+    log!(
+        Debug,
+        "output",
+        "Writing {} paths to {filepath}",
+        files.len()
+    );
+    let terminator = if nul { '\0' } else { '\n' };
+    let mut content = String::new();
+    for file in files {
+        content.push_str(file);
+        content.push(terminator);
+    }
+    fs::write(filepath, &content).map_err(|e| format!("Failed to write {filepath}: {e}"))?;
+    trace_written(filepath, content.len(), false);
+    Ok(())
+}
+
+/// Append `key=value` lines to a Java properties file, as read by Jenkins'
+/// `readProperties` and `EnvInject`
+pub fn write_properties(entries: &[(String, String)], filepath: &str) -> Result<(), String> {
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                escape_property(key, true),
+                escape_property(value, false)
+            )
+        })
+        .collect();
+    write_to_file(filepath, &lines.join("\n"))
+}
+
+/// Escape text as `java.util.Properties::store` does: separators, comment markers and
+/// control characters are backslash-escaped and non-ASCII characters written as
+/// `\uXXXX`, since properties files are read as ISO-8859-1
+fn escape_property(text: &str, is_key: bool) -> String {
+    use std::fmt::Write as _;
+
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            ' ' if is_key || i == 0 => escaped.push_str("\\ "),
+            '\\' | '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{c}' => escaped.push_str("\\f"),
+            ' '..='~' => escaped.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    let _ = write!(escaped, "\\u{unit:04X}");
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// Merge boolean pipeline parameters into the JSON object in `filepath`, as passed to
+/// CircleCI's continuation API
+///
+/// Earlier parameters in the file are kept, so several runs can fill one document; a
+/// parameter set again is overwritten in place.
+pub fn write_json_parameters(parameters: &[(String, bool)], filepath: &str) -> Result<(), String> {
+    let existing = match fs::read_to_string(filepath) {
+        Ok(content) if !content.trim().is_empty() => Some(content),
+        Ok(_) => None,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read {filepath}: {e}")),
+    };
+    let mut members = match existing.as_deref().map(json::parse).transpose()? {
+        Some(Value::Object(members)) => members,
+        Some(_) => return Err(format!("{filepath} does not hold a JSON object")),
+        None => Vec::new(),
+    };
+    for (name, value) in parameters {
+        match members.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = Value::Bool(*value),
+            None => members.push((name.clone(), Value::Bool(*value))),
+        }
+    }
+    let content = format!("{}\n", Value::Object(members));
+    fs::write(filepath, &content).map_err(|e| format!("Failed to write to {filepath}: {e}"))?;
+    trace_written(filepath, content.len(), false);
+    Ok(())
+}
+
+/// Print a `##teamcity[setParameter]` service message to stdout for each parameter
+pub fn write_teamcity_parameters(parameters: &[(String, String)]) {
+    for (name, value) in parameters {
+        println!("{}", teamcity_parameter(name, value));
+    }
+}
+
+/// Format a `setParameter` service message, escaping its attribute values
+fn teamcity_parameter(name: &str, value: &str) -> String {
+    let escape = |text: &str| {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '|' | '\'' | '[' | ']' => {
+                    escaped.push('|');
+                    escaped.push(c);
+                }
+                '\n' => escaped.push_str("|n"),
+                '\r' => escaped.push_str("|r"),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    };
+    format!(
+        "##teamcity[setParameter name='{}' value='{}']",
+        escape(name),
+        escape(value)
+    )
+}
+
 /// Append a line to a file (used for `GITHUB_OUTPUT`)
+///
+/// The content and its newline go out in one write, so appends from concurrent
+/// steps sharing the file don't interleave.
 fn write_to_file(filepath: &str, content: &str) -> Result<(), String> {
+    log!(
+        Debug,
+        "output",
+        "Appending {} bytes to {filepath}",
+        content.len() + 1
+    );
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
         .open(filepath)
         .map_err(|e| format!("Failed to open {filepath}: {e}"))?;
 
-    writeln!(file, "{content}").map_err(|e| format!("Failed to write to {filepath}: {e}"))?;
+    file.write_all(format!("{content}\n").as_bytes())
+        .map_err(|e| format!("Failed to write to {filepath}: {e}"))?;
+    trace_written(filepath, content.len() + 1, true);
 
     Ok(())
 }
 
+/// Record a file write with `--trace-json`
+#[allow(clippy::cast_precision_loss)]
+pub fn trace_written(filepath: &str, bytes: usize, append: bool) {
+    log::event("output_written", || {
+        vec![
+            ("path", Value::String(filepath.to_string())),
+            ("bytes", Value::Number(bytes as f64)),
+            ("append", Value::Bool(append)),
+        ]
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,4 +864,437 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Failed to open"));
     }
+
+    #[test]
+    fn test_write_named_outputs() {
+        let path = temp_file_path("named_outputs");
+        cleanup(&path);
+
+        let entries = vec![
+            ("api_count".to_string(), "2".to_string()),
+            ("api".to_string(), "true".to_string()),
+        ];
+        write_named_outputs(&entries, Some(path.to_str().unwrap())).unwrap();
+        write_named_outputs(&[], Some(path.to_str().unwrap())).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "api_count=2\napi=true\n");
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_write_named_outputs_rejects_line_breaks() {
+        let path = temp_file_path("named_outputs_line_break");
+        cleanup(&path);
+
+        let entries = vec![("api_files".to_string(), "a\napi=false".to_string())];
+        assert_eq!(
+            write_named_outputs(&entries, Some(path.to_str().unwrap())),
+            Err("Cannot write api_files on one line: its value contains a line break (a file list can use --list-delimiter json)".to_string())
+        );
+        assert!(write_dotenv(&entries, path.to_str().unwrap()).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_write_named_output_rejects_line_breaks() {
+        let path = temp_file_path("named_output_line_break");
+        cleanup(&path);
+
+        assert!(
+            write_named_output("api", "true\r\nweb=true", Some(path.to_str().unwrap())).is_err()
+        );
+        let files = vec!["src/a\nb.rs".to_string()];
+        assert_eq!(
+            write_multiline_output("api_files", &files, Some(path.to_str().unwrap())),
+            Err("Cannot write api_files one value per line: a value contains a line break (a file list can use --list-delimiter json)".to_string())
+        );
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_check_output_name() {
+        assert_eq!(check_output_name("api"), Ok(()));
+        assert_eq!(check_output_name("_web-app_2"), Ok(()));
+        assert_eq!(
+            check_output_name("2fa"),
+            Err("Invalid output name '2fa': it must start with a letter or '_' and contain only letters, digits, '_' and '-'".to_string())
+        );
+        assert!(check_output_name("").is_err());
+        assert!(check_output_name("-api").is_err());
+        assert!(check_output_name("api=x").is_err());
+        assert!(check_output_name("api<<EOF").is_err());
+        assert!(check_output_name("api files").is_err());
+
+        let path = temp_file_path("invalid_output_name");
+        cleanup(&path);
+        let filepath = Some(path.to_str().unwrap());
+        assert!(write_named_output("a=b", "true", filepath).is_err());
+        let entries = vec![
+            ("api".to_string(), "true".to_string()),
+            ("web app".to_string(), "true".to_string()),
+        ];
+        assert!(write_named_outputs(&entries, filepath).is_err());
+        assert!(write_multiline_output("files<<X", &[], filepath).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_list_delimiter() {
+        let files = vec!["src/a b.rs".to_string(), "docs/\"q\".md".to_string()];
+        assert_eq!(
+            "space".parse::<ListDelimiter>().unwrap().join(&files),
+            "src/a b.rs docs/\"q\".md"
+        );
+        assert_eq!(
+            ListDelimiter::default().join(&files),
+            "src/a b.rs,docs/\"q\".md"
+        );
+        assert_eq!(
+            ListDelimiter::Json.join(&files),
+            r#"["src/a b.rs","docs/\"q\".md"]"#
+        );
+        assert_eq!(ListDelimiter::Json.join(&[]), "[]");
+        assert!("tab".parse::<ListDelimiter>().is_err());
+    }
+
+    #[test]
+    fn test_terminal_summary() {
+        let files = vec!["src/main.rs".to_string()];
+        let excluded = vec!["src/README.md".to_string()];
+        let summary = TerminalSummary {
+            has_match: true,
+            files: &files,
+            excluded: &excluded,
+            patterns: &[("src/**", 2), ("!**/*.md", 1), ("web/**", 0)],
+        };
+        assert_eq!(
+            terminal_summary(&summary),
+            "\x1b[1;32mtrue\x1b[0m\n  src/main.rs\n  \x1b[2msrc/README.md (excluded)\x1b[0m\nPatterns: \x1b[1;33msrc/**\x1b[0m (2), \x1b[1;33m!**/*.md\x1b[0m (1), \x1b[2mweb/**\x1b[0m\n"
+        );
+
+        let summary = TerminalSummary {
+            has_match: false,
+            files: &[],
+            excluded: &[],
+            patterns: &[("web/**", 0)],
+        };
+        assert_eq!(
+            terminal_summary(&summary),
+            "\x1b[1;31mfalse\x1b[0m\nPatterns: \x1b[2mweb/**\x1b[0m\n"
+        );
+        assert_eq!("never".parse(), Ok(ColorMode::Never));
+        assert!("yes".parse::<ColorMode>().is_err());
+    }
+
+    #[test]
+    fn test_template() {
+        let template: Template =
+            "{name}: {result} ({count} files: {files}) {{{base}..{head}}}{commit}"
+                .parse()
+                .unwrap();
+        let files = vec!["a.rs".to_string(), "b.rs".to_string()];
+        let values = TemplateValues {
+            name: "api",
+            has_match: true,
+            files: &files,
+            delimiter: ListDelimiter::Comma,
+            base: "main",
+            head: "HEAD",
+            commit: "",
+        };
+        assert_eq!(
+            template.render(&values),
+            "api: true (2 files: a.rs,b.rs) {main..HEAD}"
+        );
+        assert!(template.lists_files());
+        assert!(!"{result}".parse::<Template>().unwrap().lists_files());
+        assert_eq!("".parse::<Template>().unwrap().render(&values), "");
+    }
+
+    #[test]
+    fn test_template_errors() {
+        assert_eq!(
+            "{nme}".parse::<Template>(),
+            Err("Unknown placeholder {nme} in format (expected name, result, count, files, base, head or commit)".to_string())
+        );
+        assert_eq!(
+            "{result".parse::<Template>(),
+            Err("Unclosed placeholder in format: {result".to_string())
+        );
+        assert_eq!(
+            "a}b".parse::<Template>(),
+            Err("Unmatched } in format: a}b".to_string())
+        );
+    }
+
+    #[test]
+    fn test_write_multiline_output() {
+        let path = temp_file_path("multiline");
+        cleanup(&path);
+
+        let files = vec!["src/main.rs".to_string(), "docs/a b.md".to_string()];
+        write_multiline_output("api_files", &files, Some(path.to_str().unwrap())).unwrap();
+        write_output(true, Some("api"), Some(path.to_str().unwrap())).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        let delimiter = lines[0].strip_prefix("api_files<<").unwrap();
+        assert!(delimiter.starts_with("ghadelimiter_"));
+        assert_eq!(
+            lines[1..],
+            ["src/main.rs", "docs/a b.md", delimiter, "api=true"]
+        );
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_write_multiline_output_empty() {
+        let path = temp_file_path("multiline_empty");
+        cleanup(&path);
+
+        write_multiline_output("files", &[], Some(path.to_str().unwrap())).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], format!("files<<{}", lines[1]));
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_heredoc_delimiter_is_random() {
+        assert_ne!(heredoc_delimiter(&[]), heredoc_delimiter(&[]));
+    }
+
+    #[test]
+    fn test_step_summary() {
+        let api_files: Vec<String> = (1..=7).map(|i| format!("api/{i}.go")).collect();
+        let rows = [
+            SummaryRow {
+                filter: "api",
+                has_match: true,
+                files: &api_files,
+            },
+            SummaryRow {
+                filter: "docs/**, !a|b",
+                has_match: false,
+                files: &[],
+            },
+        ];
+        assert_eq!(
+            step_summary(&rows),
+            "### Changed files\n\n\
+             | Filter | Result | Files | Matching files |\n\
+             | --- | --- | --- | --- |\n\
+             | `api` | ✅ true | 7 | `api/1.go`<br>`api/2.go`<br>`api/3.go`<br>`api/4.go`<br>`api/5.go`<br>and 2 more |\n\
+             | `docs/**, !a\\|b` | ➖ false | 0 |  |\n"
+        );
+    }
+
+    #[test]
+    fn test_code_span_with_backtick() {
+        assert_eq!(code_span("a`b"), "`` a`b ``");
+    }
+
+    #[test]
+    fn test_write_step_summary_appends() {
+        let path = temp_file_path("step_summary");
+        cleanup(&path);
+        fs::write(&path, "earlier step\n").unwrap();
+
+        let rows = [SummaryRow {
+            filter: "api",
+            has_match: false,
+            files: &[],
+        }];
+        write_step_summary(&rows, path.to_str().unwrap()).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("earlier step\n### Changed files\n"));
+        assert!(content.ends_with("| `api` | ➖ false | 0 |  |\n\n"));
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_sample() {
+        let files: Vec<String> = (1..=6).map(|i| i.to_string()).collect();
+        assert_eq!(sample(&files), ["1", "2", "3", "4", "5", "and 1 more"]);
+        assert_eq!(sample(&files[..2]), ["1", "2"]);
+        assert!(sample(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_annotation() {
+        assert_eq!(
+            annotation(Annotation::Notice, "api", "Matched 1 changed file(s): a.go"),
+            "::notice title=api::Matched 1 changed file(s): a.go"
+        );
+        assert_eq!(
+            annotation(Annotation::Warning, "a:b,c", "100% off\nnext"),
+            "::warning title=a%3Ab%2Cc::100%25 off%0Anext"
+        );
+    }
+
+    #[test]
+    fn test_write_dotenv() {
+        let path = temp_file_path("dotenv");
+        cleanup(&path);
+
+        let entries = [
+            ("web-api_commit".to_string(), "abc123".to_string()),
+            ("web-api".to_string(), "true".to_string()),
+        ];
+        write_dotenv(&entries, path.to_str().unwrap()).unwrap();
+        write_dotenv(
+            &[("docs".to_string(), "false".to_string())],
+            path.to_str().unwrap(),
+        )
+        .unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "web_api_commit=abc123\nweb_api=true\ndocs=false\n");
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_teamcity_parameter() {
+        assert_eq!(
+            teamcity_parameter("api_count", "3"),
+            "##teamcity[setParameter name='api_count' value='3']"
+        );
+        assert_eq!(
+            teamcity_parameter("it's", "[a|b]\n"),
+            "##teamcity[setParameter name='it|'s' value='|[a||b|]|n']"
+        );
+    }
+
+    #[test]
+    fn test_write_file_list() {
+        let path = temp_file_path("file_list");
+        fs::write(&path, "stale\n").unwrap();
+        let files = vec!["src/main.rs".to_string(), "docs/a\nb.md".to_string()];
+
+        write_file_list(&files, false, path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "src/main.rs\ndocs/a\nb.md\n"
+        );
+
+        write_file_list(&files, true, path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "src/main.rs\0docs/a\nb.md\0"
+        );
+
+        write_file_list(&[], false, path.to_str().unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_write_properties() {
+        let path = temp_file_path("properties");
+        cleanup(&path);
+
+        let entries = [
+            (
+                "api_files".to_string(),
+                "src/a b.rs,docs/x=y.md".to_string(),
+            ),
+            ("api".to_string(), "true".to_string()),
+        ];
+        write_properties(&entries, path.to_str().unwrap()).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "api_files=src/a b.rs,docs/x\\=y.md\napi=true\n");
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_escape_property() {
+        assert_eq!(escape_property("a key", true), "a\\ key");
+        assert_eq!(escape_property(" lead trail ", false), "\\ lead trail ");
+        assert_eq!(escape_property("C:\\#!\n", false), "C\\:\\\\\\#\\!\\n");
+        assert_eq!(escape_property("café😀", false), "caf\\u00E9\\uD83D\\uDE00");
+    }
+
+    #[test]
+    fn test_write_json_parameters_merges() {
+        let path = temp_file_path("circleci_parameters");
+        cleanup(&path);
+        let filepath = path.to_str().unwrap();
+
+        write_json_parameters(&[("api".to_string(), true)], filepath).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"api\":true}\n");
+
+        let parameters = [("docs".to_string(), false), ("api".to_string(), false)];
+        write_json_parameters(&parameters, filepath).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"api\":false,\"docs\":false}\n"
+        );
+
+        fs::write(&path, "[]").unwrap();
+        assert_eq!(
+            write_json_parameters(&parameters, filepath),
+            Err(format!("{filepath} does not hold a JSON object"))
+        );
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_write_env_file() {
+        let path = temp_file_path("env_file");
+        cleanup(&path);
+
+        let entries = [
+            ("web-api_files".to_string(), "it's.md,a b.rs".to_string()),
+            ("web-api".to_string(), "true".to_string()),
+        ];
+        write_env_file(&entries, path.to_str().unwrap()).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            "export web_api_files='it'\\''s.md,a b.rs'\nexport web_api='true'\n"
+        );
+
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_code_insights_report() {
+        let files = vec!["a.rs".to_string(), "b.rs".to_string()];
+        let rows: Vec<SummaryRow> = (0..6)
+            .map(|i| SummaryRow {
+                filter: ["api", "docs", "web", "core", "cli", "extra"][i],
+                has_match: i == 0,
+                files: if i == 0 { &files } else { &[] },
+            })
+            .collect();
+        let report = code_insights_report(&rows);
+        assert_eq!(
+            report.get("details").and_then(Value::as_str),
+            Some("1 of 6 filter(s) matched")
+        );
+        let Some(Value::Array(data)) = report.get("data") else {
+            panic!("data is not an array");
+        };
+        assert_eq!(data.len(), CODE_INSIGHTS_DATA_LIMIT);
+        assert_eq!(
+            data[0].to_string(),
+            r#"{"title":"api","type":"BOOLEAN","value":true}"#
+        );
+        assert_eq!(
+            data[1].to_string(),
+            r#"{"title":"api files","type":"NUMBER","value":2}"#
+        );
+    }
 }
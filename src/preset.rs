@@ -0,0 +1,117 @@
+//! `--preset`: built-in filters for checks nearly every team writes for itself, with
+//! definitions maintained here instead of copied from workflow to workflow.
+
+use crate::config::FilterGroup;
+use std::str::FromStr;
+
+/// A built-in filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Whether the change touches documentation only, so CI can skip builds and tests
+    DocsOnly,
+}
+
+/// Documentation files: prose, the images of docs directories and the configuration of
+/// documentation site generators
+const DOCS_PATTERNS: &[&str] = &[
+    "**/*.md",
+    "**/*.mdx",
+    "**/*.markdown",
+    "**/*.rst",
+    "**/*.adoc",
+    "**/*.asciidoc",
+    "**/docs/**/*.png",
+    "**/docs/**/*.jpg",
+    "**/docs/**/*.jpeg",
+    "**/docs/**/*.gif",
+    "**/docs/**/*.svg",
+    "**/docs/**/*.webp",
+    "**/mkdocs.yml",
+    "**/mkdocs.yaml",
+    "**/docusaurus.config.js",
+    "**/docusaurus.config.ts",
+    "**/sidebars.js",
+    "**/sidebars.ts",
+    "**/.readthedocs.yml",
+    "**/.readthedocs.yaml",
+    "**/LICENSE",
+    "**/LICENSE.txt",
+];
+
+impl Preset {
+    /// The filter the preset adds, named after its output
+    ///
+    /// `docs_only` matches only when every changed file is documentation, and not when
+    /// nothing changed.
+    #[must_use]
+    pub fn group(self) -> FilterGroup {
+        match self {
+            Preset::DocsOnly => FilterGroup {
+                name: Some("docs_only".to_string()),
+                patterns: DOCS_PATTERNS.iter().map(|s| (*s).to_string()).collect(),
+                min_fraction: Some(1.0),
+                preset: Some(self),
+                ..FilterGroup::default()
+            },
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "docs-only" => Ok(Preset::DocsOnly),
+            _ => Err(format!("Invalid preset: {s} (expected docs-only)")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::{Anchoring, PatternSet};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("docs-only".parse::<Preset>(), Ok(Preset::DocsOnly));
+        assert_eq!(
+            "docs".parse::<Preset>(),
+            Err("Invalid preset: docs (expected docs-only)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_docs_only_patterns() {
+        let group = Preset::DocsOnly.group();
+        assert_eq!(group.name.as_deref(), Some("docs_only"));
+        assert_eq!(group.min_fraction, Some(1.0));
+        for anchoring in [
+            Anchoring::Prefix,
+            Anchoring::Anchored,
+            Anchoring::Unanchored,
+        ] {
+            let patterns = PatternSet::new(&group.patterns, anchoring).unwrap();
+            for path in [
+                "README.md",
+                "docs/guide/install.mdx",
+                "docs/img/flow.svg",
+                "website/docs/shots/home.png",
+                "mkdocs.yml",
+                "website/docusaurus.config.ts",
+                "LICENSE",
+            ] {
+                assert!(patterns.is_match(path).unwrap(), "{path} ({anchoring:?})");
+            }
+            for path in [
+                "src/main.rs",
+                "assets/logo.png",
+                "docs/conf.py",
+                "package.json",
+            ] {
+                assert!(!patterns.is_match(path).unwrap(), "{path} ({anchoring:?})");
+            }
+        }
+    }
+}
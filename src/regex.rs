@@ -0,0 +1,691 @@
+//! Minimal regular expression engine for matching changed lines, simulating every match
+//! attempt at once so that long lines and nested quantifiers take linear time.
+//!
+//! Supported syntax: literals, `.`, character classes (`[abc]`, `[^a-z]`), shorthand classes
+//! (`\d`, `\w`, `\s` and their negations `\D`, `\W`, `\S`), anchors (`^`, `$`), word
+//! boundaries (`\b`), groups (`(...)`, `(?:...)`), alternation (`|`) and greedy or lazy
+//! quantifiers (`*`, `+`, `?`, `{n}`, `{n,}`, `{n,m}`). A `{` that does not start a valid
+//! quantifier is a literal. Matching is unanchored unless the pattern uses `^` or `$`.
+//!
+//! Counted repetitions are expanded into copies of what they repeat, so counts are capped
+//! at [`MAX_REPEAT`] and compiled regexes at [`MAX_PROGRAM_LEN`] steps.
+
+/// Largest count a `{n}`, `{n,}` or `{n,m}` quantifier may give
+const MAX_REPEAT: usize = 1000;
+
+/// Largest number of steps a regex may compile to
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+/// A compiled regular expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regex {
+    source: String,
+    program: Vec<Inst>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClassItem {
+    Range(char, char),
+    /// One of `d`, `w`, `s`, `D`, `W`, `S`
+    Shorthand(char),
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => (lo..=hi).contains(&c),
+            ClassItem::Shorthand(kind) => shorthand_matches(kind, c),
+        });
+        found != self.negated
+    }
+}
+
+fn shorthand_matches(kind: char, c: char) -> bool {
+    let matched = match kind.to_ascii_lowercase() {
+        'd' => c.is_ascii_digit(),
+        'w' => is_word_char(c),
+        _ => c.is_whitespace(),
+    };
+    matched != kind.is_ascii_uppercase()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Regex {
+    /// Compile a regular expression
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let node = parser
+            .parse_alt()
+            .and_then(|node| match parser.peek() {
+                None => Ok(node),
+                Some(_) => Err("unmatched ')'".to_string()),
+            })
+            .and_then(|node| match program_len(&node) {
+                len if len > MAX_PROGRAM_LEN => Err(format!(
+                    "pattern is too large once repetitions are expanded (over {MAX_PROGRAM_LEN} steps)"
+                )),
+                _ => Ok(node),
+            })
+            .map_err(|reason| format!("Invalid regex '{pattern}': {reason}"))?;
+        Ok(Regex {
+            source: pattern.to_string(),
+            program: compile(&node),
+        })
+    }
+
+    /// The pattern this regex was compiled from
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Check whether the regex matches anywhere in `text`
+    ///
+    /// Every match attempt advances together, one character at a time, so the time taken
+    /// grows with the length of the pattern times that of the text, whatever the pattern.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for pos in 0..=chars.len() {
+            // A new attempt starts at every position
+            if self.add_thread(&mut current, 0, &chars, pos) {
+                return true;
+            }
+            let Some(&c) = chars.get(pos) else {
+                break;
+            };
+            next.clear();
+            for &pc in &current.pcs {
+                let advances = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.matches(c),
+                    _ => false,
+                };
+                if advances && self.add_thread(&mut next, pc + 1, &chars, pos + 1) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Add the thread at `pc` to `threads`, following jumps and assertions at `pos`;
+    /// returns whether it reaches a match
+    fn add_thread(&self, threads: &mut Threads, pc: usize, text: &[char], pos: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if !threads.insert(pc) {
+                continue;
+            }
+            match &self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(*to),
+                Inst::Split(first, second) => stack.extend([*second, *first]),
+                Inst::Assert(assertion) => {
+                    if assertion.holds(text, pos) {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::Char(_) | Inst::Any | Inst::Class(_) => {}
+            }
+        }
+        false
+    }
+}
+
+/// One step of a compiled regex
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    /// Continue at both targets
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+}
+
+impl Assertion {
+    fn holds(self, text: &[char], pos: usize) -> bool {
+        match self {
+            Assertion::Start => pos == 0,
+            Assertion::End => pos == text.len(),
+            Assertion::WordBoundary => {
+                let before = pos > 0 && is_word_char(text[pos - 1]);
+                let after = text.get(pos).is_some_and(|&c| is_word_char(c));
+                before != after
+            }
+        }
+    }
+}
+
+/// The instructions the threads at one position are at, each once
+struct Threads {
+    pcs: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Threads {
+            pcs: Vec::new(),
+            seen: vec![false; len],
+        }
+    }
+
+    /// Add `pc`, returning whether it was not there yet
+    fn insert(&mut self, pc: usize) -> bool {
+        if self.seen[pc] {
+            return false;
+        }
+        self.seen[pc] = true;
+        self.pcs.push(pc);
+        true
+    }
+
+    fn clear(&mut self) {
+        for &pc in &self.pcs {
+            self.seen[pc] = false;
+        }
+        self.pcs.clear();
+    }
+}
+
+/// The number of steps [`compile`] turns `node` into, without the final match, saturating
+/// instead of overflowing
+fn program_len(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Concat(nodes) => nodes.iter().map(program_len).fold(0, usize::saturating_add),
+        // A split and a jump around every alternative but the last
+        Node::Alt(alts) => alts
+            .iter()
+            .map(program_len)
+            .fold(alts.len().saturating_sub(1) * 2, usize::saturating_add),
+        Node::Repeat { node, min, max, .. } => {
+            let len = program_len(node);
+            let optional = match max {
+                // A split, the repeated steps and a jump back
+                None => len.saturating_add(2),
+                // A split and the repeated steps for each optional copy
+                Some(max) => (max - min).saturating_mul(len.saturating_add(1)),
+            };
+            min.saturating_mul(len).saturating_add(optional)
+        }
+        _ => 1,
+    }
+}
+
+/// Compile `node` into a program ending with [`Inst::Match`]
+fn compile(node: &Node) -> Vec<Inst> {
+    let mut program = Vec::new();
+    emit(node, &mut program);
+    program.push(Inst::Match);
+    program
+}
+
+fn emit(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Assert(Assertion::Start)),
+        Node::End => program.push(Inst::Assert(Assertion::End)),
+        Node::WordBoundary => program.push(Inst::Assert(Assertion::WordBoundary)),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                emit(node, program);
+            }
+        }
+        Node::Alt(alts) => {
+            let mut jumps = Vec::new();
+            for (index, alt) in alts.iter().enumerate() {
+                if index + 1 == alts.len() {
+                    emit(alt, program);
+                    break;
+                }
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                emit(alt, program);
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        // Only whether the regex matches is needed, so greedy and lazy repetitions are
+        // compiled alike
+        Node::Repeat { node, min, max, .. } => {
+            for _ in 0..*min {
+                emit(node, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    emit(node, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    for _ in *min..*max {
+                        let split = program.len();
+                        program.push(Inst::Split(split + 1, 0));
+                        emit(node, program);
+                        program[split] = Inst::Split(split + 1, program.len());
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, String> {
+        let mut alts = vec![self.parse_concat()?];
+        while self.eat('|') {
+            alts.push(self.parse_concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.remove(0)
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.remove(0),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("unexpected end of pattern")?;
+        match c {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("unsupported group syntax '(?'".to_string());
+                }
+                let node = self.parse_alt()?;
+                if !self.eat(')') {
+                    return Err("unclosed group".to_string());
+                }
+                Ok(node)
+            }
+            '[' => self.parse_class().map(Node::Class),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '\\' => self.parse_escape(),
+            '*' | '+' | '?' => Err(format!("quantifier '{c}' has nothing to repeat")),
+            _ => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("trailing backslash")?;
+        Ok(match c {
+            'd' | 'w' | 's' | 'D' | 'W' | 'S' => Node::Class(Class {
+                negated: false,
+                items: vec![ClassItem::Shorthand(c)],
+            }),
+            'b' => Node::WordBoundary,
+            _ => Node::Char(escaped_char(c)),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or("unclosed character class")?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                let escaped = self.next().ok_or("trailing backslash")?;
+                if "dwsDWS".contains(escaped) {
+                    items.push(ClassItem::Shorthand(escaped));
+                    continue;
+                }
+                escaped_char(escaped)
+            } else {
+                c
+            };
+            let is_range = self.peek() == Some('-')
+                && self
+                    .chars
+                    .get(self.pos + 1)
+                    .is_some_and(|&next| next != ']');
+            if !is_range {
+                items.push(ClassItem::Range(lo, lo));
+                continue;
+            }
+            self.pos += 1;
+            let hi = match self.next().ok_or("unclosed character class")? {
+                '\\' => escaped_char(self.next().ok_or("trailing backslash")?),
+                hi => hi,
+            };
+            if hi < lo {
+                return Err(format!("invalid range '{lo}-{hi}'"));
+            }
+            items.push(ClassItem::Range(lo, hi));
+        }
+        Ok(Class { negated, items })
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let mut node = atom;
+        loop {
+            let (min, max) = match self.peek() {
+                Some('{') => match self.parse_braces() {
+                    Some(bounds) => bounds,
+                    None => return Ok(node),
+                },
+                Some(c @ ('*' | '+' | '?')) => {
+                    self.pos += 1;
+                    match c {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    }
+                }
+                _ => return Ok(node),
+            };
+            if matches!(node, Node::Start | Node::End | Node::WordBoundary) {
+                return Err("quantifier follows an anchor".to_string());
+            }
+            if max.is_some_and(|max| max < min) {
+                return Err(format!("invalid repetition {{{min},{}}}", max.unwrap_or(0)));
+            }
+            if max.unwrap_or(min) > MAX_REPEAT {
+                return Err(format!("repetition count over {MAX_REPEAT}"));
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    /// Parse `{n}`, `{n,}` or `{n,m}` at the current position, consuming it on success
+    fn parse_braces(&mut self) -> Option<(usize, Option<usize>)> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let body = &rest[..rest.find('}')?];
+        let (min, max) = match body.split_once(',') {
+            None => {
+                let n = body.parse().ok()?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+        };
+        self.pos += body.chars().count() + 2;
+        Some((min, max))
+    }
+}
+
+fn escaped_char(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_literal_unanchored() {
+        assert!(is_match("provider", r#"provider "aws" {"#));
+        assert!(is_match("aws", r#"provider "aws" {"#));
+        assert!(!is_match("gcp", r#"provider "aws" {"#));
+        assert!(is_match("", "anything"));
+    }
+
+    #[test]
+    fn test_anchors() {
+        assert!(is_match("^provider", "provider \"aws\""));
+        assert!(!is_match("^provider", "  provider \"aws\""));
+        assert!(is_match(r"\{$", "resource \"x\" {"));
+        assert!(!is_match("^a$", "ab"));
+        assert!(is_match("^$", ""));
+    }
+
+    #[test]
+    fn test_dot_and_star() {
+        assert!(is_match("a.*c", "abbbc"));
+        assert!(is_match("a.*c", "ac"));
+        assert!(!is_match("a.+c", "ac"));
+        assert!(is_match("^a.c$", "abc"));
+        assert!(!is_match("^a.c$", "abbc"));
+    }
+
+    #[test]
+    fn test_quantifiers() {
+        assert!(is_match("^ab?c$", "ac"));
+        assert!(is_match("^ab?c$", "abc"));
+        assert!(!is_match("^ab?c$", "abbc"));
+        assert!(is_match("^a{3}$", "aaa"));
+        assert!(!is_match("^a{3}$", "aa"));
+        assert!(is_match("^a{2,}$", "aaaa"));
+        assert!(is_match("^a{1,2}b$", "aab"));
+        assert!(!is_match("^a{1,2}b$", "aaab"));
+    }
+
+    #[test]
+    fn test_lazy_quantifiers() {
+        assert!(is_match("^a.*?b$", "axxb"));
+        assert!(is_match("^a+?$", "aaa"));
+    }
+
+    #[test]
+    fn test_literal_brace() {
+        assert!(is_match(r#"provider "aws" {"#, r#"provider "aws" {"#));
+        assert!(is_match("x{y}", "x{y}"));
+        assert!(is_match("a{,2}", "a{,2}"));
+    }
+
+    #[test]
+    fn test_groups_and_alternation() {
+        assert!(is_match("^(aws|gcp)_", "aws_instance"));
+        assert!(is_match("^(aws|gcp)_", "gcp_bucket"));
+        assert!(!is_match("^(aws|gcp)_", "azure_vm"));
+        assert!(is_match("^(?:ab)+$", "ababab"));
+        assert!(!is_match("^(?:ab)+$", "ababa"));
+        assert!(is_match("cat|dog", "hotdog"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(is_match("^[a-c]+$", "abcabc"));
+        assert!(!is_match("^[a-c]+$", "abcd"));
+        assert!(is_match("^[^0-9]+$", "abc"));
+        assert!(!is_match("^[^0-9]+$", "a1c"));
+        assert!(is_match("^[]a]+$", "]a]"));
+        assert!(is_match("^[a-]+$", "a-a"));
+        assert!(is_match(r"^[\d.]+$", "1.2.3"));
+    }
+
+    #[test]
+    fn test_shorthand_classes() {
+        assert!(is_match(r"version = \d+", "version = 42"));
+        assert!(!is_match(r"^\d+$", "4a"));
+        assert!(is_match(r"^\w+\s\w+$", "hello world"));
+        assert!(is_match(r"^\S+$", "no_spaces"));
+        assert!(!is_match(r"^\D+$", "a1"));
+        assert!(is_match(r"^\W$", "-"));
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        assert!(is_match(r"\bprovider\b", "provider \"aws\""));
+        assert!(!is_match(r"\bprovider\b", "required_providers {"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert!(is_match(r"a\.b", "a.b"));
+        assert!(!is_match(r"a\.b", "axb"));
+        assert!(is_match(r"\(\)", "f()"));
+        assert!(is_match(r"a\tb", "a\tb"));
+    }
+
+    #[test]
+    fn test_nested_empty_repetition_terminates() {
+        assert!(is_match("^(a*)*b$", "aaab"));
+        assert!(!is_match("^(a*)*b$", "aaac"));
+        assert!(is_match("^(a?)+$", ""));
+    }
+
+    #[test]
+    fn test_long_line() {
+        let line = format!("// TODO: {}", "x".repeat(300_000));
+        assert!(!is_match("TODO.*z", &line));
+        assert!(is_match("TODO.*z", &format!("{line}z")));
+        assert!(is_match("^//.*x$", &line));
+    }
+
+    #[test]
+    fn test_nested_quantifiers() {
+        let text = "a".repeat(10_000);
+        assert!(!is_match("(a+)+b", &text));
+        assert!(!is_match("^(a|aa)*c$", &text));
+        assert!(is_match("(a+)+b", &format!("{text}b")));
+        assert!(is_match("^(a{1,3})*$", &text));
+    }
+
+    #[test]
+    fn test_repetition_limits() {
+        assert!(is_match("^x{1000}$", &"x".repeat(1000)));
+        for (pattern, reason) in [
+            ("x{1001}", "repetition count over 1000"),
+            ("x{2,1001}", "repetition count over 1000"),
+            ("x{100000}{100000}", "repetition count over 1000"),
+            (
+                "(x{1000}){1000}",
+                "pattern is too large once repetitions are expanded (over 100000 steps)",
+            ),
+        ] {
+            assert_eq!(
+                Regex::new(pattern),
+                Err(format!("Invalid regex '{pattern}': {reason}"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_unicode() {
+        assert!(is_match("^caf.$", "café"));
+        assert!(is_match(r"^\w+$", "日本語"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in [
+            "(ab", "ab)", "[ab", "*a", "a\\", "[z-a]", "(?=a)", "^*", "a{3,1}",
+        ] {
+            let err = Regex::new(pattern).unwrap_err();
+            assert!(
+                err.starts_with(&format!("Invalid regex '{pattern}'")),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_as_str() {
+        assert_eq!(Regex::new("a+b").unwrap().as_str(), "a+b");
+    }
+}
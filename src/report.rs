@@ -0,0 +1,474 @@
+//! Report of an evaluation, written for CI dashboards as JSON with `--report-json` or
+//! as YAML with `--report-yaml`, and for people as Markdown with `--report-md`.
+
+use crate::json::Value;
+use crate::output;
+use crate::yaml;
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::Duration;
+
+/// Everything one run found
+#[derive(Debug)]
+pub struct Report<'a> {
+    /// Whether any group matched
+    pub has_match: bool,
+    pub elapsed: Duration,
+    pub repositories: Vec<RepositoryReport<'a>>,
+}
+
+/// What the filters found in one repository
+#[derive(Debug)]
+pub struct RepositoryReport<'a> {
+    /// Name given with `--repo`, or `vs_<name>` against an `--each-base` base; `None`
+    /// for the current repository
+    pub name: Option<&'a str>,
+    pub ranges: Vec<RangeReport>,
+    /// With `--last`, the newest commit in which any group matched
+    pub commit: Option<&'a str>,
+    pub elapsed: Duration,
+    /// One entry per filter group, in order
+    pub groups: Vec<GroupReport<'a>>,
+}
+
+/// What one filter group found in a repository
+#[derive(Debug)]
+pub struct GroupReport<'a> {
+    /// Output name of the filter group, if any
+    pub name: Option<&'a str>,
+    /// Patterns of the filter group, in the order given
+    pub patterns: &'a [String],
+    pub has_match: bool,
+    /// With `--last`, the newest commit with a matching change
+    pub commit: Option<&'a str>,
+    pub files: &'a [String],
+    /// Changed paths each pattern matched, in pattern order
+    pub pattern_hits: &'a [usize],
+}
+
+/// A diffed range, with the object IDs its ends resolved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeReport {
+    pub base: String,
+    pub head: String,
+    pub base_id: String,
+    pub head_id: String,
+    /// The merge base that replaced the base ref, if any
+    pub merge_base: Option<String>,
+}
+
+impl Report<'_> {
+    /// Build the JSON document
+    pub fn to_json(&self) -> Value {
+        object(vec![
+            ("match", Value::Bool(self.has_match)),
+            ("elapsed_ms", millis(self.elapsed)),
+            (
+                "repositories",
+                Value::Array(self.repositories.iter().map(repository_json).collect()),
+            ),
+        ])
+    }
+
+    /// Write the JSON document to `path`, replacing any existing file
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let content = format!("{}\n", self.to_json());
+        fs::write(path, &content).map_err(|e| format!("Failed to write report to {path}: {e}"))?;
+        output::trace_written(path, content.len(), false);
+        Ok(())
+    }
+
+    /// Write the same document as YAML to `path`, replacing any existing file
+    pub fn write_yaml(&self, path: &str) -> Result<(), String> {
+        let content = yaml::to_string(&self.to_json());
+        fs::write(path, &content).map_err(|e| format!("Failed to write report to {path}: {e}"))?;
+        output::trace_written(path, content.len(), false);
+        Ok(())
+    }
+
+    /// Write a standalone Markdown document to `path`, replacing any existing file
+    pub fn write_markdown(&self, path: &str) -> Result<(), String> {
+        let content = self.to_markdown();
+        fs::write(path, &content).map_err(|e| format!("Failed to write report to {path}: {e}"))?;
+        output::trace_written(path, content.len(), false);
+        Ok(())
+    }
+
+    /// Render the overall result, then for each repository its ranges, a table of the
+    /// filters and each matching filter's files grouped by directory
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# Changed files\n\n**Result:** {}\n",
+            if self.has_match {
+                "✅ match"
+            } else {
+                "➖ no match"
+            }
+        );
+        for repo in &self.repositories {
+            out.push('\n');
+            if let Some(name) = repo.name {
+                let heading = format!("## Repository {}\n\n", output::inline_code(name));
+                out.push_str(&heading);
+            }
+            repository_markdown(&mut out, repo);
+        }
+        let footer = format!("\n_Evaluated in {} ms._\n", self.elapsed.as_millis());
+        out.push_str(&footer);
+        out
+    }
+}
+
+fn repository_markdown(out: &mut String, repo: &RepositoryReport) {
+    for range in &repo.ranges {
+        let line = format!(
+            "- Range {}..{} ({}..{})",
+            output::inline_code(&range.base),
+            output::inline_code(&range.head),
+            output::inline_code(&range.base_id),
+            output::inline_code(&range.head_id),
+        );
+        out.push_str(&line);
+        if let Some(merge_base) = &range.merge_base {
+            let line = format!(", from merge base {}", output::inline_code(merge_base));
+            out.push_str(&line);
+        }
+        out.push('\n');
+    }
+    if let Some(commit) = repo.commit {
+        let line = format!(
+            "- Newest matching commit: {}\n",
+            output::inline_code(commit)
+        );
+        out.push_str(&line);
+    }
+
+    out.push_str("\n| Filter | Result | Files | Patterns |\n| --- | --- | --- | --- |\n");
+    for group in &repo.groups {
+        let patterns: Vec<String> = group
+            .patterns
+            .iter()
+            .zip(group.pattern_hits)
+            .map(|(pattern, hits)| format!("{} ({hits})", output::code_span(pattern)))
+            .collect();
+        let line = format!(
+            "| {} | {} | {} | {} |\n",
+            output::code_span(&group_label(group)),
+            if group.has_match {
+                "✅ true"
+            } else {
+                "➖ false"
+            },
+            group.files.len(),
+            patterns.join("<br>"),
+        );
+        out.push_str(&line);
+    }
+
+    for group in repo.groups.iter().filter(|group| !group.files.is_empty()) {
+        let heading = format!("\n### {}\n\n", output::inline_code(&group_label(group)));
+        out.push_str(&heading);
+        for (directory, names) in by_directory(group.files) {
+            let line = format!("- {}\n", output::inline_code(&format!("{directory}/")));
+            out.push_str(&line);
+            for name in names {
+                let line = format!("  - {}\n", output::inline_code(name));
+                out.push_str(&line);
+            }
+        }
+    }
+}
+
+/// A group's output name, or its patterns when it has none
+fn group_label(group: &GroupReport) -> String {
+    group
+        .name
+        .map_or_else(|| group.patterns.join(", "), str::to_string)
+}
+
+/// Split paths into their directory (`.` at the root) and file name, grouping the
+/// names by directory in sorted order
+fn by_directory(files: &[String]) -> BTreeMap<&str, Vec<&str>> {
+    let mut directories: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for file in files {
+        let (directory, name) = file.rsplit_once('/').unwrap_or((".", file));
+        directories.entry(directory).or_default().push(name);
+    }
+    for names in directories.values_mut() {
+        names.sort_unstable();
+    }
+    directories
+}
+
+fn repository_json(repo: &RepositoryReport) -> Value {
+    object(vec![
+        ("name", optional(repo.name)),
+        (
+            "ranges",
+            Value::Array(repo.ranges.iter().map(range_json).collect()),
+        ),
+        ("commit", optional(repo.commit)),
+        ("elapsed_ms", millis(repo.elapsed)),
+        (
+            "groups",
+            Value::Array(repo.groups.iter().map(group_json).collect()),
+        ),
+    ])
+}
+
+fn group_json(group: &GroupReport) -> Value {
+    let patterns = group
+        .patterns
+        .iter()
+        .zip(group.pattern_hits)
+        .map(|(pattern, &hits)| object(vec![("pattern", string(pattern)), ("hits", count(hits))]))
+        .collect();
+    object(vec![
+        ("name", optional(group.name)),
+        ("match", Value::Bool(group.has_match)),
+        ("commit", optional(group.commit)),
+        (
+            "files",
+            Value::Array(group.files.iter().map(|f| string(f)).collect()),
+        ),
+        ("patterns", Value::Array(patterns)),
+    ])
+}
+
+fn range_json(range: &RangeReport) -> Value {
+    object(vec![
+        ("base", string(&range.base)),
+        ("head", string(&range.head)),
+        ("base_id", string(&range.base_id)),
+        ("head_id", string(&range.head_id)),
+        ("merge_base", optional(range.merge_base.as_deref())),
+    ])
+}
+
+fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+/// A string, or `null` when absent
+fn optional(s: Option<&str>) -> Value {
+    s.map_or(Value::Null, string)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn count(n: usize) -> Value {
+    Value::Number(n as f64)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn millis(duration: Duration) -> Value {
+    Value::Number(duration.as_millis() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    #[test]
+    fn test_report_json() {
+        let patterns = vec!["src/**".to_string(), "!**/*.md".to_string()];
+        let files = vec!["src/main.rs".to_string()];
+        let docs = vec!["docs/**".to_string()];
+        let report = Report {
+            has_match: true,
+            elapsed: Duration::from_millis(42),
+            repositories: vec![RepositoryReport {
+                name: None,
+                ranges: vec![RangeReport {
+                    base: "abc".to_string(),
+                    head: "HEAD".to_string(),
+                    base_id: "abc123".to_string(),
+                    head_id: "def456".to_string(),
+                    merge_base: Some("abc".to_string()),
+                }],
+                commit: None,
+                elapsed: Duration::from_millis(40),
+                groups: vec![
+                    GroupReport {
+                        name: Some("api"),
+                        patterns: &patterns,
+                        has_match: true,
+                        commit: None,
+                        files: &files,
+                        pattern_hits: &[2, 1],
+                    },
+                    GroupReport {
+                        name: Some("docs"),
+                        patterns: &docs,
+                        has_match: false,
+                        commit: None,
+                        files: &[],
+                        pattern_hits: &[0],
+                    },
+                ],
+            }],
+        };
+        let value = json::parse(&report.to_json().to_string()).unwrap();
+        assert_eq!(value.get("match"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("elapsed_ms").and_then(Value::as_u64), Some(42));
+
+        let Some(Value::Array(repositories)) = value.get("repositories") else {
+            panic!("repositories missing: {value}");
+        };
+        let repo = &repositories[0];
+        assert_eq!(repo.get("name"), Some(&Value::Null));
+        assert_eq!(
+            repo.pointer(&["ranges"])
+                .map(ToString::to_string)
+                .as_deref(),
+            Some(
+                r#"[{"base":"abc","head":"HEAD","base_id":"abc123","head_id":"def456","merge_base":"abc"}]"#
+            )
+        );
+        let Some(Value::Array(groups)) = repo.get("groups") else {
+            panic!("groups missing: {repo}");
+        };
+        assert_eq!(
+            groups[0].to_string(),
+            r#"{"name":"api","match":true,"commit":null,"files":["src/main.rs"],"patterns":[{"pattern":"src/**","hits":2},{"pattern":"!**/*.md","hits":1}]}"#
+        );
+        assert_eq!(
+            groups[1].to_string(),
+            r#"{"name":"docs","match":false,"commit":null,"files":[],"patterns":[{"pattern":"docs/**","hits":0}]}"#
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let path =
+            std::env::temp_dir().join(format!("gdf_test_report_{}.json", std::process::id()));
+        let report = Report {
+            has_match: false,
+            elapsed: Duration::ZERO,
+            repositories: Vec::new(),
+        };
+        report.write(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\"match\":false,\"elapsed_ms\":0,\"repositories\":[]}\n"
+        );
+        let _ = fs::remove_file(&path);
+        assert!(report.write("/nonexistent/dir/report.json").is_err());
+    }
+
+    #[test]
+    fn test_write_report_yaml() {
+        let path =
+            std::env::temp_dir().join(format!("gdf_test_report_{}.yaml", std::process::id()));
+        let report = Report {
+            has_match: true,
+            elapsed: Duration::from_millis(7),
+            repositories: Vec::new(),
+        };
+        report.write_yaml(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "match: true\nelapsed_ms: 7\nrepositories: []\n"
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_report_markdown() {
+        let patterns = vec!["src/**".to_string(), "!**/*.md".to_string()];
+        let files = vec![
+            "src/main.rs".to_string(),
+            "build.rs".to_string(),
+            "src/cli/args.rs".to_string(),
+            "src/config.rs".to_string(),
+        ];
+        let docs = vec!["docs/**".to_string()];
+        let report = Report {
+            has_match: true,
+            elapsed: Duration::from_millis(42),
+            repositories: vec![RepositoryReport {
+                name: Some("core"),
+                ranges: vec![RangeReport {
+                    base: "main".to_string(),
+                    head: "HEAD".to_string(),
+                    base_id: "abc123".to_string(),
+                    head_id: "def456".to_string(),
+                    merge_base: Some("0a1b2c".to_string()),
+                }],
+                commit: None,
+                elapsed: Duration::from_millis(40),
+                groups: vec![
+                    GroupReport {
+                        name: Some("api"),
+                        patterns: &patterns,
+                        has_match: true,
+                        commit: None,
+                        files: &files,
+                        pattern_hits: &[4, 0],
+                    },
+                    GroupReport {
+                        name: None,
+                        patterns: &docs,
+                        has_match: false,
+                        commit: None,
+                        files: &[],
+                        pattern_hits: &[0],
+                    },
+                ],
+            }],
+        };
+        assert_eq!(
+            report.to_markdown(),
+            "# Changed files
+
+**Result:** ✅ match
+
+## Repository `core`
+
+- Range `main`..`HEAD` (`abc123`..`def456`), from merge base `0a1b2c`
+
+| Filter | Result | Files | Patterns |
+| --- | --- | --- | --- |
+| `api` | ✅ true | 4 | `src/**` (4)<br>`!**/*.md` (0) |
+| `docs/**` | ➖ false | 0 | `docs/**` (0) |
+
+### `api`
+
+- `./`
+  - `build.rs`
+- `src/`
+  - `config.rs`
+  - `main.rs`
+- `src/cli/`
+  - `args.rs`
+
+_Evaluated in 42 ms._
+"
+        );
+    }
+
+    #[test]
+    fn test_write_report_markdown() {
+        let path = std::env::temp_dir().join(format!("gdf_test_report_{}.md", std::process::id()));
+        let report = Report {
+            has_match: false,
+            elapsed: Duration::ZERO,
+            repositories: Vec::new(),
+        };
+        report.write_markdown(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Changed files\n\n**Result:** ➖ no match\n\n_Evaluated in 0 ms._\n"
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
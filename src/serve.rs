@@ -0,0 +1,274 @@
+//! `--serve`: answer evaluation requests from a process that stays up, so tools asking
+//! many times a minute, like a merge bot, skip starting gdf and reading its config and
+//! patterns each time.
+//!
+//! Each request is a line holding a JSON object, answered by a line holding a JSON
+//! object, in order: on stdin and stdout, or on each connection to a Unix socket with
+//! `--socket`. Patterns and content regexes are compiled once, when the server starts.
+
+use crate::config::{self, Config};
+use crate::json::{self, Value};
+use crate::log::log;
+use crate::{evaluate_compiled, CompiledFilters, EvaluationResult, FilterResult};
+use std::io::{self, BufRead, Write};
+
+/// Answer the requests read from stdin on stdout, until stdin is closed
+///
+/// # Errors
+/// Returns an error when a pattern is invalid, or when stdin cannot be read or stdout
+/// written.
+pub fn serve_stdio(config: &Config) -> Result<(), String> {
+    let compiled = CompiledFilters::new(config)?;
+    respond(config, &compiled, io::stdin().lock(), io::stdout().lock())
+        .map_err(|e| format!("Failed to answer requests: {e}"))
+}
+
+/// Answer the requests of each connection to a Unix socket created at `path`, until
+/// interrupted
+///
+/// Connections are answered concurrently. A socket file left at `path` by an earlier
+/// server is replaced.
+///
+/// # Errors
+/// Returns an error when a pattern is invalid or the socket cannot be created.
+#[cfg(unix)]
+pub fn serve_socket(config: &Config, path: &str) -> Result<(), String> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    let compiled = &CompiledFilters::new(config)?;
+    let stale = std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+    if stale {
+        let _ = std::fs::remove_file(path);
+    }
+    let listener =
+        UnixListener::bind(path).map_err(|e| format!("Failed to listen on {path}: {e}"))?;
+    log!(Info, "gdf", "Listening on {path}");
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log!(Warn, "gdf", "Failed to accept a connection: {e}");
+                    continue;
+                }
+            };
+            scope.spawn(move || {
+                let answered = stream.try_clone().and_then(|reader| {
+                    respond(config, compiled, io::BufReader::new(reader), stream)
+                });
+                if let Err(e) = answered {
+                    log!(Warn, "gdf", "Failed to answer a connection: {e}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Unix sockets are not available on this platform
+///
+/// # Errors
+/// Always returns an error.
+#[cfg(not(unix))]
+pub fn serve_socket(_config: &Config, _path: &str) -> Result<(), String> {
+    Err("--socket needs Unix domain sockets, which this platform lacks".to_string())
+}
+
+/// Write the answer to each request line of `reader` to `writer`, skipping blank lines
+fn respond(
+    config: &Config,
+    compiled: &CompiledFilters,
+    reader: impl BufRead,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(writer, "{}", answer(config, compiled, &line))?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// The answer to one request: its `id`, then the result or the error evaluating it
+fn answer(config: &Config, compiled: &CompiledFilters, line: &str) -> Value {
+    let (id, evaluation) = match json::parse(line) {
+        Ok(request) => (
+            request.get("id").cloned().unwrap_or(Value::Null),
+            evaluate(config, compiled, &request),
+        ),
+        Err(e) => (Value::Null, Err(format!("Invalid request: {e}"))),
+    };
+    let mut members = vec![("id".to_string(), id)];
+    match evaluation {
+        Ok(result) => members.extend([
+            ("match".to_string(), Value::Bool(result.has_match)),
+            ("elapsed_ms".to_string(), millis(&result)),
+            (
+                "filters".to_string(),
+                Value::Array(result.filters.iter().map(filter_json).collect()),
+            ),
+        ]),
+        Err(e) => members.push(("error".to_string(), Value::String(e))),
+    }
+    Value::Object(members)
+}
+
+/// Evaluate the filters against the request's `base-ref`, if it gives one, instead of
+/// the configured base refs
+fn evaluate(
+    config: &Config,
+    compiled: &CompiledFilters,
+    request: &Value,
+) -> Result<EvaluationResult, String> {
+    let Value::Object(members) = request else {
+        return Err("Invalid request: expected an object".to_string());
+    };
+    let mut base_refs = None;
+    for (key, value) in members {
+        match key.as_str() {
+            "id" => {}
+            "base-ref" => {
+                let refs = match value {
+                    Value::String(list) => Some(vec![list.as_str()]),
+                    Value::Array(items) => items.iter().map(Value::as_str).collect(),
+                    _ => None,
+                }
+                .ok_or("base-ref must be a string or an array of strings")?;
+                base_refs = Some(config::split_base_refs(refs)).filter(|refs| !refs.is_empty());
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown request key {key} (expected id or base-ref)"
+                ))
+            }
+        }
+    }
+    match base_refs {
+        Some(base_refs) => evaluate_compiled(
+            &Config {
+                base_refs,
+                since: None,
+                last: None,
+                ..config.clone()
+            },
+            compiled,
+        ),
+        None if needs_base_ref(config) => {
+            Err("base-ref must be given, as gdf --serve was started without one".to_string())
+        }
+        None => evaluate_compiled(config, compiled),
+    }
+}
+
+/// Whether some filter has no base ref to compare against unless the request gives one
+fn needs_base_ref(config: &Config) -> bool {
+    config.base_refs.is_empty()
+        && config.since.is_none()
+        && config.last.is_none()
+        && config.groups.iter().any(|group| group.base_refs.is_empty())
+}
+
+fn filter_json(filter: &FilterResult) -> Value {
+    let optional = |value: &Option<String>| value.clone().map_or(Value::Null, Value::String);
+    Value::Object(vec![
+        ("name".to_string(), optional(&filter.name)),
+        ("match".to_string(), Value::Bool(filter.has_match)),
+        ("commit".to_string(), optional(&filter.commit)),
+        (
+            "files".to_string(),
+            Value::Array(filter.files.iter().cloned().map(Value::String).collect()),
+        ),
+        (
+            "repositories".to_string(),
+            Value::Array(filter.repositories.iter().map(filter_json).collect()),
+        ),
+    ])
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn millis(result: &EvaluationResult) -> Value {
+    Value::Number(result.elapsed.as_millis() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::temp_repo;
+    use crate::{Args, DiffFilter};
+
+    #[test]
+    fn test_respond() {
+        let (repo, git) = temp_repo("serve");
+        std::fs::create_dir_all(repo.join("api")).unwrap();
+        std::fs::write(repo.join("README.md"), "# Demo\n").unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "initial"]);
+        std::fs::write(repo.join("api/main.rs"), "fn main() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "api"]);
+
+        // Started without a base ref, which each request then gives
+        let config = DiffFilter::config(Args {
+            patterns: vec!["api/**".to_string()],
+            github_output: vec![("api".to_string(), 0)],
+            git_dir: Some(repo.join(".git").display().to_string()),
+            serve: true,
+            ..Args::default()
+        })
+        .unwrap();
+        let requests = [
+            r#"{"id": 1, "base-ref": "HEAD~1"}"#,
+            "",
+            r#"{"id": "two", "base-ref": ["missing", "HEAD"]}"#,
+            r#"{"id": 3}"#,
+            r#"{"id": 4, "head": "main"}"#,
+            "not json",
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        let compiled = CompiledFilters::new(&config).unwrap();
+        respond(&config, &compiled, requests.as_bytes(), &mut output).unwrap();
+        let answers: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| json::parse(line).unwrap())
+            .collect();
+        assert_eq!(answers.len(), 5);
+
+        assert_eq!(answers[0].get("id"), Some(&Value::Number(1.0)));
+        assert_eq!(answers[0].get("match"), Some(&Value::Bool(true)));
+        assert_eq!(
+            answers[0].get("filters").map(ToString::to_string),
+            Some(
+                r#"[{"name":"api","match":true,"commit":null,"files":["api/main.rs"],"repositories":[]}]"#
+                    .to_string()
+            )
+        );
+        // The first base ref that resolves is used, as with -b
+        assert_eq!(answers[1].get("id").and_then(Value::as_str), Some("two"));
+        assert_eq!(answers[1].get("match"), Some(&Value::Bool(false)));
+
+        let error = |answer: &Value| {
+            answer
+                .get("error")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        assert_eq!(
+            error(&answers[2]).as_deref(),
+            Some("base-ref must be given, as gdf --serve was started without one")
+        );
+        assert_eq!(
+            error(&answers[3]).as_deref(),
+            Some("Unknown request key head (expected id or base-ref)")
+        );
+        assert_eq!(answers[4].get("id"), Some(&Value::Null));
+        assert!(error(&answers[4]).unwrap().starts_with("Invalid request: "));
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+}
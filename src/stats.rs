@@ -0,0 +1,163 @@
+//! Counts of changed and matched files per extension and per top-level directory,
+//! printed with `--stats` to show where changes land and which of them the filters
+//! pick up.
+
+use std::collections::{BTreeMap, HashSet};
+
+/// Changed and matched files sharing an extension or a top-level directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// `.rs` or `src/`; `(none)` for files without an extension and `(root)` for files
+    /// at the top of the repository
+    pub key: String,
+    pub changed: usize,
+    pub matched: usize,
+}
+
+/// Changed files grouped both ways, each table sorted by most changed files first
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub by_extension: Vec<Row>,
+    pub by_directory: Vec<Row>,
+}
+
+impl Stats {
+    /// Count each distinct changed path once, as matched when it is in `matched`
+    pub fn new(changed: &[&str], matched: &HashSet<&str>) -> Stats {
+        let mut distinct = HashSet::new();
+        let paths: Vec<&str> = changed
+            .iter()
+            .copied()
+            .filter(|path| distinct.insert(*path))
+            .collect();
+        Stats {
+            by_extension: count_by(&paths, matched, extension),
+            by_directory: count_by(&paths, matched, top_level_directory),
+        }
+    }
+
+    /// Render both tables, one line per row with aligned columns
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (title, rows) in [
+            ("extension", &self.by_extension),
+            ("top-level directory", &self.by_directory),
+        ] {
+            lines.push(format!("Changed files by {title}:"));
+            let width = rows.iter().map(|row| row.key.len()).max().unwrap_or(0);
+            let digits = rows
+                .iter()
+                .map(|row| row.changed.to_string().len())
+                .max()
+                .unwrap_or(1);
+            for row in rows {
+                lines.push(format!(
+                    "  {:width$}  {:>digits$} changed  {} matched",
+                    row.key, row.changed, row.matched
+                ));
+            }
+        }
+        lines
+    }
+}
+
+fn count_by(paths: &[&str], matched: &HashSet<&str>, key: fn(&str) -> String) -> Vec<Row> {
+    let mut counts: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for path in paths {
+        let (changed, hits) = counts.entry(key(path)).or_default();
+        *changed += 1;
+        if matched.contains(path) {
+            *hits += 1;
+        }
+    }
+    let mut rows: Vec<Row> = counts
+        .into_iter()
+        .map(|(key, (changed, matched))| Row {
+            key,
+            changed,
+            matched,
+        })
+        .collect();
+    // The sort is stable, so ties stay in key order
+    rows.sort_by_key(|row| std::cmp::Reverse(row.changed));
+    rows
+}
+
+/// The file name's extension with its dot, ignoring the leading dot of hidden files
+fn extension(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name[dot..].to_string(),
+        _ => "(none)".to_string(),
+    }
+}
+
+/// The first path component with a trailing slash
+fn top_level_directory(path: &str) -> String {
+    match path.split_once('/') {
+        Some((directory, _)) => format!("{directory}/"),
+        None => "(root)".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(key: &str, changed: usize, matched: usize) -> Row {
+        Row {
+            key: key.to_string(),
+            changed,
+            matched,
+        }
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension("src/main.rs"), ".rs");
+        assert_eq!(extension("archive.tar.gz"), ".gz");
+        assert_eq!(extension("web/.eslintrc"), "(none)");
+        assert_eq!(extension("v1.2/Makefile"), "(none)");
+    }
+
+    #[test]
+    fn test_top_level_directory() {
+        assert_eq!(top_level_directory("services/api/main.go"), "services/");
+        assert_eq!(top_level_directory("README.md"), "(root)");
+    }
+
+    #[test]
+    fn test_stats() {
+        let changed = [
+            "src/main.rs",
+            "src/cli.rs",
+            "docs/guide.md",
+            "README.md",
+            "src/main.rs",
+        ];
+        let matched = HashSet::from(["src/main.rs", "src/cli.rs"]);
+        let stats = Stats::new(&changed, &matched);
+        assert_eq!(stats.by_extension, [row(".md", 2, 0), row(".rs", 2, 2)]);
+        assert_eq!(
+            stats.by_directory,
+            [row("src/", 2, 2), row("(root)", 1, 0), row("docs/", 1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_lines() {
+        let stats = Stats {
+            by_extension: vec![row(".rs", 12, 5), row(".md", 3, 0)],
+            by_directory: Vec::new(),
+        };
+        assert_eq!(
+            stats.lines(),
+            [
+                "Changed files by extension:",
+                "  .rs  12 changed  5 matched",
+                "  .md   3 changed  0 matched",
+                "Changed files by top-level directory:",
+            ]
+        );
+    }
+}
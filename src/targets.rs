@@ -0,0 +1,112 @@
+//! `--target`: logical targets, like Bazel labels, each standing for the path globs of
+//! its sources, so build systems gdf does not read itself can build and test only the
+//! targets whose files changed.
+
+use crate::matcher::{Anchoring, PatternSet};
+use std::collections::BTreeSet;
+
+/// Group `<target>=<glob>` entries by target, in order of first appearance
+///
+/// Each target's globs form one pattern set, so `!` globs exclude paths of that target
+/// only. The target is everything before the first `=`.
+///
+/// # Errors
+/// Returns an error for an entry without a target or glob, or an unsupported glob.
+pub fn parse(
+    entries: &[String],
+    anchoring: Anchoring,
+) -> Result<Vec<(String, PatternSet)>, String> {
+    let mut globs: Vec<(String, Vec<String>)> = Vec::new();
+    for entry in entries {
+        let Some((target, glob)) = entry
+            .split_once('=')
+            .filter(|(target, glob)| !target.is_empty() && !glob.is_empty())
+        else {
+            return Err(format!(
+                "Invalid --target '{entry}': expected <target>=<glob>"
+            ));
+        };
+        match globs.iter_mut().find(|(name, _)| name == target) {
+            Some((_, target_globs)) => target_globs.push(glob.to_string()),
+            None => globs.push((target.to_string(), vec![glob.to_string()])),
+        }
+    }
+    globs
+        .into_iter()
+        .map(|(target, globs)| Ok((target, PatternSet::new(&globs, anchoring)?)))
+        .collect()
+}
+
+/// The targets whose globs select any of `files`, sorted and once each
+///
+/// # Errors
+/// Returns an error when a glob cannot be matched.
+pub fn affected(targets: &[(String, PatternSet)], files: &[String]) -> Result<Vec<String>, String> {
+    let paths: Vec<&str> = files.iter().map(String::as_str).collect();
+    let mut names = BTreeSet::new();
+    for (target, patterns) in targets {
+        if patterns.match_batch(&paths)?.any() {
+            names.insert(target.clone());
+        }
+    }
+    Ok(names.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let targets = parse(
+            &entries(&[
+                "//services/auth:*=services/auth/**",
+                "//web:app=web/**",
+                "//services/auth:*=proto/auth.proto",
+            ]),
+            Anchoring::Anchored,
+        )
+        .unwrap();
+        let names: Vec<&str> = targets.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["//services/auth:*", "//web:app"]);
+        assert_eq!(targets[0].1.patterns().len(), 2);
+
+        for entry in ["services/**", "=services/**", "//web:app="] {
+            assert_eq!(
+                parse(&entries(&[entry]), Anchoring::Anchored),
+                Err(format!(
+                    "Invalid --target '{entry}': expected <target>=<glob>"
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_affected() {
+        let targets = parse(
+            &entries(&[
+                "//services/auth:*=services/auth/**",
+                "//services/auth:*=!services/auth/README.md",
+                "//web:app=web/**",
+                "//docs=**/*.md",
+            ]),
+            Anchoring::Anchored,
+        )
+        .unwrap();
+        let files = entries(&["services/auth/README.md", "web/index.html"]);
+        assert_eq!(
+            affected(&targets, &files),
+            Ok(entries(&["//docs", "//web:app"]))
+        );
+        let files = entries(&["services/auth/server.go"]);
+        assert_eq!(
+            affected(&targets, &files),
+            Ok(entries(&["//services/auth:*"]))
+        );
+        assert_eq!(affected(&targets, &[]), Ok(Vec::new()));
+    }
+}
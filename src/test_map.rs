@@ -0,0 +1,155 @@
+//! `--test-map`: the tests covering each source glob, so a test runner can be given the
+//! test files a change calls for instead of the whole suite.
+
+use crate::matcher::{Anchoring, PatternSet};
+use std::collections::{BTreeSet, HashSet};
+
+/// A source glob with the globs of the tests covering it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub sources: PatternSet,
+    pub tests: PatternSet,
+}
+
+/// Group `<source-glob>=<test-glob>` entries by source glob, in order of first appearance
+///
+/// Each source glob's test globs form one pattern set, so `!` globs exclude tests of
+/// that source glob only. The source glob is everything before the first `=`.
+///
+/// # Errors
+/// Returns an error for an entry without a source or test glob, or an unsupported glob.
+pub fn parse(entries: &[String], anchoring: Anchoring) -> Result<Vec<Rule>, String> {
+    let mut globs: Vec<(String, Vec<String>)> = Vec::new();
+    for entry in entries {
+        let Some((source, test)) = entry
+            .split_once('=')
+            .filter(|(source, test)| !source.is_empty() && !test.is_empty())
+        else {
+            return Err(format!(
+                "Invalid --test-map '{entry}': expected <source-glob>=<test-glob>"
+            ));
+        };
+        match globs.iter_mut().find(|(glob, _)| glob == source) {
+            Some((_, tests)) => tests.push(test.to_string()),
+            None => globs.push((source.to_string(), vec![test.to_string()])),
+        }
+    }
+    globs
+        .into_iter()
+        .map(|(source, tests)| {
+            Ok(Rule {
+                sources: PatternSet::new(&[source], anchoring)?,
+                tests: PatternSet::new(&tests, anchoring)?,
+            })
+        })
+        .collect()
+}
+
+/// The test files `files` call for, sorted and once each: the `tracked` files the test
+/// globs of any rule whose source glob selects one of `files` match, and the files of
+/// `files` that are tests themselves
+///
+/// Only tracked files are listed, so a deleted test is not handed to the test runner.
+///
+/// # Errors
+/// Returns an error when a glob cannot be matched.
+pub fn implied_tests(
+    rules: &[Rule],
+    files: &[String],
+    tracked: &[String],
+) -> Result<Vec<String>, String> {
+    let changed: Vec<&str> = files.iter().map(String::as_str).collect();
+    let is_changed: HashSet<&str> = changed.iter().copied().collect();
+    let tracked: Vec<&str> = tracked.iter().map(String::as_str).collect();
+    let mut tests = BTreeSet::new();
+    for rule in rules {
+        let sources_changed = rule.sources.match_batch(&changed)?.any();
+        let matches = rule.tests.match_batch(&tracked)?;
+        tests.extend(
+            matches
+                .ones()
+                .map(|i| tracked[i])
+                .filter(|test| sources_changed || is_changed.contains(test)),
+        );
+    }
+    Ok(tests.into_iter().map(str::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let rules = parse(
+            &entries(&[
+                "src/auth/**=tests/auth/**",
+                "web/**=web/**/*.test.ts",
+                "src/auth/**=tests/integration/login*",
+            ]),
+            Anchoring::Anchored,
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].sources.patterns().len(), 1);
+        assert_eq!(rules[0].tests.patterns().len(), 2);
+
+        for entry in ["src/**", "=tests/**", "src/**="] {
+            assert_eq!(
+                parse(&entries(&[entry]), Anchoring::Anchored),
+                Err(format!(
+                    "Invalid --test-map '{entry}': expected <source-glob>=<test-glob>"
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_implied_tests() {
+        let rules = parse(
+            &entries(&[
+                "src/auth/**=tests/auth/**",
+                "src/auth/**=tests/integration/login*",
+                "src/auth/**=!tests/auth/fixtures/**",
+                "src/billing/**=tests/billing/**",
+            ]),
+            Anchoring::Anchored,
+        )
+        .unwrap();
+        let tracked = entries(&[
+            "src/auth/session.rs",
+            "tests/auth/session.rs",
+            "tests/auth/fixtures/user.json",
+            "tests/billing/invoice.rs",
+            "tests/billing/refund.rs",
+            "tests/integration/login_flow.rs",
+            "tests/integration/signup.rs",
+        ]);
+        assert_eq!(
+            implied_tests(&rules, &entries(&["src/auth/session.rs"]), &tracked),
+            Ok(entries(&[
+                "tests/auth/session.rs",
+                "tests/integration/login_flow.rs"
+            ]))
+        );
+        // A changed test runs too, even when its sources did not change, unless it was
+        // deleted
+        assert_eq!(
+            implied_tests(
+                &rules,
+                &entries(&[
+                    "README.md",
+                    "tests/billing/refund.rs",
+                    "tests/billing/old.rs"
+                ]),
+                &tracked
+            ),
+            Ok(entries(&["tests/billing/refund.rs"]))
+        );
+        assert_eq!(implied_tests(&rules, &[], &tracked), Ok(Vec::new()));
+    }
+}
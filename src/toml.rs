@@ -0,0 +1,417 @@
+//! Minimal TOML parser for configuration files.
+//!
+//! Supports the subset a configuration needs: comments, bare, quoted and dotted keys,
+//! `[table]` headers, basic and literal strings, integers, floats, booleans, arrays
+//! (spanning lines, with trailing commas) and inline tables. Multi-line strings, dates
+//! and arrays of tables are rejected.
+
+use crate::json::Value;
+
+/// Parse a TOML document into an object, keeping keys in document order
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        bytes: input.as_bytes(),
+        pos: 0,
+    };
+    let mut root = Value::Object(Vec::new());
+    // Path of the current `[table]`, and the tables given a header so far
+    let mut table: Vec<String> = Vec::new();
+    let mut headers: Vec<Vec<String>> = Vec::new();
+    loop {
+        parser.skip_blank_lines();
+        match parser.peek() {
+            None => return Ok(root),
+            Some(b'[') => {
+                let start = parser.pos;
+                parser.pos += 1;
+                if parser.peek() == Some(b'[') {
+                    return Err(parser.error("arrays of tables are not supported"));
+                }
+                table = parser.parse_key()?;
+                parser.skip_spaces();
+                parser.expect(b']')?;
+                if headers.contains(&table) {
+                    let reason = format!("table [{}] defined twice", table.join("."));
+                    return Err(parser.error_at(start, &reason));
+                }
+                headers.push(table.clone());
+                table_mut(&mut root, &table).map_err(|reason| parser.error_at(start, &reason))?;
+            }
+            Some(_) => {
+                let start = parser.pos;
+                let mut key = parser.parse_key()?;
+                parser.skip_spaces();
+                parser.expect(b'=')?;
+                let value = parser.parse_value()?;
+                let name = key.pop().unwrap_or_default();
+                let path = [table.as_slice(), key.as_slice()].concat();
+                insert(&mut root, &path, name, value)
+                    .map_err(|reason| parser.error_at(start, &reason))?;
+            }
+        }
+        parser.expect_line_end()?;
+    }
+}
+
+/// The members of the table at `path`, creating missing tables on the way
+fn table_mut<'a>(
+    root: &'a mut Value,
+    path: &[String],
+) -> Result<&'a mut Vec<(String, Value)>, String> {
+    let mut current = root;
+    for name in path {
+        let Value::Object(members) = current else {
+            unreachable!("tables are only created as objects");
+        };
+        let index = if let Some(index) = members.iter().position(|(key, _)| key == name) {
+            index
+        } else {
+            members.push((name.clone(), Value::Object(Vec::new())));
+            members.len() - 1
+        };
+        current = &mut members[index].1;
+        if !matches!(current, Value::Object(_)) {
+            return Err(format!("key {name} is not a table"));
+        }
+    }
+    match current {
+        Value::Object(members) => Ok(members),
+        _ => unreachable!("checked while walking the path"),
+    }
+}
+
+fn insert(root: &mut Value, path: &[String], name: String, value: Value) -> Result<(), String> {
+    let members = table_mut(root, path)?;
+    if members.iter().any(|(key, _)| *key == name) {
+        return Err(format!("key {name} defined twice"));
+    }
+    members.push((name, value));
+    Ok(())
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> String {
+        self.error_at(self.pos, reason)
+    }
+
+    fn error_at(&self, pos: usize, reason: &str) -> String {
+        let before = &self.bytes[..pos.min(self.bytes.len())];
+        let line = before.split(|&b| b == b'\n').count();
+        let column = before.iter().rev().take_while(|&&b| b != b'\n').count() + 1;
+        format!("Invalid TOML at line {line}, column {column}: {reason}")
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    /// Skip spaces and tabs within a line
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip a comment up to the end of its line
+    fn skip_comment(&mut self) {
+        if self.peek() == Some(b'#') {
+            while self.peek().is_some_and(|b| b != b'\n') {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Skip whitespace, comments and line breaks
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some(b'\n') => self.pos += 1,
+                Some(b'\r') if self.bytes.get(self.pos + 1) == Some(&b'\n') => self.pos += 2,
+                _ => return,
+            }
+        }
+    }
+
+    /// Require the rest of the line to be blank or a comment
+    fn expect_line_end(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        match self.peek() {
+            None | Some(b'\n') => Ok(()),
+            Some(b'\r') if self.bytes.get(self.pos + 1) == Some(&b'\n') => Ok(()),
+            Some(_) => Err(self.error("expected the end of the line")),
+        }
+    }
+
+    /// Parse a bare, quoted or dotted key into its parts
+    fn parse_key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some(b'"') => self.parse_basic_string()?,
+                Some(b'\'') => self.parse_literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(self.error("expected a key"));
+                    }
+                    String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned()
+                }
+            };
+            parts.push(part);
+            self.skip_spaces();
+            if self.peek() != Some(b'.') {
+                return Ok(parts);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(b'"') if self.bytes[self.pos..].starts_with(b"\"\"\"") => {
+                Err(self.error("multi-line strings are not supported"))
+            }
+            Some(b'\'') if self.bytes[self.pos..].starts_with(b"'''") => {
+                Err(self.error("multi-line strings are not supported"))
+            }
+            Some(b'"') => self.parse_basic_string().map(Value::String),
+            Some(b'\'') => self.parse_literal_string().map(Value::String),
+            Some(b'[') => self.parse_array(),
+            Some(b'{') => self.parse_inline_table(),
+            Some(b't') => self.parse_literal("true", Value::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Value::Bool(false)),
+            Some(b'+' | b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Value) -> Result<Value, String> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("invalid value"))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|b| matches!(b, b'+' | b'-' | b'.' | b'_' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.pos += 1;
+        }
+        let text: String = String::from_utf8_lossy(&self.bytes[start..self.pos])
+            .chars()
+            .filter(|&c| c != '_')
+            .collect();
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| self.error(&format!("invalid number '{text}'")))
+    }
+
+    /// Parse values separated by commas, each followed by blank lines or comments
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    /// Parse `{ key = value, ... }` on one line
+    fn parse_inline_table(&mut self) -> Result<Value, String> {
+        self.expect(b'{')?;
+        let mut table = Value::Object(Vec::new());
+        self.skip_spaces();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(table);
+        }
+        loop {
+            let mut key = self.parse_key()?;
+            self.skip_spaces();
+            self.expect(b'=')?;
+            let value = self.parse_value()?;
+            let name = key.pop().unwrap_or_default();
+            insert(&mut table, &key, name, value).map_err(|reason| self.error(&reason))?;
+            self.skip_spaces();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(table);
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, String> {
+        self.expect(b'\'')?;
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b != b'\'' && b != b'\n') {
+            self.pos += 1;
+        }
+        let text = String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned();
+        self.expect(b'\'')
+            .map_err(|_| self.error("unterminated string"))?;
+        Ok(text)
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = self
+                .peek()
+                .filter(|&b| b != b'\n')
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape(4)?,
+                        b'U' => self.parse_unicode_escape(8)?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in string"))
+    }
+
+    fn parse_unicode_escape(&mut self, digits: usize) -> Result<char, String> {
+        let c = self
+            .bytes
+            .get(self.pos..self.pos + digits)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += digits;
+        Ok(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() {
+        let document = parse(
+            r#"
+# Filters for the monorepo
+base-ref = "main"   # compared against
+patterns = [
+  "!**/*.md",  # docs never count
+  'services/**',
+]
+find-copies = true
+git-retries = 3
+
+[filters.api]
+patterns = ["services/api/**"]
+
+[filters."web app"]
+patterns = []
+owner = { team = "web", "on-call".name = "\u00e9" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            document.to_string(),
+            r#"{"base-ref":"main","patterns":["!**/*.md","services/**"],"find-copies":true,"git-retries":3,"filters":{"api":{"patterns":["services/api/**"]},"web app":{"patterns":[],"owner":{"team":"web","on-call":{"name":"é"}}}}}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_scalars() {
+        let document = parse("a = -1_000\nb = 2.5e1\nc = false\nd = 'C:\\path'\n").unwrap();
+        assert_eq!(
+            document.to_string(),
+            r#"{"a":-1000,"b":25,"c":false,"d":"C:\\path"}"#
+        );
+        assert_eq!(parse(""), Ok(Value::Object(Vec::new())));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("a = 1\na = 2"),
+            Err("Invalid TOML at line 2, column 1: key a defined twice".to_string())
+        );
+        assert_eq!(
+            parse("[x]\n[x]"),
+            Err("Invalid TOML at line 2, column 1: table [x] defined twice".to_string())
+        );
+        assert_eq!(
+            parse("a = 1 b = 2"),
+            Err("Invalid TOML at line 1, column 7: expected the end of the line".to_string())
+        );
+        assert!(parse("a = \"open\nb = 1").is_err());
+        assert!(parse("a = [1, 2").is_err());
+        assert!(parse("a = \"\"\"x\"\"\"").is_err());
+        assert!(parse("[[servers]]").is_err());
+        assert!(parse("a = 1\n[a]").is_err());
+        assert!(parse("a = yes").is_err());
+        assert!(parse("= 1").is_err());
+    }
+}
@@ -0,0 +1,460 @@
+//! `git-diff-filter tui`: type patterns and see at once which of the current diff's
+//! files they select, to write a nontrivial glob set without a commit per attempt.
+//!
+//! The terminal is put in raw mode with `stty`, so this needs a Unix terminal. The
+//! screen is drawn on `/dev/tty`, leaving stdout for the `-p` flags printed on Enter.
+
+use crate::cli;
+use crate::config;
+use crate::git;
+use crate::matcher::{Anchoring, PatternSet};
+use crate::output::{DIM, GREEN, RED, RESET};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+
+/// Switch to the alternate screen, so the shell's screen comes back on exit
+const ENTER_SCREEN: &str = "\x1b[?1049h";
+const LEAVE_SCREEN: &str = "\x1b[?1049l";
+
+/// Lines around the file list: the patterns and the status above, the keys below
+const CHROME_LINES: usize = 3;
+
+/// Show the files changed against the base ref, as `gdf` with the same flags would
+/// list them, and select them with the patterns typed, starting from any given with
+/// `-p`
+///
+/// On Enter, the patterns are printed as `-p` flags.
+///
+/// # Errors
+/// Returns an error when the flags are invalid, the changed files cannot be listed or
+/// there is no terminal.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut parsed = cli::parse_flags(args)?;
+    let input = parsed.patterns.join(" ");
+    // The typed patterns replace these, but the flags need one
+    if parsed.patterns.is_empty() {
+        parsed.patterns = vec!["**".to_string()];
+    }
+    let config = config::from_args(parsed)?;
+    let (_, resolved) = git::resolve_first(
+        &config.base_refs,
+        &config.diff_options,
+        git::EventHints::default(),
+    )?;
+    let files: Vec<String> = git::get_changed_files(&resolved.range, &config.diff_options)?
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+
+    let mut state = State { input, scroll: 0 };
+    let mut terminal = Terminal::raw()?;
+    let accepted = interact(&mut terminal, &mut state, &files, config.anchoring);
+    drop(terminal);
+    if accepted? {
+        println!("{}", pattern_flags(&state.input));
+    }
+    Ok(())
+}
+
+/// Redraw after each key until Enter (`true`) or Esc (`false`)
+fn interact(
+    terminal: &mut Terminal,
+    state: &mut State,
+    files: &[String],
+    anchoring: Anchoring,
+) -> Result<bool, String> {
+    let paths: Vec<&str> = files.iter().map(String::as_str).collect();
+    let mut buffer = [0; 256];
+    loop {
+        let (rows, cols) = terminal.size();
+        let page = rows.saturating_sub(CHROME_LINES).max(1);
+        let screen = Screen {
+            rows,
+            cols,
+            scroll: state.scroll,
+        };
+        let frame = render(&state.input, &paths, anchoring, &screen);
+        terminal
+            .tty
+            .write_all(frame.as_bytes())
+            .and_then(|()| terminal.tty.flush())
+            .map_err(|e| format!("Failed to draw on the terminal: {e}"))?;
+        let read = terminal
+            .tty
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read the terminal: {e}"))?;
+        if read == 0 {
+            return Ok(false);
+        }
+        for key in decode(&buffer[..read]) {
+            if let Some(accepted) = state.apply(key, page, files.len()) {
+                return Ok(accepted);
+            }
+        }
+    }
+}
+
+/// A key press, as far as the tester cares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Backspace,
+    /// Ctrl-U: erase the patterns
+    Clear,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Quit,
+}
+
+/// The keys in bytes read from a raw terminal
+///
+/// An escape sequence arrives in one read, so an escape ending the bytes is the Esc
+/// key itself. Unknown sequences and control characters are dropped.
+fn decode(bytes: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' if chars.peek() == Some(&'[') => {
+                chars.next();
+                let mut sequence = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_ascii_alphabetic() && *c != '~') {
+                    sequence.push(c);
+                }
+                match (sequence.as_str(), chars.next()) {
+                    ("", Some('A')) => Key::Up,
+                    ("", Some('B')) => Key::Down,
+                    ("5", Some('~')) => Key::PageUp,
+                    ("6", Some('~')) => Key::PageDown,
+                    _ => continue,
+                }
+            }
+            // Ctrl-C and Ctrl-D quit too, as raw mode turns off their signals
+            '\x1b' | '\x03' | '\x04' => Key::Quit,
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x15' => Key::Clear,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// What the user typed, and the first file shown
+#[derive(Debug, Default, PartialEq, Eq)]
+struct State {
+    input: String,
+    scroll: usize,
+}
+
+impl State {
+    /// Apply a key, with `page` files shown out of `total`; returns whether the
+    /// patterns were accepted once the user is done
+    fn apply(&mut self, key: Key, page: usize, total: usize) -> Option<bool> {
+        let last_page = total.saturating_sub(page);
+        match key {
+            Key::Char(c) => {
+                self.input.push(c);
+                self.scroll = 0;
+            }
+            Key::Backspace => {
+                self.input.pop();
+                self.scroll = 0;
+            }
+            Key::Clear => {
+                self.input.clear();
+                self.scroll = 0;
+            }
+            Key::Up => self.scroll = self.scroll.saturating_sub(1),
+            Key::Down => self.scroll = (self.scroll + 1).min(last_page),
+            Key::PageUp => self.scroll = self.scroll.saturating_sub(page),
+            Key::PageDown => self.scroll = (self.scroll + page).min(last_page),
+            Key::Enter => return Some(true),
+            Key::Quit => return Some(false),
+        }
+        None
+    }
+}
+
+/// How a file fares against the typed patterns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Selection {
+    Matched,
+    /// An inclusion pattern matched it but an exclusion dropped it
+    Excluded,
+    Unmatched,
+}
+
+/// Select `paths` with the whitespace-separated patterns of `input`
+///
+/// # Errors
+/// Returns an error for an unsupported pattern.
+fn classify(input: &str, paths: &[&str], anchoring: Anchoring) -> Result<Vec<Selection>, String> {
+    let patterns: Vec<String> = input.split_whitespace().map(str::to_string).collect();
+    if patterns.is_empty() {
+        return Ok(vec![Selection::Unmatched; paths.len()]);
+    }
+    let pattern_set = PatternSet::new(&patterns, anchoring)?;
+    let rows = pattern_set.match_rows(paths)?;
+    let matched: HashSet<usize> = pattern_set.resolve(&rows).ones().collect();
+    let excluded: HashSet<usize> = pattern_set.resolve_excluded(&rows).ones().collect();
+    Ok((0..paths.len())
+        .map(|index| {
+            if matched.contains(&index) {
+                Selection::Matched
+            } else if excluded.contains(&index) {
+                Selection::Excluded
+            } else {
+                Selection::Unmatched
+            }
+        })
+        .collect())
+}
+
+/// The terminal's size and the first file shown
+struct Screen {
+    rows: usize,
+    cols: usize,
+    scroll: usize,
+}
+
+/// Draw the whole screen: the patterns, how many files they select (or why they are
+/// invalid), a page of files with the selected ones highlighted and the excluded ones
+/// dimmed, and the keys; the cursor is left after the patterns
+fn render(input: &str, paths: &[&str], anchoring: Anchoring, screen: &Screen) -> String {
+    let fit = |text: &str| text.chars().take(screen.cols).collect::<String>();
+    let selections = classify(input, paths, anchoring);
+    let status = match &selections {
+        Err(e) => format!("{RED}{}{RESET}", fit(e)),
+        Ok(_) if input.trim().is_empty() => {
+            format!("{DIM}{}{RESET}", fit("Type patterns separated by spaces"))
+        }
+        Ok(selections) => {
+            let count = |wanted| selections.iter().filter(|&&s| s == wanted).count();
+            let (matched, excluded) = (count(Selection::Matched), count(Selection::Excluded));
+            fit(&format!(
+                "{matched} of {} changed files match, {excluded} excluded",
+                paths.len()
+            ))
+        }
+    };
+    let prompt = format!("Patterns: {input}");
+    let mut lines = vec![fit(&prompt), status];
+    let page = screen.rows.saturating_sub(CHROME_LINES);
+    for (index, path) in paths.iter().enumerate().skip(screen.scroll).take(page) {
+        let selection = selections
+            .as_ref()
+            .map_or(Selection::Unmatched, |selections| selections[index]);
+        lines.push(match selection {
+            Selection::Matched => format!("{GREEN}{}{RESET}", fit(&format!("  {path}"))),
+            Selection::Excluded => {
+                format!("{DIM}{}{RESET}", fit(&format!("  {path} (excluded)")))
+            }
+            Selection::Unmatched => fit(&format!("  {path}")),
+        });
+    }
+    lines.resize(
+        screen.rows.saturating_sub(1).max(lines.len()),
+        String::new(),
+    );
+    let keys = "Up/Down/PgUp/PgDn scroll, Enter prints the -p flags, Esc quits";
+    lines.push(format!("{DIM}{}{RESET}", fit(keys)));
+
+    // Home, each line cleared to its end, then the cursor after the patterns
+    let mut frame = "\x1b[H".to_string();
+    frame.push_str(&lines.join("\x1b[K\r\n"));
+    frame.push_str("\x1b[K");
+    let column = prompt.chars().count().min(screen.cols.saturating_sub(1)) + 1;
+    let cursor = format!("\x1b[1;{column}H");
+    frame.push_str(&cursor);
+    frame
+}
+
+/// The patterns of `input` as `-p` flags, quoted for a POSIX shell
+fn pattern_flags(input: &str) -> String {
+    input
+        .split_whitespace()
+        .map(|pattern| format!("-p '{}'", pattern.replace('\'', "'\\''")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The controlling terminal in raw mode on the alternate screen, restored when dropped
+struct Terminal {
+    tty: File,
+    /// The settings to restore, as printed by `stty -g`
+    saved: String,
+}
+
+impl Terminal {
+    fn raw() -> Result<Self, String> {
+        let tty = File::options()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|e| format!("gdf tui needs a terminal: {e}"))?;
+        let saved = stty(&tty, &["-g"])?;
+        stty(&tty, &["raw", "-echo"])?;
+        let mut terminal = Terminal { tty, saved };
+        let _ = terminal.tty.write_all(ENTER_SCREEN.as_bytes());
+        Ok(terminal)
+    }
+
+    /// Rows and columns, or a conventional 24x80 when `stty` cannot tell
+    fn size(&self) -> (usize, usize) {
+        let size = stty(&self.tty, &["size"]).ok().and_then(|size| {
+            let (rows, cols) = size.split_once(' ')?;
+            Some((rows.parse().ok()?, cols.parse().ok()?))
+        });
+        match size {
+            Some((rows, cols)) if rows > 0 && cols > 0 => (rows, cols),
+            _ => (24, 80),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(LEAVE_SCREEN.as_bytes());
+        let _ = stty(&self.tty, &[&self.saved]);
+    }
+}
+
+/// Run `stty` on the terminal `tty`, returning its output
+fn stty(tty: &File, args: &[&str]) -> Result<String, String> {
+    let stdin = tty
+        .try_clone()
+        .map_err(|e| format!("Failed to open the terminal: {e}"))?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(stdin))
+        .output()
+        .map_err(|e| format!("gdf tui needs stty: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "stty {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode(b"a/\x1b[A\x1b[B\x1b[5~\x1b[6~\x7f\r"),
+            [
+                Key::Char('a'),
+                Key::Char('/'),
+                Key::Up,
+                Key::Down,
+                Key::PageUp,
+                Key::PageDown,
+                Key::Backspace,
+                Key::Enter,
+            ]
+        );
+        assert_eq!(decode(b"\x1b"), [Key::Quit]);
+        assert_eq!(decode(b"\x03"), [Key::Quit]);
+        // Other sequences and control characters are dropped
+        assert_eq!(decode(b"\x1b[C\x1b[1;5D\x01\x15"), [Key::Clear]);
+        assert_eq!(decode("é".as_bytes()), [Key::Char('é')]);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut state = State::default();
+        for c in "src/*".chars() {
+            assert_eq!(state.apply(Key::Char(c), 10, 25), None);
+        }
+        state.apply(Key::Backspace, 10, 25);
+        assert_eq!(state.input, "src/");
+
+        state.apply(Key::PageDown, 10, 25);
+        assert_eq!(state.scroll, 10);
+        // The last page is kept full
+        state.apply(Key::PageDown, 10, 25);
+        state.apply(Key::Down, 10, 25);
+        assert_eq!(state.scroll, 15);
+        state.apply(Key::Up, 10, 25);
+        assert_eq!(state.scroll, 14);
+        // Typing goes back to the top
+        state.apply(Key::Char('*'), 10, 25);
+        assert_eq!(state.scroll, 0);
+        state.apply(Key::PageUp, 10, 25);
+        assert_eq!(state.scroll, 0);
+
+        state.apply(Key::Clear, 10, 25);
+        assert_eq!(state.input, "");
+        assert_eq!(state.apply(Key::Enter, 10, 25), Some(true));
+        assert_eq!(state.apply(Key::Quit, 10, 25), Some(false));
+    }
+
+    #[test]
+    fn test_classify() {
+        let paths = ["src/main.rs", "src/README.md", "docs/guide.md"];
+        assert_eq!(
+            classify("src/** !**/*.md", &paths, Anchoring::Anchored),
+            Ok(vec![
+                Selection::Matched,
+                Selection::Excluded,
+                Selection::Unmatched
+            ])
+        );
+        assert_eq!(
+            classify("  ", &paths, Anchoring::Anchored),
+            Ok(vec![Selection::Unmatched; 3])
+        );
+        assert!(classify("src/[", &paths, Anchoring::Anchored).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let paths = ["src/main.rs", "src/README.md", "docs/guide.md"];
+        let screen = Screen {
+            rows: 6,
+            cols: 40,
+            scroll: 1,
+        };
+        let frame = render("src/** !**/*.md", &paths, Anchoring::Anchored, &screen);
+        let lines: Vec<&str> = frame.split("\x1b[K\r\n").collect();
+        assert_eq!(
+            lines,
+            [
+                "\x1b[HPatterns: src/** !**/*.md",
+                "1 of 3 changed files match, 1 excluded",
+                "\x1b[2m  src/README.md (excluded)\x1b[0m",
+                "  docs/guide.md",
+                "",
+                "\x1b[2mUp/Down/PgUp/PgDn scroll, Enter prints t\x1b[0m\x1b[K\x1b[1;26H",
+            ]
+        );
+
+        let frame = render("src/[", &paths, Anchoring::Anchored, &screen);
+        assert!(frame.contains(RED));
+        let frame = render("", &paths, Anchoring::Anchored, &screen);
+        assert!(frame.contains("Type patterns separated by spaces"));
+    }
+
+    #[test]
+    fn test_pattern_flags() {
+        assert_eq!(
+            pattern_flags(" src/** !it's/** "),
+            r"-p 'src/**' -p '!it'\''s/**'"
+        );
+        assert_eq!(pattern_flags(""), "");
+    }
+}
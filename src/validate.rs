@@ -0,0 +1,308 @@
+//! `git-diff-filter validate`: check a config file without running git, so a broken
+//! config fails its own pull request instead of the next build that reads it.
+
+use crate::cli;
+use crate::config::{self, FilterGroup};
+use crate::matcher::{Anchoring, Pattern};
+use std::fs;
+use std::path::Path;
+
+/// Check the config file given with `--config`, or else the discovered one, on its own
+/// and with each of its profiles
+///
+/// Returns a summary line when the file is valid, or one line per problem, each
+/// pointing at the line and column of the text it is about when it can be found.
+pub fn run(args: &[String]) -> Result<String, Vec<String>> {
+    let path = config_arg(args).map_err(|e| vec![e])?;
+    let Some(path) = config::config_path(path.as_deref(), false) else {
+        return Err(vec![format!(
+            "No config file found; looked for {} up to the repository root",
+            config::CONFIG_FILES.join(", ")
+        )]);
+    };
+    let (filters, patterns) = check(&path, None)?;
+    let profiles = config::profile_names(&path).map_err(|e| vec![e])?;
+    let mut problems = Vec::new();
+    for profile in &profiles {
+        if let Err(found) = check(&path, Some(profile)) {
+            problems.extend(
+                found
+                    .into_iter()
+                    .map(|problem| format!("profile {profile}: {problem}")),
+            );
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    let profiles = match profiles.len() {
+        0 => String::new(),
+        count => format!(", {count} profiles"),
+    };
+    Ok(format!(
+        "{}: OK ({filters} filters, {patterns} patterns{profiles})",
+        path.display(),
+    ))
+}
+
+/// Check the config file with `profile` applied, returning its number of filters and
+/// patterns
+fn check(path: &Path, profile: Option<&str>) -> Result<(usize, usize), Vec<String>> {
+    // Parse errors already carry their line and column
+    let entries = config::file_args(path.to_str(), false, profile).map_err(|e| vec![e])?;
+    let args = cli::parse_flags(&entries.concat()).map_err(|e| vec![e])?;
+    let text = fs::read_to_string(path).unwrap_or_default();
+    let at = |problem: Problem| problem.describe(path, &text);
+
+    let groups = config::filter_groups(
+        args.patterns.clone(),
+        args.github_output.clone(),
+        &args.group_compose,
+    )
+    .and_then(|mut groups| config::group_options(&mut groups, &args).map(|_| groups))
+    .map_err(|e| vec![at(Problem::new(e, None))])?;
+    let anchoring = args
+        .anchoring
+        .as_deref()
+        .map_or(Ok(Anchoring::default()), str::parse)
+        .map_err(|e| vec![at(Problem::new(e, None))])?;
+    let mut problems: Vec<String> = args
+        .patterns
+        .iter()
+        .filter_map(|raw| Pattern::parse(raw).err().map(|e| (raw, e)))
+        .map(|(raw, e)| at(Problem::new(format!("{raw}: {e}"), Some(raw))))
+        .collect();
+    if problems.is_empty() {
+        for group in &groups {
+            problems.extend(unreachable_rules(group, anchoring).into_iter().map(at));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    Ok((groups.len(), args.patterns.len()))
+}
+
+/// The `--config <path>` argument, the only flag `validate` takes
+fn config_arg(args: &[String]) -> Result<Option<String>, String> {
+    match args {
+        [] => Ok(None),
+        [flag, path] if flag == "--config" => Ok(Some(path.clone())),
+        [flag] if flag == "--config" => Err("--config requires a value".to_string()),
+        _ => Err(format!(
+            "Unexpected arguments to validate: {} (usage: git-diff-filter validate [--config <path>])",
+            args.join(" ")
+        )),
+    }
+}
+
+/// A problem found in the config, with the text to point at
+#[derive(Debug, PartialEq, Eq)]
+struct Problem {
+    message: String,
+    needle: Option<String>,
+}
+
+impl Problem {
+    fn new(message: String, needle: Option<&str>) -> Problem {
+        Problem {
+            message,
+            needle: needle.map(str::to_string),
+        }
+    }
+
+    /// `<path>: line L, column C: <message>`, without the position when the text
+    /// the problem is about cannot be found
+    fn describe(&self, path: &Path, text: &str) -> String {
+        match self
+            .needle
+            .as_deref()
+            .and_then(|needle| locate(text, needle))
+        {
+            Some((line, column)) => format!(
+                "{}: line {line}, column {column}: {}",
+                path.display(),
+                self.message
+            ),
+            None => format!("{}: {}", path.display(), self.message),
+        }
+    }
+}
+
+/// The line and column, both from 1, where `needle` is first written as a quoted
+/// string, or else anywhere
+fn locate(text: &str, needle: &str) -> Option<(usize, usize)> {
+    let offset = [format!("\"{needle}\""), format!("'{needle}'")]
+        .iter()
+        .find_map(|quoted| text.find(quoted.as_str()))
+        .or_else(|| text.find(needle))?;
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Some((line, before[line_start..].chars().count() + 1))
+}
+
+/// Rules in a group that can never change its result: repeated patterns, inclusions
+/// that an exclusion covers entirely, and a group with no inclusion at all
+fn unreachable_rules(group: &FilterGroup, anchoring: Anchoring) -> Vec<Problem> {
+    let name = group
+        .name
+        .as_deref()
+        .map_or_else(|| "the filter".to_string(), |name| format!("filter {name}"));
+    let patterns: Vec<Pattern> = group
+        .patterns
+        .iter()
+        .filter_map(|raw| Pattern::parse(raw).ok())
+        .collect();
+    let mut problems = Vec::new();
+    for (index, raw) in group.patterns.iter().enumerate() {
+        if group.patterns[..index].contains(raw) {
+            problems.push(Problem::new(
+                format!("{raw} is listed twice in {name}"),
+                Some(raw),
+            ));
+        }
+    }
+    if !patterns.is_empty() && patterns.iter().all(Pattern::is_negated) {
+        problems.push(Problem::new(
+            format!("{name} has only exclusions, so it never matches"),
+            group.name.as_deref(),
+        ));
+    }
+    for inclusion in patterns.iter().filter(|pattern| !pattern.is_negated()) {
+        let covering = patterns
+            .iter()
+            .filter(|pattern| pattern.is_negated())
+            .find(|exclusion| covers(exclusion.glob(), inclusion.glob(), anchoring));
+        if let Some(exclusion) = covering {
+            problems.push(Problem::new(
+                format!(
+                    "{} in {name} never matches: !{} excludes every path it selects",
+                    inclusion.glob(),
+                    exclusion.glob()
+                ),
+                Some(inclusion.glob()),
+            ));
+        }
+    }
+    problems
+}
+
+/// Whether the exclusion `exclusion` drops every path the glob `inclusion` selects
+///
+/// Only decided for the same glob, an inclusion under an excluded `<dir>/**`, and a
+/// literal path the exclusion matches; anything else counts as reachable.
+fn covers(exclusion: &str, inclusion: &str, anchoring: Anchoring) -> bool {
+    if exclusion == inclusion {
+        return true;
+    }
+    let literal = |glob: &str| !glob.contains(['*', '?', '[', '{', '\\']);
+    if let Some(dir) = exclusion.strip_suffix("/**") {
+        let dir = dir.trim_start_matches('/');
+        let under = inclusion
+            .trim_start_matches('/')
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'));
+        if literal(dir) && under {
+            return true;
+        }
+    }
+    literal(inclusion)
+        && Pattern::parse(exclusion)
+            .and_then(|pattern| pattern.is_match(inclusion, anchoring))
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(name: Option<&str>, patterns: &[&str]) -> FilterGroup {
+        FilterGroup {
+            name: name.map(str::to_string),
+            patterns: patterns.iter().map(|s| (*s).to_string()).collect(),
+            ..FilterGroup::default()
+        }
+    }
+
+    fn messages(problems: Vec<Problem>) -> Vec<String> {
+        problems
+            .into_iter()
+            .map(|problem| problem.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_config_arg() {
+        assert_eq!(config_arg(&[]), Ok(None));
+        assert_eq!(
+            config_arg(&["--config".to_string(), "ci.toml".to_string()]),
+            Ok(Some("ci.toml".to_string()))
+        );
+        assert!(config_arg(&["--config".to_string()]).is_err());
+        assert!(config_arg(&["-p".to_string(), "src/**".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_locate() {
+        let text =
+            "patterns = [\"docs/**\"]\n\n[filters.api]\npatterns = [\"api/**\", \"!api/**\"]\n";
+        assert_eq!(locate(text, "docs/**"), Some((1, 13)));
+        assert_eq!(locate(text, "api/**"), Some((4, 13)));
+        assert_eq!(locate(text, "!api/**"), Some((4, 23)));
+        assert_eq!(locate(text, "web/**"), None);
+        assert_eq!(locate("filters:\n  api:\n", "api"), Some((2, 3)));
+    }
+
+    #[test]
+    fn test_describe() {
+        let path = Path::new("ci.toml");
+        let problem = Problem::new("bad".to_string(), Some("b"));
+        assert_eq!(
+            problem.describe(path, "a\n b"),
+            "ci.toml: line 2, column 2: bad"
+        );
+        assert_eq!(problem.describe(path, "a"), "ci.toml: bad");
+    }
+
+    #[test]
+    fn test_covers() {
+        let anchored = Anchoring::default();
+        assert!(covers("docs/**", "docs/**", anchored));
+        assert!(covers("docs/**", "docs/api/*.md", anchored));
+        assert!(covers("docs/**", "/docs/README.md", anchored));
+        assert!(covers("*.md", "README.md", anchored));
+        assert!(!covers("docs/**", "documentation/**", anchored));
+        assert!(!covers("docs/*.md", "docs/**", anchored));
+        assert!(!covers("d*/**", "docs/api/**", anchored));
+    }
+
+    #[test]
+    fn test_unreachable_rules() {
+        let anchored = Anchoring::default();
+        assert!(
+            unreachable_rules(&group(Some("api"), &["api/**", "!api/*.md"]), anchored).is_empty()
+        );
+        assert_eq!(
+            messages(unreachable_rules(
+                &group(Some("api"), &["api/**", "api/**"]),
+                anchored
+            )),
+            ["api/** is listed twice in filter api"]
+        );
+        assert_eq!(
+            messages(unreachable_rules(
+                &group(None, &["!docs/**", "!*.md"]),
+                anchored
+            )),
+            ["the filter has only exclusions, so it never matches"]
+        );
+        assert_eq!(
+            messages(unreachable_rules(
+                &group(Some("docs"), &["src/**", "docs/api/**", "!docs/**"]),
+                anchored
+            )),
+            ["docs/api/** in filter docs never matches: !docs/** excludes every path it selects"]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Reverse dependencies within a workspace, for `--include-dependents`: a member that
+//! depends on an affected member, directly or through others, is affected too.
+
+use std::collections::BTreeSet;
+
+/// `affected` with every member depending on one of them, sorted and once each
+///
+/// `graph` holds each member's name with the names it depends on; dependencies on
+/// anything outside the workspace are ignored.
+pub fn with_dependents(affected: Vec<String>, graph: &[(String, Vec<String>)]) -> Vec<String> {
+    let mut found: BTreeSet<String> = affected.into_iter().collect();
+    let mut queue: Vec<String> = found.iter().cloned().collect();
+    while let Some(name) = queue.pop() {
+        for (member, dependencies) in graph {
+            if dependencies.contains(&name) && found.insert(member.clone()) {
+                queue.push(member.clone());
+            }
+        }
+    }
+    found.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_dependents() {
+        let member = |name: &str, dependencies: &[&str]| {
+            (
+                name.to_string(),
+                dependencies.iter().map(|s| (*s).to_string()).collect(),
+            )
+        };
+        let graph = [
+            member("core", &["serde"]),
+            member("api", &["core"]),
+            member("cli", &["api"]),
+            member("docs", &[]),
+            member("bench", &["core", "api"]),
+        ];
+        assert_eq!(
+            with_dependents(vec!["core".to_string()], &graph),
+            ["api", "bench", "cli", "core"]
+        );
+        assert_eq!(
+            with_dependents(vec!["docs".to_string(), "cli".to_string()], &graph),
+            ["cli", "docs"]
+        );
+        // A cycle ends once every member is found
+        let graph = [member("a", &["b"]), member("b", &["a"])];
+        assert_eq!(with_dependents(vec!["a".to_string()], &graph), ["a", "b"]);
+        assert!(with_dependents(Vec::new(), &graph).is_empty());
+    }
+}
@@ -0,0 +1,685 @@
+//! Minimal YAML serializer for reports, rendering the same values as the JSON report,
+//! and parser for configuration files.
+//!
+//! The parser reads the block subset a configuration needs: indented mappings and
+//! sequences, comments, plain, single- and double-quoted scalars, and single-line flow
+//! sequences and mappings (`[a, b]`, `{k: v}`). Anchors, aliases, tags, block scalars
+//! and multiple documents are rejected.
+
+use crate::json::Value;
+
+/// Render a value as a YAML document in block style
+///
+/// Strings are always double-quoted, so no value can be read back as another type
+/// (`"true"`, `"1.0"`, `"null"`) or break the document structure.
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    if is_block(value) {
+        write_block(&mut out, value, 0);
+    } else {
+        out.push_str(&scalar(value));
+        out.push('\n');
+    }
+    out
+}
+
+/// Non-empty arrays and objects are written as indented blocks; everything else fits
+/// on the line of its key or list item
+fn is_block(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(members) => !members.is_empty(),
+        _ => false,
+    }
+}
+
+/// Write a non-empty array or object, each line indented by `indent` spaces
+fn write_block(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                out.push_str(&pad);
+                out.push('-');
+                if is_block(item) {
+                    // The item's first line goes after the dash
+                    let mut nested = String::new();
+                    write_block(&mut nested, item, indent + 2);
+                    out.push(' ');
+                    out.push_str(&nested[indent + 2..]);
+                } else {
+                    let line = format!(" {}\n", scalar(item));
+                    out.push_str(&line);
+                }
+            }
+        }
+        Value::Object(members) => {
+            for (key, member) in members {
+                let line = format!("{pad}{}:", key_scalar(key));
+                out.push_str(&line);
+                if is_block(member) {
+                    out.push('\n');
+                    write_block(out, member, indent + 2);
+                } else {
+                    let line = format!(" {}\n", scalar(member));
+                    out.push_str(&line);
+                }
+            }
+        }
+        _ => unreachable!("only arrays and objects are written as blocks"),
+    }
+}
+
+/// A value written inline: a scalar, `[]` or `{}`
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => quote(s),
+        // Numbers, booleans and null read the same in YAML as in JSON
+        other => other.to_string(),
+    }
+}
+
+/// Keys are written plain when they cannot be mistaken for anything but a string
+fn key_scalar(key: &str) -> String {
+    let plain = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !matches!(
+            key.to_ascii_lowercase().as_str(),
+            "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "y" | "n"
+        );
+    if plain {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+/// A double-quoted scalar, escaping quotes, backslashes and non-printable characters
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() || matches!(c, '\u{2028}' | '\u{2029}' | '\u{feff}') => {
+                let escape = format!("\\u{:04x}", u32::from(c));
+                quoted.push_str(&escape);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a YAML document into a value, keeping mapping keys in document order
+///
+/// An empty document reads as an empty mapping.
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut lines = Vec::new();
+    for (index, raw) in input.lines().enumerate() {
+        let number = index + 1;
+        let text = raw.trim_end();
+        let content = text.trim_start_matches(' ');
+        if content.starts_with('\t') {
+            let column = text.len() - content.len() + 1;
+            return Err(format!(
+                "Invalid YAML at line {number}, column {column}: tabs cannot indent"
+            ));
+        }
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        if content == "---" || content.starts_with("--- ") {
+            if lines.is_empty() && content.trim_start_matches('-').trim().is_empty() {
+                continue;
+            }
+            return Err(format!(
+                "Invalid YAML at line {number}, column 1: only one document is supported"
+            ));
+        }
+        lines.push(Line {
+            number,
+            indent: text.len() - content.len(),
+            text: content,
+        });
+    }
+    let Some(&first) = lines.first() else {
+        return Ok(Value::Object(Vec::new()));
+    };
+    let mut parser = BlockParser { lines, pos: 0 };
+    let value = parser.parse_node(first.indent)?;
+    match parser.lines.get(parser.pos) {
+        Some(line) => Err(line.error("unexpected indentation")),
+        None => Ok(value),
+    }
+}
+
+/// A line with content, without its indentation
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+impl Line<'_> {
+    /// An error at the start of the line's content
+    fn error(&self, reason: &str) -> String {
+        self.error_at(self.indent + 1, reason)
+    }
+
+    fn error_at(&self, column: usize, reason: &str) -> String {
+        format!(
+            "Invalid YAML at line {}, column {column}: {reason}",
+            self.number
+        )
+    }
+
+    fn is_item(&self) -> bool {
+        self.text == "-" || self.text.starts_with("- ")
+    }
+}
+
+struct BlockParser<'a> {
+    lines: Vec<Line<'a>>,
+    pos: usize,
+}
+
+impl BlockParser<'_> {
+    /// Parse the sequence or mapping whose first line is the current one, at `indent`
+    fn parse_node(&mut self, indent: usize) -> Result<Value, String> {
+        let value = if self.lines[self.pos].is_item() {
+            self.parse_sequence(indent)?
+        } else {
+            self.parse_mapping(indent)?
+        };
+        match self.lines.get(self.pos) {
+            Some(line) if line.indent > indent => Err(line.error("unexpected indentation")),
+            Some(line) if line.indent == indent => Err(line.error("expected a list item")),
+            _ => Ok(value),
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value, String> {
+        let mut items = Vec::new();
+        while let Some(&line) = self.lines.get(self.pos) {
+            // A mapping may go on after a sequence given at its keys' indentation
+            if line.indent != indent || !line.is_item() {
+                break;
+            }
+            let rest = line.text[1..].trim_start_matches(' ');
+            let item = if rest.is_empty() || rest.starts_with('#') {
+                self.pos += 1;
+                self.parse_nested(indent)?
+            } else {
+                // The item's content continues at the column after the dash
+                let item_line = Line {
+                    indent: indent + line.text.len() - rest.len(),
+                    text: rest,
+                    ..line
+                };
+                if item_line.is_item() || split_key(&item_line)?.is_some() {
+                    self.lines[self.pos] = item_line;
+                    self.parse_node(item_line.indent)?
+                } else {
+                    self.pos += 1;
+                    parse_inline(&item_line, rest)?
+                }
+            };
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value, String> {
+        let mut members: Vec<(String, Value)> = Vec::new();
+        while let Some(&line) = self.lines.get(self.pos) {
+            if line.indent != indent {
+                break;
+            }
+            let Some((key, rest)) = split_key(&line)? else {
+                return Err(line.error("expected key: value"));
+            };
+            if members.iter().any(|(existing, _)| *existing == key) {
+                return Err(line.error(&format!("key {key} defined twice")));
+            }
+            self.pos += 1;
+            let value = if rest.is_empty() || rest.starts_with('#') {
+                // A sequence may sit at the same indentation as its key
+                match self.lines.get(self.pos) {
+                    Some(next) if next.indent == indent && next.is_item() => {
+                        self.parse_sequence(indent)?
+                    }
+                    _ => self.parse_nested(indent)?,
+                }
+            } else {
+                parse_inline(&line, rest)?
+            };
+            members.push((key, value));
+        }
+        Ok(Value::Object(members))
+    }
+
+    /// The block indented deeper than `indent` on the following lines, or null
+    fn parse_nested(&mut self, indent: usize) -> Result<Value, String> {
+        match self.lines.get(self.pos) {
+            Some(next) if next.indent > indent => self.parse_node(next.indent),
+            _ => Ok(Value::Null),
+        }
+    }
+}
+
+/// Split a `key: value` line into its key and the rest of the line, or `None` when the
+/// line holds no key
+fn split_key<'a>(line: &Line<'a>) -> Result<Option<(String, &'a str)>, String> {
+    let text = line.text;
+    let (key, after) = if text.starts_with(['"', '\'']) {
+        let mut scanner = Scanner::new(line, text);
+        let Value::String(key) = scanner.parse_value(true)? else {
+            unreachable!("quoted scalars are strings");
+        };
+        (key, &text[scanner.pos..])
+    } else {
+        let bytes = text.as_bytes();
+        let Some(colon) = (0..bytes.len())
+            .find(|&i| bytes[i] == b':' && matches!(bytes.get(i + 1), None | Some(b' ' | b'\t')))
+        else {
+            return Ok(None);
+        };
+        (text[..colon].trim_end().to_string(), &text[colon..])
+    };
+    match after.trim_start().strip_prefix(':') {
+        Some(rest) if rest.is_empty() || rest.starts_with([' ', '\t']) => {
+            Ok(Some((key, rest.trim_start())))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parse a value written on the rest of a line, which may end with a comment
+fn parse_inline(line: &Line, text: &str) -> Result<Value, String> {
+    let mut scanner = Scanner::new(line, text);
+    let value = scanner.parse_value(false)?;
+    scanner.skip_spaces();
+    match scanner.peek() {
+        None | Some(b'#') => Ok(value),
+        Some(_) => Err(scanner.error("unexpected characters after the value")),
+    }
+}
+
+/// Reads scalars and flow collections within one line
+struct Scanner<'a> {
+    line: Line<'a>,
+    /// The rest of the line's content, from where the scanner started
+    text: &'a str,
+    /// Column of the start of `text` on the line, from 0
+    offset: usize,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(line: &Line<'a>, text: &'a str) -> Scanner<'a> {
+        Scanner {
+            line: *line,
+            text,
+            offset: line.indent + line.text.len() - text.len(),
+            pos: 0,
+        }
+    }
+
+    /// An error at the scanner's position
+    fn error(&self, reason: &str) -> String {
+        self.line.error_at(self.offset + self.pos + 1, reason)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Parse a value; in flow context plain scalars also end at `,`, `]`, `}` and `: `
+    fn parse_value(&mut self, flow: bool) -> Result<Value, String> {
+        match self.peek() {
+            Some(b'[') => self.parse_flow_sequence(),
+            Some(b'{') => self.parse_flow_mapping(),
+            Some(b'"') => self.parse_double_quoted().map(Value::String),
+            Some(b'\'') => self.parse_single_quoted().map(Value::String),
+            Some(b'&' | b'*' | b'!' | b'|' | b'>' | b'%' | b'@' | b'`') => Err(self.error(
+                "anchors, aliases, tags and block scalars are not supported \
+                 (quote values starting with *, ! or &)",
+            )),
+            _ => Ok(plain_value(self.parse_plain(flow))),
+        }
+    }
+
+    fn parse_plain(&mut self, flow: bool) -> &'a str {
+        let bytes = self.text.as_bytes();
+        let start = self.pos;
+        while let Some(&b) = bytes.get(self.pos) {
+            let next = bytes.get(self.pos + 1).copied();
+            let at_comment = b == b'#' && self.pos > start && bytes[self.pos - 1] == b' ';
+            let at_flow_end = flow
+                && (matches!(b, b',' | b']' | b'}')
+                    || (b == b':' && matches!(next, None | Some(b' '))));
+            if at_comment || at_flow_end {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.text[start..self.pos].trim_end()
+    }
+
+    fn parse_double_quoted(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(out);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c @ ('"' | '\\' | '/' | ' ')) => c,
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid \\u escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    out.push(escaped);
+                }
+                c => out.push(c),
+            }
+        }
+        self.pos = start;
+        Err(self.error("unterminated string"))
+    }
+
+    fn parse_single_quoted(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let Some(quote) = rest.find('\'') else {
+                self.pos = start;
+                return Err(self.error("unterminated string"));
+            };
+            out.push_str(&rest[..quote]);
+            self.pos += quote + 1;
+            // A doubled quote stands for one quote
+            if self.peek() == Some(b'\'') {
+                out.push('\'');
+                self.pos += 1;
+            } else {
+                return Ok(out);
+            }
+        }
+    }
+
+    fn parse_flow_sequence(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value(true)?);
+            self.skip_spaces();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {}
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_flow_mapping(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut members: Vec<(String, Value)> = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                return Ok(Value::Object(members));
+            }
+            let key = match self.parse_value(true)? {
+                Value::String(key) => key,
+                other => other.to_string(),
+            };
+            if members.iter().any(|(existing, _)| *existing == key) {
+                return Err(self.error(&format!("key {key} defined twice")));
+            }
+            self.skip_spaces();
+            if self.peek() != Some(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            self.pos += 1;
+            self.skip_spaces();
+            let value = self.parse_value(true)?;
+            members.push((key, value));
+            self.skip_spaces();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {}
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+/// Resolve a plain scalar to null, a boolean, a number or a string
+fn plain_value(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => Value::Null,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        _ => {
+            let numeric = text.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c))
+                && text
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E'));
+            match text.parse() {
+                Ok(number) if numeric => Value::Number(number),
+                _ => Value::String(text.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(to_string(&Value::Null), "null\n");
+        assert_eq!(to_string(&Value::Number(41.0)), "41\n");
+        assert_eq!(to_string(&Value::String("true".to_string())), "\"true\"\n");
+        assert_eq!(to_string(&Value::Array(Vec::new())), "[]\n");
+    }
+
+    #[test]
+    fn test_nested_blocks() {
+        let value = json::parse(
+            r#"{"match":true,"groups":[{"name":"api","files":["a.go","b c.go"],"patterns":[]},{"name":null,"files":[]}],"ranges":[[1,2]]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            to_string(&value),
+            "match: true
+groups:
+  - name: \"api\"
+    files:
+      - \"a.go\"
+      - \"b c.go\"
+    patterns: []
+  - name: null
+    files: []
+ranges:
+  - - 1
+    - 2
+"
+        );
+    }
+
+    #[test]
+    fn test_quote_escapes() {
+        assert_eq!(
+            quote("a\"b\\c\nd\u{7f}\u{2028}"),
+            r#""a\"b\\c\nd\u007f\u2028""#
+        );
+    }
+
+    #[test]
+    fn test_key_scalar() {
+        assert_eq!(key_scalar("elapsed_ms"), "elapsed_ms");
+        assert_eq!(key_scalar("web-api"), "web-api");
+        assert_eq!(key_scalar("yes"), "\"yes\"");
+        assert_eq!(key_scalar("a: b"), "\"a: b\"");
+        assert_eq!(key_scalar("1st"), "\"1st\"");
+    }
+
+    fn parsed(input: &str) -> String {
+        parse(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_parse_block_mappings_and_sequences() {
+        let input = "
+# Filters for the monorepo
+---
+base-ref: main
+find-copies: true
+max-lines-changed: 500
+patterns:
+- \"services/**\"   # every service
+- '!**/*.md'
+filters:
+  api:
+    patterns:
+      - services/api/**
+    ignore-case: yes
+  empty:
+";
+        assert_eq!(
+            parsed(input),
+            r#"{"base-ref":"main","find-copies":true,"max-lines-changed":500,"patterns":["services/**","!**/*.md"],"filters":{"api":{"patterns":["services/api/**"],"ignore-case":"yes"},"empty":null}}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_sequence_of_mappings() {
+        let input = "
+- name: api
+  patterns: [a/**, 'b c']
+-
+  - nested
+- - inner
+  - second
+";
+        assert_eq!(
+            parsed(input),
+            r#"[{"name":"api","patterns":["a/**","b c"]},["nested"],["inner","second"]]"#
+        );
+    }
+
+    #[test]
+    fn test_parse_scalars() {
+        let input = r#"
+plain: a # b#c
+hash: a#b
+quoted: "tab\t\"q\" \u00e9"
+single: 'it''s'
+null: ~
+float: 1.5
+version: 1.2.3
+flow: {a: 1, "b": [x, y], c: }
+empty: []
+"#;
+        assert_eq!(
+            parsed(input),
+            r#"{"plain":"a","hash":"a#b","quoted":"tab\t\"q\" é","single":"it's","null":null,"float":1.5,"version":"1.2.3","flow":{"a":1,"b":["x","y"],"c":null},"empty":[]}"#
+        );
+        assert_eq!(parsed(""), "{}");
+        assert_eq!(parsed("# only a comment\n"), "{}");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |input: &str| parse(input).unwrap_err();
+        assert_eq!(
+            error("patterns:\n  - *.md\n"),
+            "Invalid YAML at line 2, column 5: anchors, aliases, tags and block scalars are not supported (quote values starting with *, ! or &)"
+        );
+        assert_eq!(
+            error("a: 1\na: 2\n"),
+            "Invalid YAML at line 2, column 1: key a defined twice"
+        );
+        assert_eq!(
+            error("a:\n    b: 1\n  c: 2\n"),
+            "Invalid YAML at line 3, column 3: unexpected indentation"
+        );
+        assert_eq!(
+            error("a: 1\n  b: 2\n"),
+            "Invalid YAML at line 2, column 3: unexpected indentation"
+        );
+        assert_eq!(
+            error("- a\nb: 1\n"),
+            "Invalid YAML at line 2, column 1: expected a list item"
+        );
+        assert_eq!(
+            error("just text\n"),
+            "Invalid YAML at line 1, column 1: expected key: value"
+        );
+        assert_eq!(
+            error("a:\n\t- b\n"),
+            "Invalid YAML at line 2, column 1: tabs cannot indent"
+        );
+        assert_eq!(
+            error("a: \"open\n"),
+            "Invalid YAML at line 1, column 4: unterminated string"
+        );
+        assert_eq!(
+            error("a: [1, 2\n"),
+            "Invalid YAML at line 1, column 9: expected ',' or ']'"
+        );
+        assert_eq!(
+            error("a: 1\n---\nb: 2\n"),
+            "Invalid YAML at line 2, column 1: only one document is supported"
+        );
+    }
+
+    #[test]
+    fn test_parse_round_trips_reports() {
+        let value = json::parse(
+            r#"{"match":true,"groups":[{"name":"api","files":["a.go","yes"],"patterns":[]}],"n":null}"#,
+        )
+        .unwrap();
+        assert_eq!(parse(&to_string(&value)).unwrap(), value);
+    }
+}